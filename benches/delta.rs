@@ -0,0 +1,68 @@
+/*
+    Benchmarks building the delta segments from large chunk lists, as done by
+    DiffArtifacts::delta once the LCS of the chunk hashes is known.
+
+    The chunk lists are synthetic: the new list is the old one with regions deleted and
+    inserted at regular intervals, so that the delta alternates Old and New segments.
+*/
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use differ::{Chunk, DiffArtifacts};
+
+const CHUNK_SIZE: u64 = 4096;
+
+fn chunk_hash(id: u64) -> Vec<u8> {
+    let mut hash = id.wrapping_mul(0x9e3779b97f4a7c15).to_le_bytes().to_vec();
+    hash.resize(32, 0); // the size of a SHA256 hash
+    hash
+}
+
+fn make_chunks(ids: &[u64]) -> Vec<Chunk> {
+    ids.iter()
+        .enumerate()
+        .map(|(index, &id)| Chunk {
+            hash: chunk_hash(id),
+            end: (index as u64 + 1) * CHUNK_SIZE,
+        })
+        .collect()
+}
+
+// the old chunks, the new chunks (with every 'edit_interval'-th run of 3 chunks either
+// deleted or replaced by new ones) and their LCS
+fn make_artifacts(chunk_count: u64, edit_interval: u64) -> DiffArtifacts {
+    let old_ids: Vec<u64> = (0..chunk_count).collect();
+    let mut new_ids: Vec<u64> = Vec::with_capacity(old_ids.len());
+    let mut lcs_ids: Vec<u64> = Vec::with_capacity(old_ids.len());
+    for &id in &old_ids {
+        let edit = id / 3 % edit_interval;
+        if edit == 0 {
+            new_ids.push(chunk_count + id); // replaced
+        } else if edit != 1 {
+            new_ids.push(id);
+            lcs_ids.push(id);
+        } // else deleted
+    }
+    DiffArtifacts {
+        chunks_old: make_chunks(&old_ids),
+        chunks_new: make_chunks(&new_ids),
+        lcs: lcs_ids.iter().map(|&id| chunk_hash(id)).collect(),
+    }
+}
+
+fn bench_delta(c: &mut Criterion) {
+    let mut group = c.benchmark_group("delta");
+    for chunk_count in [10_000, 100_000, 500_000] {
+        for edit_interval in [4, 100] {
+            let artifacts = make_artifacts(chunk_count, edit_interval);
+            group.bench_with_input(
+                BenchmarkId::new(format!("edit_interval_{}", edit_interval), chunk_count),
+                &artifacts,
+                |b, artifacts| b.iter(|| black_box(artifacts.delta())),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_delta);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+/*
+    Benchmarks the two-tier chunk hashing (diff_two_tier) against the default Differ, on
+    similar inputs (most chunks are reused, so most need the strong hash anyway) and on
+    dissimilar ones (hardly any chunk needs it). The number of SHA256-hashed chunks is
+    printed for each case.
+*/
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use differ::{diff_two_tier, ChunkingParameters, Differ, Segment};
+
+const PARAMETERS: ChunkingParameters = ChunkingParameters {
+    window_size: 64,
+    min_chunk_size: 2048,
+    max_chunk_size: 8192,
+    boundary_mask: (1 << 12) - 1,
+};
+
+// xorshift bytes
+fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+fn diff_default(old: &[u8], new: &[u8]) -> Vec<Segment> {
+    let mut differ = Differ::try_new(
+        Some(PARAMETERS.window_size),
+        Some(PARAMETERS.min_chunk_size),
+        Some(PARAMETERS.max_chunk_size),
+        Some(PARAMETERS.boundary_mask),
+        None,
+    )
+    .unwrap();
+    differ.process_old(old);
+    differ.process_new(new);
+    differ.finalize()
+}
+
+fn bench_two_tier(c: &mut Criterion) {
+    let old = pseudo_random(4_000_000, 1);
+    let mut similar = old.clone();
+    for offset in (0..similar.len()).step_by(100_000) {
+        similar[offset] ^= 0xff;
+    }
+    let dissimilar = pseudo_random(4_000_000, 12345);
+
+    let mut group = c.benchmark_group("two_tier");
+    group.sample_size(10);
+    for (name, new) in [("similar", &similar), ("dissimilar", &dissimilar)] {
+        let (_, strong_hash_count) = diff_two_tier(&old, new, PARAMETERS).unwrap();
+        println!("{}: {} chunks hashed with SHA256", name, strong_hash_count);
+        group.bench_function(format!("{}/default", name), |b| {
+            b.iter(|| black_box(diff_default(&old, new)))
+        });
+        group.bench_function(format!("{}/two_tier", name), |b| {
+            b.iter(|| black_box(diff_two_tier(&old, new, PARAMETERS).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_two_tier);
+criterion_main!(benches);
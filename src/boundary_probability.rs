@@ -0,0 +1,134 @@
+/*
+    Diagnoses how well a rolling hasher suits the boundary detection: a position is a
+    boundary when the masked bits of the rolling hash are all zeros, which for a uniformly
+    distributed hash happens with the probability 1/(mask+1). A poorly distributed hash (e.g.
+    the moving sum on structured data) deviates from it, making the chunk sizes unpredictable.
+
+    The sample is run through the rolling hasher and the boundaries are counted at every
+    position where the window is filled, the min and max chunk sizes being ignored. The
+    observed counts of boundary and non-boundary positions are compared with the expected
+    ones with Pearson's chi-square statistic (one degree of freedom): the larger it is, the
+    less likely the deviation is due to chance.
+*/
+
+use crate::rolling_hasher::rolling_hasher::*;
+
+// the chi-square statistic (one degree of freedom) exceeded by chance with probability 0.001
+const CHI_SQUARE_CRITICAL_VALUE: f64 = 10.828;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundaryProbability {
+    pub positions: u64,  // the positions tested, i.e. where the window is filled
+    pub boundaries: u64, // the positions found to be boundaries
+    pub expected_rate: f64,
+    pub observed_rate: f64,
+    pub chi_square: f64,
+}
+
+impl BoundaryProbability {
+    // whether the observed rate is consistent with the expected one, i.e. the deviation
+    // is unlikely to be a sign of a poorly distributed hash
+    pub fn is_consistent(&self) -> bool {
+        self.chi_square <= CHI_SQUARE_CRITICAL_VALUE
+    }
+}
+
+// Counts the boundaries the rolling hasher finds in the sample with the given mask and
+// compares their rate with the theoretical one
+pub fn measure_boundary_probability<RH: RollingHasher>(
+    mut rolling_hasher: RH,
+    boundary_mask: u32,
+    sample: &[u8],
+) -> BoundaryProbability {
+    let window_size = rolling_hasher.get_window_size();
+    let mut positions: u64 = 0;
+    let mut boundaries: u64 = 0;
+    for (index, &byte) in sample.iter().enumerate() {
+        let rolling_hash = rolling_hasher.push(byte);
+        if index + 1 >= window_size {
+            positions += 1;
+            if rolling_hash & boundary_mask == 0 {
+                boundaries += 1;
+            }
+        }
+    }
+
+    let expected_rate = 1.0 / (u64::from(boundary_mask) + 1) as f64;
+    let expected_boundaries = positions as f64 * expected_rate;
+    let expected_others = positions as f64 - expected_boundaries;
+    let chi_square_term = |observed: u64, expected: f64| {
+        if expected > 0.0 {
+            (observed as f64 - expected).powi(2) / expected
+        } else {
+            0.0 // a category which can't be observed (a mask of 0 makes every position a boundary)
+        }
+    };
+    BoundaryProbability {
+        positions,
+        boundaries,
+        expected_rate,
+        observed_rate: if positions > 0 {
+            boundaries as f64 / positions as f64
+        } else {
+            0.0
+        },
+        chi_square: chi_square_term(boundaries, expected_boundaries)
+            + chi_square_term(positions - boundaries, expected_others),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rolling_hasher::moving_sum::*;
+    use crate::rolling_hasher::polynomial::*;
+
+    #[test]
+    fn test_boundary_probability() {
+        // xorshift pseudo-random bytes
+        let mut state: u32 = 2463534242;
+        let random: Vec<u8> = (0..1 << 20)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let boundary_mask: u32 = (1 << 8) - 1;
+
+        let measured = measure_boundary_probability(
+            PolynomialRollingHasher::new(64, None, None),
+            boundary_mask,
+            &random,
+        );
+        assert_eq!(measured.positions, random.len() as u64 - 63);
+        assert_eq!(measured.expected_rate, 1.0 / 256.0);
+        assert!(
+            (measured.observed_rate - measured.expected_rate).abs() < measured.expected_rate * 0.1
+        );
+        assert!(measured.is_consistent());
+
+        // the hash of a constant input is constant: either no position or all of them
+        let constant: Vec<u8> = vec![0xa5; 1 << 20];
+        let measured = measure_boundary_probability(
+            PolynomialRollingHasher::new(64, None, None),
+            boundary_mask,
+            &constant,
+        );
+        assert!(measured.boundaries == 0 || measured.boundaries == measured.positions);
+        assert!(!measured.is_consistent());
+        assert!(measured.chi_square > 1000.0);
+
+        // the moving sum only spans a narrow range of values, which the mask doesn't cut evenly
+        let measured =
+            measure_boundary_probability(MovingSumRollingHasher::new(64), (1 << 12) - 1, &random);
+        assert!(!measured.is_consistent());
+
+        assert_eq!(
+            measure_boundary_probability(MovingSumRollingHasher::new(64), boundary_mask, &[])
+                .positions,
+            0
+        );
+    }
+}
@@ -0,0 +1,56 @@
+/*
+    ChunkIndex is a read-only (once populated) set of chunk hashes, e.g. of all the chunks
+    previously stored by a deduplicating backup. New data can be diffed against it with
+    Differ::diff_against_index, which references the indexed chunks by hash (Store segments)
+    rather than by a range of a specific old file.
+
+    Chunks only match if they have been produced with the same parameters, so the index
+    is bound to a DifferConfig, used both to populate it and to diff against it.
+*/
+
+use crate::diffable::*;
+use crate::differ::*;
+use std::collections::HashSet;
+
+pub struct ChunkIndex {
+    config: DifferConfig,
+    hashes: HashSet<Vec<u8>>,
+}
+
+impl ChunkIndex {
+    pub fn new(config: DifferConfig) -> ChunkIndex {
+        ChunkIndex {
+            config,
+            hashes: HashSet::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DifferConfig {
+        &self.config
+    }
+
+    // slices the data using the index config and adds all its chunks to the index; fails if
+    // the data cannot be read, in which case the index is left as it was
+    pub fn add<D: Diffable>(&mut self, data: D) -> std::io::Result<()> {
+        let mut slicer = make_slicer(&self.config)
+            .unwrap_or_else(|error| panic!("Invalid index config: {}", error));
+        data.feed(|bytes| slicer.process(bytes))?;
+        let chunks = slicer
+            .finalize()
+            .unwrap_or_else(|error| panic!("Slicing indexed data failed: {}", error));
+        self.hashes.extend(chunks.iter().map(|chunk| chunk.hash.clone()));
+        Ok(())
+    }
+
+    pub fn contains(&self, hash: &[u8]) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
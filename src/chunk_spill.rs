@@ -0,0 +1,138 @@
+/*
+    ChunkSpill is a temporary file the Slicer moves its chunks to while slicing, so that the
+    chunk list of an enormous stream doesn't have to be held in memory until the stream ends.
+    The chunks are read back (in order) once the stream is finalized, and the file is removed
+    when the ChunkSpill is dropped.
+
+    Each chunk is stored as:
+    [hash length: u32][hash][end: u64]
+
+    If writing fails (e.g. the disk is full), the error is returned and the chunks are left to
+    the caller; only what has been written completely is read back.
+*/
+
+use crate::slicer::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// makes the spill file names unique within the process
+static SPILL_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct ChunkSpill {
+    path: PathBuf,
+    file: File,
+    bytes_written: u64, // by the complete writes only
+}
+
+impl ChunkSpill {
+    // creates a new spill file in the directory
+    pub(crate) fn create(directory: &Path) -> Result<ChunkSpill> {
+        let path = directory.join(format!(
+            "differ_chunks_{}_{}",
+            std::process::id(),
+            SPILL_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(ChunkSpill {
+            path,
+            file,
+            bytes_written: 0,
+        })
+    }
+
+    // appends the chunks to the file; on failure the chunks must be kept by the caller
+    pub(crate) fn spill(&mut self, chunks: &[Chunk]) -> Result<()> {
+        let mut bytes: Vec<u8> = Vec::new();
+        for chunk in chunks {
+            bytes.extend_from_slice(&(chunk.hash.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&chunk.hash);
+            bytes.extend_from_slice(&chunk.end.to_le_bytes());
+        }
+        self.file.write_all(&bytes)?;
+        self.bytes_written += bytes.len() as u64;
+        Ok(())
+    }
+
+    // reads back all the spilled chunks; the file is kept (until the ChunkSpill is dropped),
+    // so a failed reload can be retried
+    pub(crate) fn reload(&mut self) -> Result<Vec<Chunk>> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new((&self.file).take(self.bytes_written));
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut len_bytes = [0u8; 4];
+        let mut end_bytes = [0u8; 8];
+        loop {
+            match reader.read_exact(&mut len_bytes) {
+                Ok(()) => {}
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
+            let mut hash: Vec<u8> = vec![0; u32::from_le_bytes(len_bytes) as usize];
+            reader.read_exact(&mut hash)?;
+            reader.read_exact(&mut end_bytes)?;
+            chunks.push(Chunk {
+                hash,
+                end: u64::from_le_bytes(end_bytes),
+            });
+        }
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+impl ChunkSpill {
+    // creates a spill file which cannot be written to, so that every spill fails
+    pub(crate) fn create_read_only(directory: &Path) -> Result<ChunkSpill> {
+        let mut spill = ChunkSpill::create(directory)?;
+        spill.file = File::open(&spill.path)?;
+        Ok(spill)
+    }
+}
+
+impl Drop for ChunkSpill {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_spill() -> Result<()> {
+        let directory = std::env::temp_dir().join(format!("differ_chunk_spill_{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+        let chunks: Vec<Chunk> = (0..100u8)
+            .map(|index| Chunk {
+                hash: vec![index; index as usize % 33],
+                end: index as u64 * 1000,
+            })
+            .collect();
+
+        let mut spill = ChunkSpill::create(&directory)?;
+        spill.spill(&chunks[..40])?;
+        spill.spill(&[])?;
+        spill.spill(&chunks[40..])?;
+        assert_eq!(fs::read_dir(&directory)?.count(), 1);
+        assert_eq!(spill.reload()?, chunks);
+        assert_eq!(spill.reload()?, chunks);
+        // the file is removed once the spill is dropped
+        drop(spill);
+        assert_eq!(fs::read_dir(&directory)?.count(), 0);
+
+        // a failed write leaves nothing to read back
+        let mut spill = ChunkSpill::create_read_only(&directory)?;
+        assert!(spill.spill(&chunks).is_err());
+        assert_eq!(spill.reload()?, vec![]);
+        drop(spill);
+
+        fs::remove_dir_all(&directory)
+    }
+}
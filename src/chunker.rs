@@ -0,0 +1,461 @@
+/*
+    Standalone content-defined chunking, for uses which don't need any diffing (e.g.
+    deduplicating storage). Chunker is the streaming interface, chunk is a shortcut for
+    in-memory buffers.
+
+    Both use the same slicer as the default Differ (Polynomial rolling hash, SHA256 chunk
+    digest), so the chunks they produce are the ones the Differ compares.
+
+    Without the "sha2" feature (e.g. for non-security dedup, not pulling any crypto crate),
+    the chunks are hashed with 64-bit FNV-1a (see FnvHasher) instead of SHA256 by default.
+    new_with_hasher takes any other Hasher.
+
+    A Chunker created with new_salted hashes the chunks with HMAC-SHA256 keyed with a secret
+    salt instead (see HmacSha256Hasher), e.g. a per-tenant one in a shared chunk store. The
+    boundaries are the same, only the hashes differ from one salt to another.
+
+    A Chunker created with new_stable uses a stable chunking version, whose parameters (and
+    slicing behavior) are pinned rather than following the defaults, so that the chunks of
+    persisted data (e.g. stored manifests) can be reproduced by later releases of the crate.
+    A change making a version's chunks differ is a bug: new behavior gets a new version.
+
+    A Chunker created with new_pipelined hashes the chunks on worker threads while the
+    calling thread only detects the boundaries; the chunks are the same as with new. The
+    other options are set before processing any data: local minimum boundaries change the
+    chunks (so they no longer are the Differ's), while the boundary and the chunk metadata
+    callbacks only observe them (see the Slicer description).
+*/
+
+#[cfg(feature = "sha2")]
+use crate::differ::*;
+#[cfg(not(feature = "sha2"))]
+use crate::hasher::fnv::*;
+use crate::hasher::hasher::*;
+#[cfg(feature = "sha2")]
+use crate::hasher::hmac::*;
+#[cfg(feature = "sha2")]
+use crate::hasher::sha256::*;
+use crate::helper::*;
+use crate::rolling_hasher::polynomial::*;
+use crate::slicer::*;
+
+// the chunk hash of the Chunker created with new
+#[cfg(feature = "sha2")]
+type DefaultChunkHasher = Sha256Hasher;
+#[cfg(not(feature = "sha2"))]
+type DefaultChunkHasher = FnvHasher;
+
+/// A stable chunking version, producing byte-identical chunks (boundaries and hashes) in
+/// every release of the crate
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg(feature = "sha2")]
+pub enum StableChunking {
+    /// Polynomial rolling hash (window 64, modulus 1000000007, base 29791) reset and primed
+    /// at each boundary; a boundary once the 12 low bits of the hash are zeros, between 2KiB
+    /// and 16KiB (cut by force) chunk sizes; SHA256 chunk hashes
+    V1,
+}
+
+#[cfg(feature = "sha2")]
+impl StableChunking {
+    /// The version tag, e.g. to be stored along with the chunks
+    pub fn version(&self) -> u32 {
+        match self {
+            StableChunking::V1 => 1,
+        }
+    }
+
+    /// The pinned parameters, independent of the DifferConfig defaults
+    pub fn config(&self) -> DifferConfig {
+        match self {
+            StableChunking::V1 => DifferConfig {
+                chunking: ChunkingParameters {
+                    window_size: 64,
+                    min_chunk_size: 2048,
+                    max_chunk_size: 16384,
+                    boundary_mask: (1 << 12) - 1,
+                },
+                rolling_hash_modulus: 1000000007,
+                rolling_hash_base: 29791,
+                lcs_algorithm: None,
+            },
+        }
+    }
+}
+
+pub struct Chunker<H: Hasher = DefaultChunkHasher> {
+    slicer: Slicer<PolynomialRollingHasher, H>,
+}
+
+impl Chunker {
+    /// Creates a new Chunker instance
+    ///
+    /// Arguments:
+    /// parameters      - the chunking parameters
+    ///
+    /// Returned:
+    /// the Chunker instance or the error describing the invalid parameter combination
+    pub fn new(parameters: ChunkingParameters) -> Result<Chunker, SlicerError> {
+        #[cfg(feature = "sha2")]
+        let hasher = Sha256Hasher::new(parameters.max_chunk_size);
+        #[cfg(not(feature = "sha2"))]
+        let hasher = FnvHasher::new();
+        Chunker::new_with_hasher(parameters, hasher)
+    }
+
+    /// Creates a new Chunker instance hashing the chunks on worker threads, overlapping the
+    /// hashing with the boundary detection. The chunks are identical (and in the same order)
+    /// to those of a Chunker created with new
+    ///
+    /// Arguments:
+    /// parameters      - the chunking parameters
+    /// threads         - the number of hashing worker threads, at least one
+    ///
+    /// Returned:
+    /// the Chunker instance or the error describing the invalid parameter combination
+    pub fn new_pipelined(parameters: ChunkingParameters, threads: usize) -> Result<Chunker, SlicerError> {
+        let max_chunk_size = parameters.max_chunk_size;
+        #[cfg(feature = "sha2")]
+        let make_hasher = move || Sha256Hasher::new(max_chunk_size);
+        #[cfg(not(feature = "sha2"))]
+        let make_hasher = FnvHasher::new;
+        Ok(Chunker {
+            slicer: Slicer::new_pipelined(
+                make_rolling_hasher(&parameters)?,
+                make_hasher,
+                threads,
+                parameters.boundary_mask,
+                parameters.min_chunk_size,
+                max_chunk_size,
+            )?,
+        })
+    }
+
+    /// Creates a new Chunker instance for the stable chunking version
+    ///
+    /// Arguments:
+    /// version         - the stable chunking version
+    ///
+    /// Returned:
+    /// the Chunker instance
+    #[cfg(feature = "sha2")]
+    pub fn new_stable(version: StableChunking) -> Chunker {
+        Chunker {
+            slicer: make_slicer(&version.config()).expect("Stable chunking config is valid"),
+        }
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Chunker<HmacSha256Hasher> {
+    /// Creates a new Chunker instance hashing the chunks with HMAC-SHA256 keyed with the salt
+    ///
+    /// Arguments:
+    /// parameters      - the chunking parameters
+    /// salt            - the secret key of the chunk hashes
+    ///
+    /// Returned:
+    /// the Chunker instance or the error describing the invalid parameter combination
+    pub fn new_salted(
+        parameters: ChunkingParameters,
+        salt: &[u8],
+    ) -> Result<Chunker<HmacSha256Hasher>, SlicerError> {
+        let hasher = HmacSha256Hasher::new(salt, parameters.max_chunk_size);
+        Chunker::new_with_hasher(parameters, hasher)
+    }
+}
+
+impl<H: Hasher> Chunker<H> {
+    /// Creates a new Chunker instance hashing the chunks with the given hasher; the
+    /// boundaries are those of any other Chunker with the same parameters
+    ///
+    /// Arguments:
+    /// parameters      - the chunking parameters
+    /// hasher          - the chunk hasher
+    ///
+    /// Returned:
+    /// the Chunker instance or the error describing the invalid parameter combination
+    pub fn new_with_hasher(parameters: ChunkingParameters, hasher: H) -> Result<Chunker<H>, SlicerError> {
+        Ok(Chunker {
+            slicer: Slicer::new(
+                make_rolling_hasher(&parameters)?,
+                hasher,
+                parameters.boundary_mask,
+                parameters.min_chunk_size,
+                parameters.max_chunk_size,
+            )?,
+        })
+    }
+
+    /// Places the boundaries at the positions whose rolling hash is the minimum of the last
+    /// 'window' positions' hashes, rather than where the hash matches the boundary mask. To
+    /// be called before any data is processed
+    ///
+    /// Arguments:
+    /// window          - the number of positions the minimum is taken over, at least one
+    pub fn set_local_minimum_boundaries(&mut self, window: usize) {
+        self.slicer.set_local_minimum_boundaries(window);
+    }
+
+    /// Sets the callback receiving an event (offset, size, hash prefix and the reason of the
+    /// cut) for each chunk as soon as it's terminated. To be called before any data is
+    /// processed
+    ///
+    /// Arguments:
+    /// callback        - the callback receiving the boundary events
+    pub fn set_boundary_callback(&mut self, callback: BoundaryCallback) {
+        self.slicer.set_boundary_callback(callback);
+    }
+
+    /// Sets the callback computing each chunk's metadata from the chunk's byte range, the
+    /// metadata being returned by finalize_with_metadata. To be called before any data is
+    /// processed
+    ///
+    /// Arguments:
+    /// callback        - the callback computing the chunk metadata
+    pub fn set_chunk_metadata_callback(&mut self, callback: ChunkMetadataCallback) {
+        self.slicer.set_chunk_metadata_callback(callback);
+    }
+
+    /// Processes the next buffer of the stream
+    ///
+    /// Arguments:
+    /// buffer          - the buffer to be processed
+    pub fn process(&mut self, buffer: &[u8]) {
+        self.slicer.process(buffer);
+    }
+
+    /// Processes the next symbols of a stream of symbols wider than a byte (e.g. UTF-16 code
+    /// units), which the chunks never split. The chunk offsets are byte offsets.
+    ///
+    /// Arguments:
+    /// symbols         - the symbols to be processed
+    pub fn process_symbols<S: Symbol>(&mut self, symbols: &[S]) {
+        self.slicer.process_symbols(symbols);
+    }
+
+    /// Terminates the last chunk. To be called once the whole stream has been processed.
+    ///
+    /// Returned:
+    /// the chunks, each having its hash and the offset at which it ends
+    pub fn finalize(mut self) -> Result<Vec<Chunk>, SlicerError> {
+        self.slicer.finalize()?;
+        Ok(self.slicer.into_chunks())
+    }
+
+    /// Same as finalize but also returns the metadata of each chunk, computed by the callback
+    /// set with set_chunk_metadata_callback (empty if none has been set)
+    ///
+    /// Returned:
+    /// the chunks and their metadata
+    pub fn finalize_with_metadata(mut self) -> Result<(Vec<Chunk>, Vec<Vec<u8>>), SlicerError> {
+        self.slicer.finalize()?;
+        let metadata = self.slicer.chunk_metadata().to_vec();
+        Ok((self.slicer.into_chunks(), metadata))
+    }
+}
+
+// the rolling hasher of the Chunker, the window size being checked here as the rolling
+// hasher would panic
+fn make_rolling_hasher(parameters: &ChunkingParameters) -> Result<PolynomialRollingHasher, SlicerError> {
+    if parameters.window_size == 0 || !is_power_of_two(parameters.window_size) {
+        return Err(SlicerError::InvalidWindowSize(parameters.window_size));
+    }
+    Ok(PolynomialRollingHasher::new(parameters.window_size, None, None))
+}
+
+/// Slices the buffer into content-defined chunks
+///
+/// Arguments:
+/// data            - the buffer
+/// parameters      - the chunking parameters
+///
+/// Returned:
+/// the chunks, each having its hash and the offset at which it ends
+///
+/// ```
+/// use differ::{chunk, ChunkingParameters};
+///
+/// let parameters = ChunkingParameters {
+///     window_size: 16,
+///     min_chunk_size: 64,
+///     max_chunk_size: 1024,
+///     boundary_mask: (1 << 8) - 1, // avg chunk size is 2^8 = 256
+/// };
+/// let data: Vec<u8> = (0..10000u32).map(|i| (i * 7919 % 251) as u8).collect();
+/// let chunks = chunk(&data, parameters).unwrap();
+///
+/// let mut chunk_start: u64 = 0;
+/// for chunk in chunks.iter() {
+///     let hash: String = chunk.hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+///     println!("{}..{} {}", chunk_start, chunk.end, hash);
+///     assert!(chunk.end - chunk_start <= 1024);
+///     // SHA256, or FNV-1a without the "sha2" feature
+///     assert_eq!(chunk.hash.len(), if cfg!(feature = "sha2") { 32 } else { 8 });
+///     chunk_start = chunk.end;
+/// }
+/// assert_eq!(chunk_start, data.len() as u64);
+/// ```
+pub fn chunk(data: &[u8], parameters: ChunkingParameters) -> Result<Vec<Chunk>, SlicerError> {
+    let mut chunker = Chunker::new(parameters)?;
+    chunker.process(data);
+    chunker.finalize()
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_chunker_streaming() {
+        let parameters = ChunkingParameters {
+            window_size: 16,
+            min_chunk_size: 64,
+            max_chunk_size: 1024,
+            boundary_mask: (1 << 8) - 1,
+        };
+        let data: Vec<u8> = (0..10000u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+        let mut chunker = Chunker::new(parameters).unwrap();
+        for buffer in data.chunks(100) {
+            chunker.process(buffer);
+        }
+        assert_eq!(chunker.finalize().unwrap(), chunk(&data, parameters).unwrap());
+
+        let mut invalid_parameters = parameters;
+        invalid_parameters.max_chunk_size = 32;
+        assert!(Chunker::new(invalid_parameters).is_err());
+    }
+
+    #[test]
+    fn test_chunker_salted() {
+        let parameters = ChunkingParameters {
+            window_size: 16,
+            min_chunk_size: 64,
+            max_chunk_size: 1024,
+            boundary_mask: (1 << 8) - 1,
+        };
+        let block: Vec<u8> = (0..5000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let data = [&block[..], &block[..]].concat(); // the second block dedups against the first
+        let salted_manifest = |salt: &[u8]| {
+            let mut chunker = Chunker::new_salted(parameters, salt).unwrap();
+            chunker.process(&data);
+            Manifest::new(chunker.finalize().unwrap())
+        };
+        let tenant_a = salted_manifest(b"tenant A secret");
+        let tenant_b = salted_manifest(b"tenant B secret");
+
+        // the same content under the same salt gets the same hashes
+        assert_eq!(salted_manifest(b"tenant A secret"), tenant_a);
+        assert_eq!(tenant_a.merkle_root(), salted_manifest(b"tenant A secret").merkle_root());
+
+        // but under another salt (or none), the same boundaries with different hashes
+        let unsalted = Manifest::new(chunk(&data, parameters).unwrap());
+        for other in [&tenant_b, &unsalted] {
+            assert_eq!(tenant_a.chunks().len(), other.chunks().len());
+            for (chunk, other_chunk) in tenant_a.chunks().iter().zip(other.chunks()) {
+                assert_eq!(chunk.end, other_chunk.end);
+                assert_ne!(chunk.hash, other_chunk.hash);
+            }
+            assert_ne!(tenant_a.merkle_root(), other.merkle_root());
+        }
+
+        // the dedup within the tenant still works: the repeated block has the same hashes
+        let hashes: HashSet<&Vec<u8>> = tenant_a.chunks().iter().map(|chunk| &chunk.hash).collect();
+        assert!(hashes.len() < tenant_a.chunks().len());
+        assert_eq!(
+            hashes.len(),
+            unsalted.chunks().iter().map(|chunk| &chunk.hash).collect::<HashSet<_>>().len()
+        );
+    }
+
+    #[test]
+    fn test_chunker_stable_golden() {
+        // xorshift pseudo-random bytes
+        let mut state: u32 = 2463534242;
+        let data: Vec<u8> = (0..1 << 16)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let mut chunker = Chunker::new_stable(StableChunking::V1);
+        for buffer in data.chunks(1000) {
+            chunker.process(buffer);
+        }
+        let chunks = chunker.finalize().unwrap();
+
+        // the golden fixture: it must never change, as long as the version is the same
+        let ends: Vec<u64> = chunks.iter().map(|chunk| chunk.end).collect();
+        assert_eq!(
+            ends,
+            [4867, 10318, 12864, 19809, 22395, 37658, 54042, 62902, 64986, 65536]
+        );
+        let merkle_root: String = Manifest::new(chunks)
+            .merkle_root()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(merkle_root, "08c1e639f42fe7cce8b9cd7e9f3293c8a5a3711f089b998720d08d36f5f235c7");
+        assert_eq!(StableChunking::V1.version(), 1);
+    }
+
+    #[test]
+    fn test_chunker_options() {
+        use std::sync::{Arc, Mutex};
+
+        let parameters = ChunkingParameters {
+            window_size: 16,
+            min_chunk_size: 64,
+            max_chunk_size: 1024,
+            boundary_mask: (1 << 8) - 1,
+        };
+        let data: Vec<u8> = (0..10000u32).map(|i| (i * 7919 % 251 + i / 997) as u8).collect();
+        let chunks = chunk(&data, parameters).unwrap();
+
+        // pipelined hashing doesn't change the chunks
+        let mut chunker = Chunker::new_pipelined(parameters, 3).unwrap();
+        chunker.process(&data);
+        assert_eq!(chunker.finalize().unwrap(), chunks);
+
+        // the callbacks observe the chunks without changing them
+        let events: Arc<Mutex<Vec<BoundaryEvent>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut chunker = Chunker::new(parameters).unwrap();
+        let collected_events = Arc::clone(&events);
+        chunker.set_boundary_callback(Box::new(move |event| {
+            collected_events.lock().unwrap().push(event.clone());
+        }));
+        chunker.set_chunk_metadata_callback(Box::new(|range| vec![(range.end - range.start) as u8 % 2]));
+        chunker.process(&data);
+        let (metadata_chunks, metadata) = chunker.finalize_with_metadata().unwrap();
+        assert_eq!(metadata_chunks, chunks);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), chunks.len());
+        assert_eq!(metadata.len(), chunks.len());
+        let mut chunk_start: u64 = 0;
+        for ((chunk, event), tag) in chunks.iter().zip(events.iter()).zip(metadata.iter()) {
+            assert_eq!((event.offset, event.size), (chunk_start, chunk.end - chunk_start));
+            assert_eq!(tag, &[(chunk.end - chunk_start) as u8 % 2]);
+            chunk_start = chunk.end;
+        }
+        assert_eq!(events.last().unwrap().cut, CutKind::End);
+
+        // local minimum boundaries cut other chunks, still within the chunk size limits
+        let mut chunker = Chunker::new(parameters).unwrap();
+        chunker.set_local_minimum_boundaries(256);
+        chunker.process(&data);
+        let local_minimum_chunks = chunker.finalize().unwrap();
+        assert_ne!(local_minimum_chunks, chunks);
+        let mut chunk_start: u64 = 0;
+        for chunk in local_minimum_chunks.iter() {
+            assert!(chunk.end - chunk_start <= 1024);
+            assert!(chunk.end - chunk_start >= 64 || chunk.end == data.len() as u64);
+            chunk_start = chunk.end;
+        }
+        assert_eq!(chunk_start, data.len() as u64);
+    }
+}
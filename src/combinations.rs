@@ -0,0 +1,332 @@
+/*
+    End-to-end comparison of the available configurations: every combination of rolling
+    hasher, chunk hasher, LCS algorithm and chunking mode diffs each pair of a fixed corpus,
+    and the delta size (the New bytes and the segment count) and the runtime are printed as
+    a table to pick a configuration from. Every delta is also applied, the reconstruction
+    having to equal the new data.
+
+    It takes a while (hundreds of diffs, the quadratic Nakatsu among them), so it's ignored
+    by default; run with:
+    cargo test --release bench_combinations -- --ignored --nocapture
+
+    The corpus is the example image pair and synthetic pairs generated with fixed seeds:
+    text with inserted and deleted lines, random data with scattered edits and low-entropy
+    data (where the mask rarely matches, so chunking modes differ the most). FastCDC only
+    chunks (see fastcdc_chunk), it's not a Slicer mode, so it's not part of the comparison.
+*/
+
+use crate::delta::*;
+use crate::differ::*;
+use crate::hasher::fnv::*;
+use crate::hasher::hasher::*;
+use crate::hasher::hmac::*;
+use crate::hasher::md5::*;
+use crate::hasher::sha1::*;
+use crate::hasher::sha256::*;
+use crate::lcs::lcs::*;
+use crate::patcher::reconstruct_iter;
+use crate::rolling_hasher::fibonacci::*;
+use crate::rolling_hasher::gear::*;
+use crate::rolling_hasher::moving_sum::*;
+use crate::rolling_hasher::polynomial::*;
+use crate::rolling_hasher::rolling_hasher::*;
+use crate::slicer::*;
+use std::time::{Duration, Instant};
+
+const PARAMETERS: ChunkingParameters = ChunkingParameters {
+    window_size: 64,
+    min_chunk_size: 2048,
+    max_chunk_size: 16384,
+    boundary_mask: (1 << 12) - 1,
+};
+const SECONDARY_BOUNDARY_MASK: u32 = (1 << 6) - 1;
+const SECONDARY_MASK_CHUNK_SIZE: usize = 8192;
+const LOCAL_MINIMUM_WINDOW: usize = 2048;
+const NORMALIZED_MASK_SMALL: u32 = (1 << 14) - 1;
+const NORMALIZED_MASK_LARGE: u32 = (1 << 10) - 1;
+const NORMALIZED_CHUNK_SIZE: usize = 2048 + 4096; // the min size plus the mask's average
+
+const ROLLING_HASHERS: [&str; 4] = ["polynomial", "moving_sum", "fibonacci", "gear"];
+const HASHERS: [&str; 5] = ["sha256", "sha1", "md5", "hmac_sha256", "fnv"];
+const LCS_ALGORITHMS: [LcsAlgorithm; 5] = [
+    LcsAlgorithm::Nakatsu,
+    LcsAlgorithm::HuntSzymanski,
+    LcsAlgorithm::Weighted,
+    LcsAlgorithm::Hirschberg,
+    LcsAlgorithm::Kumar,
+];
+
+#[derive(Debug, Clone, Copy)]
+enum ChunkingMode {
+    Mask,
+    SecondaryMask,
+    Normalized,
+    LocalMinimum,
+}
+
+const CHUNKING_MODES: [ChunkingMode; 4] = [
+    ChunkingMode::Mask,
+    ChunkingMode::SecondaryMask,
+    ChunkingMode::Normalized,
+    ChunkingMode::LocalMinimum,
+];
+
+struct Fixture {
+    name: &'static str,
+    old: Vec<u8>,
+    new: Vec<u8>,
+}
+
+struct Outcome {
+    new_bytes: u64,
+    segments: usize,
+    elapsed: Duration,
+}
+
+// deterministic pseudo-random bytes (xorshift)
+fn random_bytes(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+fn fixtures() -> Vec<Fixture> {
+    let mut fixtures = vec![Fixture {
+        name: "image",
+        old: std::fs::read("./example/monkey_before.tiff").unwrap(),
+        new: std::fs::read("./example/monkey_after.tiff").unwrap(),
+    }];
+
+    // lines of pseudo-random words, every 500th line deleted and a line inserted every 700
+    let words: Vec<String> = random_bytes(4000, 1)
+        .chunks(8)
+        .map(|bytes| {
+            let len = 2 + bytes[0] as usize % 7;
+            bytes.iter().take(len).map(|&byte| (b'a' + byte % 26) as char).collect()
+        })
+        .collect();
+    let lines: Vec<String> = (0..6000)
+        .map(|line| {
+            let line_words: Vec<&str> =
+                (0..8).map(|word| words[(line * 31 + word * 7) % words.len()].as_str()).collect();
+            line_words.join(" ")
+        })
+        .collect();
+    let mut edited_lines: Vec<String> = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        if index % 700 == 0 {
+            edited_lines.push(format!("inserted line {}", index));
+        }
+        if index % 500 != 0 {
+            edited_lines.push(line.clone());
+        }
+    }
+    fixtures.push(Fixture {
+        name: "text",
+        old: lines.join("\n").into_bytes(),
+        new: edited_lines.join("\n").into_bytes(),
+    });
+
+    // a byte flipped every 40KB and a block inserted in the middle
+    let old = random_bytes(1 << 19, 2);
+    let mut new = old.clone();
+    for position in (0..new.len()).step_by(40000) {
+        new[position] ^= 0xff;
+    }
+    new.splice(200_000..200_000, random_bytes(10000, 3));
+    fixtures.push(Fixture {
+        name: "random",
+        old,
+        new,
+    });
+
+    // regions repeating short patterns, some of them edited
+    let old: Vec<u8> = (0..400_000u32)
+        .map(|i| ((i % 150 + i / 50_000 * 1000).wrapping_mul(2654435761) >> 13) as u8)
+        .collect();
+    let mut new = old.clone();
+    new.splice(120_000..120_000, random_bytes(3000, 4));
+    new.drain(300_000..302_000);
+    fixtures.push(Fixture {
+        name: "low_entropy",
+        old,
+        new,
+    });
+    fixtures
+}
+
+fn make_slicer<RH: RollingHasher, H: Hasher>(
+    rolling_hasher: RH,
+    hasher: H,
+    mode: ChunkingMode,
+) -> Slicer<RH, H> {
+    let mut slicer = Slicer::new(
+        rolling_hasher,
+        hasher,
+        PARAMETERS.boundary_mask,
+        PARAMETERS.min_chunk_size,
+        PARAMETERS.max_chunk_size,
+    )
+    .unwrap();
+    match mode {
+        ChunkingMode::Mask => {}
+        ChunkingMode::SecondaryMask => {
+            slicer.set_secondary_boundary_mask(SECONDARY_BOUNDARY_MASK, SECONDARY_MASK_CHUNK_SIZE)
+        }
+        ChunkingMode::Normalized => slicer.set_normalized_chunking(
+            NORMALIZED_MASK_SMALL,
+            NORMALIZED_MASK_LARGE,
+            NORMALIZED_CHUNK_SIZE,
+        ),
+        ChunkingMode::LocalMinimum => slicer.set_local_minimum_boundaries(LOCAL_MINIMUM_WINDOW),
+    }
+    slicer
+}
+
+// diffs the fixture with the configuration and checks the delta reconstructs the new data
+fn diff<RH: RollingHasher, H: Hasher>(
+    make_rolling_hasher: &dyn Fn() -> RH,
+    make_hasher: &dyn Fn() -> H,
+    mode: ChunkingMode,
+    algorithm: LcsAlgorithm,
+    fixture: &Fixture,
+) -> Outcome {
+    let start = Instant::now();
+    let mut differ = Differ::with_slicers(
+        make_slicer(make_rolling_hasher(), make_hasher(), mode),
+        make_slicer(make_rolling_hasher(), make_hasher(), mode),
+    );
+    differ.set_lcs_algorithm(algorithm);
+    differ.process_old(&fixture.old);
+    differ.process_new(&fixture.new);
+    let segments = differ.finalize();
+    let elapsed = start.elapsed();
+
+    let patched: Vec<u8> =
+        reconstruct_iter(&fixture.old, &fixture.new, &segments).flatten().copied().collect();
+    assert!(patched == fixture.new, "Wrong reconstruction of {}", fixture.name);
+    Outcome {
+        new_bytes: segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::New(_)))
+            .map(|segment| segment.len())
+            .sum(),
+        segments: segments.len(),
+        elapsed,
+    }
+}
+
+fn diff_with_hasher<RH: RollingHasher>(
+    make_rolling_hasher: &dyn Fn() -> RH,
+    hasher: &str,
+    mode: ChunkingMode,
+    algorithm: LcsAlgorithm,
+    fixture: &Fixture,
+) -> Outcome {
+    let max_chunk_size = PARAMETERS.max_chunk_size;
+    match hasher {
+        "sha256" => diff(
+            make_rolling_hasher,
+            &|| Sha256Hasher::new(max_chunk_size),
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "sha1" => diff(
+            make_rolling_hasher,
+            &|| Sha1Hasher::new(max_chunk_size),
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "md5" => diff(
+            make_rolling_hasher,
+            &|| Md5Hasher::new(max_chunk_size),
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "hmac_sha256" => diff(
+            make_rolling_hasher,
+            &|| HmacSha256Hasher::new(b"salt", max_chunk_size),
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "fnv" => diff(make_rolling_hasher, &FnvHasher::new, mode, algorithm, fixture),
+        _ => unreachable!("Unknown hasher {}", hasher),
+    }
+}
+
+fn diff_with(
+    rolling_hasher: &str,
+    hasher: &str,
+    mode: ChunkingMode,
+    algorithm: LcsAlgorithm,
+    fixture: &Fixture,
+) -> Outcome {
+    let window_size = PARAMETERS.window_size;
+    match rolling_hasher {
+        "polynomial" => diff_with_hasher(
+            &|| PolynomialRollingHasher::new(window_size, None, None),
+            hasher,
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "moving_sum" => diff_with_hasher(
+            &|| MovingSumRollingHasher::new(window_size),
+            hasher,
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "fibonacci" => diff_with_hasher(
+            &|| FibonacciRollingHasher::new(PolynomialRollingHasher::new(window_size, None, None)),
+            hasher,
+            mode,
+            algorithm,
+            fixture,
+        ),
+        "gear" => diff_with_hasher(&|| GearRollingHasher::new(None), hasher, mode, algorithm, fixture),
+        _ => unreachable!("Unknown rolling hasher {}", rolling_hasher),
+    }
+}
+
+// run with: cargo test --release bench_combinations -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_combinations() {
+    println!(
+        "{:<12} {:<11} {:<12} {:<14} {:<14} {:>10} {:>9} {:>10}",
+        "fixture", "rolling", "hasher", "lcs", "chunking", "new bytes", "segments", "time (ms)"
+    );
+    for fixture in &fixtures() {
+        for rolling_hasher in ROLLING_HASHERS {
+            for hasher in HASHERS {
+                for algorithm in LCS_ALGORITHMS {
+                    for mode in CHUNKING_MODES {
+                        let outcome = diff_with(rolling_hasher, hasher, mode, algorithm, fixture);
+                        println!(
+                            "{:<12} {:<11} {:<12} {:<14} {:<14} {:>10} {:>9} {:>10.1}",
+                            fixture.name,
+                            rolling_hasher,
+                            hasher,
+                            format!("{:?}", algorithm),
+                            format!("{:?}", mode),
+                            outcome.new_bytes,
+                            outcome.segments,
+                            outcome.elapsed.as_secs_f64() * 1000.0
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
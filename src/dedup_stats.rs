@@ -0,0 +1,117 @@
+/*
+    DedupStats accumulates deduplication statistics over a whole session of diffs, e.g. of all
+    the files processed by a backup run. It's updated after each diff (see
+    Differ::finalize_with_stats) with the new data chunks and the resulting segments.
+
+    Two kinds of reuse are tracked:
+    - the reused and new bytes of the deltas, i.e. the reuse of each file's old version
+    - the unique chunks of all the new data seen, which also captures the reuse across
+      files (a chunk already seen in another file is not unique)
+
+    The dedup ratio is the number of bytes seen divided by the number of unique chunk bytes.
+*/
+
+use crate::delta::*;
+use crate::slicer::*;
+use std::collections::HashSet;
+
+#[derive(Debug, Default)]
+pub struct DedupStats {
+    unique_hashes: HashSet<Vec<u8>>,
+    bytes_seen: u64,   // total size of the new data
+    unique_bytes: u64, // total size of the unique chunks
+    reused_bytes: u64, // total size of the Old and Store segments
+    new_bytes: u64,    // total size of the New segments
+}
+
+impl DedupStats {
+    pub fn new() -> DedupStats {
+        DedupStats::default()
+    }
+
+    // accounts for a single diff, chunks being the chunks of its new data
+    pub(crate) fn update(&mut self, chunks: &[Chunk], segments: &[Segment]) {
+        let mut chunk_start: u64 = 0;
+        for chunk in chunks {
+            if self.unique_hashes.insert(chunk.hash.clone()) {
+                self.unique_bytes += chunk.end - chunk_start;
+            }
+            chunk_start = chunk.end;
+        }
+        self.bytes_seen += chunk_start;
+        for segment in segments {
+            match segment {
+                Segment::Old(_) | Segment::Store(..) => self.reused_bytes += segment.len(),
+                Segment::New(_) => self.new_bytes += segment.len(),
+            }
+        }
+    }
+
+    // the number of bytes seen per unique chunk byte, 1.0 if nothing has been seen yet
+    pub fn ratio(&self) -> f64 {
+        if self.unique_bytes == 0 {
+            return 1.0;
+        }
+        self.bytes_seen as f64 / self.unique_bytes as f64
+    }
+
+    pub fn unique_chunk_count(&self) -> usize {
+        self.unique_hashes.len()
+    }
+
+    pub fn bytes_seen(&self) -> u64 {
+        self.bytes_seen
+    }
+
+    pub fn reused_bytes(&self) -> u64 {
+        self.reused_bytes
+    }
+
+    pub fn new_bytes(&self) -> u64 {
+        self.new_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differ::Differ;
+
+    #[test]
+    fn test_dedup_stats() {
+        let data: Vec<u8> = (0..65536u32).map(|i| (i * 7919 % 251 + i / 251) as u8).collect();
+        let diff = |stats: &mut DedupStats, old: &[u8], new: &[u8]| {
+            let mut differ = Differ::new(Some(16), Some(64), Some(1024), Some((1 << 8) - 1), None);
+            differ.process_old(old);
+            differ.process_new(new);
+            differ.finalize_with_stats(stats);
+        };
+
+        let mut stats = DedupStats::new();
+        assert_eq!(stats.ratio(), 1.0);
+
+        // a new file, nothing to reuse
+        diff(&mut stats, &[], &data[..32768]);
+        assert_eq!(stats.bytes_seen(), 32768);
+        assert_eq!(stats.new_bytes(), 32768);
+        assert_eq!(stats.ratio(), 1.0);
+        let unique_chunk_count = stats.unique_chunk_count();
+
+        // another new file, overlapping the first one: the deltas don't reuse anything but
+        // the shared chunks are only counted once
+        diff(&mut stats, &[], &data[16384..49152]);
+        assert_eq!(stats.reused_bytes(), 0);
+        assert!(stats.ratio() > 1.2);
+        assert!(stats.unique_chunk_count() < 2 * unique_chunk_count);
+
+        // an update of the first file, reusing its old version
+        let mut updated = data[..32768].to_vec();
+        updated[100] ^= 0xff;
+        let ratio = stats.ratio();
+        diff(&mut stats, &data[..32768], &updated);
+        assert!(stats.reused_bytes() > 30000);
+        assert_eq!(stats.bytes_seen(), 3 * 32768);
+        assert_eq!(stats.reused_bytes() + stats.new_bytes(), stats.bytes_seen());
+        assert!(stats.ratio() > ratio);
+    }
+}
@@ -1,100 +1,1185 @@
-use crate::slicer::Chunk;
-use std::fmt::{Debug, Display, Formatter, Result};
+use crate::differ::*;
+use crate::helper::*;
+use crate::lcs::lcs::LcsAlgorithm;
+use crate::refiner::push_coalesced;
+use crate::slicer::{Chunk, ChunkingParameters};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::ops::Range;
 
-#[derive(Debug, PartialEq)]
+// serialized delta header
+const MAGIC: [u8; 4] = *b"DLTA";
+const FORMAT_VERSION: u8 = 1;
+
+// serialized segment tags
+const SEGMENT_OLD: u8 = 0;
+const SEGMENT_NEW: u8 = 1;
+const SEGMENT_STORE: u8 = 2;
+
+// git packfile delta opcodes: a copy sets the high bit, the low bits flagging which of its
+// offset (4) and size (3) bytes follow; an insert is the count (1..=127) of literal bytes
+const GIT_COPY: u8 = 0x80;
+const GIT_MAX_COPY_SIZE: u64 = 0x10000; // encoded as size 0, the largest copy git emits
+const GIT_MAX_INSERT_SIZE: usize = 0x7f;
+
+// serialized LCS algorithm tags
+const LCS_AUTO: u8 = 0;
+const LCS_NAKATSU: u8 = 1;
+const LCS_HUNT_SZYMANSKI: u8 = 2;
+const LCS_WEIGHTED: u8 = 3;
+const LCS_HIRSCHBERG: u8 = 4;
+const LCS_KUMAR: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Segment {
-    Old(Range<usize>),
-    New(Range<usize>),
+    Old(Range<u64>),
+    New(Range<u64>),
+    Store(Vec<u8>, u64), // a chunk (hash, size) to be taken from a chunk store (see ChunkIndex)
 }
 
 impl Display for Segment {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Segment::Old(range) => { write!(f, "OLD[{}..{}]", range.start, range.end) },
             Segment::New(range) => { write!(f, "NEW[{}..{}]", range.start, range.end) },
+            Segment::Store(hash, size) => { write!(f, "STORE[{}, {}]", to_hex(hash), size) },
         }
     }
 }
 
-pub(crate) fn delta(chunks_old: &[Chunk], chunks_new: &[Chunk], lcs: &[Vec<u8>]) -> Vec<Segment> {
-    if lcs.is_empty() {
-        return if let Some(last_new_chunk) = chunks_new.last() {
-            vec![Segment::New(0..last_new_chunk.end)]
+#[derive(Debug, PartialEq)]
+pub enum DeltaError {
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidParameter(&'static str),
+    InvalidSegmentTag(u8),
+    ConfigMismatch { expected: u64, found: u64 },
+    InvalidSegmentRange,
+    UnresolvedStoreSegment,
+    SizeMismatch { expected: u64, found: u64 },
+    HashTooLong(usize),
+}
+
+impl Display for DeltaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaError::InvalidMagic => write!(f, "not a delta (invalid magic bytes)"),
+            DeltaError::UnsupportedVersion(version) => {
+                write!(f, "unsupported delta format version {}", version)
+            }
+            DeltaError::Truncated => write!(f, "delta is truncated"),
+            DeltaError::InvalidParameter(name) => write!(f, "invalid {} in delta header", name),
+            DeltaError::InvalidSegmentTag(tag) => write!(f, "invalid segment tag {}", tag),
+            DeltaError::ConfigMismatch { expected, found } => write!(
+                f,
+                "delta was produced with config {:016x}, expected {:016x}",
+                found, expected
+            ),
+            DeltaError::InvalidSegmentRange => write!(f, "segment range is out of bounds"),
+            DeltaError::UnresolvedStoreSegment => {
+                write!(f, "Store segments cannot be applied without a chunk store")
+            }
+            DeltaError::SizeMismatch { expected, found } => {
+                write!(f, "delta expects {} bytes, found {}", expected, found)
+            }
+            DeltaError::HashTooLong(len) => {
+                write!(f, "Store segment hash of {} bytes cannot be serialized (at most 255)", len)
+            }
+        }
+    }
+}
+
+impl Error for DeltaError {}
+
+impl Segment {
+    // the number of bytes the segment contributes to the patched data
+    pub fn len(&self) -> u64 {
+        match self {
+            Segment::Old(range) | Segment::New(range) => range.end - range.start,
+            Segment::Store(_, size) => *size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Segment offsets are u64 (rather than usize) so that data larger than 4GiB can be diffed
+// on 32-bit targets, too; this converts a segment range into an index range of an in-memory
+// buffer (which is always addressable)
+pub(crate) fn buffer_range(range: &Range<u64>) -> Range<usize> {
+    let to_usize = |offset: u64| {
+        usize::try_from(offset).expect("Segment range exceeds the addressable memory")
+    };
+    to_usize(range.start)..to_usize(range.end)
+}
+
+// returns the offset at which each segment starts in the patched (output) file
+pub fn output_offsets(segments: &[Segment]) -> Vec<u64> {
+    let mut offsets: Vec<u64> = Vec::with_capacity(segments.len());
+    let mut offset: u64 = 0;
+    for segment in segments {
+        offsets.push(offset);
+        offset += segment.len();
+    }
+    offsets
+}
+
+// Serializes segments into a compact binary form, prefixed with a self-describing header:
+// [magic: 4 bytes][version: u8]
+// [window_size: u32][min_chunk_size: u64][max_chunk_size: u64][boundary_mask: u32]
+// [rolling_hash_modulus: u32][rolling_hash_base: u32][lcs_algorithm: u8][fingerprint: u64]
+// [segment count: u64][tag: u8, start: u64, end: u64]...
+// Store segments are encoded as [tag: u8, hash length: u8, hash, size: u64] instead, so
+// a Store segment whose hash is longer than 255 bytes is reported as HashTooLong
+// all integers are little-endian
+pub fn serialize(
+    segments: &[Segment],
+    config: &DifferConfig,
+) -> Result<Vec<u8>, DeltaError> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(58 + 17 * segments.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(FORMAT_VERSION);
+    let chunking = &config.chunking;
+    bytes.extend_from_slice(&chunking.window_size.to_le_bytes());
+    bytes.extend_from_slice(&(chunking.min_chunk_size as u64).to_le_bytes());
+    bytes.extend_from_slice(&(chunking.max_chunk_size as u64).to_le_bytes());
+    bytes.extend_from_slice(&chunking.boundary_mask.to_le_bytes());
+    bytes.extend_from_slice(&config.rolling_hash_modulus.to_le_bytes());
+    bytes.extend_from_slice(&config.rolling_hash_base.to_le_bytes());
+    bytes.push(match config.lcs_algorithm {
+        None => LCS_AUTO,
+        Some(LcsAlgorithm::Nakatsu) => LCS_NAKATSU,
+        Some(LcsAlgorithm::HuntSzymanski) => LCS_HUNT_SZYMANSKI,
+        Some(LcsAlgorithm::Weighted) => LCS_WEIGHTED,
+        Some(LcsAlgorithm::Hirschberg) => LCS_HIRSCHBERG,
+        Some(LcsAlgorithm::Kumar) => LCS_KUMAR,
+    });
+    bytes.extend_from_slice(&config.fingerprint().to_le_bytes());
+    bytes.extend_from_slice(&(segments.len() as u64).to_le_bytes());
+    for segment in segments {
+        let (tag, range) = match segment {
+            Segment::Old(range) => (SEGMENT_OLD, range),
+            Segment::New(range) => (SEGMENT_NEW, range),
+            Segment::Store(hash, size) => {
+                bytes.push(SEGMENT_STORE);
+                let hash_len =
+                    u8::try_from(hash.len()).map_err(|_| DeltaError::HashTooLong(hash.len()))?;
+                bytes.push(hash_len);
+                bytes.extend_from_slice(hash);
+                bytes.extend_from_slice(&size.to_le_bytes());
+                continue;
+            }
+        };
+        bytes.push(tag);
+        bytes.extend_from_slice(&range.start.to_le_bytes());
+        bytes.extend_from_slice(&range.end.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+// Parses a delta serialized with 'serialize', returning the config it has been produced
+// with and the segments
+pub fn deserialize(bytes: &[u8]) -> Result<(DifferConfig, Vec<Segment>), DeltaError> {
+    let mut reader = ByteReader { bytes };
+    if reader.read_bytes(MAGIC.len())? != MAGIC {
+        return Err(DeltaError::InvalidMagic);
+    }
+    let version = reader.read_u8()?;
+    if version != FORMAT_VERSION {
+        return Err(DeltaError::UnsupportedVersion(version));
+    }
+    let config = DifferConfig {
+        chunking: ChunkingParameters {
+            window_size: reader.read_u32()?,
+            min_chunk_size: reader.read_u64()? as usize,
+            max_chunk_size: reader.read_u64()? as usize,
+            boundary_mask: reader.read_u32()?,
+        },
+        rolling_hash_modulus: reader.read_u32()?,
+        rolling_hash_base: reader.read_u32()?,
+        lcs_algorithm: match reader.read_u8()? {
+            LCS_AUTO => None,
+            LCS_NAKATSU => Some(LcsAlgorithm::Nakatsu),
+            LCS_HUNT_SZYMANSKI => Some(LcsAlgorithm::HuntSzymanski),
+            LCS_WEIGHTED => Some(LcsAlgorithm::Weighted),
+            LCS_HIRSCHBERG => Some(LcsAlgorithm::Hirschberg),
+            LCS_KUMAR => Some(LcsAlgorithm::Kumar),
+            _ => return Err(DeltaError::InvalidParameter("lcs_algorithm")),
+        },
+    };
+    if reader.read_u64()? != config.fingerprint() {
+        return Err(DeltaError::InvalidParameter("fingerprint"));
+    }
+
+    let segment_count = reader.read_u64()? as usize;
+    let mut segments: Vec<Segment> = Vec::with_capacity(segment_count.min(bytes.len() / 17));
+    for _ in 0..segment_count {
+        let tag = reader.read_u8()?;
+        if tag == SEGMENT_STORE {
+            let hash_len = reader.read_u8()? as usize;
+            let hash = reader.read_bytes(hash_len)?.to_vec();
+            segments.push(Segment::Store(hash, reader.read_u64()?));
+            continue;
+        }
+        let range = reader.read_u64()?..reader.read_u64()?;
+        segments.push(match tag {
+            SEGMENT_OLD => Segment::Old(range),
+            SEGMENT_NEW => Segment::New(range),
+            _ => return Err(DeltaError::InvalidSegmentTag(tag)),
+        });
+    }
+    Ok((config, segments))
+}
+
+// Same as 'deserialize' but also checks that the delta has been produced with parameters
+// compatible with the expected config
+pub fn deserialize_checked(
+    bytes: &[u8],
+    expected_config: &DifferConfig,
+) -> Result<Vec<Segment>, DeltaError> {
+    let (config, segments) = deserialize(bytes)?;
+    if config.fingerprint() != expected_config.fingerprint() {
+        return Err(DeltaError::ConfigMismatch {
+            expected: expected_config.fingerprint(),
+            found: config.fingerprint(),
+        });
+    }
+    Ok(segments)
+}
+
+// Serializes the delta as two separate buffers: the structure (as produced by 'serialize',
+// with the New segments ranges referring to the payload) and the payload, which is the
+// concatenation of the New segments bytes. This way the structure can be stored and queried
+// separately from the (large) payload
+pub fn serialize_split(
+    segments: &[Segment],
+    new: &[u8],
+    config: &DifferConfig,
+) -> Result<(Vec<u8>, Vec<u8>), DeltaError> {
+    let mut payload: Vec<u8> = Vec::new();
+    let structure_segments: Vec<Segment> = segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::New(range) => {
+                let payload_start = payload.len() as u64;
+                payload.extend_from_slice(&new[buffer_range(range)]);
+                Segment::New(payload_start..payload.len() as u64)
+            }
+            Segment::Old(range) => Segment::Old(range.clone()),
+            Segment::Store(hash, size) => Segment::Store(hash.clone(), *size),
+        })
+        .collect();
+    Ok((serialize(&structure_segments, config)?, payload))
+}
+
+// Same as 'serialize_split', but the payload stores each distinct new chunk of the New
+// segments once (e.g. the same data inserted repeatedly): the payload is then a dictionary
+// of the unique chunks, which the New segments refer to, one per chunk unless consecutive
+// chunks are adjacent in the payload. The structure format is unchanged, so the delta is
+// applied in the same way
+pub fn serialize_split_deduplicated(
+    segments: &[Segment],
+    new: &[u8],
+    chunks_new: &[Chunk],
+    config: &DifferConfig,
+) -> Result<(Vec<u8>, Vec<u8>), DeltaError> {
+    let mut payload: Vec<u8> = Vec::new();
+    let mut payload_ranges: HashMap<&[u8], Range<u64>> = HashMap::new();
+    let mut structure_segments: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let Segment::New(range) = segment else {
+            push_coalesced(&mut structure_segments, segment.clone());
+            continue;
+        };
+        // the New segment split at the chunk boundaries within it
+        let first = chunks_new.partition_point(|chunk| chunk.end <= range.start);
+        let chunk_ends = chunks_new[first..]
+            .iter()
+            .map(|chunk| chunk.end)
+            .take_while(|&end| end < range.end)
+            .chain([range.end]);
+        let mut chunk_start = range.start;
+        for chunk_end in chunk_ends {
+            let bytes = &new[buffer_range(&(chunk_start..chunk_end))];
+            chunk_start = chunk_end;
+            let payload_range = payload_ranges.entry(bytes).or_insert_with(|| {
+                let payload_start = payload.len() as u64;
+                payload.extend_from_slice(bytes);
+                payload_start..payload.len() as u64
+            });
+            push_coalesced(&mut structure_segments, Segment::New(payload_range.clone()));
+        }
+    }
+    Ok((serialize(&structure_segments, config)?, payload))
+}
+
+// Parses the structure serialized with 'serialize_split'; the New segments ranges refer
+// to the payload
+pub fn deserialize_split(
+    structure: &[u8],
+) -> Result<(DifferConfig, Vec<Segment>), DeltaError> {
+    deserialize(structure)
+}
+
+// Reconstructs the new data from the old data and the delta serialized with 'serialize_split'
+pub fn apply_split(
+    structure: &[u8],
+    payload: &[u8],
+    old: &[u8],
+) -> Result<Vec<u8>, DeltaError> {
+    let (_, segments) = deserialize_split(structure)?;
+    let mut new: Vec<u8> = Vec::new();
+    for segment in segments {
+        let (source, range) = match segment {
+            Segment::Old(range) => (old, range),
+            Segment::New(range) => (payload, range),
+            Segment::Store(..) => return Err(DeltaError::UnresolvedStoreSegment),
+        };
+        let bytes = match (usize::try_from(range.start), usize::try_from(range.end)) {
+            (Ok(start), Ok(end)) => source.get(start..end),
+            _ => None,
+        }
+        .ok_or(DeltaError::InvalidSegmentRange)?;
+        new.extend_from_slice(bytes);
+    }
+    Ok(new)
+}
+
+// An operation of the run-length edit encoding, see 'to_rle'
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Keep, // copies the next old bytes
+    Skip, // skips the next old bytes (deleted)
+    Add,  // copies the next payload (new) bytes
+}
+
+// Encodes the delta as (op, length) runs, the offsets being implied by the position: the
+// old data and the payload (the concatenation of the New segments bytes) are both consumed
+// sequentially, Skip stepping over the old bytes which aren't reused. Only deltas whose Old
+// segments are in ascending offset order without overlapping and whose New segments refer
+// to the new data (both as computed by the Differ) can be encoded; Store segments can't be
+pub fn to_rle(segments: &[Segment]) -> Result<Vec<(Op, u64)>, DeltaError> {
+    let mut runs: Vec<(Op, u64)> = Vec::with_capacity(segments.len());
+    let mut old_position: u64 = 0;
+    let mut new_position: u64 = 0; // the output position
+    let push_run = |runs: &mut Vec<(Op, u64)>, op: Op, len: u64| match runs.last_mut() {
+        _ if len == 0 => {}
+        Some((last_op, last_len)) if *last_op == op => *last_len += len,
+        _ => runs.push((op, len)),
+    };
+    for segment in segments {
+        match segment {
+            Segment::Old(range) => {
+                if range.start < old_position || range.end < range.start {
+                    return Err(DeltaError::InvalidSegmentRange);
+                }
+                push_run(&mut runs, Op::Skip, range.start - old_position);
+                push_run(&mut runs, Op::Keep, range.end - range.start);
+                old_position = range.end;
+            }
+            Segment::New(range) => {
+                if range.start != new_position || range.end < range.start {
+                    return Err(DeltaError::InvalidSegmentRange);
+                }
+                push_run(&mut runs, Op::Add, range.end - range.start);
+            }
+            Segment::Store(..) => return Err(DeltaError::UnresolvedStoreSegment),
+        }
+        new_position += segment.len();
+    }
+    Ok(runs)
+}
+
+// Decodes the runs produced by 'to_rle' back into segments, the New segments ranges
+// referring to the new data
+pub fn from_rle(runs: &[(Op, u64)]) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::with_capacity(runs.len());
+    let mut old_position: u64 = 0;
+    let mut new_position: u64 = 0;
+    for &(op, len) in runs {
+        match op {
+            Op::Keep => {
+                segments.push(Segment::Old(old_position..old_position + len));
+                old_position += len;
+                new_position += len;
+            }
+            Op::Skip => old_position += len,
+            Op::Add => {
+                segments.push(Segment::New(new_position..new_position + len));
+                new_position += len;
+            }
+        }
+    }
+    segments
+}
+
+// Reconstructs the new data from the old data and the payload (the concatenation of the
+// New segments bytes), applying the runs sequentially
+pub fn apply_rle(runs: &[(Op, u64)], old: &[u8], payload: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let mut new: Vec<u8> = Vec::new();
+    let (mut old, mut payload) = (old, payload);
+    for &(op, len) in runs {
+        let source = match op {
+            Op::Keep | Op::Skip => &mut old,
+            Op::Add => &mut payload,
+        };
+        let len = usize::try_from(len).map_err(|_| DeltaError::InvalidSegmentRange)?;
+        if source.len() < len {
+            return Err(DeltaError::InvalidSegmentRange);
+        }
+        let (bytes, rest) = source.split_at(len);
+        if op != Op::Skip {
+            new.extend_from_slice(bytes);
+        }
+        *source = rest;
+    }
+    Ok(new)
+}
+
+// Serializes the delta in git's packfile delta format, so that it can be applied by git
+// tooling: [old size: varint][new size: varint] followed by the instructions, copies of
+// old ranges (offsets up to 4GiB, split into copies of at most 64KiB) and inserts of the New
+// segments bytes (in runs of at most 127 bytes). The varints are little-endian base 128, the
+// high bit of each byte flagging that another one follows. Store segments can't be encoded
+pub fn serialize_git(
+    segments: &[Segment],
+    old_len: u64,
+    new: &[u8],
+) -> Result<Vec<u8>, DeltaError> {
+    let new_len: u64 = segments.iter().map(Segment::len).sum();
+    let mut bytes: Vec<u8> = Vec::new();
+    push_git_varint(&mut bytes, old_len);
+    push_git_varint(&mut bytes, new_len);
+    for segment in segments {
+        match segment {
+            Segment::Old(range) => {
+                if range.end > old_len || range.end > u64::from(u32::MAX) + 1 {
+                    return Err(DeltaError::InvalidSegmentRange);
+                }
+                let mut offset = range.start;
+                while offset < range.end {
+                    let size = (range.end - offset).min(GIT_MAX_COPY_SIZE);
+                    push_git_copy(&mut bytes, offset as u32, size);
+                    offset += size;
+                }
+            }
+            Segment::New(range) => {
+                let inserted =
+                    new.get(buffer_range(range)).ok_or(DeltaError::InvalidSegmentRange)?;
+                for run in inserted.chunks(GIT_MAX_INSERT_SIZE) {
+                    bytes.push(run.len() as u8);
+                    bytes.extend_from_slice(run);
+                }
+            }
+            Segment::Store(..) => return Err(DeltaError::UnresolvedStoreSegment),
+        }
+    }
+    Ok(bytes)
+}
+
+// Parses a delta in git's packfile delta format (see 'serialize_git'), returning the old size
+// it expects, the segments and the payload, the inserted bytes which the New segments refer
+// to (as with 'serialize_split')
+pub fn deserialize_git(bytes: &[u8]) -> Result<(u64, Vec<Segment>, Vec<u8>), DeltaError> {
+    let mut reader = ByteReader { bytes };
+    let old_len = read_git_varint(&mut reader)?;
+    let new_len = read_git_varint(&mut reader)?;
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut payload: Vec<u8> = Vec::new();
+    while !reader.bytes.is_empty() {
+        let opcode = reader.read_u8()?;
+        let segment = if opcode & GIT_COPY != 0 {
+            // the offset and size bytes flagged by the opcode bits, little-endian
+            let mut read_flagged = |first_bit: u8, byte_count: u8| -> Result<u64, DeltaError> {
+                let mut value: u64 = 0;
+                for index in 0..byte_count {
+                    if opcode & (1 << (first_bit + index)) != 0 {
+                        value |= u64::from(reader.read_u8()?) << (8 * index);
+                    }
+                }
+                Ok(value)
+            };
+            let offset = read_flagged(0, 4)?;
+            let size = match read_flagged(4, 3)? {
+                0 => GIT_MAX_COPY_SIZE,
+                size => size,
+            };
+            if offset + size > old_len {
+                return Err(DeltaError::InvalidSegmentRange);
+            }
+            Segment::Old(offset..offset + size)
+        } else if opcode != 0 {
+            let payload_start = payload.len() as u64;
+            payload.extend_from_slice(reader.read_bytes(usize::from(opcode))?);
+            Segment::New(payload_start..payload.len() as u64)
         } else {
-            Vec::new()
+            return Err(DeltaError::InvalidSegmentTag(opcode)); // reserved by git
         };
+        push_coalesced(&mut segments, segment);
+    }
+    let found: u64 = segments.iter().map(Segment::len).sum();
+    if found != new_len {
+        return Err(DeltaError::SizeMismatch {
+            expected: new_len,
+            found,
+        });
+    }
+    Ok((old_len, segments, payload))
+}
+
+// Reconstructs the new data from the old data and the delta in git's packfile delta format
+pub fn apply_git(bytes: &[u8], old: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let (old_len, segments, payload) = deserialize_git(bytes)?;
+    if old_len != old.len() as u64 {
+        return Err(DeltaError::SizeMismatch {
+            expected: old_len,
+            found: old.len() as u64,
+        });
+    }
+    let mut new: Vec<u8> = Vec::new();
+    for segment in segments {
+        // the ranges have been checked against the sizes
+        let (source, range) = match segment {
+            Segment::Old(range) => (old, range),
+            Segment::New(range) => (&payload[..], range),
+            Segment::Store(..) => unreachable!("git deltas have no Store segments"),
+        };
+        new.extend_from_slice(&source[buffer_range(&range)]);
+    }
+    Ok(new)
+}
+
+fn push_git_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_git_varint(reader: &mut ByteReader) -> Result<u64, DeltaError> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = reader.read_u8()?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DeltaError::InvalidParameter("size"))
+}
+
+// a copy instruction, only the non-zero offset and size bytes being stored
+fn push_git_copy(bytes: &mut Vec<u8>, offset: u32, size: u64) {
+    let opcode_position = bytes.len();
+    let mut opcode = GIT_COPY;
+    let size = if size == GIT_MAX_COPY_SIZE { 0 } else { size as u32 };
+    bytes.push(opcode);
+    for (index, byte) in offset.to_le_bytes().into_iter().enumerate() {
+        if byte != 0 {
+            opcode |= 1 << index;
+            bytes.push(byte);
+        }
+    }
+    for (index, byte) in size.to_le_bytes()[..3].iter().enumerate() {
+        if *byte != 0 {
+            opcode |= 1 << (4 + index);
+            bytes.push(*byte);
+        }
+    }
+    bytes[opcode_position] = opcode;
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl ByteReader<'_> {
+    fn read_u8(&mut self) -> Result<u8, DeltaError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(DeltaError::Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
     }
 
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], DeltaError> {
+        if self.bytes.len() < len {
+            return Err(DeltaError::Truncated);
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(value)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DeltaError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DeltaError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+}
+
+// Replaces the Old segments with Store segments, one per old chunk they span, so that reused
+// chunks are referenced by hash rather than by their position within the old data
+pub(crate) fn hash_references(segments: Vec<Segment>, chunks_old: &[Chunk]) -> Vec<Segment> {
+    let mut referenced: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let range = match segment {
+            Segment::Old(range) => range,
+            _ => {
+                referenced.push(segment);
+                continue;
+            }
+        };
+        // Old segments always start and end at old chunk boundaries
+        let mut chunk_index = chunks_old.partition_point(|chunk| chunk.end <= range.start);
+        let mut chunk_start = range.start;
+        while chunk_start < range.end {
+            let chunk = &chunks_old[chunk_index];
+            referenced.push(Segment::Store(chunk.hash.clone(), chunk.end - chunk_start));
+            chunk_start = chunk.end;
+            chunk_index += 1;
+        }
+    }
+    referenced
+}
+
+// Replaces the chunks of the New segments which exist anywhere in the old data (e.g. blocks
+// moved or swapped, which the common subsequence can only keep one of) with Old segments
+// referring to their first old occurrence. The Old segments are then no longer in ascending
+// offset order, so the delta can't be applied to a forward-only old stream. The excluded
+// hashes (e.g. volatile chunks) are never reused
+pub(crate) fn reuse_moved(
+    segments: Vec<Segment>,
+    chunks_old: &[Chunk],
+    chunks_new: &[Chunk],
+    excluded: &HashSet<Vec<u8>>,
+) -> Vec<Segment> {
+    let mut old_ranges: HashMap<&[u8], Range<u64>> = HashMap::new();
+    let mut chunk_start: u64 = 0;
+    for chunk in chunks_old {
+        if !excluded.contains(&chunk.hash) {
+            old_ranges.entry(&chunk.hash).or_insert(chunk_start..chunk.end);
+        }
+        chunk_start = chunk.end;
+    }
+
+    let mut reused: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let range = match segment {
+            Segment::New(range) => range,
+            _ => {
+                push_coalesced(&mut reused, segment);
+                continue;
+            }
+        };
+        // New segments always start and end at new chunk boundaries
+        let mut chunk_index = chunks_new.partition_point(|chunk| chunk.end <= range.start);
+        let mut chunk_start = range.start;
+        while chunk_start < range.end {
+            let chunk = &chunks_new[chunk_index];
+            let segment = match old_ranges.get(&chunk.hash[..]) {
+                Some(old_range) => Segment::Old(old_range.clone()),
+                None => Segment::New(chunk_start..chunk.end),
+            };
+            push_coalesced(&mut reused, segment);
+            chunk_start = chunk.end;
+            chunk_index += 1;
+        }
+    }
+    reused
+}
+
+// Splits the New segments longer than max_size at chunk boundaries, into pieces of whole
+// chunks no longer than max_size (a single chunk longer than it is kept whole), so that the
+// inserted data is made of storable chunks rather than one huge segment
+pub(crate) fn split_new_segments(segments: Vec<Segment>, chunks: &[Chunk], max_size: u64) -> Vec<Segment> {
+    let mut split: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let range = match segment {
+            Segment::New(range) if range.end - range.start > max_size => range,
+            segment => {
+                split.push(segment);
+                continue;
+            }
+        };
+        // New segments always start and end at chunk boundaries
+        let first = chunks.partition_point(|chunk| chunk.end <= range.start);
+        let mut piece_start = range.start;
+        let mut chunk_start = range.start;
+        for chunk in chunks[first..].iter().take_while(|chunk| chunk.end <= range.end) {
+            if chunk.end - piece_start > max_size && chunk_start > piece_start {
+                split.push(Segment::New(piece_start..chunk_start));
+                piece_start = chunk_start;
+            }
+            chunk_start = chunk.end;
+        }
+        split.push(Segment::New(piece_start..range.end));
+    }
+    split
+}
+
+// Verifies the common subsequence of the hash prefixes (e.g. computed by an LCS comparing only
+// the first bytes of the hashes, see Differ::set_lcs_hash_prefix_len) against the whole
+// hashes: the prefix subsequence is matched to the leftmost chunks of both sequences with
+// the same prefixes and the pairs whose whole hashes differ (prefix collisions) are dropped.
+// Returns the whole hashes of the verified pairs, a common subsequence of the hash sequences
+// to be passed to delta
+pub(crate) fn verify_prefix_lcs(
+    hashes_old: &[Vec<u8>],
+    hashes_new: &[Vec<u8>],
+    prefix_lcs: &[Vec<u8>],
+) -> Vec<Vec<u8>> {
+    let mut verified: Vec<Vec<u8>> = Vec::with_capacity(prefix_lcs.len());
+    let mut old = hashes_old.iter();
+    let mut new = hashes_new.iter();
+    for prefix in prefix_lcs {
+        let has_prefix = |hash: &&Vec<u8>| hash.starts_with(prefix);
+        let old_hash = old.find(has_prefix).expect("Prefix LCS is a subsequence of the old hashes");
+        let new_hash = new.find(has_prefix).expect("Prefix LCS is a subsequence of the new hashes");
+        if old_hash == new_hash {
+            verified.push(new_hash.clone());
+        }
+    }
+    verified
+}
+
+// Replaces the reused (Old and Store) segments shorter than min_reuse with New segments, e.g.
+// when fetching a small range of the old data costs more than sending it. As the patched data
+// is the new data, a reused segment is replaced with the new data range at the same offset
+pub(crate) fn drop_short_reuse(segments: Vec<Segment>, min_reuse: u64) -> Vec<Segment> {
+    let mut kept: Vec<Segment> = Vec::with_capacity(segments.len());
+    let mut offset: u64 = 0;
+    for segment in segments {
+        let segment_len = segment.len();
+        let segment = match segment {
+            Segment::Old(_) | Segment::Store(..) if segment_len < min_reuse => {
+                Segment::New(offset..offset + segment_len)
+            }
+            segment => segment,
+        };
+        offset += segment_len;
+        match (kept.last_mut(), segment) {
+            (Some(Segment::New(last_range)), Segment::New(range)) if last_range.end == range.start => {
+                last_range.end = range.end;
+            }
+            (_, segment) => kept.push(segment),
+        }
+    }
+    kept
+}
+
+pub(crate) fn delta(chunks_old: &[Chunk], chunks_new: &[Chunk], lcs: &[Vec<u8>]) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::with_capacity(chunks_new.len());
+    stream(chunks_old, chunks_new, lcs, |segment| segments.push(segment));
+    segments
+}
+
+// Returns both the forward delta (as delta does) and the reverse one (recreating the old data
+// from the new one, i.e. delta with the roles swapped), walking the common chunks once. Each
+// common chunk is aligned with its first occurrence in both chunk lists, whichever the
+// direction, so both deltas reuse the very same chunks
+pub(crate) fn delta_bidirectional(
+    chunks_old: &[Chunk],
+    chunks_new: &[Chunk],
+    lcs: &[Vec<u8>],
+) -> (Vec<Segment>, Vec<Segment>) {
     let mut segments: Vec<Segment> = Vec::with_capacity(chunks_new.len());
+    let mut reverse_segments: Vec<Segment> = Vec::with_capacity(chunks_old.len());
+    stream_bidirectional(
+        chunks_old,
+        chunks_new,
+        lcs,
+        |segment| segments.push(segment),
+        |segment| reverse_segments.push(segment),
+    );
+    (segments, reverse_segments)
+}
+
+// Same as delta but passes each segment to the sink as soon as it's determined rather than
+// collecting them, e.g. so that the delta can be sent while it's still being built; the
+// chunks and the LCS are those of DiffArtifacts
+pub fn stream<F>(chunks_old: &[Chunk], chunks_new: &[Chunk], lcs: &[Vec<u8>], sink: F)
+where
+    F: FnMut(Segment),
+{
+    stream_bidirectional(chunks_old, chunks_new, lcs, sink, |_| {});
+}
+
+// Same as stream, also passing the segments of the reverse delta to their own sink
+fn stream_bidirectional<F, R>(
+    chunks_old: &[Chunk],
+    chunks_new: &[Chunk],
+    lcs: &[Vec<u8>],
+    mut sink: F,
+    mut reverse_sink: R,
+) where
+    F: FnMut(Segment),
+    R: FnMut(Segment),
+{
+    // the byte range of the chunks (positions start..end) of the list
+    let chunk_range = |chunks: &[Chunk], start: usize, end: usize| -> Range<u64> {
+        let range_start = if start == 0 { 0 } else { chunks[start - 1].end };
+        range_start..chunks[end - 1].end
+    };
+
     let mut new_pos: usize = 0;
     let mut old_pos: usize = 0;
     let mut lcs_pos: usize = 0;
-    let mut common_chunk_hash = &lcs[lcs_pos];
-    let lcs_len = lcs.len();
 
-    while lcs_pos < lcs_len {
+    // Each position only moves forward, so the whole scan is linear in the number of chunks
+    // (the chunk hashes being compared as slices, without bounds checks)
+    while lcs_pos < lcs.len() {
+        let common_chunk_hash = lcs[lcs_pos].as_slice();
+
         // Create concatenated New segment (if any)
         let new_segment_start = new_pos;
-        while chunks_new[new_pos].hash != *common_chunk_hash {
-            new_pos += 1;
-        }
+        new_pos += chunks_new[new_pos..]
+            .iter()
+            .position(|chunk| chunk.hash == common_chunk_hash)
+            .expect("LCS is a subsequence of the new chunks");
         if new_pos != new_segment_start {
-            let segment_start = if new_segment_start == 0 {
-                0
-            } else {
-                chunks_new[new_segment_start - 1].end
-            };
-            let new_segment = Segment::New(segment_start..chunks_new[new_pos - 1].end);
-            segments.push(new_segment);
+            sink(Segment::New(chunk_range(chunks_new, new_segment_start, new_pos)));
         }
 
-        // Skip deleted old region
-        while chunks_old[old_pos].hash != *common_chunk_hash {
-            old_pos += 1;
+        // Skip deleted old region, which the reverse delta recreates
+        let old_gap_start = old_pos;
+        old_pos += chunks_old[old_pos..]
+            .iter()
+            .position(|chunk| chunk.hash == common_chunk_hash)
+            .expect("LCS is a subsequence of the old chunks");
+        if old_pos != old_gap_start {
+            reverse_sink(Segment::New(chunk_range(chunks_old, old_gap_start, old_pos)));
         }
 
         // Create concatenated Old segment
         let old_segment_start = old_pos;
-        while chunks_new[new_pos].hash == *common_chunk_hash
-            && chunks_old[old_pos].hash == *common_chunk_hash
-        {
+        let new_run_start = new_pos;
+        let run_len = chunks_new[new_pos..]
+            .iter()
+            .zip(&chunks_old[old_pos..])
+            .zip(&lcs[lcs_pos..])
+            .take_while(|((new_chunk, old_chunk), common_chunk_hash)| {
+                new_chunk.hash == **common_chunk_hash && old_chunk.hash == **common_chunk_hash
+            })
+            .count();
+        new_pos += run_len;
+        old_pos += run_len;
+        lcs_pos += run_len;
+        if old_pos != old_segment_start {
+            sink(Segment::Old(chunk_range(chunks_old, old_segment_start, old_pos)));
+            reverse_sink(Segment::Old(chunk_range(chunks_new, new_run_start, new_pos)));
+        }
+    }
+
+    // Append remaining New segments
+    if new_pos < chunks_new.len() {
+        sink(Segment::New(chunk_range(chunks_new, new_pos, chunks_new.len())));
+    }
+    if old_pos < chunks_old.len() {
+        reverse_sink(Segment::New(chunk_range(chunks_old, old_pos, chunks_old.len())));
+    }
+}
+
+// Same as delta but, among the alignments of the LCS to the old chunks, prefers those making
+// the reused old ranges contiguous: delta matches each common chunk to its first occurrence
+// in the old data, which can split a run of common chunks into several Old segments, e.g.
+// old: A X A B, new: A B makes Old(A) + Old(B) rather than a single Old(A B). Fewer, longer
+// Old segments (which are always in ascending offset order) let the patcher read the old
+// data with fewer seeks. The reuse is the same, but finding the longest runs scans all the
+// candidate occurrences of a chunk, which is slower on data with many repeated chunks
+pub(crate) fn delta_contiguous(
+    chunks_old: &[Chunk],
+    chunks_new: &[Chunk],
+    lcs: &[Vec<u8>],
+) -> Vec<Segment> {
+    let lcs_len = lcs.len();
+
+    // the last old position the LCS suffix starting at each index can be matched from
+    let mut last_starts: Vec<usize> = vec![chunks_old.len(); lcs_len + 1];
+    for lcs_pos in (0..lcs_len).rev() {
+        let mut old_pos = last_starts[lcs_pos + 1] - 1;
+        while chunks_old[old_pos].hash != lcs[lcs_pos] {
+            old_pos -= 1;
+        }
+        last_starts[lcs_pos] = old_pos;
+    }
+    let mut occurrences: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (old_pos, chunk) in chunks_old.iter().enumerate() {
+        occurrences.entry(&chunk.hash).or_default().push(old_pos);
+    }
+
+    let chunk_start = |chunks: &[Chunk], index: usize| if index == 0 { 0 } else { chunks[index - 1].end };
+    let mut segments: Vec<Segment> = Vec::with_capacity(chunks_new.len());
+    let mut new_pos: usize = 0;
+    let mut old_pos: usize = 0;
+    let mut lcs_pos: usize = 0;
+    while lcs_pos < lcs_len {
+        // the run of common chunks which are contiguous in the new data
+        let new_run_start = new_pos;
+        while chunks_new[new_pos].hash != lcs[lcs_pos] {
             new_pos += 1;
-            old_pos += 1;
-            lcs_pos += 1;
-            if lcs_pos == lcs_len {
+        }
+        if new_pos != new_run_start {
+            let new_range = chunk_start(chunks_new, new_run_start)..chunk_start(chunks_new, new_pos);
+            segments.push(Segment::New(new_range));
+        }
+        let mut new_run_len: usize = 1;
+        while lcs_pos + new_run_len < lcs_len
+            && new_pos + new_run_len < chunks_new.len()
+            && chunks_new[new_pos + new_run_len].hash == lcs[lcs_pos + new_run_len]
+        {
+            new_run_len += 1;
+        }
+
+        // the old occurrence continuing the run the furthest (the first one on ties), as long
+        // as the rest of the LCS can still be matched after it
+        let candidates = &occurrences[&lcs[lcs_pos][..]];
+        let first_candidate = candidates.partition_point(|&candidate| candidate < old_pos);
+        let (mut run_old_start, mut run_len) = (0, 0);
+        for &candidate in &candidates[first_candidate..] {
+            if candidate > last_starts[lcs_pos] {
                 break;
             }
-            common_chunk_hash = &lcs[lcs_pos];
-        }
-        if old_pos != old_segment_start {
-            let segment_start = if old_segment_start == 0 {
-                0
-            } else {
-                chunks_old[old_segment_start - 1].end
-            };
-            let old_segment = Segment::Old(segment_start..chunks_old[old_pos - 1].end);
-            segments.push(old_segment);
+            let mut len: usize = 1;
+            while len < new_run_len
+                && candidate + len <= last_starts[lcs_pos + len]
+                && chunks_old[candidate + len].hash == lcs[lcs_pos + len]
+            {
+                len += 1;
+            }
+            if len > run_len {
+                (run_old_start, run_len) = (candidate, len);
+                if run_len == new_run_len {
+                    break;
+                }
+            }
         }
+
+        let old_range =
+            chunk_start(chunks_old, run_old_start)..chunks_old[run_old_start + run_len - 1].end;
+        segments.push(Segment::Old(old_range));
+        old_pos = run_old_start + run_len;
+        new_pos += run_len;
+        lcs_pos += run_len;
     }
 
-    // Append remaining New segment
     if new_pos < chunks_new.len() {
-        let segment_start = if new_pos == 0 {
-            0
-        } else {
-            chunks_new[new_pos - 1].end
-        };
-        let new_segment = Segment::New(segment_start..chunks_new.last().unwrap().end);
-        segments.push(new_segment);
+        let new_range = chunk_start(chunks_new, new_pos)..chunks_new.last().unwrap().end;
+        segments.push(Segment::New(new_range));
     }
-
     segments
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lcs::cross_validation::random_symbols;
+    use crate::lcs::hunt_szymanski::lcs_hunt_szymanski;
+    use crate::patcher::reconstruct_iter;
+
+    // 4-byte chunks hashed with the symbols (e.g. letters), and the LCS of their hashes
+    fn make_chunks<S: Copy + Into<u64>>(
+        symbols_old: &[S],
+        symbols_new: &[S],
+    ) -> (Vec<Chunk>, Vec<Chunk>, Vec<Vec<u8>>) {
+        let make = |symbols: &[S]| -> Vec<Chunk> {
+            symbols
+                .iter()
+                .enumerate()
+                .map(|(index, &symbol)| Chunk {
+                    hash: symbol.into().to_le_bytes().to_vec(),
+                    end: (index as u64 + 1) * 4,
+                })
+                .collect()
+        };
+        let (chunks_old, chunks_new) = (make(symbols_old), make(symbols_new));
+        let hashes = |chunks: &[Chunk]| -> Vec<Vec<u8>> {
+            chunks.iter().map(|chunk| chunk.hash.clone()).collect()
+        };
+        let lcs = lcs_hunt_szymanski(&hashes(&chunks_old), &hashes(&chunks_new));
+        (chunks_old, chunks_new, lcs)
+    }
+
+    #[test]
+    fn test_delta_serialize() {
+        let config = DifferConfig {
+            lcs_algorithm: Some(LcsAlgorithm::HuntSzymanski),
+            ..DifferConfig::default()
+        };
+        let segments = vec![
+            Segment::Old(0..4),
+            Segment::New(4..16),
+            Segment::Store(vec![0xab; 32], 12),
+            Segment::Old(4..8),
+        ];
+        let bytes = serialize(&segments, &config).unwrap();
+        assert_eq!(&bytes[..4], b"DLTA");
+        assert_eq!(deserialize(&bytes), Ok((config, segments)));
+
+        assert_eq!(
+            deserialize(&bytes[..bytes.len() - 1]),
+            Err(DeltaError::Truncated)
+        );
+
+        // the hash length is a single byte, so longer hashes are reported rather than panicking
+        let segments = vec![Segment::Store(vec![0xab; 255], 12)];
+        assert!(serialize(&segments, &config).is_ok());
+        let segments = vec![Segment::Store(vec![0xab; 256], 12)];
+        assert_eq!(serialize(&segments, &config), Err(DeltaError::HashTooLong(256)));
+    }
+
+    #[test]
+    fn test_delta_serialize_split() {
+        let old_bytes = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new_bytes = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let mut differ = Differ::from_config(config);
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let segments = differ.finalize();
+
+        // the combined form: the delta applied to the old and new data
+        let combined: Vec<u8> = reconstruct_iter(&old_bytes, &new_bytes, &segments)
+            .flatten()
+            .copied()
+            .collect();
+
+        let (structure, payload) = serialize_split(&segments, &new_bytes, &config).unwrap();
+        let new_segments_len: u64 = segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::New(_)))
+            .map(Segment::len)
+            .sum();
+        assert_eq!(payload.len() as u64, new_segments_len);
+        let (split_config, split_segments) = deserialize_split(&structure).unwrap();
+        assert_eq!(split_config, config);
+        assert_eq!(split_segments.len(), segments.len());
+        assert_eq!(apply_split(&structure, &payload, &old_bytes).unwrap(), combined);
+        assert_eq!(combined, new_bytes);
+
+        assert_eq!(
+            apply_split(&structure, &payload[..payload.len() - 1], &old_bytes),
+            Err(DeltaError::InvalidSegmentRange)
+        );
+    }
+
+    #[test]
+    fn test_delta_serialize_split_deduplicated() {
+        // the same 1000 bytes (two chunks) inserted three times between old data
+        let old: Vec<u8> = (0..4000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let insert: Vec<u8> = (0..1000u32).map(|i| (i * 7907 % 241) as u8).collect();
+        let mut new: Vec<u8> = Vec::new();
+        let mut chunk_ends: Vec<u64> = Vec::new();
+        for old_part in old.chunks(1000).take(3) {
+            new.extend_from_slice(old_part);
+            chunk_ends.push(new.len() as u64);
+            new.extend_from_slice(&insert[..400]);
+            chunk_ends.push(new.len() as u64);
+            new.extend_from_slice(&insert[400..]);
+            chunk_ends.push(new.len() as u64);
+        }
+        new.extend_from_slice(&insert[400..]);
+        chunk_ends.push(new.len() as u64);
+        let chunks_new: Vec<Chunk> = chunk_ends
+            .into_iter()
+            .map(|end| Chunk { hash: vec![], end })
+            .collect();
+        // the first insert is a New segment on its own, the others extend up to the end
+        let segments = vec![
+            Segment::Old(0..1000),
+            Segment::New(1000..2000),
+            Segment::Old(1000..2000),
+            Segment::New(3000..4000),
+            Segment::Old(2000..3000),
+            Segment::New(5000..6600),
+        ];
+        let config = DifferConfig::default();
+
+        let (structure, payload) = serialize_split(&segments, &new, &config).unwrap();
+        assert_eq!(payload.len(), 3600);
+        let (deduplicated_structure, deduplicated_payload) =
+            serialize_split_deduplicated(&segments, &new, &chunks_new, &config).unwrap();
+        // the payload holds the insert once, which all the New segments refer to
+        assert_eq!(deduplicated_payload, insert);
+        let (_, deduplicated_segments) = deserialize_split(&deduplicated_structure).unwrap();
+        assert_eq!(
+            deduplicated_segments,
+            vec![
+                Segment::Old(0..1000),
+                Segment::New(0..1000),
+                Segment::Old(1000..2000),
+                Segment::New(0..1000),
+                Segment::Old(2000..3000),
+                Segment::New(0..1000),
+                Segment::New(400..1000),
+            ]
+        );
+
+        assert_eq!(apply_split(&structure, &payload, &old).unwrap(), new);
+        assert_eq!(
+            apply_split(&deduplicated_structure, &deduplicated_payload, &old).unwrap(),
+            new
+        );
+    }
+
+    #[test]
+    fn test_delta_deserialize_wrong_magic() {
+        let mut bytes = serialize(&[Segment::New(0..4)], &DifferConfig::default()).unwrap();
+        bytes[0] = b'X';
+        assert_eq!(deserialize(&bytes), Err(DeltaError::InvalidMagic));
+        assert_eq!(deserialize(b"DL"), Err(DeltaError::Truncated));
+    }
+
+    #[test]
+    fn test_delta_deserialize_future_version() {
+        let mut bytes = serialize(&[Segment::New(0..4)], &DifferConfig::default()).unwrap();
+        bytes[4] = FORMAT_VERSION + 1;
+        assert_eq!(
+            deserialize(&bytes),
+            Err(DeltaError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn test_delta_hash_references() {
+        let old_chunks: &[Chunk] = &[
+            Chunk {
+                hash: "A".as_bytes().to_vec(),
+                end: 4,
+            },
+            Chunk {
+                hash: "B".as_bytes().to_vec(),
+                end: 10,
+            },
+            Chunk {
+                hash: "C".as_bytes().to_vec(),
+                end: 12,
+            },
+        ];
+        let segments = vec![Segment::Old(4..12), Segment::New(0..3), Segment::Old(0..4)];
+        assert_eq!(
+            hash_references(segments, old_chunks),
+            vec![
+                Segment::Store("B".as_bytes().to_vec(), 6),
+                Segment::Store("C".as_bytes().to_vec(), 2),
+                Segment::New(0..3),
+                Segment::Store("A".as_bytes().to_vec(), 4),
+            ]
+        );
+    }
 
     #[test]
     fn test_delta_nothing_in_common() {
@@ -312,4 +1397,263 @@ mod tests {
             vec![Segment::Old(0..4), Segment::New(4..16), Segment::Old(4..8)]
         );
     }
+
+    #[test]
+    fn test_delta_contiguous() {
+        let cases = [
+            ("AXAB", "AB", 1),
+            ("ABXABCYBC", "ABC", 1),
+            ("AXABYAB", "ABAB", 2),
+            ("ABCDEF", "AXBCYF", 3),
+            ("AAAB", "BAAA", 1),
+            ("ABC", "XYZ", 0),
+            ("", "ABC", 0),
+        ];
+        for (old_hashes, new_hashes, old_segment_count) in cases {
+            let (chunks_old, chunks_new, lcs) = make_chunks(old_hashes.as_bytes(), new_hashes.as_bytes());
+            let old: Vec<u8> = old_hashes.bytes().flat_map(|hash| [hash; 4]).collect();
+            let new: Vec<u8> = new_hashes.bytes().flat_map(|hash| [hash; 4]).collect();
+
+            let segments = delta_contiguous(&chunks_old, &chunks_new, &lcs);
+            let patched: Vec<u8> = reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+            assert_eq!(patched, new);
+            let old_ranges: Vec<&Range<u64>> = segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::Old(range) => Some(range),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(old_ranges.len(), old_segment_count, "{} -> {}", old_hashes, new_hashes);
+            // the old data is read sequentially
+            assert!(old_ranges.windows(2).all(|ranges| ranges[0].end <= ranges[1].start));
+            let reused = |segments: &[Segment]| -> u64 {
+                segments
+                    .iter()
+                    .filter(|segment| matches!(segment, Segment::Old(_)))
+                    .map(|segment| segment.len())
+                    .sum()
+            };
+            assert_eq!(reused(&segments), reused(&delta(&chunks_old, &chunks_new, &lcs)));
+        }
+    }
+
+    #[test]
+    fn test_delta_stream() {
+        let cases = [
+            ("ABCDEF", "AXBCYF"),
+            ("ABCDEF", "ABCDEF"),
+            ("ABC", "XYZ"),
+            ("", "ABC"),
+            ("ABC", ""),
+            ("AAAB", "BAAA"),
+        ];
+        for (old_hashes, new_hashes) in cases {
+            let (chunks_old, chunks_new, lcs) = make_chunks(old_hashes.as_bytes(), new_hashes.as_bytes());
+
+            let mut streamed: Vec<Segment> = Vec::new();
+            stream(&chunks_old, &chunks_new, &lcs, |segment| streamed.push(segment));
+            assert_eq!(streamed, delta(&chunks_old, &chunks_new, &lcs));
+        }
+    }
+
+    #[test]
+    fn test_delta_bidirectional() {
+        let cases = [
+            ("ABCDEF", "AXBCYF"),
+            ("XABCDEFY", "ABZCDEF"),
+            ("ABCDEF", "ABCDEF"),
+            ("ABC", "XYZ"),
+            ("", "ABC"),
+            ("ABC", ""),
+            ("", ""),
+            ("AAAB", "BAAA"),
+            ("AXAB", "AB"),
+        ];
+        for (old_hashes, new_hashes) in cases {
+            let (chunks_old, chunks_new, lcs) = make_chunks(old_hashes.as_bytes(), new_hashes.as_bytes());
+
+            // the single walk yields the same deltas as both one-directional ones
+            assert_eq!(
+                delta_bidirectional(&chunks_old, &chunks_new, &lcs),
+                (delta(&chunks_old, &chunks_new, &lcs), delta(&chunks_new, &chunks_old, &lcs))
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_prefix_lcs() {
+        let hashes = |hashes: &[&str]| -> Vec<Vec<u8>> {
+            hashes.iter().map(|hash| hash.as_bytes().to_vec()).collect()
+        };
+        let prefixes = |hashes: &[Vec<u8>]| -> Vec<Vec<u8>> {
+            hashes.iter().map(|hash| hash[..1].to_vec()).collect()
+        };
+
+        // no collisions, the verified subsequence is the LCS of the whole hashes
+        let hashes_old = hashes(&["a1", "b1", "c1", "d1"]);
+        let hashes_new = hashes(&["a1", "x1", "c1", "d1"]);
+        let prefix_lcs = lcs_hunt_szymanski(&prefixes(&hashes_old), &prefixes(&hashes_new));
+        let verified = verify_prefix_lcs(&hashes_old, &hashes_new, &prefix_lcs);
+        assert_eq!(verified, lcs_hunt_szymanski(&hashes_old, &hashes_new));
+
+        // b1 and b2 only share the prefix, the collision is dropped
+        let hashes_new = hashes(&["a1", "b2", "c1", "d1"]);
+        let prefix_lcs = lcs_hunt_szymanski(&prefixes(&hashes_old), &prefixes(&hashes_new));
+        assert_eq!(prefix_lcs, hashes(&["a", "b", "c", "d"]));
+        let verified = verify_prefix_lcs(&hashes_old, &hashes_new, &prefix_lcs);
+        assert_eq!(verified, hashes(&["a1", "c1", "d1"]));
+    }
+
+    // the segment builder as it was before scanning the chunks with iterators, one position
+    // at a time
+    fn reference_delta(chunks_old: &[Chunk], chunks_new: &[Chunk], lcs: &[Vec<u8>]) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = Vec::new();
+        let start = |chunks: &[Chunk], pos: usize| if pos == 0 { 0 } else { chunks[pos - 1].end };
+        let (mut new_pos, mut old_pos, mut lcs_pos) = (0, 0, 0);
+        while lcs_pos < lcs.len() {
+            let new_segment_start = new_pos;
+            while chunks_new[new_pos].hash != lcs[lcs_pos] {
+                new_pos += 1;
+            }
+            if new_pos != new_segment_start {
+                segments.push(Segment::New(start(chunks_new, new_segment_start)..chunks_new[new_pos - 1].end));
+            }
+            while chunks_old[old_pos].hash != lcs[lcs_pos] {
+                old_pos += 1;
+            }
+            let old_segment_start = old_pos;
+            while lcs_pos < lcs.len()
+                && chunks_new[new_pos].hash == lcs[lcs_pos]
+                && chunks_old[old_pos].hash == lcs[lcs_pos]
+            {
+                new_pos += 1;
+                old_pos += 1;
+                lcs_pos += 1;
+            }
+            if old_pos != old_segment_start {
+                segments.push(Segment::Old(start(chunks_old, old_segment_start)..chunks_old[old_pos - 1].end));
+            }
+        }
+        if new_pos < chunks_new.len() {
+            segments.push(Segment::New(start(chunks_new, new_pos)..chunks_new.last().unwrap().end));
+        }
+        segments
+    }
+
+    #[test]
+    fn test_delta_matches_reference() {
+        let mut seed: u64 = 1;
+        for alphabet_size in [2, 4, 16, 1000] {
+            for (old_len, new_len) in [(0, 10), (10, 0), (1, 1), (50, 60), (300, 200)] {
+                let (chunks_old, chunks_new, lcs) = make_chunks(
+                    &random_symbols(old_len, alphabet_size, seed),
+                    &random_symbols(new_len, alphabet_size, seed + 1),
+                );
+                seed += 2;
+                assert_eq!(
+                    delta(&chunks_old, &chunks_new, &lcs),
+                    reference_delta(&chunks_old, &chunks_new, &lcs)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_delta_rle() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let mut differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let segments = differ.finalize();
+
+        let runs = to_rle(&segments).unwrap();
+        assert!(runs.iter().any(|&(op, _)| op == Op::Skip));
+        assert!(runs.windows(2).all(|runs| runs[0].0 != runs[1].0 && runs[0].1 > 0));
+        assert_eq!(from_rle(&runs), segments);
+        let (_, payload) = serialize_split(&segments, &new, &DifferConfig::default()).unwrap();
+        assert_eq!(apply_rle(&runs, &old, &payload).unwrap(), new);
+        assert_eq!(
+            apply_rle(&runs, &old, &payload[1..]),
+            Err(DeltaError::InvalidSegmentRange)
+        );
+
+        // the runs are merged, the offsets are implied
+        let segments = [Segment::Old(4..8), Segment::Old(8..10), Segment::New(6..9), Segment::Old(12..16)];
+        let runs = vec![(Op::Skip, 4), (Op::Keep, 6), (Op::Add, 3), (Op::Skip, 2), (Op::Keep, 4)];
+        assert_eq!(to_rle(&segments), Ok(runs.clone()));
+        assert_eq!(from_rle(&runs), vec![Segment::Old(4..10), Segment::New(6..9), Segment::Old(12..16)]);
+
+        // old data read backwards, New segments not at their output position, Store segments
+        let backwards = [Segment::Old(8..12), Segment::Old(0..4)];
+        assert_eq!(to_rle(&backwards), Err(DeltaError::InvalidSegmentRange));
+        assert_eq!(to_rle(&[Segment::New(4..8)]), Err(DeltaError::InvalidSegmentRange));
+        let stored = [Segment::Store(vec![0xab; 32], 16)];
+        assert_eq!(to_rle(&stored), Err(DeltaError::UnresolvedStoreSegment));
+    }
+
+    #[test]
+    fn test_delta_git() {
+        // "the quick brown fox" to "the quick red fox", laid out as git does
+        let old = b"the quick brown fox";
+        let new = b"the quick red fox";
+        let segments = vec![Segment::Old(0..10), Segment::New(10..13), Segment::Old(15..19)];
+        let expected: &[u8] = &[
+            0x13, 0x11, // the sizes, 19 and 17
+            0x90, 0x0a, // copy 10 bytes at offset 0 (zero bytes are left out)
+            0x03, b'r', b'e', b'd', // insert 3 bytes
+            0x91, 0x0f, 0x04, // copy 4 bytes at offset 15
+        ];
+        let bytes = serialize_git(&segments, old.len() as u64, new).unwrap();
+        assert_eq!(bytes, expected);
+        let (old_len, parsed_segments, payload) = deserialize_git(&bytes).unwrap();
+        assert_eq!(old_len, 19);
+        assert_eq!(
+            parsed_segments,
+            vec![Segment::Old(0..10), Segment::New(0..3), Segment::Old(15..19)]
+        );
+        assert_eq!(payload, b"red");
+        assert_eq!(apply_git(&bytes, old).unwrap(), new);
+
+        // a real delta, with copies longer than 64KiB and inserts longer than 127 bytes
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let mut differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let segments = differ.finalize();
+        assert!(segments.iter().any(|segment| segment.len() > GIT_MAX_COPY_SIZE));
+        let bytes = serialize_git(&segments, old.len() as u64, &new).unwrap();
+        assert_eq!(apply_git(&bytes, &old).unwrap(), new);
+        let (_, parsed_segments, _) = deserialize_git(&bytes).unwrap();
+        let old_segments = |segments: &[Segment]| -> Vec<Segment> {
+            let is_old = |segment: &&Segment| matches!(segment, Segment::Old(_));
+            segments.iter().filter(is_old).cloned().collect()
+        };
+        assert_eq!(old_segments(&parsed_segments), old_segments(&segments));
+
+        // invalid deltas
+        let delta: &[u8] = &[0x13, 0x11, 0x90, 0x0a, 0x03, b'r', b'e', b'd', 0x91, 0x0f, 0x04];
+        assert_eq!(
+            apply_git(delta, b"the quick brown"),
+            Err(DeltaError::SizeMismatch { expected: 19, found: 15 })
+        );
+        assert_eq!(deserialize_git(&delta[..7]), Err(DeltaError::Truncated));
+        assert_eq!(
+            deserialize_git(&delta[..8]),
+            Err(DeltaError::SizeMismatch { expected: 17, found: 13 })
+        );
+        assert_eq!(deserialize_git(&[0x13, 0x00, 0x00]), Err(DeltaError::InvalidSegmentTag(0)));
+        let past_the_end: &[u8] = &[0x13, 0x04, 0x91, 0x10, 0x04];
+        assert_eq!(deserialize_git(past_the_end), Err(DeltaError::InvalidSegmentRange));
+        assert_eq!(
+            serialize_git(&[Segment::Old(0..20)], 19, &[]),
+            Err(DeltaError::InvalidSegmentRange)
+        );
+        assert_eq!(
+            serialize_git(&[Segment::Store(vec![0xab; 32], 16)], 19, &[]),
+            Err(DeltaError::UnresolvedStoreSegment)
+        );
+    }
 }
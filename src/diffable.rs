@@ -0,0 +1,128 @@
+/*
+    Diffable unifies the kinds of inputs the Differ can compare. Anything implementing
+    it knows how to feed its bytes (in order) to a callback, e.g. the Slicer's 'process',
+    so that Differ::diff can accept:
+    - in-memory byte slices (&[u8])
+    - file paths (&str), opened and read until exhausted
+    - readers (Box<dyn Read>), read until exhausted
+
+    Files and readers are read with a large buffer (STREAM_READ_BUF_SIZE); failing to open
+    or read them is returned as the I/O error, the bytes fed so far are then incomplete.
+*/
+
+use crate::reader::*;
+use std::fs::File;
+use std::io::{Read, Result};
+
+pub trait Diffable {
+    fn feed<F: FnMut(&[u8])>(self, process: F) -> Result<()>;
+}
+
+impl Diffable for &[u8] {
+    fn feed<F: FnMut(&[u8])>(self, mut process: F) -> Result<()> {
+        process(self);
+        Ok(())
+    }
+}
+
+impl Diffable for &str {
+    fn feed<F: FnMut(&[u8])>(self, process: F) -> Result<()> {
+        feed_reader(File::open(self)?, process)
+    }
+}
+
+impl Diffable for Box<dyn Read> {
+    fn feed<F: FnMut(&[u8])>(self, process: F) -> Result<()> {
+        feed_reader(self, process)
+    }
+}
+
+// feeds everything the reader yields to the callback
+fn feed_reader<R: Read, F: FnMut(&[u8])>(mut reader: R, mut process: F) -> Result<()> {
+    let mut buffer: Vec<u8> = vec![0; STREAM_READ_BUF_SIZE];
+    while let Some(bytes_read) = read_buffer(&mut reader, &mut buffer)? {
+        process(&buffer[..bytes_read]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delta::{buffer_range, Segment};
+    use crate::differ::Differ;
+    use std::{fs, fs::File, io::Read};
+
+    const OLD_FILE_PATH: &str = "./example/monkey_before.tiff";
+    const NEW_FILE_PATH: &str = "./example/monkey_after.tiff";
+
+    fn diff<A: super::Diffable, B: super::Diffable>(old: A, new: B) -> Vec<Segment> {
+        let window_size: u32 = 64;
+        let min_chunk_size: usize = 2048;
+        let max_chunk_size: usize = 8192;
+        let boundary_mask: u32 = (1 << 12) - 1; // avg chunk size is 2^12 = 4096
+        Differ::diff(
+            old,
+            new,
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+        )
+        .unwrap()
+    }
+
+    fn patch(old: &[u8], new: &[u8], segments: Vec<Segment>) -> Vec<u8> {
+        let mut patched: Vec<u8> = Vec::with_capacity(new.len());
+        for segment in segments {
+            patched.extend_from_slice(match segment {
+                Segment::Old(range) => &old[buffer_range(&range)],
+                Segment::New(range) => &new[buffer_range(&range)],
+                Segment::Store(..) => unreachable!(),
+            });
+        }
+        patched
+    }
+
+    #[test]
+    fn test_diffable_bytes() {
+        let old = fs::read(OLD_FILE_PATH).unwrap();
+        let new = fs::read(NEW_FILE_PATH).unwrap();
+        let segments = diff(&old[..], &new[..]);
+        assert_eq!(patch(&old, &new, segments), new);
+    }
+
+    #[test]
+    fn test_diffable_path() {
+        let old = fs::read(OLD_FILE_PATH).unwrap();
+        let new = fs::read(NEW_FILE_PATH).unwrap();
+        let segments = diff(OLD_FILE_PATH, NEW_FILE_PATH);
+        assert_eq!(segments, diff(&old[..], &new[..]));
+        assert_eq!(patch(&old, &new, segments), new);
+    }
+
+    #[test]
+    fn test_diffable_reader() {
+        let old = fs::read(OLD_FILE_PATH).unwrap();
+        let new = fs::read(NEW_FILE_PATH).unwrap();
+        let old_reader: Box<dyn Read> = Box::new(File::open(OLD_FILE_PATH).unwrap());
+        let new_reader: Box<dyn Read> = Box::new(File::open(NEW_FILE_PATH).unwrap());
+        let segments = diff(old_reader, new_reader);
+        assert_eq!(segments, diff(&old[..], &new[..]));
+        assert_eq!(patch(&old, &new, segments), new);
+    }
+
+    #[test]
+    fn test_diffable_errors() {
+        let missing = "./example/missing_file";
+        assert!(super::Diffable::feed(missing, |_| {}).is_err());
+
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("read failed"))
+            }
+        }
+        let reader: Box<dyn Read> = Box::new(FailingReader);
+        assert!(super::Diffable::feed(reader, |_| {}).is_err());
+    }
+}
@@ -1,24 +1,44 @@
+use crate::chunk_index::*;
+use crate::dedup_stats::*;
 use crate::delta::*;
+use crate::diffable::*;
+use crate::hasher::hasher::*;
 use crate::hasher::sha256::*;
-// use crate::lcs::hunt_szymanski::*;
-use crate::lcs::nakatsu::*;
+use crate::helper::*;
+use crate::lcs::anchored::*;
+use crate::lcs::lcs::*;
+use crate::normalizer::*;
+use crate::refiner::*;
 use crate::rolling_hasher::polynomial::*;
+use crate::rolling_hasher::rolling_hasher::*;
 use crate::slicer::*;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
 
-const DEFAULT_WINDOW_SIZE: u32 = 1000000007;
+const DEFAULT_WINDOW_SIZE: u32 = 64; // bytes, must be a power of 2
 const DEFAULT_MIN_CHUNK_SIZE: usize = 4096;
 const DEFAULT_MAX_CHUNK_SIZE: usize = 16384;
 const DEFAULT_BOUNDARY_MASK: u32 = (1 << 12) - 1; // 12 least significant bits set, avg chunk size is 2^12=4096
 
+// the hashers a Differ built from a DifferConfig uses, as identified in the fingerprint
+const ROLLING_HASHER_ID: &[u8] = b"polynomial";
+const CHUNK_HASHER_ID: &[u8] = b"sha256";
+
 /*
     Compares two versions of data buffers or streams and returns delta which
     describes how to patch the old data to become new data, reusing chunks of
     old data whenever possible
 
     Two ways of computing delta are possible:
-    1. Comparing complete in-memory buffers of data. To do so, simply call
-       let delta = Differ::diff(...);
-       passing the buffers as well as some slicing parameters as arguments
+    1. Comparing complete inputs. To do so, simply call
+       let delta = Differ::diff(...)?;
+       passing the inputs as well as some slicing parameters as arguments; the inputs
+       can be in-memory buffers, file paths or readers (anything that is Diffable), and
+       failing to read them is returned as the I/O error
 
     2. Buffered processing which allows for feeding the Differ instance with
        incoming data and then, once the streams have been read, obtaining delta:
@@ -31,6 +51,10 @@ const DEFAULT_BOUNDARY_MASK: u32 = (1 << 12) - 1; // 12 least significant bits s
        differ.process_new(...);
        let delta = differ.finalize();       // will consume differ
 
+       Finalizing without processing any data compares two empty streams, which is a no-op
+       resulting in an empty delta. As finalize consumes the differ, no data can be processed
+       after it.
+
     The code uses Polynomial rolling hash (Rabin-Karp) for slicing streams of data into chunks
     of variable size, which are then hashed with SHA256 and compared using Nakatsu Longest
     Common Subseqence algorithm which is efficient when streams are similar (this seems to
@@ -38,19 +62,24 @@ const DEFAULT_BOUNDARY_MASK: u32 = (1 << 12) - 1; // 12 least significant bits s
 
     Alternative versions of rolling hash (moving sum), digest (SHA1, MD5) and LCS (Hunt-Szymanski)
     are available.
-    They cannot be switched at runtime and require the code to be modified.
     The Slicer generic struct is taking RollingHasher and Hasher traits as compile-time arguments.
-    To try Hunt-Szymanski LCS (more appropriate when differences are substantial) replace
-    lcs_nakatsu function call with lcs_hunt_szymanski.
+    The default Differ uses Polynomial rolling hash and SHA256, any other combination can be used
+    by creating the slicers and passing them to Differ::with_slicers.
+    The LCS algorithm can be passed to Differ::new (e.g. based on the expected similarity of the
+    inputs), Nakatsu being the default. A Differ created from a DifferConfig without an LCS
+    algorithm picks it automatically: Hunt-Szymanski (more appropriate when differences are
+    substantial) is used instead of Nakatsu when a cheap similarity estimate is low.
 
     Some ideas to consider/explore:
 
     - implementing Kumar LCS algorithm which is O(n(m-p)) time (like  Nakatsu) but also linear
       space (unlike Nakatsu which is quadratic, what may become a problem for large data)
       https://www.academia.edu/4127816/A_Linear_Space_Algorithm_for_the_LCS_Problem
+      (LcsAlgorithm::Kumar is available, picked when Nakatsu exceeds the memory budget)
 
     - using more efficient rolling hash algorithms, like the Gear used in FastCDC
       https://pdfs.semanticscholar.org/64b5/ce9ff6c7f5396cd1ec6bba8a9f5f27bc8dba.pdf
+      (GearRollingHasher is available, the default Differ still uses the polynomial one)
 
     - the actual delta file (to be sent over network) should contain OLD/NEW segments, where
       OLD segments only define ranges (client already has the data), while NEW contains actual
@@ -61,23 +90,118 @@ const DEFAULT_BOUNDARY_MASK: u32 = (1 << 12) - 1; // 12 least significant bits s
 
     - using more sophisticated slicing to minimize producing chunk of fixed size (max_chunk_size)
       which may result in some boundary-shift issues and thus increased bandwidth (too much of a
-      new file being sent over the network); two or more alternative boundary thresholds is one
-      idea to explore (to increase probability of boundary detection when chunks size is becoming
-      large)
+      new file being sent over the network); a secondary, weaker boundary threshold checked once
+      chunks grow large is available (set_secondary_boundary_mask), as is FastCDC's normalized
+      chunking (set_normalized_chunking), more of them could be tried
 */
 
-pub struct Differ {
-    slicer_old: Slicer<PolynomialRollingHasher, Sha256Hasher>,
-    slicer_new: Slicer<PolynomialRollingHasher, Sha256Hasher>,
+/// The complete set of parameters a Differ is built from. Capturing it allows for reproducing
+/// a given delta. The chunk digest (SHA256) is not configurable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DifferConfig {
+    pub chunking: ChunkingParameters,
+    pub rolling_hash_modulus: u32,
+    pub rolling_hash_base: u32,
+    pub lcs_algorithm: Option<LcsAlgorithm>, // picked automatically if None
+}
+
+impl Default for DifferConfig {
+    fn default() -> DifferConfig {
+        DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: DEFAULT_WINDOW_SIZE,
+                min_chunk_size: DEFAULT_MIN_CHUNK_SIZE,
+                max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+                boundary_mask: DEFAULT_BOUNDARY_MASK,
+            },
+            rolling_hash_modulus: DEFAULT_MODULUS,
+            rolling_hash_base: DEFAULT_BASE,
+            lcs_algorithm: None,
+        }
+    }
+}
+
+impl DifferConfig {
+    /// Returns a short digest of the parameters affecting chunk compatibility: the chunking
+    /// parameters, the rolling hasher (polynomial) and its modulus and base, which seed it,
+    /// and the chunk hasher (SHA256). It's embedded in serialized deltas so that applying a
+    /// delta produced under incompatible parameters can be detected. The LCS algorithm only
+    /// affects which chunks get reused, not the chunks, so it's left out. It's stable across
+    /// platforms and builds.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.chunking.window_size.to_le_bytes());
+        hasher.update((self.chunking.min_chunk_size as u64).to_le_bytes());
+        hasher.update((self.chunking.max_chunk_size as u64).to_le_bytes());
+        hasher.update(self.chunking.boundary_mask.to_le_bytes());
+        for hasher_id in [ROLLING_HASHER_ID, CHUNK_HASHER_ID] {
+            hasher.update([hasher_id.len() as u8]);
+            hasher.update(hasher_id);
+        }
+        hasher.update(self.rolling_hash_modulus.to_le_bytes());
+        hasher.update(self.rolling_hash_base.to_le_bytes());
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash[..8].try_into().unwrap())
+    }
+}
+
+/// Wall-clock time spent in each stage of the diffing pipeline
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiffTimings {
+    pub slicing_old: Duration,
+    pub slicing_new: Duration,
+    pub lcs: Duration,
+    pub delta: Duration,
+}
+
+impl DiffTimings {
+    pub fn total(&self) -> Duration {
+        self.slicing_old + self.slicing_new + self.lcs + self.delta
+    }
+}
+
+/// What the Differ does with a new chunk sharing its hash with an old chunk whose bytes
+/// differ (as verified with a secondary hash of the chunks)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CollisionPolicy {
+    /// the colliding chunks are never reused, their bytes are emitted as New (safe)
+    #[default]
+    TreatAsNew,
+    /// the hashes are trusted to never collide, skipping the verification (faster, but a
+    /// collision makes the patched data wrong)
+    TrustHash,
+}
+
+pub struct Differ<RH: RollingHasher = PolynomialRollingHasher, H: Hasher = Sha256Hasher> {
+    config: Option<DifferConfig>, // unknown if built from custom slicers
+    lcs_algorithm: Option<LcsAlgorithm>, // picked automatically if None
+    memory_budget: Option<u64>,          // limits the automatically picked LCS algorithm memory
+    lcs_deadline: Option<Instant>,       // the LCS is skipped once passed
+    lcs_hash_prefix_len: Option<usize>,  // the LCS only compares the hash prefixes if set
+    anchored_lcs: bool,                  // the LCS is split at anchors and run in parallel
+    parallelism: usize,                  // the threads the parallel stages may use
+    slicer_old: Slicer<RH, H>,
+    slicer_new: Slicer<RH, H>,
+    volatile_hashes: HashSet<Vec<u8>>, // chunks never to be reused from the old data
+    collision_policy: CollisionPolicy,
+    timings: Option<DiffTimings>,      // only collected if enabled
+    hash_references: bool,             // emit reused old chunks as Store segments
+    contiguous_reuse: bool,            // prefer contiguous reused old ranges
+    moved_reuse: bool,                 // reuse new chunks found out of order in the old data
+    max_new_segment_size: Option<u64>, // larger New segments are split at chunk boundaries
+    common_header_len: u64,            // leading bytes known to be identical, never sliced
+    header_skipped_old: u64,           // header bytes skipped so far
+    header_skipped_new: u64,
     is_finalized: bool,
 }
 
 impl Differ {
-    /// Compares two versions of in-memory data (byte) buffers and returns delta
+    /// Compares two versions of data and returns delta
     /// 
     /// Arguments:
-    /// buffer_old      - points at the old data buffer
-    /// buffer_new      - points at the new (updated) data buffer
+    /// old             - the old data (a byte buffer, a file path or a reader)
+    /// new             - the new (updated) data (a byte buffer, a file path or a reader)
     /// window_size     - is rolling hash sliding window size
     /// min_chunk_size  - the minimum chunk size
     /// max_chunk_size  - the maximum chunk size
@@ -85,22 +209,54 @@ impl Differ {
     /// 
     /// Returned:
     /// the vector of Segments which are the byte ranges of the old and new data buffers
-    /// that need to be put together to recreate the new updated file
-    #[allow(dead_code)]
-    pub(crate) fn diff(
-        buffer_old: &[u8],
-        buffer_new: &[u8],
+    /// that need to be put together to recreate the new updated file, or the error if an
+    /// input cannot be read
+    pub fn diff<A: Diffable, B: Diffable>(
+        old: A,
+        new: B,
+        window_size: Option<u32>,
+        min_chunk_size: Option<usize>,
+        max_chunk_size: Option<usize>,
+        boundary_mask: Option<u32>,
+    ) -> std::io::Result<Vec<Segment>> {
+        let mut differ = Differ::new(window_size, min_chunk_size, max_chunk_size, boundary_mask, None);
+
+        old.feed(|bytes| differ.slicer_old.process(bytes))?;
+        new.feed(|bytes| differ.slicer_new.process(bytes))?;
+
+        Ok(differ.finalize())
+    }
+
+    /// Compares two versions of data and returns both the forward delta (recreating the new
+    /// data from the old one) and the reverse delta (recreating the old data from the new
+    /// one), e.g. to store the latest version whole along with the deltas to the older ones
+    /// 
+    /// Arguments:
+    /// old             - the old data (a byte buffer, a file path or a reader)
+    /// new             - the new (updated) data (a byte buffer, a file path or a reader)
+    /// window_size     - is rolling hash sliding window size
+    /// min_chunk_size  - the minimum chunk size
+    /// max_chunk_size  - the maximum chunk size
+    /// boundary_mask   - the bit mask used as a threshold for boundary detection
+    /// 
+    /// Returned:
+    /// the forward and the reverse vectors of Segments; in the reverse one, Old segments
+    /// refer to the new data and New segments to the old data. Or the error if an input
+    /// cannot be read
+    pub fn diff_bidirectional<A: Diffable, B: Diffable>(
+        old: A,
+        new: B,
         window_size: Option<u32>,
         min_chunk_size: Option<usize>,
         max_chunk_size: Option<usize>,
         boundary_mask: Option<u32>,
-    ) -> Vec<Segment> {
-        let mut differ = Differ::new(window_size, min_chunk_size, max_chunk_size, boundary_mask);
+    ) -> std::io::Result<(Vec<Segment>, Vec<Segment>)> {
+        let mut differ = Differ::new(window_size, min_chunk_size, max_chunk_size, boundary_mask, None);
 
-        differ.process_old(buffer_old);
-        differ.process_new(buffer_new);
+        old.feed(|bytes| differ.slicer_old.process(bytes))?;
+        new.feed(|bytes| differ.slicer_new.process(bytes))?;
 
-        differ.finalize()
+        Ok(differ.finalize_bidirectional())
     }
 
     /// Creates a new Differ instance to be used with buffered file processing
@@ -110,49 +266,531 @@ impl Differ {
     /// min_chunk_size  - the minimum chunk size
     /// max_chunk_size  - the maximum chunk size
     /// boundary_mask   - the bit mask used as a threshold for boundary detection
+    /// lcs_algorithm   - the LCS algorithm, e.g. Hunt-Szymanski if the inputs are expected
+    ///                   to differ substantially; Nakatsu if None
     /// 
     /// Returned:
     /// the Differ instance
-    pub(crate) fn new(
+    pub fn new(
         window_size: Option<u32>,
         min_chunk_size: Option<usize>,
         max_chunk_size: Option<usize>,
         boundary_mask: Option<u32>,
+        lcs_algorithm: Option<LcsAlgorithm>,
     ) -> Differ {
-        let window_size = window_size.unwrap_or(DEFAULT_WINDOW_SIZE);
-        let min_chunk_size = min_chunk_size.unwrap_or(DEFAULT_MIN_CHUNK_SIZE);
-        let max_chunk_size = max_chunk_size.unwrap_or(DEFAULT_MAX_CHUNK_SIZE);
-        let boundary_mask = boundary_mask.unwrap_or(DEFAULT_BOUNDARY_MASK);
+        Differ::try_new(window_size, min_chunk_size, max_chunk_size, boundary_mask, lcs_algorithm)
+            .unwrap_or_else(|error| panic!("Invalid Differ config: {}", error))
+    }
+
+    /// Same as new but returns an error naming the offending parameter, rather than
+    /// panicking, if the parameters are invalid: the window size must be a power of 2 not
+    /// larger than min_chunk_size, which must be positive and not larger than max_chunk_size
+    /// 
+    /// Returned:
+    /// the Differ instance or the error
+    pub fn try_new(
+        window_size: Option<u32>,
+        min_chunk_size: Option<usize>,
+        max_chunk_size: Option<usize>,
+        boundary_mask: Option<u32>,
+        lcs_algorithm: Option<LcsAlgorithm>,
+    ) -> Result<Differ, SlicerError> {
+        let mut config = DifferConfig::default();
+        let chunking = &mut config.chunking;
+        chunking.window_size = window_size.unwrap_or(chunking.window_size);
+        chunking.min_chunk_size = min_chunk_size.unwrap_or(chunking.min_chunk_size);
+        chunking.max_chunk_size = max_chunk_size.unwrap_or(chunking.max_chunk_size);
+        chunking.boundary_mask = boundary_mask.unwrap_or(chunking.boundary_mask);
+        config.lcs_algorithm = Some(lcs_algorithm.unwrap_or(LcsAlgorithm::Nakatsu));
+
+        Differ::try_from_config(config)
+    }
+
+    /// Creates a new Differ instance from the complete set of parameters. Panics if the
+    /// parameters are invalid, see try_from_config
+    /// 
+    /// Arguments:
+    /// config          - the parameters, e.g. captured from another Differ instance
+    /// 
+    /// Returned:
+    /// the Differ instance
+    pub fn from_config(config: DifferConfig) -> Differ {
+        Differ::try_from_config(config)
+            .unwrap_or_else(|error| panic!("Invalid Differ config: {}", error))
+    }
+
+    /// Creates a new Differ instance from the complete set of parameters
+    /// 
+    /// Arguments:
+    /// config          - the parameters, e.g. captured from another Differ instance
+    /// 
+    /// Returned:
+    /// the Differ instance or the error describing the invalid parameter combination
+    pub fn try_from_config(config: DifferConfig) -> Result<Differ, SlicerError> {
+        let (slicer_old, slicer_new) = make_slicers(&config)?;
+
+        let mut differ = Differ::with_slicers(slicer_old, slicer_new);
+        differ.config = Some(config);
+        differ.lcs_algorithm = config.lcs_algorithm;
+        Ok(differ)
+    }
+
+    /// Creates a new Differ instance with chunk sizes adapted to the input size
+    /// 
+    /// Arguments:
+    /// input_size      - the (expected or estimated) size of the input in bytes
+    /// 
+    /// Returned:
+    /// the Differ instance
+    pub fn new_auto(input_size: u64) -> Differ {
+        let parameters = ChunkingParameters::auto(input_size);
+        Differ::new(
+            Some(parameters.window_size),
+            Some(parameters.min_chunk_size),
+            Some(parameters.max_chunk_size),
+            Some(parameters.boundary_mask),
+            None,
+        )
+    }
+
+    /// Creates a new Differ instance picking the LCS algorithm which fits the memory
+    /// budget. The choice is made once the chunk counts are known: Nakatsu (quadratic
+    /// space) is used only if its matrix fits the budget, Kumar (linear space) otherwise
+    /// 
+    /// Arguments:
+    /// memory_budget   - the maximum memory (in bytes) the LCS algorithm should allocate
+    /// parameters      - the chunking parameters
+    /// 
+    /// Returned:
+    /// the Differ instance
+    pub fn new_with_memory_budget(memory_budget: u64, parameters: ChunkingParameters) -> Differ {
+        let config = DifferConfig {
+            chunking: parameters,
+            ..DifferConfig::default()
+        };
+        let mut differ = Differ::from_config(config);
+        differ.memory_budget = Some(memory_budget);
+        differ
+    }
+
+    /// Compares data against a chunk index rather than against a specific old version,
+    /// e.g. to deduplicate against all the chunks previously stored by a backup
+    /// 
+    /// Arguments:
+    /// new             - the data (a byte buffer, a file path or a reader)
+    /// index           - the index, the data is sliced using its config
+    /// 
+    /// Returned:
+    /// the vector of Segments, where the chunks found in the index are Store segments
+    /// (referencing them by hash) and the remaining byte ranges of the data are New, or
+    /// the error if the data cannot be read
+    pub fn diff_against_index<D: Diffable>(new: D, index: &ChunkIndex) -> std::io::Result<Vec<Segment>> {
+        let mut slicer = make_slicer(index.config())
+            .unwrap_or_else(|error| panic!("Invalid index config: {}", error));
+        new.feed(|bytes| slicer.process(bytes))?;
+        let chunks = slicer
+            .finalize()
+            .unwrap_or_else(|error| panic!("Slicing new data failed: {}", error));
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut chunk_start: u64 = 0;
+        for chunk in chunks {
+            if index.contains(&chunk.hash) {
+                segments.push(Segment::Store(chunk.hash.clone(), chunk.end - chunk_start));
+            } else if let Some(Segment::New(range)) = segments.last_mut() {
+                range.end = chunk.end; // concatenate consecutive New chunks
+            } else {
+                segments.push(Segment::New(chunk_start..chunk.end));
+            }
+            chunk_start = chunk.end;
+        }
+        Ok(segments)
+    }
+
+    /// Compares two versions of data with the given chunking parameters, reusing the Differ
+    /// rather than building a new one, e.g. for a batch of files of very different sizes.
+    /// The slicers keep their hashers, buffers and settings, only the rolling hasher and the
+    /// chunk sizes are replaced; the other Differ settings (LCS, collision policy, common
+    /// header, etc.) apply to each pair and the timings are restarted. Can be called
+    /// repeatedly, but fails if data has been passed to process_old/process_new and not
+    /// finalized, or if normalized chunking is set (its masks only fit the chunk sizes it
+    /// was set for)
+    /// 
+    /// Arguments:
+    /// old             - the old data (a byte buffer, a file path or a reader)
+    /// new             - the new (updated) data (a byte buffer, a file path or a reader)
+    /// parameters      - the chunking parameters of this pair
+    /// 
+    /// Returned:
+    /// the vector of Segments, see finalize, or the error if an input cannot be read or the
+    /// pair cannot be diffed (an InvalidInput error wrapping the SlicerError)
+    pub fn diff_pair_with_params<A: Diffable, B: Diffable>(
+        &mut self,
+        old: A,
+        new: B,
+        parameters: ChunkingParameters,
+    ) -> std::io::Result<Vec<Segment>> {
+        let invalid_input = |error: SlicerError| std::io::Error::new(std::io::ErrorKind::InvalidInput, error);
+        if !self.is_finalized && (self.header_skipped_old > 0 || self.header_skipped_new > 0) {
+            return Err(invalid_input(SlicerError::UnfinalizedData));
+        }
+        let config = DifferConfig {
+            chunking: parameters,
+            ..self.config.unwrap_or_default()
+        };
+        for slicer in [&mut self.slicer_old, &mut self.slicer_new] {
+            slicer
+                .reset(
+                    make_rolling_hasher(&config).map_err(invalid_input)?,
+                    parameters.boundary_mask,
+                    parameters.min_chunk_size,
+                    parameters.max_chunk_size,
+                )
+                .map_err(invalid_input)?;
+        }
+        self.config = Some(config);
+        self.header_skipped_old = 0;
+        self.header_skipped_new = 0;
+        self.is_finalized = false;
+        if self.timings.is_some() {
+            self.timings = Some(DiffTimings::default());
+        }
+
+        let header_len = self.common_header_len;
+        let fed = old
+            .feed(|bytes| {
+                self.slicer_old.process(skip_header(bytes, header_len, &mut self.header_skipped_old))
+            })
+            .and_then(|()| {
+                new.feed(|bytes| {
+                    self.slicer_new.process(skip_header(bytes, header_len, &mut self.header_skipped_new))
+                })
+            });
+        if let Err(error) = fed {
+            // the partial data is dropped, so that it doesn't prevent diffing the next pair
+            _ = self.slicer_old.finalize();
+            _ = self.slicer_new.finalize();
+            self.is_finalized = true;
+            return Err(error);
+        }
+
+        Ok(self.finalize_internal(false, None, false).segments)
+    }
+}
 
-        let (slicer_old, slicer_new) =
-            make_slicers(window_size, min_chunk_size, max_chunk_size, boundary_mask);
+impl<RH: RollingHasher, H: Hasher> Differ<RH, H> {
+    /// Creates a new Differ instance using custom hashers, allowing for any combination
+    /// of RollingHasher and Hasher. Both pairs of hashers are expected to be configured
+    /// identically
+    /// 
+    /// Arguments:
+    /// rolling_hasher_old  - the rolling hasher to be used for the old data
+    /// rolling_hasher_new  - the rolling hasher to be used for the new data
+    /// hasher_old          - the chunk hasher to be used for the old data
+    /// hasher_new          - the chunk hasher to be used for the new data
+    /// parameters          - the chunk sizes and boundary mask; the window size is the
+    ///                       rolling hashers' own, so parameters.window_size is unused
+    /// 
+    /// Returned:
+    /// the Differ instance or the error if the parameters are invalid
+    pub fn with_hashers(
+        rolling_hasher_old: RH,
+        rolling_hasher_new: RH,
+        hasher_old: H,
+        hasher_new: H,
+        parameters: ChunkingParameters,
+    ) -> Result<Differ<RH, H>, SlicerError> {
+        let make_slicer = |rolling_hasher, hasher| {
+            Slicer::new(
+                rolling_hasher,
+                hasher,
+                parameters.boundary_mask,
+                parameters.min_chunk_size,
+                parameters.max_chunk_size,
+            )
+        };
+        Ok(Differ::with_slicers(
+            make_slicer(rolling_hasher_old, hasher_old)?,
+            make_slicer(rolling_hasher_new, hasher_new)?,
+        ))
+    }
 
+    /// Creates a new Differ instance using custom slicers, allowing for any combination
+    /// of RollingHasher and Hasher. Both slicers are expected to be configured identically
+    /// 
+    /// Arguments:
+    /// slicer_old      - the slicer to be used for the old data
+    /// slicer_new      - the slicer to be used for the new data
+    /// 
+    /// Returned:
+    /// the Differ instance
+    pub(crate) fn with_slicers(
+        mut slicer_old: Slicer<RH, H>,
+        mut slicer_new: Slicer<RH, H>,
+    ) -> Differ<RH, H> {
+        // the default collision policy verifies the chunks
+        slicer_old.enable_collision_detection();
+        slicer_new.enable_collision_detection();
         Differ {
+            config: None,
+            lcs_algorithm: None,
+            memory_budget: None,
+            lcs_deadline: None,
+            lcs_hash_prefix_len: None,
+            anchored_lcs: false,
+            parallelism: thread::available_parallelism().map_or(1, |threads| threads.get()),
             slicer_old,
             slicer_new,
+            volatile_hashes: HashSet::new(),
+            collision_policy: CollisionPolicy::default(),
+            timings: None,
+            hash_references: false,
+            contiguous_reuse: false,
+            moved_reuse: false,
+            max_new_segment_size: None,
+            common_header_len: 0,
+            header_skipped_old: 0,
+            header_skipped_new: 0,
             is_finalized: false,
         }
     }
 
+    /// Returns the parameters the Differ has been built from (unless built from custom slicers)
+    pub fn config(&self) -> Option<&DifferConfig> {
+        self.config.as_ref()
+    }
+
+    /// Marks the chunk with the given hash as volatile. Volatile chunks (e.g. a mutable
+    /// metadata block) are always emitted as New, even if an identical old chunk exists
+    /// 
+    /// Arguments:
+    /// hash            - the chunk hash (as computed by the Differ's hasher)
+    pub fn mark_volatile(&mut self, hash: Vec<u8>) {
+        self.volatile_hashes.insert(hash);
+    }
+
+    /// Sets what to do with new chunks sharing their hash with distinct old chunks. By
+    /// default (TreatAsNew) a secondary hash of each chunk is computed while slicing to
+    /// verify the reused chunks, TrustHash skips it. To be called before any data is processed
+    /// 
+    /// Arguments:
+    /// policy          - the collision policy
+    pub fn set_collision_policy(&mut self, policy: CollisionPolicy) {
+        match policy {
+            CollisionPolicy::TreatAsNew => {
+                self.slicer_old.enable_collision_detection();
+                self.slicer_new.enable_collision_detection();
+            }
+            CollisionPolicy::TrustHash => {
+                self.slicer_old.disable_collision_detection();
+                self.slicer_new.disable_collision_detection();
+            }
+        }
+        self.collision_policy = policy;
+    }
+
+    /// Sets the normalizer applied to both old and new data before chunking and hashing,
+    /// e.g. to make the diff case or whitespace insensitive. To be called before any data
+    /// is processed. The chunk boundaries don't depend on the formatting then, but only the
+    /// chunks whose original bytes are the same are reused, so that the patched file equals
+    /// the new file byte for byte
+    /// 
+    /// Arguments:
+    /// normalizer      - the normalizer (cloned for old and new data)
+    pub fn set_normalizer<N: Normalizer + Clone + Send + 'static>(&mut self, normalizer: N) {
+        self.slicer_old.set_normalizer(Box::new(normalizer.clone()));
+        self.slicer_new.set_normalizer(Box::new(normalizer));
+    }
+
+    /// Makes both slicers use the raw chunk bytes as the chunk hashes, skipping the digest
+    /// computation, if the max chunk size doesn't exceed the threshold (i.e. the chunks are
+    /// not larger than the digests would be). To be called before any data is processed.
+    /// The deltas are the same, but the Store segments (see enable_hash_references) then
+    /// hold the raw chunk bytes rather than the digests
+    /// 
+    /// Arguments:
+    /// threshold       - the largest max chunk size for which the raw bytes are used
+    pub fn set_raw_hash_threshold(&mut self, threshold: usize) {
+        self.slicer_old.set_raw_hash_threshold(threshold);
+        self.slicer_new.set_raw_hash_threshold(threshold);
+    }
+
+    /// Makes both slicers also check a second, weaker boundary mask once a chunk has grown
+    /// to the given size, so that fewer chunks are cut by force at the max chunk size. To be
+    /// called before any data is processed
+    /// 
+    /// Arguments:
+    /// mask            - the secondary boundary mask, with fewer bits than the boundary mask
+    /// chunk_size      - the chunk size from which it's checked, e.g. twice the average one
+    pub fn set_secondary_boundary_mask(&mut self, mask: u32, chunk_size: usize) {
+        self.slicer_old.set_secondary_boundary_mask(mask, chunk_size);
+        self.slicer_new.set_secondary_boundary_mask(mask, chunk_size);
+    }
+
+    /// Makes both slicers normalize the chunk sizes as in FastCDC: a stricter mask is checked
+    /// below the average chunk size and a looser one from it on, so that the chunk sizes are
+    /// closer to the average. The masks replace the boundary mask. To be called before any
+    /// data is processed
+    /// 
+    /// Arguments:
+    /// mask_small      - the mask checked below the average size, with more bits
+    /// mask_large      - the mask checked from the average size on, with fewer bits
+    /// avg_chunk_size  - the chunk size at which the masks are switched
+    pub fn set_normalized_chunking(&mut self, mask_small: u32, mask_large: u32, avg_chunk_size: usize) {
+        self.slicer_old.set_normalized_chunking(mask_small, mask_large, avg_chunk_size);
+        self.slicer_new.set_normalized_chunking(mask_small, mask_large, avg_chunk_size);
+    }
+
+    /// Makes both slicers move their chunks to temporary files in the directory whenever
+    /// the threshold number of chunks is held in memory, reading them back on finalize. It
+    /// bounds the memory used while slicing enormous inputs, not the memory of the LCS
+    /// computation. To be called before any data is processed. Finalizing panics if the
+    /// chunks cannot be written to or read back from the files
+    /// 
+    /// Arguments:
+    /// threshold       - the number of chunks held in memory before spilling
+    /// directory       - where the temporary files are created
+    /// 
+    /// Returned:
+    /// the error if the temporary files cannot be created
+    pub fn enable_chunk_spilling(&mut self, threshold: usize, directory: &Path) -> std::io::Result<()> {
+        self.slicer_old.enable_chunk_spilling(threshold, directory)?;
+        self.slicer_new.enable_chunk_spilling(threshold, directory)
+    }
+
+    /// Sets the LCS algorithm, rather than picking it automatically once the chunk counts
+    /// are known
+    /// 
+    /// Arguments:
+    /// algorithm       - the LCS algorithm to be used
+    pub fn set_lcs_algorithm(&mut self, algorithm: LcsAlgorithm) {
+        self.lcs_algorithm = Some(algorithm);
+    }
+
+    /// Sets the point in time after which the LCS computation gives up, falling back to the
+    /// trivial delta (all the new data as a New segment). It bounds the worst-case latency
+    /// (e.g. of interactive tools) on large dissimilar inputs
+    /// 
+    /// Arguments:
+    /// deadline        - the instant after which the LCS is abandoned
+    pub fn set_lcs_deadline(&mut self, deadline: Instant) {
+        self.lcs_deadline = Some(deadline);
+    }
+
+    /// Makes the LCS compare only the first bytes of the chunk hashes, which is faster and
+    /// takes less memory than comparing whole hashes. The matched chunks are then verified
+    /// against the whole hashes before computing the delta, so that chunks which only share
+    /// a prefix are never reused (at worst, a prefix collision makes the delta suboptimal)
+    /// 
+    /// Arguments:
+    /// prefix_len      - the number of leading hash bytes compared, e.g. 8
+    pub fn set_lcs_hash_prefix_len(&mut self, prefix_len: usize) {
+        self.lcs_hash_prefix_len = Some(prefix_len);
+    }
+
+    /// Enables measuring the time spent in each stage, to be obtained with finalize_with_timings
+    pub fn enable_timings(&mut self) {
+        self.timings = Some(DiffTimings::default());
+    }
+
+    /// Makes the delta reference the reused old chunks by hash (as Store segments, one per
+    /// chunk) instead of by range of the old data, so that it can be applied against any
+    /// chunk store holding these chunks, regardless of the old data layout
+    pub fn enable_hash_references(&mut self) {
+        self.hash_references = true;
+    }
+
+    /// Makes the LCS split the chunk streams at anchors (chunks appearing exactly once in
+    /// each stream, in the same order) and compare the chunks between consecutive anchors
+    /// in parallel (see set_parallelism). It's much faster on large similar inputs, where the
+    /// result is the same, but on dissimilar inputs the anchors may lead to a shorter common
+    /// subsequence
+    pub fn enable_anchored_lcs(&mut self) {
+        self.anchored_lcs = true;
+    }
+
+    /// Sets the number of threads the parallel stages (currently the anchored LCS) may use,
+    /// so that a host can cap the CPU usage; 1 makes the Differ fully sequential (no thread
+    /// is spawned). Defaults to the number of available cores. The delta doesn't depend on it
+    /// 
+    /// Arguments:
+    /// threads         - the maximum number of threads
+    pub fn set_parallelism(&mut self, threads: usize) {
+        assert!(threads > 0, "Parallelism must be at least one thread");
+        self.parallelism = threads;
+    }
+
+    /// Makes the delta prefer, among the alignments of the common chunks to the old data,
+    /// those reusing contiguous old ranges, so that the patcher reads the old file with as
+    /// few seeks as possible (e.g. on spinning disks or network storage). The reused bytes
+    /// are the same, only the number of Old segments can decrease
+    pub fn enable_contiguous_reuse(&mut self) {
+        self.contiguous_reuse = true;
+    }
+
+    /// Makes the delta reuse the new chunks which exist in the old data out of order, e.g.
+    /// blocks moved or swapped, rather than emitting them as New: the common subsequence
+    /// can only keep one of two swapped blocks. The Old segments are then no longer in
+    /// ascending offset order (see patch_from_reader)
+    pub fn enable_moved_reuse(&mut self) {
+        self.moved_reuse = true;
+    }
+
+    /// Caps the size of the New segments: larger ones (e.g. a huge inserted block) are split
+    /// at chunk boundaries into pieces made of whole chunks, so that each piece can be stored
+    /// and deduplicated against later versions on its own. A single chunk larger than the cap
+    /// makes a piece of its own. The reverse delta's New segments are capped as well
+    /// 
+    /// Arguments:
+    /// max_size        - the maximum New segment size, in bytes
+    pub fn set_max_new_segment_size(&mut self, max_size: u64) {
+        assert!(max_size > 0, "Max New segment size must be positive");
+        self.max_new_segment_size = Some(max_size);
+    }
+
+    /// Declares the leading bytes of both inputs as a common header (e.g. of a file format)
+    /// known to be identical across versions. The header is neither sliced nor compared, it's
+    /// emitted as a single Old segment and only the data following it is diffed. To be called
+    /// before any data is processed. The artifacts (see finalize_with_artifacts) and the
+    /// statistics only cover the data following the header
+    /// 
+    /// Arguments:
+    /// header_len      - the header length, in bytes
+    pub fn set_common_header_len(&mut self, header_len: u64) {
+        self.common_header_len = header_len;
+    }
+
     /// Processes new buffer of the old and new file, respectively. Can be called in
-    /// any order, e.g. old and new buffers can be interleaved and processed concurrently
+    /// any order, e.g. old and new buffers can be interleaved as they arrive. Both take
+    /// the Differ mutably, so they can't be called from two threads at once; the Differ
+    /// can however be moved to another thread between the calls (it's Send)
     /// 
     /// Arguments:
     /// buffer          - the buffer of the file to be processed
-    pub(crate) fn process_old(&mut self, buffer: &[u8]) {
+    pub fn process_old(&mut self, buffer: &[u8]) {
         assert!(
             !self.is_finalized,
             "Alrady finalized, cannot accept more input."
         );
+        let buffer = skip_header(buffer, self.common_header_len, &mut self.header_skipped_old);
+        let start = Instant::now();
         self.slicer_old.process(buffer);
+        if let Some(timings) = self.timings.as_mut() {
+            timings.slicing_old += start.elapsed();
+        }
     }
 
-    pub(crate) fn process_new(&mut self, buffer: &[u8]) {
+    pub fn process_new(&mut self, buffer: &[u8]) {
         assert!(
             !self.is_finalized,
             "Alrady finalized, cannot accept more input."
         );
+        let buffer = skip_header(buffer, self.common_header_len, &mut self.header_skipped_new);
+        let start = Instant::now();
         self.slicer_new.process(buffer);
+        if let Some(timings) = self.timings.as_mut() {
+            timings.slicing_new += start.elapsed();
+        }
     }
 
     /// Determines the delta description. To be called once both files have been read.
@@ -160,131 +798,1769 @@ impl Differ {
     /// Returned:
     /// the vector of Segments which are the byte ranges of the old and new data buffers
     /// that need to be put together to recreate the new updated file
-    pub(crate) fn finalize(mut self) -> Vec<Segment> {
-        assert!(!self.is_finalized, "Alrady finalized!");
-        self.is_finalized = true;
+    pub fn finalize(self) -> Vec<Segment> {
+        self.finalize_with_timings().0
+    }
 
-        let chunks_old = self.slicer_old.finalize();
-        let chunks_new = self.slicer_new.finalize();
+    /// Same as finalize but also returns the time spent in each stage (if enabled
+    /// with enable_timings)
+    pub fn finalize_with_timings(mut self) -> (Vec<Segment>, Option<DiffTimings>) {
+        let finalized = self.finalize_internal(false, None, false);
+        (finalized.segments, finalized.timings)
+    }
 
-        // TODO: iterating over chunk arrays (to get vectors of hashes) could be avoided if we
-        // introduced a Hashed trait and pass it to LCS routines instead
-        let hashes_old: Vec<Vec<u8>> = chunks_old.iter().map(|chunk| chunk.hash.clone()).collect();
-        let hashes_new: Vec<Vec<u8>> = chunks_new.iter().map(|chunk| chunk.hash.clone()).collect();
+    /// Same as finalize but also returns whether the LCS has been skipped because the
+    /// deadline set with set_lcs_deadline has passed, in which case the delta is the
+    /// trivial one (all the new data as a New segment)
+    pub fn finalize_with_lcs_status(mut self) -> (Vec<Segment>, bool) {
+        let finalized = self.finalize_internal(false, None, false);
+        (finalized.segments, finalized.lcs_skipped)
+    }
 
-        let lcs = lcs_nakatsu(&hashes_old[..], &hashes_new[..]);
-        // let lcs = lcs_hunt_szymanski(&hashes_old[..], &hashes_new[..]);
+    /// Same as finalize but also returns the reverse delta, recreating the old data from
+    /// the new one. Both are derived from the same common chunks, so the slicing and the LCS
+    /// are only run once
+    /// 
+    /// Returned:
+    /// the forward and the reverse vectors of Segments; in the reverse one, Old segments
+    /// refer to the new data and New segments to the old data
+    pub fn finalize_bidirectional(mut self) -> (Vec<Segment>, Vec<Segment>) {
+        let finalized = self.finalize_internal(true, None, false);
+        (finalized.segments, finalized.reverse_segments)
+    }
 
-        delta(&chunks_old, &chunks_new, &lcs[..])
+    /// Same as finalize but also returns the intermediate results of the diff, the chunks
+    /// and their LCS, from which deltas can be rebuilt (e.g. with different parameters)
+    /// without running the LCS again, or the reuse of a new chunk explained (see
+    /// DiffArtifacts::explain)
+    /// 
+    /// Returned:
+    /// the vector of Segments, see finalize, and the artifacts
+    pub fn finalize_with_artifacts(mut self) -> (Vec<Segment>, DiffArtifacts) {
+        let finalized = self.finalize_internal(false, None, true);
+        let artifacts = finalized.artifacts.expect("Artifacts have been requested");
+        (finalized.segments, artifacts)
     }
-}
 
-fn make_slicers(
-    window_size: u32,
-    min_chunk_size: usize,
-    max_chunk_size: usize,
-    boundary_mask: u32,
-) -> (
-    Slicer<PolynomialRollingHasher, Sha256Hasher>,
-    Slicer<PolynomialRollingHasher, Sha256Hasher>,
-) {
-    let rolling_hasher_old = PolynomialRollingHasher::new(window_size, None, None);
-    let hasher_old = Sha256Hasher::new(max_chunk_size);
-    let slicer_old = Slicer::new(
-        rolling_hasher_old,
-        hasher_old,
-        boundary_mask,
-        min_chunk_size,
-        max_chunk_size,
-    );
-
-    let rolling_hasher_new = PolynomialRollingHasher::new(window_size, None, None);
-    let hasher_new = Sha256Hasher::new(max_chunk_size);
-    let slicer_new = Slicer::new(
-        rolling_hasher_new,
-        hasher_new,
-        boundary_mask,
-        min_chunk_size,
-        max_chunk_size,
-    );
-
-    (slicer_old, slicer_new)
-}
+    /// Same as finalize but also accounts for the diff in the session-wide deduplication
+    /// statistics
+    /// 
+    /// Arguments:
+    /// stats           - the statistics to be updated
+    /// 
+    /// Returned:
+    /// the vector of Segments, see finalize
+    pub fn finalize_with_stats(mut self, stats: &mut DedupStats) -> Vec<Segment> {
+        self.finalize_internal(false, Some(stats), false).segments
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::Differ;
-    use crate::delta::Segment;
-    use crate::reader::read_file;
-    use crate::patcher::patch;
-    use sha2::{Sha256, Digest};
-    use std::{
-        fs::{File, OpenOptions, /*,remove_file*/}, 
-        io::{copy, Write}
-    };
+    // computes the forward delta and, if requested, the reverse one (empty otherwise) and
+    // the artifacts, updating the stats (if any)
+    fn finalize_internal(
+        &mut self,
+        reverse: bool,
+        stats: Option<&mut DedupStats>,
+        artifacts: bool,
+    ) -> Finalized {
+        assert!(!self.is_finalized, "Alrady finalized!");
+        self.is_finalized = true;
 
-    #[test]
-    fn test_differ_data() {
-        let old_string = "What a a year in the blockchain sphere. It's also been quite a year for Equilibrium and I thought I'd recap everything that has happened in the company.";
-        let new_string = "It's been a year in the blockchain sphere. It's also been quite a year for Equilibrium. I thought I'd recap everything that has happened in the company with a Year In Review post.";
+        let mut timings = self.timings;
 
-        // avg chunk size 16
-        let window_size: u32 = 8;
-        let min_chunk_size: usize = 8;
-        let max_chunk_size: usize = 32;
-        let boundary_mask: u32 = (1 << 4) - 1; // avg chunk size is 2^4 = 16
-        let segments = Differ::diff(
-            old_string.as_bytes(),
-            new_string.as_bytes(),
-            Some(window_size),
-            Some(min_chunk_size),
-            Some(max_chunk_size),
-            Some(boundary_mask),
-        );
-        let mut patched_string = String::from("");
-        for segment in segments {
-            patched_string += match segment {
-                Segment::Old(range) => &old_string[range],
-                Segment::New(range) => &new_string[range],
-            };
+        let start = Instant::now();
+        self.slicer_old
+            .finalize()
+            .unwrap_or_else(|error| panic!("Slicing old data failed: {}", error));
+        let slicing_old_end = Instant::now();
+        self.slicer_new
+            .finalize()
+            .unwrap_or_else(|error| panic!("Slicing new data failed: {}", error));
+        let slicing_new_end = Instant::now();
+        let mut volatile_hashes = self.volatile_hashes.clone();
+        if self.collision_policy == CollisionPolicy::TreatAsNew {
+            // the colliding chunks are handled as volatile ones
+            volatile_hashes.extend(colliding_hashes(&self.slicer_old, &self.slicer_new));
         }
-        assert_eq!(new_string, patched_string);
+        let (chunks_old, chunks_new) = (self.slicer_old.chunks(), self.slicer_new.chunks());
 
-        // avg chunk size 8
-        let window_size: u32 = 4;
-        let min_chunk_size: usize = 4;
-        let max_chunk_size: usize = 16;
-        let boundary_mask: u32 = (1 << 3) - 1; // avg chunk size is 2^3 = 8
-        let segments = Differ::diff(
-            old_string.as_bytes(),
-            new_string.as_bytes(),
-            Some(window_size),
-            Some(min_chunk_size),
-            Some(max_chunk_size),
-            Some(boundary_mask),
-        );
-        let mut patched_string = String::from("");
-        for segment in segments {
-            patched_string += match segment {
-                Segment::Old(range) => &old_string[range],
-                Segment::New(range) => &new_string[range],
+        // identity fast path: identical chunk streams are reused as a whole, skipping the LCS
+        let is_identical = chunks_old.len() == chunks_new.len()
+            && chunks_old
+                .iter()
+                .zip(chunks_new.iter())
+                .all(|(old, new)| old.hash == new.hash && !volatile_hashes.contains(&old.hash));
+        let mut lcs_skipped = false;
+        let (segments, reverse_segments, lcs, lcs_end) = if is_identical {
+            let segments = match chunks_new.last() {
+                Some(last_chunk) if last_chunk.end > 0 => vec![Segment::Old(0..last_chunk.end)],
+                _ => Vec::new(),
             };
+            let reverse_segments = if reverse { segments.clone() } else { Vec::new() };
+            // all the chunks are common, the LCS is only materialized if needed
+            let lcs: Vec<Vec<u8>> = if artifacts {
+                chunks_new.iter().map(|chunk| chunk.hash.clone()).collect()
+            } else {
+                Vec::new()
+            };
+            (segments, reverse_segments, lcs, slicing_new_end)
+        } else {
+            // TODO: iterating over chunk arrays (to get vectors of hashes) could be avoided if we
+            // introduced a Hashed trait and pass it to LCS routines instead
+            // volatile chunks are left out of the old sequence so that they can never be matched
+            let hashes_old: Vec<Vec<u8>> = chunks_old
+                .iter()
+                .filter(|chunk| !volatile_hashes.contains(&chunk.hash))
+                .map(|chunk| chunk.hash.clone())
+                .collect();
+            let hashes_new: Vec<Vec<u8>> =
+                chunks_new.iter().map(|chunk| chunk.hash.clone()).collect();
+
+            let lcs_algorithm = self.lcs_algorithm.unwrap_or_else(|| match self.memory_budget {
+                Some(memory_budget) => {
+                    choose_lcs_algorithm_for_budget(&hashes_old[..], &hashes_new[..], memory_budget)
+                }
+                None => choose_lcs_algorithm(&hashes_old[..], &hashes_new[..]),
+            });
+            // the weighted algorithm maximizes the reused bytes, so it needs the chunk sizes
+            let weights_new: Vec<u64> = if lcs_algorithm == LcsAlgorithm::Weighted {
+                chunks_new
+                    .iter()
+                    .scan(0, |chunk_start, chunk| {
+                        let size = chunk.end - *chunk_start;
+                        *chunk_start = chunk.end;
+                        Some(size)
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let compute_lcs = |a_string: &[Vec<u8>], b_string: &[Vec<u8>]| {
+                if self.anchored_lcs {
+                    anchored_lcs_with_deadline(
+                        lcs_algorithm,
+                        a_string,
+                        b_string,
+                        &weights_new[..],
+                        self.parallelism,
+                        self.lcs_deadline,
+                    )
+                } else {
+                    lcs_with_deadline(
+                        lcs_algorithm,
+                        a_string,
+                        b_string,
+                        &weights_new[..],
+                        self.lcs_deadline,
+                    )
+                }
+            };
+            let lcs = match self.lcs_hash_prefix_len {
+                Some(prefix_len) => {
+                    let prefix = |hash: &Vec<u8>| hash[..prefix_len.min(hash.len())].to_vec();
+                    let prefixes_old: Vec<Vec<u8>> = hashes_old.iter().map(prefix).collect();
+                    let prefixes_new: Vec<Vec<u8>> = hashes_new.iter().map(prefix).collect();
+                    compute_lcs(&prefixes_old, &prefixes_new)
+                        .map(|prefix_lcs| verify_prefix_lcs(&hashes_old, &hashes_new, &prefix_lcs))
+                }
+                None => compute_lcs(&hashes_old, &hashes_new),
+            }
+            .unwrap_or_else(|| {
+                // deadline passed, fall back to the trivial delta (nothing in common)
+                lcs_skipped = true;
+                Vec::new()
+            });
+            let lcs_end = Instant::now();
+
+            // the common subsequence is symmetric, so the reverse delta just swaps the roles
+            let (segments, reverse_segments) = match (reverse, self.contiguous_reuse) {
+                // both derived from a single walk over the common chunks
+                (true, false) => delta_bidirectional(chunks_old, chunks_new, &lcs[..]),
+                // the contiguous alignment depends on the direction
+                (true, true) => (
+                    delta_contiguous(chunks_old, chunks_new, &lcs[..]),
+                    delta_contiguous(chunks_new, chunks_old, &lcs[..]),
+                ),
+                (false, false) => (delta(chunks_old, chunks_new, &lcs[..]), Vec::new()),
+                (false, true) => (delta_contiguous(chunks_old, chunks_new, &lcs[..]), Vec::new()),
+            };
+            let (segments, reverse_segments) = if self.moved_reuse {
+                (
+                    reuse_moved(segments, chunks_old, chunks_new, &volatile_hashes),
+                    reuse_moved(reverse_segments, chunks_new, chunks_old, &HashSet::new()),
+                )
+            } else {
+                (segments, reverse_segments)
+            };
+            (segments, reverse_segments, lcs, lcs_end)
+        };
+        let segments = if self.hash_references {
+            hash_references(segments, chunks_old)
+        } else {
+            segments
+        };
+        let (segments, reverse_segments) = match self.max_new_segment_size {
+            Some(max_size) => (
+                split_new_segments(segments, chunks_new, max_size),
+                split_new_segments(reverse_segments, chunks_old, max_size),
+            ),
+            None => (segments, reverse_segments),
+        };
+
+        if let Some(stats) = stats {
+            stats.update(chunks_new, &segments);
         }
-        assert_eq!(new_string, patched_string);
-    }
 
-    #[test]
-    fn test_differ_files() -> std::io::Result<()> {
-        // avg chunk size 16
-        let window_size: u32 = 64;
-        let min_chunk_size: usize = 2048;
-        let max_chunk_size: usize = 8192;
+        let (segments, reverse_segments) = if self.common_header_len > 0 {
+            let (old_header_len, new_header_len) = (self.header_skipped_old, self.header_skipped_new);
+            (
+                with_header(segments, old_header_len, new_header_len),
+                with_header(reverse_segments, new_header_len, old_header_len),
+            )
+        } else {
+            (segments, reverse_segments)
+        };
+
+        if let Some(timings) = timings.as_mut() {
+            timings.slicing_old += slicing_old_end - start;
+            timings.slicing_new += slicing_new_end - slicing_old_end;
+            timings.lcs += lcs_end - slicing_new_end;
+            timings.delta += lcs_end.elapsed();
+        }
+
+        let artifacts = artifacts.then(|| DiffArtifacts {
+            chunks_old: chunks_old.to_vec(),
+            chunks_new: chunks_new.to_vec(),
+            lcs,
+        });
+
+        Finalized {
+            segments,
+            reverse_segments,
+            timings,
+            lcs_skipped,
+            artifacts,
+        }
+    }
+}
+
+// the hashes shared by distinct chunks, either within one of the inputs or between them
+fn colliding_hashes<RH: RollingHasher, H: Hasher>(
+    slicer_old: &Slicer<RH, H>,
+    slicer_new: &Slicer<RH, H>,
+) -> HashSet<Vec<u8>> {
+    let mut colliding_hashes: HashSet<Vec<u8>> = slicer_old
+        .collisions()
+        .iter()
+        .chain(slicer_new.collisions())
+        .cloned()
+        .collect();
+    let secondary_hashes_old: HashMap<&[u8], u64> = slicer_old
+        .chunks()
+        .iter()
+        .zip(slicer_old.secondary_hashes())
+        .map(|(chunk, &secondary_hash)| (&chunk.hash[..], secondary_hash))
+        .collect();
+    for (chunk, secondary_hash) in slicer_new.chunks().iter().zip(slicer_new.secondary_hashes()) {
+        match secondary_hashes_old.get(&chunk.hash[..]) {
+            Some(secondary_hash_old) if secondary_hash_old != secondary_hash => {
+                colliding_hashes.insert(chunk.hash.clone());
+            }
+            _ => {}
+        }
+    }
+    colliding_hashes
+}
+
+// skips the part of the buffer belonging to the common header, counting the skipped bytes
+fn skip_header<'a>(buffer: &'a [u8], header_len: u64, skipped: &mut u64) -> &'a [u8] {
+    let skip_len = (header_len - *skipped).min(buffer.len() as u64);
+    *skipped += skip_len;
+    &buffer[skip_len as usize..]
+}
+
+// prepends the common header to the segments computed for the data following it, whose
+// ranges are shifted accordingly; the header is reused as far as the source has it
+fn with_header(segments: Vec<Segment>, source_header_len: u64, target_header_len: u64) -> Vec<Segment> {
+    if segments.is_empty() && target_header_len == 0 {
+        return segments;
+    }
+    let reused_header_len = source_header_len.min(target_header_len);
+    let mut header_segments: Vec<Segment> = Vec::with_capacity(segments.len() + 2);
+    if reused_header_len > 0 {
+        header_segments.push(Segment::Old(0..reused_header_len));
+    }
+    if target_header_len > reused_header_len {
+        header_segments.push(Segment::New(reused_header_len..target_header_len));
+    }
+    for segment in segments {
+        let segment = match segment {
+            Segment::Old(range) => {
+                Segment::Old(range.start + source_header_len..range.end + source_header_len)
+            }
+            Segment::New(range) => {
+                Segment::New(range.start + target_header_len..range.end + target_header_len)
+            }
+            Segment::Store(hash, size) => Segment::Store(hash, size),
+        };
+        push_coalesced(&mut header_segments, segment);
+    }
+    header_segments
+}
+
+// the outcome of Differ::finalize_internal
+struct Finalized {
+    segments: Vec<Segment>,
+    reverse_segments: Vec<Segment>, // empty unless requested
+    timings: Option<DiffTimings>,
+    lcs_skipped: bool, // the LCS deadline passed
+    artifacts: Option<DiffArtifacts>, // only if requested
+}
+
+/// The intermediate results of a diff (see Differ::finalize_with_artifacts): the chunks of
+/// both inputs (following the common header, if any, see set_common_header_len) and the
+/// hashes of their longest common subsequence. Computing the LCS is the
+/// costly step of a diff, assembling a delta from it is cheap, so the artifacts can be kept
+/// (or persisted) to build deltas with different parameters later
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiffArtifacts {
+    pub chunks_old: Vec<Chunk>,
+    pub chunks_new: Vec<Chunk>,
+    pub lcs: Vec<Vec<u8>>, // empty if the LCS has been skipped (see set_lcs_deadline)
+}
+
+impl DiffArtifacts {
+    /// Assembles the delta from the LCS, same as finalize (unless hash references are
+    /// enabled, the artifacts only produce plain Old and New segments)
+    pub fn delta(&self) -> Vec<Segment> {
+        delta(&self.chunks_old, &self.chunks_new, &self.lcs)
+    }
+
+    /// Same as delta but the reused old data ranges shorter than min_reuse bytes are sent
+    /// as New segments instead
+    /// 
+    /// Arguments:
+    /// min_reuse       - the smallest reused range, in bytes
+    pub fn delta_with_min_reuse(&self, min_reuse: u64) -> Vec<Segment> {
+        drop_short_reuse(self.delta(), min_reuse)
+    }
+
+    /// The similarity of the inputs by chunk count, 2 * |LCS| / (old chunks + new chunks).
+    /// Each chunk counts equally, so many small common chunks can make inputs sharing
+    /// little data look similar, see weighted_similarity
+    /// 
+    /// Returned:
+    /// the similarity, from 0 (no common chunk) to 1 (identical chunks); 1 if both are empty
+    pub fn similarity(&self) -> f32 {
+        let chunk_count = self.chunks_old.len() + self.chunks_new.len();
+        if chunk_count == 0 {
+            return 1.0;
+        }
+        (2 * self.lcs.len()) as f32 / chunk_count as f32
+    }
+
+    /// The similarity of the inputs by bytes, the share of the new data reused from the old
+    /// one by the delta, i.e. how much the delta saves compared with sending the new data
+    /// 
+    /// Returned:
+    /// the similarity, from 0 (nothing reused) to 1 (all reused); 1 if the new data is empty
+    pub fn weighted_similarity(&self) -> f32 {
+        let new_len = self.chunks_new.last().map_or(0, |chunk| chunk.end);
+        if new_len == 0 {
+            return 1.0;
+        }
+        let reused_len: u64 = self
+            .delta()
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Old(_)))
+            .map(Segment::len)
+            .sum();
+        reused_len as f32 / new_len as f32
+    }
+
+    /// Explains why the new chunk containing the given offset is (or isn't) reused by the
+    /// delta, e.g. to understand a poor deduplication. The offsets are those of the chunks
+    /// (following the common header, if any). It's a method of the artifacts rather than of
+    /// the Differ, as finalize consumes the Differ and the explanation needs the chunks and
+    /// the LCS: finalize_with_artifacts, then explain
+    /// 
+    /// Arguments:
+    /// new_offset      - the offset within the new data
+    /// 
+    /// Returned:
+    /// the explanation, None if the offset is past the end of the new data
+    pub fn explain(&self, new_offset: u64) -> Option<Explanation> {
+        let chunk_index = self.chunks_new.partition_point(|chunk| chunk.end <= new_offset);
+        let chunk = self.chunks_new.get(chunk_index)?;
+        let chunk_start = chunk_index.checked_sub(1).map_or(0, |index| self.chunks_new[index].end);
+        let new_chunk = chunk_start..chunk.end;
+
+        let mut output_position: u64 = 0;
+        for segment in self.delta() {
+            if new_offset < output_position + segment.len() {
+                if let Segment::Old(range) = segment {
+                    // Old segments always start and end at chunk boundaries
+                    let old_start = range.start + (chunk_start - output_position);
+                    let old_chunk = old_start..old_start + (chunk.end - chunk_start);
+                    return Some(Explanation::Reused { new_chunk, old_chunk });
+                }
+                break;
+            }
+            output_position += segment.len();
+        }
+
+        let mut old_chunks: Vec<Range<u64>> = Vec::new();
+        let mut old_chunk_start: u64 = 0;
+        for old_chunk in &self.chunks_old {
+            if old_chunk.hash == chunk.hash {
+                old_chunks.push(old_chunk_start..old_chunk.end);
+            }
+            old_chunk_start = old_chunk.end;
+        }
+        Some(if old_chunks.is_empty() {
+            Explanation::Absent { new_chunk }
+        } else {
+            Explanation::Unmatched { new_chunk, old_chunks }
+        })
+    }
+}
+
+/// Why a new chunk is (or isn't) reused, see DiffArtifacts::explain
+#[derive(Debug, Clone, PartialEq)]
+pub enum Explanation {
+    /// the chunk is part of the common subsequence, reused from the old chunk
+    Reused { new_chunk: Range<u64>, old_chunk: Range<u64> },
+    /// the chunk exists in the old data (at the given chunks) but isn't part of the common
+    /// subsequence: keeping it would break the order of the common chunks (e.g. it's been
+    /// moved or swapped with another block), its old occurrences are all matched to other new
+    /// chunks (it's repeated) or the LCS has been skipped (see set_lcs_deadline)
+    Unmatched { new_chunk: Range<u64>, old_chunks: Vec<Range<u64>> },
+    /// the chunk doesn't exist anywhere in the old data
+    Absent { new_chunk: Range<u64> },
+}
+
+type DefaultSlicer = Slicer<PolynomialRollingHasher, Sha256Hasher>;
+
+fn make_slicers(config: &DifferConfig) -> Result<(DefaultSlicer, DefaultSlicer), SlicerError> {
+    Ok((make_slicer(config)?, make_slicer(config)?))
+}
+
+// creates a slicer as used by the default Differ built from the given config
+pub(crate) fn make_slicer(config: &DifferConfig) -> Result<DefaultSlicer, SlicerError> {
+    make_slicer_with_hasher(config, Sha256Hasher::new(config.chunking.max_chunk_size))
+}
+
+// creates a slicer cutting the same chunks as the default Differ's, hashing them with the
+// given hasher
+pub(crate) fn make_slicer_with_hasher<H: Hasher>(
+    config: &DifferConfig,
+    hasher: H,
+) -> Result<Slicer<PolynomialRollingHasher, H>, SlicerError> {
+    let chunking = &config.chunking;
+    Slicer::new(
+        make_rolling_hasher(config)?,
+        hasher,
+        chunking.boundary_mask,
+        chunking.min_chunk_size,
+        chunking.max_chunk_size,
+    )
+}
+
+// creates the rolling hasher of the slicers built from the given config
+fn make_rolling_hasher(config: &DifferConfig) -> Result<PolynomialRollingHasher, SlicerError> {
+    let window_size = config.chunking.window_size;
+    // checked here, the rolling hasher would panic
+    if window_size == 0 || !is_power_of_two(window_size) {
+        return Err(SlicerError::InvalidWindowSize(window_size));
+    }
+    Ok(PolynomialRollingHasher::new(
+        window_size,
+        Some(config.rolling_hash_modulus),
+        Some(config.rolling_hash_base),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::{buffer_range, Segment};
+    #[cfg(feature = "sha1")]
+    use crate::hasher::sha1::*;
+    use crate::lcs::lcs::LCS_CALLS;
+    use crate::reader::read_file;
+    use crate::rolling_hasher::moving_sum::*;
+    use crate::patcher::patch;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::{
+        fs::{File, OpenOptions, /*,remove_file*/}, 
+        io::{copy, Write}
+    };
+
+    #[test]
+    fn test_differ_data() {
+        let old_string = "What a a year in the blockchain sphere. It's also been quite a year for Equilibrium and I thought I'd recap everything that has happened in the company.";
+        let new_string = "It's been a year in the blockchain sphere. It's also been quite a year for Equilibrium. I thought I'd recap everything that has happened in the company with a Year In Review post.";
+
+        // avg chunk size 16
+        let window_size: u32 = 8;
+        let min_chunk_size: usize = 8;
+        let max_chunk_size: usize = 32;
+        let boundary_mask: u32 = (1 << 4) - 1; // avg chunk size is 2^4 = 16
+        let segments = Differ::diff(
+            old_string.as_bytes(),
+            new_string.as_bytes(),
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+        )
+        .unwrap();
+        let mut patched_string = String::from("");
+        for segment in segments {
+            patched_string += match segment {
+                Segment::Old(range) => &old_string[buffer_range(&range)],
+                Segment::New(range) => &new_string[buffer_range(&range)],
+                Segment::Store(..) => unreachable!(),
+            };
+        }
+        assert_eq!(new_string, patched_string);
+
+        // avg chunk size 8
+        let window_size: u32 = 4;
+        let min_chunk_size: usize = 4;
+        let max_chunk_size: usize = 16;
+        let boundary_mask: u32 = (1 << 3) - 1; // avg chunk size is 2^3 = 8
+        let segments = Differ::diff(
+            old_string.as_bytes(),
+            new_string.as_bytes(),
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+        )
+        .unwrap();
+        let mut patched_string = String::from("");
+        for segment in segments {
+            patched_string += match segment {
+                Segment::Old(range) => &old_string[buffer_range(&range)],
+                Segment::New(range) => &new_string[buffer_range(&range)],
+                Segment::Store(..) => unreachable!(),
+            };
+        }
+        assert_eq!(new_string, patched_string);
+    }
+
+    #[test]
+    fn test_differ_empty() {
+        let data = "It's also been quite a year for Equilibrium.".as_bytes();
+        let other_data = "I thought I'd recap everything.".as_bytes();
+        let cases: [(&[u8], &[u8], Vec<Segment>); 4] = [
+            (&[], &[], vec![]),
+            (&[], data, vec![Segment::New(0..data.len() as u64)]),
+            (data, &[], vec![]),
+            (other_data, data, vec![Segment::New(0..data.len() as u64)]),
+        ];
+        for (old, new, expected) in cases {
+            let segments = Differ::diff(old, new, Some(4), Some(4), Some(16), Some((1 << 3) - 1)).unwrap();
+            let mut patched: Vec<u8> = Vec::new();
+            for segment in segments.iter() {
+                patched.extend_from_slice(match segment {
+                    Segment::Old(range) => &old[buffer_range(range)],
+                    Segment::New(range) => &new[buffer_range(range)],
+                    Segment::Store(..) => unreachable!(),
+                });
+            }
+            assert_eq!(segments, expected);
+            assert_eq!(patched, new);
+        }
+    }
+
+    #[test]
+    fn test_differ_volatile() {
+        // fixed-size 16-byte chunks to make the chunk boundaries predictable
+        let old_string = "this is a header-mutable block!!-this is a footer";
+        let new_string = old_string;
+        let window_size: u32 = 8;
+        let chunk_size: usize = 16;
+        let boundary_mask: u32 = (1 << 4) - 1;
+
+        // without volatile chunks the whole data is reused
+        let segments = Differ::diff(
+            old_string.as_bytes(),
+            new_string.as_bytes(),
+            Some(window_size),
+            Some(chunk_size),
+            Some(chunk_size),
+            Some(boundary_mask),
+        )
+        .unwrap();
+        assert_eq!(segments, vec![Segment::Old(0..49)]);
+
+        // the middle chunk is volatile, so it must be sent despite being unchanged
+        let mut differ = Differ::new(
+            Some(window_size),
+            Some(chunk_size),
+            Some(chunk_size),
+            Some(boundary_mask),
+            None,
+        );
+        differ.mark_volatile(Sha256::digest(&old_string.as_bytes()[16..32]).to_vec());
+        differ.process_old(old_string.as_bytes());
+        differ.process_new(new_string.as_bytes());
+        let segments = differ.finalize();
+        assert_eq!(
+            segments,
+            vec![Segment::Old(0..16), Segment::New(16..32), Segment::Old(32..49)]
+        );
+    }
+
+    #[test]
+    fn test_differ_timings() {
+        let window_size: u32 = 64;
+        let min_chunk_size: usize = 2048;
+        let max_chunk_size: usize = 8192;
+        let boundary_mask: u32 = (1 << 12) - 1; // avg chunk size is 2^12 = 4096
+
+        // not collected unless enabled
+        let differ = Differ::new(
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+            None,
+        );
+        let (_, timings) = differ.finalize_with_timings();
+        assert!(timings.is_none());
+
+        let mut differ = Differ::new(
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+            None,
+        );
+        differ.enable_timings();
+        read_file("./example/monkey_before.tiff", |bytes, _| {
+            differ.process_old(bytes);
+        });
+        read_file("./example/monkey_after.tiff", |bytes, _| {
+            differ.process_new(bytes);
+        });
+        let (_, timings) = differ.finalize_with_timings();
+        let timings = timings.unwrap();
+        assert!(timings.slicing_old > Duration::ZERO);
+        assert!(timings.slicing_new > Duration::ZERO);
+        assert!(timings.total() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_differ_identical() {
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let mut differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+        differ.enable_timings();
+        differ.process_old(&data);
+        differ.process_new(&data);
+        let (segments, timings) = differ.finalize_with_timings();
+        assert_eq!(segments, vec![Segment::Old(0..data.len() as u64)]);
+        // no time spent in LCS means it has been skipped
+        assert_eq!(timings.unwrap().lcs, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_differ_auto_chunking() {
+        // pseudo-random data so that boundaries are found at the expected rate
+        let mut state: u32 = 2463534242;
+        let data: Vec<u8> = (0..(4 << 20))
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        for size in [1 << 10, 64 << 10, 1 << 20, 4 << 20] {
+            let parameters = ChunkingParameters::auto(size as u64);
+            let mut slicer = make_slicer(&DifferConfig {
+                chunking: parameters,
+                ..DifferConfig::default()
+            })
+            .unwrap();
+            slicer.process(&data[..size]);
+            let chunk_count = slicer.finalize().unwrap().len();
+            // roughly sqrt(size) chunks
+            let expected = (size as f64).sqrt() as usize;
+            assert!(
+                chunk_count >= expected / 4 && chunk_count <= expected * 2,
+                "{} chunks for {} bytes",
+                chunk_count,
+                size
+            );
+        }
+
+        // the chunk count stays manageable for huge inputs, too
+        let size: u64 = 10 << 30;
+        let parameters = ChunkingParameters::auto(size);
+        assert_eq!(parameters.boundary_mask, (1 << 17) - 1);
+        assert!(size / (parameters.boundary_mask as u64 + 1) < 100_000);
+
+        // and doesn't fall below sane bounds for tiny ones
+        let parameters = ChunkingParameters::auto(10);
+        assert_eq!(parameters.min_chunk_size, 8);
+        assert_eq!(parameters.window_size, 8);
+    }
+
+    #[test]
+    fn test_differ_config() {
+        let differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+        let config = *differ.config().unwrap();
+        assert_eq!(config.chunking.window_size, 64);
+        assert_eq!(config.rolling_hash_modulus, DEFAULT_MODULUS);
+
+        // a Differ rebuilt from the config is equivalent
+        let rebuilt = Differ::from_config(config);
+        assert_eq!(*rebuilt.config().unwrap(), config);
+        assert_eq!(rebuilt.config().unwrap().fingerprint(), config.fingerprint());
+        let old = "What a a year in the blockchain sphere.".as_bytes();
+        let new = "It's been a year in the blockchain sphere.".as_bytes();
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 4,
+                min_chunk_size: 4,
+                max_chunk_size: 16,
+                boundary_mask: (1 << 3) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let segments = Differ::diff(old, new, Some(4), Some(4), Some(16), Some((1 << 3) - 1)).unwrap();
+        let mut differ = Differ::from_config(config);
+        differ.process_old(old);
+        differ.process_new(new);
+        assert_eq!(differ.finalize(), segments);
+
+        // any chunking-relevant change alters the fingerprint
+        let mut other = config;
+        other.rolling_hash_base += 2;
+        assert_ne!(other.fingerprint(), config.fingerprint());
+        let mut other = config;
+        other.chunking.boundary_mask = (1 << 4) - 1;
+        assert_ne!(other.fingerprint(), config.fingerprint());
+        // while the LCS algorithm doesn't affect the chunks
+        let mut other = config;
+        other.lcs_algorithm = Some(LcsAlgorithm::HuntSzymanski);
+        assert_eq!(other.fingerprint(), config.fingerprint());
+    }
+
+    #[test]
+    fn test_differ_config_mismatch() {
+        let old = "What a a year in the blockchain sphere.".as_bytes();
+        let new = "It's been a year in the blockchain sphere.".as_bytes();
+        let mut differ = Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+        let config = *differ.config().unwrap();
+        differ.process_old(old);
+        differ.process_new(new);
+        let segments = differ.finalize();
+        let bytes = serialize(&segments, &config).unwrap();
+
+        assert_eq!(deserialize_checked(&bytes, &config), Ok(segments));
+        let mut other = config;
+        other.chunking.min_chunk_size = 8;
+        assert!(matches!(
+            deserialize_checked(&bytes, &other),
+            Err(DeltaError::ConfigMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_differ_config_serde() {
+        let config = DifferConfig {
+            lcs_algorithm: Some(LcsAlgorithm::HuntSzymanski),
+            ..DifferConfig::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: DifferConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, config);
+        assert_eq!(restored.fingerprint(), config.fingerprint());
+    }
+
+    #[test]
+    fn test_differ_normalized() {
+        let sentences = [
+            "It's been a year in the blockchain sphere.",
+            "It's also been quite a year for Equilibrium.",
+            "I thought I'd recap everything that has happened in the company.",
+            "With a Year In Review post.",
+        ];
+        let old_string: String = (0..40)
+            .map(|i| sentences[i % sentences.len()].to_owned() + " ")
+            .collect();
+        // same text, a few lines reformatted (whitespace only)
+        let new_string = old_string
+            .replacen("sphere. ", "sphere.\n\n  ", 2)
+            .replacen("a Year", "a\tYear", 1);
+
+        let diff = |normalize: bool| {
+            let mut differ = Differ::new(Some(8), Some(16), Some(64), Some((1 << 5) - 1), None);
+            if normalize {
+                differ.set_normalizer(TextNormalizer::new(false, true));
+            }
+            differ.process_old(old_string.as_bytes());
+            differ.process_new(new_string.as_bytes());
+            differ.finalize()
+        };
+        let new_bytes_sent = |segments: &[Segment]| -> u64 {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::New(_)))
+                .map(|segment| segment.len())
+                .sum()
+        };
+
+        let segments = diff(true);
+        // most of the text is reused
+        assert!(new_bytes_sent(&segments) < new_string.len() as u64 / 4);
+        // but not the chunks which only match once normalized: the patched text is the new
+        // one byte for byte, reformatted lines included
+        let patched: Vec<u8> = crate::patcher::reconstruct_iter(
+            old_string.as_bytes(),
+            new_string.as_bytes(),
+            &segments,
+        )
+        .flatten()
+        .copied()
+        .collect();
+        assert_eq!(patched, new_string.as_bytes());
+        // and the reformatted lines don't cost more than without normalization
+        assert!(new_bytes_sent(&segments) <= new_bytes_sent(&diff(false)));
+    }
+
+    #[cfg(feature = "sha1")]
+    #[test]
+    fn test_differ_with_slicers() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let make_slicer = || {
+            Slicer::new(
+                MovingSumRollingHasher::new(64),
+                Sha1Hasher::new(8192),
+                (1 << 12) - 1,
+                2048,
+                8192,
+            )
+            .unwrap()
+        };
+        let mut differ = Differ::with_slicers(make_slicer(), make_slicer());
+        assert!(differ.config().is_none());
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let segments = differ.finalize();
+
+        let mut patched: Vec<u8> = Vec::with_capacity(new.len());
+        for segment in segments {
+            patched.extend_from_slice(match segment {
+                Segment::Old(range) => &old[buffer_range(&range)],
+                Segment::New(range) => &new[buffer_range(&range)],
+                Segment::Store(..) => unreachable!(),
+            });
+        }
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_differ_against_index() -> std::io::Result<()> {
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+
+        let mut index = ChunkIndex::new(config);
+        index.add(&old_bytes[..]).unwrap();
+        assert!(!index.is_empty());
+
+        // chunks shared with the indexed file become Store references
+        let segments = Differ::diff_against_index(&new_bytes[..], &index).unwrap();
+        let mut offset: u64 = 0;
+        let mut stored_bytes: u64 = 0;
+        for segment in segments.iter() {
+            match segment {
+                Segment::Store(hash, size) => {
+                    assert!(index.contains(hash));
+                    stored_bytes += size;
+                }
+                Segment::New(range) => assert_eq!(range.start, offset),
+                Segment::Old(_) => panic!("no Old segments expected"),
+            }
+            offset += segment.len();
+        }
+        assert_eq!(offset, new_bytes.len() as u64);
+        assert!(stored_bytes > new_bytes.len() as u64 / 2);
+
+        // the indexed file itself is stored entirely
+        let segments = Differ::diff_against_index(&old_bytes[..], &index).unwrap();
+        assert!(segments
+            .iter()
+            .all(|segment| matches!(segment, Segment::Store(..))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differ_hash_references() -> std::io::Result<()> {
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+
+        // the receiver's chunk store, holding the old chunks keyed by their hashes
+        let mut slicer = make_slicer(&config).unwrap();
+        slicer.process(&old_bytes);
+        let mut store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+        let mut chunk_start: u64 = 0;
+        for chunk in slicer.finalize().unwrap() {
+            store.insert(
+                chunk.hash.clone(),
+                old_bytes[buffer_range(&(chunk_start..chunk.end))].to_vec(),
+            );
+            chunk_start = chunk.end;
+        }
+
+        let mut differ = Differ::from_config(config);
+        differ.enable_hash_references();
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let segments = differ.finalize();
+        assert!(segments.iter().any(|segment| matches!(segment, Segment::Store(..))));
+
+        let mut patched: Vec<u8> = Vec::with_capacity(new_bytes.len());
+        for segment in segments {
+            match segment {
+                Segment::Store(hash, size) => {
+                    let chunk = &store[&hash];
+                    assert_eq!(chunk.len() as u64, size);
+                    patched.extend_from_slice(chunk);
+                }
+                Segment::New(range) => patched.extend_from_slice(&new_bytes[buffer_range(&range)]),
+                Segment::Old(_) => panic!("no Old segments expected"),
+            }
+        }
+        assert_eq!(patched, new_bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differ_invalid_config() {
+        let mut config = DifferConfig::default();
+        config.chunking.window_size = 48;
+        assert_eq!(
+            Differ::try_from_config(config).err(),
+            Some(SlicerError::InvalidWindowSize(48))
+        );
+    }
+
+    #[test]
+    fn test_differ_try_new() {
+        let error = Differ::try_new(Some(33), Some(64), Some(1024), Some(255), None).err();
+        assert_eq!(error, Some(SlicerError::InvalidWindowSize(33)));
+        assert_eq!(error.unwrap().to_string(), "window_size (33) must be a power of 2");
+        let error = Differ::try_new(Some(0), Some(64), Some(1024), Some(255), None).err();
+        assert_eq!(error, Some(SlicerError::InvalidWindowSize(0)));
+
+        // min_chunk_size is independent of the window size
+        assert!(Differ::try_new(Some(64), Some(32), Some(1024), Some(255), None).is_ok());
+        assert!(Differ::try_new(Some(64), Some(64), Some(1024), Some(255), None).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid Differ config: window_size (33) must be a power of 2")]
+    fn test_differ_new_invalid_window_size() {
+        let _ = Differ::new(Some(33), Some(64), Some(1024), Some(255), None);
+    }
+
+    #[test]
+    fn test_differ_new_defaults() {
+        let old: Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        new[50_000] ^= 0xff;
+
+        let config = *Differ::new(None, None, None, None, None).config().unwrap();
+        assert!(is_power_of_two(config.chunking.window_size));
+        assert!(config.chunking.window_size as usize <= config.chunking.min_chunk_size);
+
+        let mut differ = Differ::new(None, None, None, None, None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let segments = differ.finalize();
+        let reused: u64 = segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Old(_)))
+            .map(|segment| segment.len())
+            .sum();
+        assert!(reused > 80_000);
+        let patched: Vec<u8> =
+            crate::patcher::reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_differ_new_lcs_algorithm() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        for lcs_algorithm in [LcsAlgorithm::Nakatsu, LcsAlgorithm::HuntSzymanski] {
+            let mut differ = Differ::new(
+                Some(64),
+                Some(2048),
+                Some(8192),
+                Some((1 << 12) - 1),
+                Some(lcs_algorithm),
+            );
+            assert_eq!(differ.config().unwrap().lcs_algorithm, Some(lcs_algorithm));
+            differ.process_old(&old);
+            differ.process_new(&new);
+            let segments = differ.finalize();
+            assert!(segments.iter().any(|segment| matches!(segment, Segment::Old(_))));
+            let patched: Vec<u8> =
+                crate::patcher::reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+            assert!(patched == new, "Wrong reconstruction with {:?}", lcs_algorithm);
+        }
+        let differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+        assert_eq!(differ.config().unwrap().lcs_algorithm, Some(LcsAlgorithm::Nakatsu));
+    }
+
+    #[test]
+    fn test_differ_weighted() -> std::io::Result<()> {
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+        let reused_bytes = |lcs_algorithm: LcsAlgorithm| -> (u64, Vec<u8>) {
+            let mut config = DifferConfig {
+                lcs_algorithm: Some(lcs_algorithm),
+                ..DifferConfig::default()
+            };
+            config.chunking.window_size = 64;
+            let mut differ = Differ::from_config(config);
+            differ.process_old(&old_bytes);
+            differ.process_new(&new_bytes);
+            let segments = differ.finalize();
+            let reused = segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::Old(_)))
+                .map(|segment| segment.len())
+                .sum();
+            let patched = segments
+                .iter()
+                .flat_map(|segment| match segment {
+                    Segment::Old(range) => &old_bytes[buffer_range(range)],
+                    Segment::New(range) => &new_bytes[buffer_range(range)],
+                    Segment::Store(..) => unreachable!(),
+                })
+                .copied()
+                .collect();
+            (reused, patched)
+        };
+
+        let (weighted_reused, weighted_patched) = reused_bytes(LcsAlgorithm::Weighted);
+        let (reused, _) = reused_bytes(LcsAlgorithm::Nakatsu);
+        assert_eq!(weighted_patched, new_bytes);
+        assert!(weighted_reused >= reused);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differ_memory_budget() -> std::io::Result<()> {
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+        let parameters = ChunkingParameters {
+            window_size: 64,
+            min_chunk_size: 2048,
+            max_chunk_size: 8192,
+            boundary_mask: (1 << 12) - 1,
+        };
+        let chunk_count = |data: &[u8]| {
+            let config = DifferConfig {
+                chunking: parameters,
+                ..DifferConfig::default()
+            };
+            let mut slicer = make_slicer(&config).unwrap();
+            slicer.process(data);
+            slicer.finalize().unwrap().len()
+        };
+        let matrix_size = nakatsu_memory(chunk_count(&old_bytes), chunk_count(&new_bytes));
+
+        // the budget just fitting the Nakatsu matrix and the one just below it (Kumar)
+        for memory_budget in [matrix_size, matrix_size - 1] {
+            let mut differ = Differ::new_with_memory_budget(memory_budget, parameters);
+            differ.process_old(&old_bytes);
+            differ.process_new(&new_bytes);
+            let segments = differ.finalize();
+            assert!(segments.iter().any(|segment| matches!(segment, Segment::Old(_))));
+            let patched: Vec<u8> = segments
+                .iter()
+                .flat_map(|segment| match segment {
+                    Segment::Old(range) => &old_bytes[buffer_range(range)],
+                    Segment::New(range) => &new_bytes[buffer_range(range)],
+                    Segment::Store(..) => unreachable!(),
+                })
+                .copied()
+                .collect();
+            assert_eq!(patched, new_bytes);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differ_bidirectional() -> std::io::Result<()> {
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+        let patch = |source: &[u8], target: &[u8], segments: &[Segment]| -> Vec<u8> {
+            segments
+                .iter()
+                .flat_map(|segment| match segment {
+                    Segment::Old(range) => &source[buffer_range(range)],
+                    Segment::New(range) => &target[buffer_range(range)],
+                    Segment::Store(..) => unreachable!(),
+                })
+                .copied()
+                .collect()
+        };
+
+        let (forward, reverse) = Differ::diff_bidirectional(
+            &old_bytes[..],
+            &new_bytes[..],
+            Some(64),
+            Some(2048),
+            Some(8192),
+            Some((1 << 12) - 1),
+        )
+        .unwrap();
+        assert_eq!(
+            forward,
+            Differ::diff(
+                &old_bytes[..],
+                &new_bytes[..],
+                Some(64),
+                Some(2048),
+                Some(8192),
+                Some((1 << 12) - 1)
+            )
+            .unwrap()
+        );
+        assert_eq!(patch(&old_bytes, &new_bytes, &forward), new_bytes);
+        // the reverse delta recreates the old data from the new one
+        assert_eq!(patch(&new_bytes, &old_bytes, &reverse), old_bytes);
+        // both reuse the same (common) chunks
+        let reused = |segments: &[Segment]| -> u64 {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::Old(_)))
+                .map(Segment::len)
+                .sum()
+        };
+        assert_eq!(reused(&forward), reused(&reverse));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_differ_raw_hashes() {
+        let old_string = "What a a year in the blockchain sphere. It's also been quite a year for Equilibrium and I thought I'd recap everything that has happened in the company.";
+        let new_string = "It's been a year in the blockchain sphere. It's also been quite a year for Equilibrium. I thought I'd recap everything that has happened in the company with a Year In Review post.";
+        let diff = |raw_hash_threshold: usize| {
+            let mut differ = Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+            differ.set_raw_hash_threshold(raw_hash_threshold);
+            differ.process_old(old_string.as_bytes());
+            differ.process_new(new_string.as_bytes());
+            differ.finalize()
+        };
+        // the raw chunk bytes are used below the threshold, the digests above it
+        assert_eq!(diff(16), diff(15));
+    }
+
+    #[test]
+    fn test_differ_chunk_spilling() -> std::io::Result<()> {
+        let directory = std::env::temp_dir().join(format!("differ_chunk_spilling_{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let old = std::fs::read("./example/monkey_before.tiff")?;
+        let new = std::fs::read("./example/monkey_after.tiff")?;
+        let diff = |spill_threshold: Option<usize>| -> std::io::Result<Vec<Segment>> {
+            let mut differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+            if let Some(spill_threshold) = spill_threshold {
+                differ.enable_chunk_spilling(spill_threshold, &directory)?;
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            Ok(differ.finalize())
+        };
+
+        let segments = diff(None)?;
+        for spill_threshold in [1, 7, 1 << 20] {
+            assert_eq!(diff(Some(spill_threshold))?, segments);
+        }
+        // the spill files have been removed
+        assert_eq!(std::fs::read_dir(&directory)?.count(), 0);
+
+        std::fs::remove_dir_all(&directory)
+    }
+
+    #[test]
+    fn test_differ_lcs_deadline() {
+        // large inputs made of few distinct chunks, hence with a quadratic number of
+        // matching chunk pairs
+        let old: Vec<u8> = (0..1 << 20).map(|i: u32| ((i / 16) % 7) as u8).collect();
+        let new: Vec<u8> = (0..1 << 20).map(|i: u32| ((i / 16) % 5) as u8).collect();
+        let mut differ = Differ::new(Some(4), Some(16), Some(16), Some(0), None);
+        differ.lcs_algorithm = Some(LcsAlgorithm::HuntSzymanski);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let start = Instant::now();
+        differ.set_lcs_deadline(start + Duration::from_millis(10));
+        let (segments, lcs_skipped) = differ.finalize_with_lcs_status();
+        assert!(lcs_skipped);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the trivial delta is still valid
+        assert_eq!(segments, vec![Segment::New(0..new.len() as u64)]);
+
+        // a deadline far enough is never hit
+        let mut differ = Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+        differ.set_lcs_deadline(Instant::now() + Duration::from_secs(3600));
+        differ.process_old("It's also been quite a year for Equilibrium.".as_bytes());
+        differ.process_new("It's been quite a year for Equilibrium.".as_bytes());
+        let (segments, lcs_skipped) = differ.finalize_with_lcs_status();
+        assert!(!lcs_skipped);
+        assert!(segments.iter().any(|segment| matches!(segment, Segment::Old(_))));
+    }
+
+    // hashes a chunk as its first byte only, so that chunks collide at will
+    struct FirstByteHasher(Option<u8>);
+
+    impl Hasher for FirstByteHasher {
+        fn push(&mut self, byte: u8) {
+            self.0.get_or_insert(byte);
+        }
+
+        fn finalize(&mut self) -> Vec<u8> {
+            self.0.take().into_iter().collect()
+        }
+    }
+
+    #[test]
+    fn test_differ_collision_policy() {
+        // 16 fixed-size chunks with distinct first bytes; the new data keeps the first 8 of
+        // them and replaces the others with distinct chunks sharing their first bytes
+        let chunk = |first_byte: u8, fill: u8| -> Vec<u8> {
+            let mut chunk = vec![fill; 64];
+            chunk[0] = first_byte;
+            chunk
+        };
+        let old: Vec<u8> = (0..16).flat_map(|index| chunk(index, 1)).collect();
+        let new: Vec<u8> = (0..16)
+            .flat_map(|index| chunk(index, if index < 8 { 1 } else { 2 }))
+            .collect();
+        let diff = |policy: Option<CollisionPolicy>| {
+            let make_slicer = || {
+                Slicer::new(MovingSumRollingHasher::new(16), FirstByteHasher(None), 0, 64, 64)
+                    .unwrap()
+            };
+            let mut differ = Differ::with_slicers(make_slicer(), make_slicer());
+            if let Some(policy) = policy {
+                differ.set_collision_policy(policy);
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            let segments = differ.finalize();
+            let mut patched: Vec<u8> = Vec::with_capacity(new.len());
+            for segment in &segments {
+                patched.extend_from_slice(match segment {
+                    Segment::Old(range) => &old[buffer_range(range)],
+                    Segment::New(range) => &new[buffer_range(range)],
+                    Segment::Store(..) => unreachable!(),
+                });
+            }
+            (segments, patched)
+        };
+
+        // the colliding chunks are New, the identical ones are still reused
+        let expected_segments = vec![Segment::Old(0..512), Segment::New(512..1024)];
+        assert_eq!(diff(None), (expected_segments.clone(), new.clone()));
+        assert_eq!(diff(Some(CollisionPolicy::TreatAsNew)), (expected_segments, new.clone()));
+
+        // trusting the hash reuses the colliding old chunks, the patched data is wrong
+        let (segments, patched) = diff(Some(CollisionPolicy::TrustHash));
+        assert_eq!(segments, vec![Segment::Old(0..1024)]);
+        assert_eq!(patched, old);
+        assert_ne!(patched, new);
+    }
+
+    #[test]
+    fn test_differ_disjoint() {
+        // a completely rewritten input, with no chunk in common with the old one
+        let old: Vec<u8> = (0..1 << 16).map(|i: u32| (i * 7919 % 251) as u8).collect();
+        let new: Vec<u8> = (0..1 << 16).map(|i: u32| (i * 7907 % 241) as u8 ^ 0x5a).collect();
+        let mut differ = Differ::new(Some(16), Some(256), Some(1024), Some((1 << 8) - 1), None);
+        differ.lcs_algorithm = Some(LcsAlgorithm::HuntSzymanski);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        // the deadline has already passed, yet the disjointness check skips the LCS sweep
+        differ.set_lcs_deadline(Instant::now() - Duration::from_secs(1));
+        let (segments, lcs_skipped) = differ.finalize_with_lcs_status();
+        assert!(!lcs_skipped);
+        assert_eq!(segments, vec![Segment::New(0..new.len() as u64)]);
+    }
+
+    #[test]
+    fn test_differ_lcs_hash_prefix() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let diff = |prefix_len: Option<usize>| {
+            let mut differ = Differ::new(Some(64), Some(1024), Some(8192), Some((1 << 11) - 1), None);
+            if let Some(prefix_len) = prefix_len {
+                differ.set_lcs_hash_prefix_len(prefix_len);
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            differ.finalize()
+        };
+        let segments = diff(None);
+        assert!(segments.iter().any(|segment| matches!(segment, Segment::Old(_))));
+        assert_eq!(diff(Some(8)), segments);
+        // longer than the digest, the whole hashes are compared
+        assert_eq!(diff(Some(64)), segments);
+    }
+
+    #[test]
+    fn test_differ_artifacts() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let mut differ = Differ::new(Some(64), Some(1024), Some(8192), Some((1 << 11) - 1), None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+        assert_eq!(artifacts.delta(), segments);
+        assert_eq!(artifacts.delta_with_min_reuse(0), segments);
+
+        // both deltas built from the same LCS reconstruct the new data
+        let reconstruct = |segments: &[Segment]| -> Vec<u8> {
+            segments
+                .iter()
+                .flat_map(|segment| match segment {
+                    Segment::Old(range) => &old[buffer_range(range)],
+                    Segment::New(range) => &new[buffer_range(range)],
+                    Segment::Store(..) => panic!("Unexpected Store segment"),
+                })
+                .copied()
+                .collect()
+        };
+        let old_segment_count = |segments: &[Segment]| {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::Old(_)))
+                .count()
+        };
+        // drops (at least) the shortest reused range
+        let min_reuse = segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Old(_)))
+            .map(|segment| segment.len() + 1)
+            .min()
+            .unwrap();
+        let pruned_segments = artifacts.delta_with_min_reuse(min_reuse);
+        assert_eq!(reconstruct(&segments), new);
+        assert_eq!(reconstruct(&pruned_segments), new);
+        assert!(old_segment_count(&pruned_segments) < old_segment_count(&segments));
+        assert!(pruned_segments.iter().all(|segment| match segment {
+            Segment::Old(range) => range.end - range.start >= min_reuse,
+            _ => true,
+        }));
+
+        // identical inputs skip the LCS, yet the artifacts hold it
+        let mut differ = Differ::new(Some(64), Some(1024), Some(8192), Some((1 << 11) - 1), None);
+        differ.process_old(&old);
+        differ.process_new(&old);
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+        assert_eq!(artifacts.lcs.len(), artifacts.chunks_new.len());
+        assert_eq!(artifacts.delta(), segments);
+    }
+
+    #[test]
+    fn test_differ_common_header() {
+        let header_len: u64 = 1024;
+        let header: Vec<u8> = (0..header_len).map(|i| (i * 7919 % 251) as u8).collect();
+        let tail: Vec<u8> = (0..16384u32).map(|i| (i * 31 % 253 + i / 253) as u8).collect();
+        let old = [&header[..], &tail[..]].concat();
+        let mut new = old.clone();
+        new[header.len()..header.len() + 16].fill(0); // the tail changes right after the header
+
+        let mut differ = Differ::new(Some(16), Some(64), Some(1024), Some((1 << 8) - 1), None);
+        differ.set_common_header_len(header_len);
+        // the header spans several buffers
+        for buffer in old.chunks(300) {
+            differ.process_old(buffer);
+        }
+        for buffer in new.chunks(500) {
+            differ.process_new(buffer);
+        }
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+        assert_eq!(segments[0], Segment::Old(0..header_len));
+        assert!(matches!(segments[1], Segment::New(_)));
+        let patched: Vec<u8> = segments
+            .iter()
+            .flat_map(|segment| match segment {
+                Segment::Old(range) => &old[buffer_range(range)],
+                Segment::New(range) => &new[buffer_range(range)],
+                Segment::Store(..) => panic!("Unexpected Store segment"),
+            })
+            .copied()
+            .collect();
+        assert_eq!(patched, new);
+
+        // only the tails have been sliced
+        assert_eq!(artifacts.chunks_old.last().unwrap().end, tail.len() as u64);
+        assert_eq!(artifacts.chunks_new.last().unwrap().end, tail.len() as u64);
+
+        // identical inputs are a single Old segment, header included
+        let mut differ = Differ::new(Some(16), Some(64), Some(1024), Some((1 << 8) - 1), None);
+        differ.set_common_header_len(header_len);
+        differ.process_old(&old);
+        differ.process_new(&old);
+        assert_eq!(differ.finalize(), vec![Segment::Old(0..old.len() as u64)]);
+
+        // inputs shorter than the header
+        let mut differ = Differ::new(Some(16), Some(64), Some(1024), Some((1 << 8) - 1), None);
+        differ.set_common_header_len(header_len);
+        differ.process_old(&old[..100]);
+        differ.process_new(&old[..200]);
+        assert_eq!(differ.finalize(), vec![Segment::Old(0..100), Segment::New(100..200)]);
+    }
+
+    #[test]
+    fn test_differ_contiguous_reuse() {
+        // the first block occurs twice in the old data, only its second occurrence is
+        // followed by the next block; fixed-size chunks keep the blocks' chunks aligned
+        let block = |seed: u32| -> Vec<u8> {
+            (0..4096u32).map(|i| (i * seed % 251 + i / 251) as u8).collect()
+        };
+        let old = [block(31), block(37), block(31), block(41)].concat();
+        let new = [block(31), block(41)].concat();
+        let diff = |contiguous_reuse: bool| {
+            let mut differ = Differ::new(Some(16), Some(64), Some(64), Some(0), None);
+            if contiguous_reuse {
+                differ.enable_contiguous_reuse();
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            differ.finalize()
+        };
+        let old_ranges = |segments: &[Segment]| -> Vec<std::ops::Range<u64>> {
+            segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::Old(range) => Some(range.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let segments = diff(true);
+        assert_eq!(old_ranges(&segments), vec![8192..16384]);
+        assert_eq!(old_ranges(&diff(false)), vec![0..4096, 12288..16384]);
+    }
+
+    #[test]
+    fn test_differ_moved_reuse() {
+        // two swapped blocks; fixed-size chunks keep the blocks' chunks aligned
+        let block = |seed: u32| -> Vec<u8> {
+            (0..4096u32).map(|i| (i * seed % 251 + i / 251) as u8).collect()
+        };
+        let old = [block(31), block(37), block(41), block(43)].concat();
+        let new = [block(31), block(41), block(37), block(43)].concat();
+        let diff = |moved_reuse: bool| {
+            let mut differ = Differ::new(Some(16), Some(64), Some(64), Some(0), None);
+            if moved_reuse {
+                differ.enable_moved_reuse();
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            differ.finalize()
+        };
+        let new_bytes = |segments: &[Segment]| -> u64 {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::New(_)))
+                .map(|segment| segment.len())
+                .sum()
+        };
+
+        // the common subsequence only keeps one of the swapped blocks
+        assert_eq!(new_bytes(&diff(false)), 4096);
+        let segments = diff(true);
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Old(0..4096),
+                Segment::Old(8192..12288),
+                Segment::Old(4096..8192),
+                Segment::Old(12288..16384)
+            ]
+        );
+        let patched: Vec<u8> = crate::patcher::reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_differ_similarity() {
+        // the artifacts of chunk sizes, each chunk being distinct, and of the common chunks
+        let artifacts = |old_sizes: &[u64], new_sizes: &[u64], common: &[u8]| {
+            let chunks = |sizes: &[u64], first_hash: u8| -> Vec<Chunk> {
+                let mut end: u64 = 0;
+                (first_hash..)
+                    .zip(sizes)
+                    .map(|(hash, size)| {
+                        end += size;
+                        let hash = if common.contains(&hash) { hash } else { hash + 100 };
+                        Chunk { hash: vec![hash], end }
+                    })
+                    .collect()
+            };
+            DiffArtifacts {
+                chunks_old: chunks(old_sizes, 0),
+                chunks_new: chunks(new_sizes, 0),
+                lcs: common.iter().map(|&hash| vec![hash]).collect(),
+            }
+        };
+
+        // ten tiny chunks in common, a large one changed: similar by count, not by bytes
+        let mut sizes = vec![10; 10];
+        sizes.push(10000);
+        let tiny_matches = artifacts(&sizes, &sizes, &(0..10).collect::<Vec<u8>>());
+        assert!((tiny_matches.similarity() - 20.0 / 22.0).abs() < 1e-6);
+        assert!((tiny_matches.weighted_similarity() - 100.0 / 10100.0).abs() < 1e-6);
+
+        // a large chunk in common, ten tiny ones changed: the other way round
+        let large_match = artifacts(&sizes, &sizes, &[10]);
+        assert!((large_match.similarity() - 2.0 / 22.0).abs() < 1e-6);
+        assert!((large_match.weighted_similarity() - 10000.0 / 10100.0).abs() < 1e-6);
+
+        assert_eq!(artifacts(&[], &[], &[]).similarity(), 1.0);
+        assert_eq!(artifacts(&[10], &[], &[]).weighted_similarity(), 1.0);
+        assert_eq!(artifacts(&[10], &[10], &[]).weighted_similarity(), 0.0);
+    }
+
+    #[test]
+    fn test_differ_explain() {
+        // two swapped blocks and an added one; fixed-size chunks keep the blocks' chunks aligned
+        let block = |seed: u32| -> Vec<u8> {
+            (0..4096u32).map(|i| (i * seed % 251 + i / 251) as u8).collect()
+        };
+        let old = [block(31), block(37), block(41)].concat();
+        let new = [block(31), block(41), block(37), block(43)].concat();
+        let mut differ = Differ::new(Some(16), Some(64), Some(64), Some(0), None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+
+        // one of the swapped blocks is reused, the other one is out of order
+        let (reused_block, unmatched_block) = if segments.contains(&Segment::Old(4096..8192)) {
+            (4096..8192, 8192..12288)
+        } else {
+            (8192..12288, 4096..8192)
+        };
+        // the new offset of a swapped block's old offset
+        let new_offset_in_old = |old_offset: u64| if old_offset < 8192 { old_offset + 4096 } else { old_offset - 4096 };
+        assert_eq!(
+            artifacts.explain(100),
+            Some(Explanation::Reused { new_chunk: 64..128, old_chunk: 64..128 })
+        );
+        let old_chunk = reused_block.start + 960..reused_block.start + 1024;
+        assert_eq!(
+            artifacts.explain(new_offset_in_old(reused_block.start + 1000)),
+            Some(Explanation::Reused {
+                new_chunk: new_offset_in_old(old_chunk.start)..new_offset_in_old(old_chunk.end),
+                old_chunk,
+            })
+        );
+        // the out-of-order chunk is found at its (single) old position
+        let old_chunk = unmatched_block.start + 960..unmatched_block.start + 1024;
+        assert_eq!(
+            artifacts.explain(new_offset_in_old(unmatched_block.start + 1000)),
+            Some(Explanation::Unmatched {
+                new_chunk: new_offset_in_old(old_chunk.start)..new_offset_in_old(old_chunk.end),
+                old_chunks: vec![old_chunk],
+            })
+        );
+        assert_eq!(
+            artifacts.explain(12288),
+            Some(Explanation::Absent { new_chunk: 12288..12352 })
+        );
+        assert_eq!(artifacts.explain(16384), None);
+    }
+
+    #[test]
+    fn test_differ_anchored_lcs() {
+        let old = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let new = std::fs::read("./example/monkey_after.tiff").unwrap();
+        let diff = |anchored_lcs: bool, parallelism: Option<usize>| {
+            let mut differ = Differ::new(Some(64), Some(512), Some(4096), Some((1 << 10) - 1), None);
+            if anchored_lcs {
+                differ.enable_anchored_lcs();
+            }
+            if let Some(parallelism) = parallelism {
+                differ.set_parallelism(parallelism);
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            differ.finalize()
+        };
+        // the same delta whatever the number of threads, sequential included
+        let segments = diff(false, None);
+        for parallelism in [Some(1), Some(4), None] {
+            assert_eq!(diff(true, parallelism), segments);
+            assert_eq!(diff(false, parallelism), segments);
+        }
+    }
+
+    #[test]
+    fn test_differ_finalize_unprocessed() {
+        let differ = Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+        assert_eq!(differ.finalize(), vec![]);
+
+        let (segments, reverse_segments) =
+            Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None).finalize_bidirectional();
+        assert!(segments.is_empty() && reverse_segments.is_empty());
+    }
+
+    #[test]
+    fn test_differ_empty_process() {
+        let old_string = "What a a year in the blockchain sphere. It's also been quite a year for Equilibrium and I thought I'd recap everything that has happened in the company.";
+        let new_string = "It's been a year in the blockchain sphere. It's also been quite a year for Equilibrium. I thought I'd recap everything that has happened in the company with a Year In Review post.";
+        let make_differ = || Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+
+        let mut differ = make_differ();
+        differ.process_old(old_string.as_bytes());
+        differ.process_new(new_string.as_bytes());
+        let expected_segments = differ.finalize();
+
+        // empty buffers interleaved with the real ones
+        let mut differ = make_differ();
+        let mut old_buffers = old_string.as_bytes().chunks(7);
+        let mut new_buffers = new_string.as_bytes().chunks(5);
+        loop {
+            let (old_bytes, new_bytes) = match (old_buffers.next(), new_buffers.next()) {
+                (None, None) => break,
+                (old_bytes, new_bytes) => (old_bytes.unwrap_or(&[]), new_bytes.unwrap_or(&[])),
+            };
+            differ.process_old(&[]);
+            differ.process_old(old_bytes);
+            differ.process_new(new_bytes);
+            differ.process_new(&[]);
+        }
+        assert_eq!(differ.finalize(), expected_segments);
+    }
+
+    #[test]
+    fn test_differ_one_sided() {
+        let data = "It's been a year in the blockchain sphere. It's also been quite a year for Equilibrium.".as_bytes();
+        let data_len = data.len() as u64;
+        let make_differ = || {
+            let mut differ = Differ::new(Some(4), Some(4), Some(16), Some((1 << 3) - 1), None);
+            differ.enable_anchored_lcs();
+            differ.enable_moved_reuse();
+            differ
+        };
+
+        // only the old data: everything is deleted, the new data is empty
+        let mut differ = make_differ();
+        differ.set_common_header_len(8);
+        for buffer in data.chunks(10) {
+            differ.process_old(buffer);
+        }
+        assert_eq!(differ.finalize_bidirectional(), (vec![], vec![Segment::New(0..data_len)]));
+        let mut differ = make_differ();
+        differ.process_old(data);
+        let mut stats = DedupStats::new();
+        assert_eq!(differ.finalize_with_stats(&mut stats), vec![]);
+        assert_eq!(stats.bytes_seen(), 0);
+
+        // only the new data: everything is new
+        let mut differ = make_differ();
+        differ.set_common_header_len(8);
+        for buffer in data.chunks(10) {
+            differ.process_new(buffer);
+        }
+        assert_eq!(differ.finalize_bidirectional(), (vec![Segment::New(0..data_len)], vec![]));
+        let mut differ = make_differ();
+        differ.process_new(data);
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+        assert_eq!(segments, vec![Segment::New(0..data_len)]);
+        assert!(matches!(artifacts.explain(0), Some(Explanation::Absent { .. })));
+    }
+
+    #[test]
+    fn test_differ_interleaved() {
+        let old: Vec<u8> = (0..20000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut new = old.clone();
+        new.drain(5000..6000);
+        new[12000..12100].fill(0);
+        let make_differ = || Differ::new(Some(16), Some(64), Some(1024), Some((1 << 8) - 1), None);
+
+        let mut differ = make_differ();
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let expected = differ.finalize();
+
+        // the new data first, then the old one
+        let mut differ = make_differ();
+        differ.process_new(&new);
+        differ.process_old(&old);
+        assert_eq!(differ.finalize(), expected);
+
+        // buffers of different sizes interleaved unevenly, the Differ moved across threads
+        let mut differ = make_differ();
+        let mut old_buffers = old.chunks(700);
+        let mut new_buffers = new.chunks(300);
+        for feed_old in [true, false, false].into_iter().cycle() {
+            match (feed_old, if feed_old { old_buffers.next() } else { new_buffers.next() }) {
+                (true, Some(buffer)) => differ.process_old(buffer),
+                (false, Some(buffer)) => differ.process_new(buffer),
+                (_, None) => break,
+            }
+        }
+        old_buffers.for_each(|buffer| differ.process_old(buffer));
+        new_buffers.for_each(|buffer| differ.process_new(buffer));
+        let segments = thread::spawn(move || differ.finalize()).join().unwrap();
+        assert_eq!(segments, expected);
+    }
+
+    #[test]
+    fn test_differ_files() -> std::io::Result<()> {
+        // avg chunk size 16
+        let window_size: u32 = 64;
+        let min_chunk_size: usize = 2048;
+        let max_chunk_size: usize = 8192;
         let boundary_mask: u32 = (1 << 12) - 1; // avg chunk size is 2^12 = 4096
         let mut differ = Differ::new(
             Some(window_size),
             Some(min_chunk_size),
             Some(max_chunk_size),
             Some(boundary_mask),
+            None,
         );
         
         // process old and new files
@@ -306,6 +2582,7 @@ mod tests {
         _ = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open("./example/monkey_edits.txt")?
             .write(segments_text.as_bytes())?;
     
@@ -334,4 +2611,220 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_differ_max_new_segment_size() {
+        let pseudo_random = |len: u32, seed: u32| -> Vec<u8> {
+            (0..len).map(|i| ((i ^ seed).wrapping_mul(2654435761) >> 13) as u8).collect()
+        };
+        // a 200KB block inserted in the middle
+        let old = pseudo_random(65536, 0);
+        let inserted = pseudo_random(200000, 0x5a5a5a5a);
+        let new = [&old[..32768], &inserted[..], &old[32768..]].concat();
+        let max_size: u64 = 32768;
+
+        let diff = |max_new_segment_size: Option<u64>| {
+            let mut differ = Differ::new(Some(64), Some(2048), Some(8192), Some((1 << 12) - 1), None);
+            if let Some(max_size) = max_new_segment_size {
+                differ.set_max_new_segment_size(max_size);
+            }
+            differ.process_old(&old);
+            differ.process_new(&new);
+            differ.finalize_with_artifacts()
+        };
+        let new_segments = |segments: &[Segment]| -> Vec<Range<u64>> {
+            segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::New(range) => Some(range.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // the inserted block makes a single huge New segment
+        let (segments, _) = diff(None);
+        assert!(new_segments(&segments).iter().any(|range| range.end - range.start > 200000));
+
+        // under the cap, it's split into chunk-aligned pieces
+        let (capped_segments, artifacts) = diff(Some(max_size));
+        let boundaries: HashSet<u64> = artifacts.chunks_new.iter().map(|chunk| chunk.end).collect();
+        let pieces = new_segments(&capped_segments);
+        assert!(pieces.len() >= 7);
+        for piece in &pieces {
+            assert!(piece.end - piece.start <= max_size);
+            assert!(piece.start == 0 || boundaries.contains(&piece.start));
+            assert!(boundaries.contains(&piece.end));
+        }
+        // the pieces only split the New segments, covering the same bytes
+        assert_eq!(
+            pieces.iter().map(|range| range.end - range.start).sum::<u64>(),
+            new_segments(&segments).iter().map(|range| range.end - range.start).sum::<u64>()
+        );
+        let patched: Vec<u8> = crate::patcher::reconstruct_iter(&old, &new, &capped_segments)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_differ_diff_pair_with_params() {
+        let pseudo_random = |len: u32, seed: u32| -> Vec<u8> {
+            (0..len).map(|i| ((i ^ seed).wrapping_mul(2654435761) >> 13) as u8).collect()
+        };
+        let edit = |old: &[u8], at: usize| -> Vec<u8> {
+            [&old[..at], &pseudo_random(1000, 0x5a5a5a5a)[..], &old[at..]].concat()
+        };
+        // a small and a large file, needing different chunk sizes
+        let small_old = pseudo_random(20000, 1);
+        let small_new = edit(&small_old, 5000);
+        let large_old = pseudo_random(1 << 20, 2);
+        let large_new = edit(&large_old, 500000);
+        let small_parameters = ChunkingParameters {
+            window_size: 16,
+            min_chunk_size: 128,
+            max_chunk_size: 2048,
+            boundary_mask: (1 << 9) - 1,
+        };
+        let large_parameters = ChunkingParameters::auto(large_old.len() as u64);
+
+        let independent = |old: &[u8], new: &[u8], parameters: ChunkingParameters| {
+            let mut differ = Differ::new(
+                Some(parameters.window_size),
+                Some(parameters.min_chunk_size),
+                Some(parameters.max_chunk_size),
+                Some(parameters.boundary_mask),
+                None,
+            );
+            differ.process_old(old);
+            differ.process_new(new);
+            differ.finalize()
+        };
+        let mut differ = Differ::new(Some(64), None, None, None, None);
+        for (old, new, parameters) in [
+            (&small_old, &small_new, small_parameters),
+            (&large_old, &large_new, large_parameters),
+            (&small_old, &small_new, small_parameters),
+        ] {
+            let segments = differ.diff_pair_with_params(&old[..], &new[..], parameters).unwrap();
+            assert_eq!(segments, independent(old, new, parameters));
+            assert_eq!(differ.config().unwrap().chunking, parameters);
+            let patched: Vec<u8> =
+                crate::patcher::reconstruct_iter(old, new, &segments).flatten().copied().collect();
+            assert_eq!(patched, *new);
+        }
+
+        let assert_slicer_error = |result: std::io::Result<Vec<Segment>>, expected: SlicerError| {
+            let error = result.unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+            assert_eq!(error.get_ref().unwrap().downcast_ref::<SlicerError>(), Some(&expected));
+        };
+
+        // invalid parameters are reported, the differ can still be reused
+        let invalid = ChunkingParameters {
+            window_size: 15,
+            ..small_parameters
+        };
+        assert_slicer_error(
+            differ.diff_pair_with_params(&small_old[..], &small_new[..], invalid),
+            SlicerError::InvalidWindowSize(15),
+        );
+        let segments = differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters);
+        assert_eq!(segments.unwrap(), independent(&small_old, &small_new, small_parameters));
+
+        // so is a failed read, the partial pair being dropped
+        let error = differ
+            .diff_pair_with_params(&small_old[..], "./example/missing_file", small_parameters)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        let segments = differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters);
+        assert_eq!(segments.unwrap(), independent(&small_old, &small_new, small_parameters));
+
+        // the data processed but not finalized isn't discarded, nor is normalized chunking
+        let mut differ = Differ::new(Some(64), None, None, None, None);
+        differ.process_old(&small_old);
+        assert_slicer_error(
+            differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters),
+            SlicerError::UnfinalizedData,
+        );
+        let mut differ = Differ::new(Some(64), None, None, None, None);
+        differ.set_normalized_chunking((1 << 14) - 1, (1 << 10) - 1, 4096);
+        assert_slicer_error(
+            differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters),
+            SlicerError::NormalizedChunkingReset,
+        );
+
+        // the common header applies to each pair
+        let mut differ = Differ::new(Some(64), None, None, None, None);
+        differ.set_common_header_len(100);
+        let segments = differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters);
+        let mut expected_differ = Differ::try_from_config(*differ.config().unwrap()).unwrap();
+        expected_differ.set_common_header_len(100);
+        expected_differ.process_old(&small_old);
+        expected_differ.process_new(&small_new);
+        assert_eq!(segments.unwrap(), expected_differ.finalize());
+
+        // and the raw hash threshold to the chunk sizes of each pair
+        let mut differ = Differ::new(Some(64), None, None, None, None);
+        differ.set_raw_hash_threshold(small_parameters.max_chunk_size);
+        differ.diff_pair_with_params(&small_old[..], &small_new[..], small_parameters).unwrap();
+        assert!(differ.slicer_old.chunks().iter().any(|chunk| chunk.hash.len() != 32));
+        differ.diff_pair_with_params(&large_old[..], &large_new[..], large_parameters).unwrap();
+        assert!(differ.slicer_old.chunks().iter().all(|chunk| chunk.hash.len() == 32));
+    }
+
+    #[test]
+    fn test_differ_bidirectional_computed_once() -> std::io::Result<()> {
+        // counts the chunk digests, i.e. the chunks sliced
+        struct CountingHasher(Sha256Hasher, Arc<AtomicUsize>);
+        impl Hasher for CountingHasher {
+            fn push(&mut self, byte: u8) {
+                self.0.push(byte);
+            }
+            fn finalize(&mut self) -> Vec<u8> {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.finalize()
+            }
+        }
+
+        let old_bytes = std::fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+        let digests = Arc::new(AtomicUsize::new(0));
+        let make_slicer = || {
+            let rolling_hasher = PolynomialRollingHasher::new(64, None, None);
+            let hasher = CountingHasher(Sha256Hasher::new(8192), digests.clone());
+            Slicer::new(rolling_hasher, hasher, (1 << 12) - 1, 2048, 8192).unwrap()
+        };
+        let chunk_count = |bytes: &[u8]| {
+            let mut slicer = make_slicer();
+            slicer.process(bytes);
+            slicer.finalize().unwrap().len()
+        };
+        let chunk_count = chunk_count(&old_bytes) + chunk_count(&new_bytes);
+
+        digests.store(0, Ordering::Relaxed);
+        let lcs_calls = LCS_CALLS.with(|calls| calls.get());
+        let mut differ = Differ::with_slicers(make_slicer(), make_slicer());
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let (forward, reverse) = differ.finalize_bidirectional();
+
+        // each chunk has been hashed once and a single LCS has been computed for both deltas
+        assert_eq!(digests.load(Ordering::Relaxed), chunk_count);
+        assert_eq!(LCS_CALLS.with(|calls| calls.get()), lcs_calls + 1);
+
+        let patched: Vec<u8> = crate::patcher::reconstruct_iter(&old_bytes, &new_bytes, &forward)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(patched, new_bytes);
+        let patched: Vec<u8> = crate::patcher::reconstruct_iter(&new_bytes, &old_bytes, &reverse)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(patched, old_bytes);
+
+        Ok(())
+    }
 }
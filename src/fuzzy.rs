@@ -0,0 +1,149 @@
+/*
+    Fuzzy chunk reuse, for lossy-tolerant data (e.g. sensor streams or images) where a chunk
+    differing from an old one by a few bytes is close enough to be reused.
+
+    The pass revisits every New segment of a delta and matches its chunks against the old
+    chunks of the region it replaces (the bytes between the preceding and the following Old
+    segments). A new chunk is matched with an old chunk of the same length whose Hamming
+    distance (the number of differing bytes) doesn't exceed the threshold, and replaced with
+    an Old segment referring to the old chunk. The chunks are matched in order, so the Old
+    segments stay in ascending offset order.
+
+    The reconstruction is then only approximate: the patched data holds the old chunks'
+    bytes wherever a near-duplicate was reused, so it differs from the new data by up to
+    max_distance bytes per reused chunk. A threshold of 0 leaves the delta as it is.
+
+    Like the refinement, this needs random access to both old and new data, so it's a
+    separate pass over in-memory buffers.
+*/
+
+use crate::delta::*;
+use crate::refiner::*;
+use crate::slicer::*;
+use std::ops::Range;
+
+// the old chunks compared with each new chunk, bounding the cost on large regions
+const MAX_FUZZY_CANDIDATES: usize = 64;
+
+// Replaces the chunks of the New segments with Old ones referring to old chunks of the
+// same length differing by at most max_distance bytes
+pub fn fuzzy_reuse(
+    segments: Vec<Segment>,
+    chunks_old: &[Chunk],
+    chunks_new: &[Chunk],
+    old: &[u8],
+    new: &[u8],
+    max_distance: usize,
+) -> Vec<Segment> {
+    if max_distance == 0 {
+        return segments;
+    }
+    let mut fuzzy: Vec<Segment> = Vec::with_capacity(segments.len());
+    for (index, segment) in segments.iter().enumerate() {
+        let Segment::New(new_range) = segment else {
+            push_coalesced(&mut fuzzy, segment.clone());
+            continue;
+        };
+        let old_range = replaced_old_range(&segments, index, old.len() as u64);
+        let old_candidates = chunk_ranges(chunks_old, &old_range);
+        let mut old_position: usize = 0; // the first old candidate not matched yet
+        for new_chunk in chunk_ranges(chunks_new, new_range) {
+            let new_bytes = &new[buffer_range(&new_chunk)];
+            let matched = old_candidates[old_position..]
+                .iter()
+                .take(MAX_FUZZY_CANDIDATES)
+                .position(|old_chunk| {
+                    let old_bytes = &old[buffer_range(old_chunk)];
+                    old_bytes.len() == new_bytes.len()
+                        && hamming_distance(old_bytes, new_bytes) <= max_distance
+                });
+            match matched {
+                Some(offset) => {
+                    let old_chunk = &old_candidates[old_position + offset];
+                    push_coalesced(&mut fuzzy, Segment::Old(old_chunk.clone()));
+                    old_position += offset + 1;
+                }
+                None => push_coalesced(&mut fuzzy, Segment::New(new_chunk)),
+            }
+        }
+    }
+    fuzzy
+}
+
+// Returns the ranges of the chunks lying within the range (partially covered chunks are
+// left out, but the delta segments always start and end at chunk boundaries)
+fn chunk_ranges(chunks: &[Chunk], range: &Range<u64>) -> Vec<Range<u64>> {
+    let first = chunks.partition_point(|chunk| chunk.end <= range.start);
+    let mut chunk_start = if first == 0 { 0 } else { chunks[first - 1].end };
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    for chunk in &chunks[first..] {
+        if chunk.end > range.end {
+            break;
+        }
+        if chunk_start >= range.start {
+            ranges.push(chunk_start..chunk.end);
+        }
+        chunk_start = chunk.end;
+    }
+    ranges
+}
+
+// the number of positions at which the (equally long) byte strings differ
+fn hamming_distance(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).filter(|(a_byte, b_byte)| a_byte != b_byte).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differ::Differ;
+    use crate::patcher::reconstruct_iter;
+
+    #[test]
+    fn test_fuzzy_reuse() {
+        // fixed-size 1KiB chunks, three of them changed by 1, 2 and 100 bytes
+        let old: Vec<u8> = (0..65536u32).map(|i| (i * 7919 % 251 + i / 251) as u8).collect();
+        let mut new = old.clone();
+        new[5000] ^= 0xff;
+        new[20000] ^= 0xff;
+        new[20400] ^= 0xff;
+        for byte in &mut new[40000..40100] {
+            *byte ^= 0xff;
+        }
+
+        let mut differ = Differ::new(Some(64), Some(1024), Some(1024), Some(0), None);
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let (segments, artifacts) = differ.finalize_with_artifacts();
+        let fuzzy = |max_distance: usize| {
+            fuzzy_reuse(
+                segments.clone(),
+                &artifacts.chunks_old,
+                &artifacts.chunks_new,
+                &old,
+                &new,
+                max_distance,
+            )
+        };
+        let new_chunk_count = |segments: &[Segment]| -> u64 {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::New(_)))
+                .map(|segment| segment.len() / 1024)
+                .sum()
+        };
+        assert_eq!(new_chunk_count(&segments), 3);
+        assert_eq!(fuzzy(0), segments);
+        assert_eq!(new_chunk_count(&fuzzy(1)), 2);
+
+        // a permissive threshold reuses the near-duplicates, not the chunk changed by 100 bytes
+        let segments = fuzzy(2);
+        assert_eq!(
+            segments,
+            vec![Segment::Old(0..39936), Segment::New(39936..40960), Segment::Old(40960..65536)]
+        );
+        let patched: Vec<u8> = reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+        assert_eq!(patched.len(), new.len());
+        assert_eq!(hamming_distance(&patched, &new), 3); // approximate reconstruction
+    }
+}
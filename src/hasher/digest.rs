@@ -0,0 +1,71 @@
+/*
+Hasher implementation for any RustCrypto Digest (SHA2, SHA1, SHA3, BLAKE2, ...), so that
+a compatible digest can be plugged into the Slicer without a dedicated wrapper, e.g.
+DigestHasher<sha2::Sha512>. The chunk bytes are buffered and digested at once on finalize.
+*/
+
+use super::hasher::*;
+use digest::Digest;
+use std::marker::PhantomData;
+
+pub struct DigestHasher<D: Digest> {
+    buffer: Vec<u8>,
+    digest: PhantomData<fn() -> D>, // only used on finalize, no state kept
+}
+
+impl<D: Digest> Hasher for DigestHasher<D> {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) {
+        self.buffer.push(byte);
+    }
+
+    #[inline(always)]
+    fn finalize(&mut self) -> Vec<u8> {
+        let hash = D::digest(&self.buffer).to_vec();
+        self.buffer.clear();
+        hash
+    }
+}
+
+impl<D: Digest> DigestHasher<D> {
+    pub fn new(max_chunk_size: usize) -> DigestHasher<D> {
+        DigestHasher {
+            buffer: Vec::with_capacity(max_chunk_size),
+            digest: PhantomData,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::*;
+    use crate::hasher::sha256::*;
+    use crate::rolling_hasher::polynomial::*;
+    use crate::slicer::*;
+    use sha2::Sha512;
+
+    fn slice<H: Hasher>(data: &[u8], hasher: H) -> Vec<Chunk> {
+        let rolling_hasher = PolynomialRollingHasher::new(64, None, None);
+        let mut slicer = Slicer::new(rolling_hasher, hasher, (1 << 12) - 1, 2048, 8192).unwrap();
+        slicer.process(data);
+        slicer.finalize().unwrap().clone()
+    }
+
+    #[test]
+    fn test_digest_hasher_sha512() {
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let chunks = slice(&data, DigestHasher::<Sha512>::new(8192));
+
+        // the boundaries only depend on the rolling hash, the digest is computed per chunk
+        let sha256_chunks = slice(&data, Sha256Hasher::new(8192));
+        let ends = |chunks: &[Chunk]| chunks.iter().map(|chunk| chunk.end).collect::<Vec<u64>>();
+        assert_eq!(ends(&chunks), ends(&sha256_chunks));
+
+        let mut chunk_start: usize = 0;
+        for chunk in &chunks {
+            let chunk_end = chunk.end as usize;
+            assert_eq!(chunk.hash, Sha512::digest(&data[chunk_start..chunk_end]).to_vec());
+            chunk_start = chunk_end;
+        }
+    }
+}
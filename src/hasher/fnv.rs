@@ -0,0 +1,86 @@
+/*
+Hasher implementation computing the 64-bit FNV-1a hash of the chunks, without any external
+crate. It's not collision-resistant, but it's cheap and always available: it serves as the
+chunk fingerprint of the two-tier hashing and as the chunk hash when the crate is built
+without the digest backends (e.g. for non-security dedup). The hash is computed as the
+bytes are pushed, nothing is buffered.
+*/
+
+use super::hasher::*;
+use crate::helper::*;
+
+pub struct FnvHasher {
+    hash: u64,
+}
+
+impl Hasher for FnvHasher {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) {
+        self.hash = fnv1a(self.hash, byte);
+    }
+
+    #[inline(always)]
+    fn finalize(&mut self) -> Vec<u8> {
+        std::mem::replace(&mut self.hash, FNV_OFFSET_BASIS).to_le_bytes().to_vec()
+    }
+}
+
+impl FnvHasher {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> FnvHasher {
+        FnvHasher {
+            hash: FNV_OFFSET_BASIS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::*;
+    use crate::slicer::ChunkingParameters;
+
+    #[test]
+    fn test_fnv_hasher() {
+        // the reference FNV-1a 64 test vectors
+        let mut hasher = FnvHasher::new();
+        assert_eq!(hasher.finalize(), 0xcbf29ce484222325u64.to_le_bytes());
+        for &byte in b"foobar" {
+            hasher.push(byte);
+        }
+        assert_eq!(hasher.finalize(), 0x85944171f73967e8u64.to_le_bytes());
+        hasher.push(b'a');
+        assert_eq!(hasher.finalize(), 0xaf63dc4c8601ec8cu64.to_le_bytes());
+    }
+
+    // also run without the digest backends (cargo test --no-default-features), where FNV-1a
+    // is the default chunk hash
+    #[test]
+    fn test_fnv_hasher_chunking() {
+        let parameters = ChunkingParameters {
+            window_size: 64,
+            min_chunk_size: 2048,
+            max_chunk_size: 8192,
+            boundary_mask: (1 << 12) - 1,
+        };
+        let block: Vec<u8> = (0..100000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let data = [&block[..], &block[..]].concat();
+        let mut chunker = Chunker::new_with_hasher(parameters, FnvHasher::new()).unwrap();
+        chunker.process(&data);
+        let chunks = chunker.finalize().unwrap();
+
+        let mut chunk_start: usize = 0;
+        for chunk in &chunks {
+            let chunk_end = chunk.end as usize;
+            assert!(chunk_end - chunk_start <= parameters.max_chunk_size);
+            let mut hasher = FnvHasher::new();
+            data[chunk_start..chunk_end].iter().for_each(|&byte| hasher.push(byte));
+            assert_eq!(chunk.hash, hasher.finalize());
+            chunk_start = chunk_end;
+        }
+        assert_eq!(chunk_start, data.len());
+        // the repeated block is made of the same chunks, so it's deduplicated
+        let distinct: std::collections::HashSet<&Vec<u8>> = chunks.iter().map(|chunk| &chunk.hash).collect();
+        assert!(distinct.len() < chunks.len() * 2 / 3);
+    }
+}
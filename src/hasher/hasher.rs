@@ -6,7 +6,7 @@ is called a new hash is computed on the buffered data and the buffer
 gets cleared.
 */
 
-pub(crate) trait Hasher {
+pub trait Hasher {
     fn push(&mut self, byte: u8);                           // push byte, don't compute hash yet
     fn finalize(&mut self) -> Vec<u8>;                     // compute hash and reset
 }
@@ -0,0 +1,40 @@
+/*
+Keyed chunk hashing (HMAC-SHA256) for multi-tenant chunk stores. Raw content hashes would
+reveal whether two tenants store the same chunk (and let anyone confirm that a tenant holds
+a known file); keyed with a per-tenant secret salt, identical content gets distinct hashes
+across tenants while chunks of the same tenant still deduplicate. The chunk bytes are
+buffered and digested at once on finalize, as with DigestHasher.
+*/
+
+use super::hasher::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub struct HmacSha256Hasher {
+    buffer: Vec<u8>,
+    mac: Hmac<Sha256>, // keyed, never updated itself (cloned for each chunk)
+}
+
+impl Hasher for HmacSha256Hasher {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) {
+        self.buffer.push(byte);
+    }
+
+    #[inline(always)]
+    fn finalize(&mut self) -> Vec<u8> {
+        let mut mac = self.mac.clone();
+        mac.update(&self.buffer);
+        self.buffer.clear();
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+impl HmacSha256Hasher {
+    pub fn new(salt: &[u8], max_chunk_size: usize) -> HmacSha256Hasher {
+        HmacSha256Hasher {
+            buffer: Vec::with_capacity(max_chunk_size),
+            mac: Hmac::<Sha256>::new_from_slice(salt).expect("HMAC accepts keys of any length"),
+        }
+    }
+}
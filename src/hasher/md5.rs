@@ -7,7 +7,7 @@ This file uses SHA1 hashing algorithm which is not cryptographically safe anymor
 Still, it's ok to use it for file comparison purposes
 */
 
-pub(crate) struct Md5Hasher {
+pub struct Md5Hasher {
     buffer: Vec<u8>,
 }
 
@@ -30,8 +30,7 @@ impl Hasher for Md5Hasher {
 
 impl Md5Hasher {
 
-    #[allow(dead_code)]
-    pub(crate) fn new(max_chunk_size: usize) -> Md5Hasher {
+    pub fn new(max_chunk_size: usize) -> Md5Hasher {
         Md5Hasher {
             buffer: Vec::with_capacity(max_chunk_size),
         }
@@ -1,4 +1,13 @@
+#[allow(clippy::module_inception)]
 pub mod hasher;
+#[cfg(any(feature = "sha2", feature = "sha1"))]
+pub mod digest;
+pub mod fnv;
+#[cfg(feature = "sha2")]
+pub mod hmac;
+#[cfg(feature = "md5")]
 pub mod md5;
+#[cfg(feature = "sha1")]
 pub mod sha1;
+#[cfg(feature = "sha2")]
 pub mod sha256;
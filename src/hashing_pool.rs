@@ -0,0 +1,95 @@
+/*
+    HashingPool computes collision-resistant chunk digests on a pool of worker threads.
+
+    It is used by the Slicer in the pipelined mode, where the (sequential) rolling hash
+    scan runs on the calling thread and only finds chunk boundaries, while the (expensive)
+    chunk hashing is sent over an mpsc channel to the workers. This hides hashing latency
+    behind the scan.
+
+    Each worker owns its Hasher instance (created with the factory passed to 'new').
+    Chunks are tagged with their index so that the hashes can be put back in order once
+    all of them have been computed.
+*/
+
+use crate::hasher::hasher::*;
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+pub(crate) struct HashingPool {
+    job_sender: Option<Sender<(usize, Vec<u8>)>>,
+    result_receiver: Receiver<(usize, Vec<u8>)>,
+    workers: Vec<JoinHandle<()>>,
+    jobs_submitted: usize,
+}
+
+impl HashingPool {
+    pub(crate) fn new<H, F>(threads: usize, make_hasher: F) -> HashingPool
+    where
+        H: Hasher + Send + 'static,
+        F: Fn() -> H,
+    {
+        assert!(threads > 0, "HashingPool needs at least one thread");
+        let (job_sender, job_receiver) = channel::<(usize, Vec<u8>)>();
+        let (result_sender, result_receiver) = channel::<(usize, Vec<u8>)>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..threads)
+            .map(|_| {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                let mut hasher = make_hasher();
+                thread::spawn(move || loop {
+                    // the lock is released as soon as the job has been received
+                    let job = job_receiver.lock().unwrap().recv();
+                    let Ok((index, bytes)) = job else {
+                        break; // all senders dropped, no more jobs
+                    };
+                    for byte in bytes {
+                        hasher.push(byte);
+                    }
+                    if result_sender.send((index, hasher.finalize())).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        HashingPool {
+            job_sender: Some(job_sender),
+            result_receiver,
+            workers,
+            jobs_submitted: 0,
+        }
+    }
+
+    // queues chunk bytes for hashing, chunks must be submitted in order
+    pub(crate) fn submit(&mut self, bytes: Vec<u8>) {
+        let job_sender = self.job_sender.as_ref().expect("HashingPool already finished");
+        job_sender
+            .send((self.jobs_submitted, bytes))
+            .expect("Hashing worker terminated unexpectedly");
+        self.jobs_submitted += 1;
+    }
+
+    // waits for all workers to complete and returns hashes in submission order
+    pub(crate) fn finish(&mut self) -> Vec<Vec<u8>> {
+        self.job_sender = None; // closes the channel so that workers exit once it's drained
+        let mut hashes: Vec<Vec<u8>> = vec![Vec::new(); self.jobs_submitted];
+        for _ in 0..self.jobs_submitted {
+            let (index, hash) = self
+                .result_receiver
+                .recv()
+                .expect("Hashing worker terminated unexpectedly");
+            hashes[index] = hash;
+        }
+        for worker in self.workers.drain(..) {
+            worker.join().expect("Hashing worker panicked");
+        }
+        hashes
+    }
+}
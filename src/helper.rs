@@ -22,6 +22,20 @@ pub(crate) fn mod_power(base: u32, exponent: u32, modulus: u32) -> u32 {
     u32::try_from(c).unwrap()
 }
 
+// 64-bit FNV-1a, a cheap non-cryptographic hash, updated byte by byte starting with
+// FNV_OFFSET_BASIS
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+#[allow(dead_code)]
+pub(crate) fn fnv1a(hash: u64, byte: u8) -> u64 {
+    (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+}
+
+// formats a chunk hash (always kept as bytes) as a lowercase hex string, e.g. for messages
+pub(crate) fn to_hex(hash: &[u8]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 // performs binary search operations, if the searched item appears multiple times in
 // slice, any of the matching indices will be returned
 #[allow(dead_code)]
@@ -129,6 +143,12 @@ mod tests {
         assert!(!is_power_of_two(32769));
     }
 
+    #[test]
+    fn test_to_hex() {
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa5, 0xff]), "000fa5ff");
+    }
+
     #[test]
     fn test_mod_power() {
         assert_eq!(mod_power(2, 12, 13), 1);
@@ -0,0 +1,197 @@
+/*
+    HttpChunkSource resolves the Old segments from an old file served over HTTP, fetching
+    each segment's byte range with a Range request, so that patching only downloads the
+    old bytes the delta reuses rather than the whole old file.
+
+    The server must support range requests: a response other than 206 Partial Content
+    (e.g. 200 with the whole file) is reported as an error rather than downloading the
+    whole file for each segment. Store segments can't be resolved, as with FileChunkSource.
+
+    Only available with the "http" feature.
+*/
+
+use crate::patcher::*;
+use std::io::{copy, Error, ErrorKind, Read, Result, Write};
+
+const HTTP_PARTIAL_CONTENT: u16 = 206;
+const HTTP_RANGE_NOT_SATISFIABLE: u16 = 416;
+
+// The old file at the given URL, resolving offset ranges only
+pub struct HttpChunkSource {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl HttpChunkSource {
+    pub fn new(url: &str) -> HttpChunkSource {
+        HttpChunkSource {
+            agent: ureq::Agent::new(),
+            url: url.to_string(),
+        }
+    }
+}
+
+impl ChunkSource for HttpChunkSource {
+    fn read(&self, key: ChunkKey) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        self.copy_to(key, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    // copied in a streaming manner, as the response body is received
+    fn copy_to(&self, key: ChunkKey, writer: &mut dyn Write) -> Result<u64> {
+        let range = match key {
+            ChunkKey::Range(range) => range,
+            ChunkKey::Hash(_) => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Store segments cannot be patched from the old file",
+                ))
+            }
+        };
+        let len = range.end.saturating_sub(range.start);
+        if len == 0 {
+            return Ok(0); // an empty range can't be expressed in a Range header
+        }
+
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", range.start, range.end - 1))
+            .call()
+            .map_err(|error| match error {
+                ureq::Error::Status(HTTP_RANGE_NOT_SATISFIABLE, _) => Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Segment range exceeds the source file",
+                ),
+                error => Error::other(error),
+            })?;
+        if response.status() != HTTP_PARTIAL_CONTENT {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Range requests not supported, status {}", response.status()),
+            ));
+        }
+        let bytes_copied = copy(&mut response.into_reader().take(len), writer)?;
+        if bytes_copied != len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Segment range exceeds the source file",
+            ));
+        }
+        Ok(bytes_copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::Segment;
+    use crate::differ::{Differ, DifferConfig};
+    use crate::slicer::ChunkingParameters;
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // answers a single request with the requested range of the data, or the whole data
+    // if there's no Range header, counting the body bytes sent
+    fn serve_range(stream: TcpStream, data: &[u8], bytes_served: &AtomicU64) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut range: Option<(u64, u64)> = None;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line.trim_end().is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                let (start, end) = value.trim_end().split_once('-').unwrap();
+                range = Some((start.parse().unwrap(), end.parse().unwrap()));
+            }
+        }
+        let data_len = data.len() as u64;
+        let mut stream = stream;
+        let body = match range {
+            Some((start, _)) if start >= data_len => {
+                write!(stream, "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+                return Ok(());
+            }
+            Some((start, end)) => {
+                let end = end.min(data_len - 1);
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                    start, end, data_len
+                )?;
+                &data[start as usize..=end as usize]
+            }
+            None => {
+                write!(stream, "HTTP/1.1 200 OK\r\n")?;
+                data
+            }
+        };
+        write!(stream, "Content-Length: {}\r\nConnection: close\r\n\r\n", body.len())?;
+        // counted before sending, so that the count is up to date once the client is done
+        bytes_served.fetch_add(body.len() as u64, Ordering::SeqCst);
+        stream.write_all(body)
+    }
+
+    // serves the data on a local port, counting the body bytes sent; returns the URL
+    fn spawn_server(data: Vec<u8>, bytes_served: Arc<AtomicU64>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/old", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                serve_range(stream.unwrap(), &data, &bytes_served).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_http_chunk_source() -> Result<()> {
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let new_file_path = "./example/monkey_after.tiff";
+        let old_bytes = fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = fs::read(new_file_path)?;
+        let mut differ = Differ::from_config(config);
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let segments = differ.finalize();
+
+        let bytes_served = Arc::new(AtomicU64::new(0));
+        let source = HttpChunkSource::new(&spawn_server(old_bytes.clone(), bytes_served.clone()));
+        let patched_file_path = std::env::temp_dir()
+            .join(format!("differ_http_chunk_source_{}", std::process::id()));
+        let patched_file_path = patched_file_path.to_str().unwrap();
+        let (old_bytes_used, _) =
+            patch_with_source(&source, new_file_path, patched_file_path, segments)?;
+        assert_eq!(fs::read(patched_file_path)?, new_bytes);
+
+        // only the reused old bytes were downloaded
+        assert!(old_bytes_used < old_bytes.len() as u64);
+        assert_eq!(bytes_served.load(Ordering::SeqCst), old_bytes_used);
+
+        // ranges past the end of the file fail rather than truncating the output
+        let old_len = old_bytes.len() as u64;
+        let segments = vec![Segment::Old(old_len - 3..old_len + 3)];
+        let error = patch_with_source(&source, new_file_path, patched_file_path, segments);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let segments = vec![Segment::Old(old_len + 3..old_len + 6)];
+        let error = patch_with_source(&source, new_file_path, patched_file_path, segments);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        fs::remove_file(patched_file_path)
+    }
+}
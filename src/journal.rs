@@ -0,0 +1,208 @@
+/*
+    Journal is an append-only log of the versions of a file, stored as deltas: version 0 is
+    stored whole (as a delta against empty data), then each version as the delta against the
+    previous one. Any version is reconstructed by replaying the deltas from the base, so the
+    journal stays small for files that change a little between versions.
+
+    Each entry is laid out as:
+    [structure length: u64][payload length: u64][structure][payload]
+    where the structure and the payload are those produced by delta::serialize_split. The
+    journal bytes are the concatenation of the entries, so that appending a version only
+    appends bytes, e.g. to a file opened in append mode.
+
+    The latest version is kept in memory so that appending doesn't need to replay the journal.
+
+    Optionally, the payload of each entry stores the bytes of identical New segments once
+    (see delta::serialize_split_deduplicated), which shrinks the versions repeating the same insert.
+*/
+
+use crate::delta::*;
+use crate::differ::*;
+use crate::slicer::*;
+use std::ops::Range;
+
+pub struct Journal {
+    config: DifferConfig,
+    bytes: Vec<u8>,
+    entries: Vec<(Range<usize>, Range<usize>)>, // (structure, payload) ranges within bytes
+    latest: Vec<u8>,
+    deduplicate_payload: bool,
+}
+
+impl Journal {
+    // creates an empty journal, failing if the config isn't valid
+    pub fn new(config: DifferConfig) -> Result<Journal, SlicerError> {
+        Differ::try_from_config(config)?;
+        Ok(Journal {
+            config,
+            bytes: Vec::new(),
+            entries: Vec::new(),
+            latest: Vec::new(),
+            deduplicate_payload: false,
+        })
+    }
+
+    // parses journal bytes, as returned by as_bytes; the config is taken from the entries
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Journal, DeltaError> {
+        let mut config = DifferConfig::default();
+        let mut entries: Vec<(Range<usize>, Range<usize>)> = Vec::new();
+        let mut latest: Vec<u8> = Vec::new();
+        let mut offset: usize = 0;
+        while offset < bytes.len() {
+            let structure_len = read_len(&bytes, offset)?;
+            let payload_len = read_len(&bytes, offset + 8)?;
+            let structure_start = offset + 16;
+            let payload_start = structure_start
+                .checked_add(structure_len)
+                .ok_or(DeltaError::Truncated)?;
+            offset = payload_start
+                .checked_add(payload_len)
+                .ok_or(DeltaError::Truncated)?;
+            if offset > bytes.len() {
+                return Err(DeltaError::Truncated);
+            }
+            let structure = structure_start..payload_start;
+            let payload = payload_start..offset;
+            config = deserialize_split(&bytes[structure.clone()])?.0;
+            latest = apply_split(&bytes[structure.clone()], &bytes[payload.clone()], &latest)?;
+            entries.push((structure, payload));
+        }
+        Ok(Journal {
+            config,
+            bytes,
+            entries,
+            latest,
+            deduplicate_payload: false,
+        })
+    }
+
+    // stores the identical new chunks of each appended delta once; the journal bytes remain
+    // readable by from_bytes either way
+    pub fn enable_payload_deduplication(&mut self) {
+        self.deduplicate_payload = true;
+    }
+
+    // diffs the new version against the latest one and appends the delta
+    pub fn append(&mut self, new: &[u8]) {
+        let mut differ =
+            Differ::try_from_config(self.config).expect("Journal config has been validated");
+        differ.process_old(&self.latest);
+        differ.process_new(new);
+        let (structure, payload) = if self.deduplicate_payload {
+            let (segments, artifacts) = differ.finalize_with_artifacts();
+            serialize_split_deduplicated(&segments, new, &artifacts.chunks_new, &self.config)
+        } else {
+            serialize_split(&differ.finalize(), new, &self.config)
+        }
+        .expect("Journal deltas have no Store segments");
+        self.bytes
+            .extend_from_slice(&(structure.len() as u64).to_le_bytes());
+        self.bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        let structure_start = self.bytes.len();
+        self.bytes.extend_from_slice(&structure);
+        let payload_start = self.bytes.len();
+        self.bytes.extend_from_slice(&payload);
+        self.entries
+            .push((structure_start..payload_start, payload_start..self.bytes.len()));
+        self.latest = new.to_vec();
+    }
+
+    // replays the deltas up to the given version, None if there's no such version
+    pub fn reconstruct(&self, version: usize) -> Option<Vec<u8>> {
+        if version >= self.entries.len() {
+            return None;
+        }
+        if version == self.entries.len() - 1 {
+            return Some(self.latest.clone());
+        }
+        let mut data: Vec<u8> = Vec::new();
+        for (structure, payload) in &self.entries[..=version] {
+            data = apply_split(&self.bytes[structure.clone()], &self.bytes[payload.clone()], &data)
+                .expect("Journal entries have been validated");
+        }
+        Some(data)
+    }
+
+    pub fn version_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+// reads an entry's length field
+fn read_len(bytes: &[u8], offset: usize) -> Result<usize, DeltaError> {
+    let len_bytes = bytes.get(offset..offset + 8).ok_or(DeltaError::Truncated)?;
+    usize::try_from(u64::from_le_bytes(len_bytes.try_into().unwrap()))
+        .map_err(|_| DeltaError::Truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 16,
+                min_chunk_size: 64,
+                max_chunk_size: 1024,
+                boundary_mask: (1 << 8) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let version_0: Vec<u8> = (0..65536u32).map(|i| (i * 7919 % 251 + i / 251) as u8).collect();
+        let mut version_1 = version_0.clone();
+        version_1[1000..1100].fill(0);
+        let mut version_2 = version_1.clone();
+        version_2.drain(20000..21000);
+        version_2.extend_from_slice(b"appended at the end");
+        let versions = [version_0, version_1, version_2];
+
+        let mut journal = Journal::new(config).unwrap();
+        assert_eq!(journal.reconstruct(0), None);
+        for version in &versions {
+            journal.append(version);
+        }
+        assert_eq!(journal.version_count(), 3);
+        for (index, version) in versions.iter().enumerate() {
+            assert_eq!(journal.reconstruct(index).as_ref(), Some(version));
+        }
+        assert_eq!(journal.reconstruct(3), None);
+
+        // the later versions only store what changed
+        let base_len = journal.entries[0].1.len();
+        assert_eq!(base_len, versions[0].len());
+        assert!(journal.entries[1].1.len() < base_len / 10);
+        assert!(journal.entries[2].1.len() < base_len / 10);
+
+        // the journal can be reopened and appended to
+        let mut reopened = Journal::from_bytes(journal.as_bytes().to_vec()).unwrap();
+        assert_eq!(reopened.config, config);
+        assert_eq!(reopened.reconstruct(1).as_ref(), Some(&versions[1]));
+        reopened.append(&versions[0]);
+        assert_eq!(reopened.reconstruct(3).as_ref(), Some(&versions[0]));
+        assert_eq!(reopened.reconstruct(2).as_ref(), Some(&versions[2]));
+
+        let truncated = journal.as_bytes()[..journal.as_bytes().len() - 1].to_vec();
+        assert_eq!(Journal::from_bytes(truncated).err(), Some(DeltaError::Truncated));
+
+        // with the payload deduplicated, a version repeating an insert stores it once
+        let insert: Vec<u8> = (0..4096u32).map(|i| ((i * 7907 % 241) ^ (i / 241)) as u8).collect();
+        let mut version_3 = versions[2].clone();
+        version_3.splice(40000..40000, insert.iter().copied());
+        version_3.splice(10000..10000, insert.iter().copied());
+        let mut deduplicated = Journal::new(config).unwrap();
+        deduplicated.enable_payload_deduplication();
+        for version in [&versions[2], &version_3] {
+            journal.append(version);
+            deduplicated.append(version);
+        }
+        assert!(deduplicated.entries[1].1.len() < journal.entries[4].1.len());
+        let reopened = Journal::from_bytes(deduplicated.as_bytes().to_vec()).unwrap();
+        assert_eq!(reopened.reconstruct(1), Some(version_3));
+    }
+}
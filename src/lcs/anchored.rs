@@ -0,0 +1,190 @@
+/*
+Computes the LCS of large inputs in parallel, split at anchors, as done by patience diff.
+
+Anchors are the characters appearing exactly once in each input. Those matched in the same
+relative order in both inputs (the longest increasing subsequence of their positions) are
+taken as part of the common subsequence, so the inputs between two consecutive anchors
+can be compared independently of the rest. The LCS of these gaps are computed concurrently
+and concatenated with the anchors.
+
+For chunk hashes of similar inputs nearly every chunk is an anchor, so the gaps are small
+and the result equals the global LCS. In general, the anchors are not guaranteed to belong
+to an LCS, so the result is a common subsequence which can be shorter than the global one.
+TIME:   O(n log n) to find the anchors, then the chosen algorithm on each gap
+*/
+
+use super::lcs::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::thread;
+use std::time::Instant;
+
+// Computes the common subsequence split at the anchors, running the chosen algorithm on
+// the gaps between them on up to 'threads' threads; returns None once the deadline (if any)
+// has passed
+pub(crate) fn anchored_lcs_with_deadline<T>(
+    algorithm: LcsAlgorithm,
+    a_string: &[T],
+    b_string: &[T],
+    b_weights: &[u64],
+    threads: usize,
+    deadline: Option<Instant>,
+) -> Option<Vec<T>>
+where
+    T: Ord + Clone + Hash + Send + Sync,
+{
+    assert!(threads > 0, "Anchored LCS needs at least one thread");
+    let anchors = anchors(a_string, b_string);
+
+    // the gaps before, between and after the anchors
+    let mut gaps: Vec<(usize, usize, usize, usize)> = Vec::with_capacity(anchors.len() + 1);
+    let (mut a_start, mut b_start) = (0, 0);
+    for &(a_index, b_index) in anchors.iter().chain([(a_string.len(), b_string.len())].iter()) {
+        gaps.push((a_start, a_index, b_start, b_index));
+        (a_start, b_start) = (a_index + 1, b_index + 1);
+    }
+
+    let gap_lcs = |&(a_start, a_end, b_start, b_end): &(usize, usize, usize, usize)| {
+        if a_start == a_end || b_start == b_end {
+            return Some(Vec::new());
+        }
+        let b_weights = if b_weights.is_empty() { b_weights } else { &b_weights[b_start..b_end] };
+        lcs_with_deadline(
+            algorithm,
+            &a_string[a_start..a_end],
+            &b_string[b_start..b_end],
+            b_weights,
+            deadline,
+        )
+    };
+    let gaps_per_thread = gaps.len().div_ceil(threads);
+    let gap_lcses: Vec<Option<Vec<T>>> = if threads == 1 {
+        gaps.iter().map(gap_lcs).collect() // sequential, no thread is spawned
+    } else {
+        thread::scope(|scope| {
+            let workers: Vec<_> = gaps
+                .chunks(gaps_per_thread)
+                .map(|gaps| scope.spawn(move || gaps.iter().map(gap_lcs).collect::<Vec<_>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Anchored LCS worker panicked"))
+                .collect()
+        })
+    };
+
+    let mut lcs: Vec<T> = Vec::new();
+    for (index, gap_lcs) in gap_lcses.into_iter().enumerate() {
+        lcs.extend(gap_lcs?);
+        if let Some(&(a_index, _)) = anchors.get(index) {
+            lcs.push(a_string[a_index].clone());
+        }
+    }
+    Some(lcs)
+}
+
+// Returns the positions (in a and b) of the characters appearing exactly once in each input,
+// keeping the longest sequence of them which is in the same order in both
+pub(crate) fn anchors<T>(a_string: &[T], b_string: &[T]) -> Vec<(usize, usize)>
+where
+    T: Eq + Hash,
+{
+    // the occurrence count and the last position in each input
+    let mut occurrences: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+    for (a_index, character) in a_string.iter().enumerate() {
+        let entry = occurrences.entry(character).or_insert((0, 0, 0, 0));
+        entry.0 += 1;
+        entry.1 = a_index;
+    }
+    for (b_index, character) in b_string.iter().enumerate() {
+        if let Some(entry) = occurrences.get_mut(character) {
+            entry.2 += 1;
+            entry.3 = b_index;
+        }
+    }
+    let mut unique_pairs: Vec<(usize, usize)> = occurrences
+        .into_values()
+        .filter(|&(a_count, _, b_count, _)| a_count == 1 && b_count == 1)
+        .map(|(_, a_index, _, b_index)| (a_index, b_index))
+        .collect();
+    unique_pairs.sort_unstable_by_key(|&(_, b_index)| b_index);
+
+    // the longest increasing subsequence of the positions in a (patience sorting), the
+    // pairs being ordered by the positions in b
+    let mut pile_tops: Vec<usize> = Vec::new(); // the index of each pile's top pair
+    let mut predecessors: Vec<Option<usize>> = Vec::with_capacity(unique_pairs.len());
+    for (pair_index, &(a_index, _)) in unique_pairs.iter().enumerate() {
+        let pile = pile_tops.partition_point(|&top| unique_pairs[top].0 < a_index);
+        predecessors.push(pile.checked_sub(1).map(|previous_pile| pile_tops[previous_pile]));
+        if pile == pile_tops.len() {
+            pile_tops.push(pair_index);
+        } else {
+            pile_tops[pile] = pair_index;
+        }
+    }
+    let mut anchors: Vec<(usize, usize)> = Vec::with_capacity(pile_tops.len());
+    let mut pair_index = pile_tops.last().copied();
+    while let Some(index) = pair_index {
+        anchors.push(unique_pairs[index]);
+        pair_index = predecessors[index];
+    }
+    anchors.reverse();
+    anchors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcs::cross_validation::{lcs_len, random_symbols};
+
+    fn is_subsequence(subsequence: &[u64], sequence: &[u64]) -> bool {
+        let mut sequence = sequence.iter();
+        subsequence
+            .iter()
+            .all(|item| sequence.any(|sequence_item| sequence_item == item))
+    }
+
+    #[test]
+    fn test_anchors() {
+        let a_string = "XABCDYE".as_bytes();
+        let b_string = "ACBDEXZ".as_bytes();
+        // X is out of order, either B or C can be kept, Y and Z are not common
+        let anchors = anchors(a_string, b_string);
+        assert_eq!(anchors.len(), 4);
+        assert_eq!(anchors[0], (1, 0));
+        assert_eq!(anchors[2..], [(4, 3), (6, 4)]);
+
+        // repeated characters are never anchors
+        assert_eq!(super::anchors("AAB".as_bytes(), "ABA".as_bytes()), vec![(2, 1)]);
+        assert!(super::anchors::<u8>(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_anchored_lcs() {
+        // near-identical inputs with unique characters but a few repeated ones
+        let mut a: Vec<u64> = (1000..4000).collect();
+        a.extend(random_symbols(200, 4, 1));
+        let mut b = a.clone();
+        b.drain(100..150);
+        b[1000] = 7;
+        b.insert(2000, 1);
+        b.extend(random_symbols(100, 4, 2));
+        let expected_len = lcs_len(&a, &b);
+        let weights: Vec<u64> = vec![1; b.len()];
+
+        for algorithm in [
+            LcsAlgorithm::Nakatsu,
+            LcsAlgorithm::HuntSzymanski,
+            LcsAlgorithm::Weighted,
+            LcsAlgorithm::Hirschberg,
+            LcsAlgorithm::Kumar,
+        ] {
+            for threads in [1, 4] {
+                let lcs = anchored_lcs_with_deadline(algorithm, &a, &b, &weights, threads, None)
+                    .unwrap();
+                assert_eq!(lcs.len(), expected_len);
+                assert!(is_subsequence(&lcs, &a) && is_subsequence(&lcs, &b));
+            }
+        }
+    }
+}
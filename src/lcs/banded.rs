@@ -0,0 +1,134 @@
+/*
+Computes the Longest Common Subsequence with the dynamic programming restricted to a
+diagonal band: only the cells (i, j) with |i - j| <= k are filled, assuming the optimal
+alignment stays near the main diagonal, as it does for near-identical inputs.
+TIME:   O(nk)
+SPACE:  O(nk) for the backtrace (a byte per band cell), O(k) for the lengths
+
+where:
+n   - the length of the inputs
+k   - the band half-width
+
+The result is only returned if it's provably the LCS. An alignment leaving the band
+reaches a diagonal j - i beyond k, so it has at least 2k + 2 - |m - n| unmatched characters
+(in a and b combined); once the banded subsequence leaves fewer than that unmatched, no
+alignment outside the band can do better. Otherwise (or if the inputs lengths differ by
+more than k) None is returned, so that the caller widens the band or falls back to another
+algorithm.
+*/
+
+const UNREACHABLE: i64 = i64::MIN / 2; // the cells outside the band
+
+// the backtrace steps
+const MATCH: u8 = 0;
+const SKIP_A: u8 = 1; // from (i - 1, j)
+const SKIP_B: u8 = 2; // from (i, j - 1)
+
+// Computes the longest common subsequence, None if it can't be found within the band
+#[allow(dead_code)]
+pub(crate) fn lcs_banded<T>(a_string: &[T], b_string: &[T], band: usize) -> Option<Vec<T>>
+where
+    T: Ord + Clone,
+{
+    let (n, m) = (a_string.len(), b_string.len());
+    if n.abs_diff(m) > band {
+        return None; // the end cell is outside the band
+    }
+    let width = 2 * band + 1; // cell (i, j) is at offset j + band - i of row i
+    let mut previous_row: Vec<i64> = vec![UNREACHABLE; width];
+    let mut row: Vec<i64> = vec![UNREACHABLE; width];
+    let mut steps: Vec<u8> = vec![SKIP_A; (n + 1) * width];
+    for offset in band..width.min(band + m + 1) {
+        previous_row[offset] = 0; // row 0: nothing of a matched yet
+        steps[offset] = SKIP_B;
+    }
+    for i in 1..=n {
+        row.fill(UNREACHABLE);
+        for offset in 0..width {
+            let Some(j) = (i + offset).checked_sub(band).filter(|&j| j <= m) else {
+                continue;
+            };
+            let (length, step) = if j == 0 {
+                (0, SKIP_A)
+            } else if a_string[i - 1] == b_string[j - 1] {
+                (previous_row[offset] + 1, MATCH)
+            } else {
+                let from_above = previous_row.get(offset + 1).copied().unwrap_or(UNREACHABLE);
+                let from_left = offset.checked_sub(1).map_or(UNREACHABLE, |left| row[left]);
+                if from_above >= from_left {
+                    (from_above, SKIP_A)
+                } else {
+                    (from_left, SKIP_B)
+                }
+            };
+            row[offset] = length;
+            steps[i * width + offset] = step;
+        }
+        std::mem::swap(&mut previous_row, &mut row);
+    }
+
+    let end_offset = m + band - n;
+    let lcs_len = usize::try_from(previous_row[end_offset]).ok()?;
+    let unmatched = n + m - 2 * lcs_len;
+    if unmatched >= 2 * band + 2 - n.abs_diff(m) {
+        return None; // an alignment outside the band might be longer
+    }
+
+    let mut lcs: Vec<T> = Vec::with_capacity(lcs_len);
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        match steps[i * width + j + band - i] {
+            MATCH => {
+                lcs.push(a_string[i - 1].clone());
+                (i, j) = (i - 1, j - 1);
+            }
+            SKIP_A => i -= 1,
+            _ => j -= 1,
+        }
+    }
+    lcs.reverse();
+    Some(lcs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcs::cross_validation::{lcs_len, random_symbols};
+
+    fn is_subsequence(subsequence: &[u64], sequence: &[u64]) -> bool {
+        let mut sequence = sequence.iter();
+        subsequence
+            .iter()
+            .all(|item| sequence.any(|sequence_item| sequence_item == item))
+    }
+
+    #[test]
+    fn test_lcs_banded() {
+        let lcs = lcs_banded("bcdabab".as_bytes(), "cbacbaaba".as_bytes(), 4).unwrap();
+        assert_eq!(lcs.len(), 5);
+        assert_eq!(lcs_banded::<u8>(&[], &[], 0), Some(vec![]));
+        assert_eq!(lcs_banded("abc".as_bytes(), "abc".as_bytes(), 0), Some(b"abc".to_vec()));
+        // the lengths differ by more than the band
+        assert_eq!(lcs_banded("abcd".as_bytes(), "a".as_bytes(), 2), None);
+
+        // a few small edits stay within the band
+        let a = random_symbols(3000, 1000, 1);
+        let mut b = a.clone();
+        b.drain(1000..1005);
+        b.insert(2500, 7);
+        b[2800] = 8;
+        b.splice(2900..2900, random_symbols(3, 1000, 2));
+        let lcs = lcs_banded(&a, &b, 16).unwrap();
+        assert_eq!(lcs.len(), lcs_len(&a, &b));
+        assert!(is_subsequence(&lcs, &a) && is_subsequence(&lcs, &b));
+
+        // a large block moved to the end pushes the alignment far from the diagonal
+        let mut b = a.clone();
+        let moved: Vec<u64> = b.drain(500..1500).collect();
+        b.extend(moved);
+        assert_eq!(lcs_banded(&a, &b, 16), None);
+        // widening the band enough finds it
+        let lcs = lcs_banded(&a, &b, 1024).unwrap();
+        assert_eq!(lcs.len(), lcs_len(&a, &b));
+    }
+}
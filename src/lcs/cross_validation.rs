@@ -0,0 +1,107 @@
+/*
+Cross-validation of the LCS algorithms: they all must agree on the LCS length (the actual
+subsequences may differ, there can be many) with the basic dynamic programming algorithm,
+used as the reference. Random inputs over small alphabets (with many matching pairs and
+long LCSes) are generated with a fixed seed, so that failures are reproducible.
+*/
+
+use super::lcs::*;
+
+const ALGORITHMS: [LcsAlgorithm; 5] = [
+    LcsAlgorithm::Nakatsu,
+    LcsAlgorithm::HuntSzymanski,
+    LcsAlgorithm::Weighted,
+    LcsAlgorithm::Hirschberg,
+    LcsAlgorithm::Kumar,
+];
+
+// deterministic pseudo-random sequence of symbols from an alphabet of the given size
+pub(crate) fn random_symbols(len: usize, alphabet_size: u64, seed: u64) -> Vec<u64> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % alphabet_size
+        })
+        .collect()
+}
+
+// the LCS length computed with the basic dynamic programming algorithm
+pub(crate) fn lcs_len(a: &[u64], b: &[u64]) -> usize {
+    let mut row: Vec<usize> = vec![0; b.len() + 1];
+    for a_item in a {
+        let mut diagonal = 0;
+        for (j, b_item) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if a_item == b_item {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn is_subsequence(subsequence: &[u64], sequence: &[u64]) -> bool {
+    let mut sequence = sequence.iter();
+    subsequence
+        .iter()
+        .all(|item| sequence.any(|sequence_item| sequence_item == item))
+}
+
+// checks all the algorithms against the reference on a single pair of inputs
+fn cross_validate(a: &[u64], b: &[u64]) {
+    let expected_len = lcs_len(a, b);
+    let unit_weights: Vec<u64> = vec![1; b.len()]; // the heaviest subsequence is the longest
+    for algorithm in ALGORITHMS {
+        let lcs = lcs(algorithm, a, b, &unit_weights);
+        assert_eq!(
+            lcs.len(),
+            expected_len,
+            "{:?} failed on a = {:?}, b = {:?}",
+            algorithm,
+            a,
+            b
+        );
+        assert!(is_subsequence(&lcs, a) && is_subsequence(&lcs, b));
+    }
+}
+
+#[test]
+fn test_lcs_cross_validation_random() {
+    let mut seed: u64 = 1;
+    for alphabet_size in [1, 2, 4, 16] {
+        for (a_len, b_len) in [(1, 1), (1, 10), (10, 1), (10, 10), (37, 53), (100, 80), (200, 200)] {
+            for _ in 0..10 {
+                let a = random_symbols(a_len, alphabet_size, seed);
+                let b = random_symbols(b_len, alphabet_size, seed + 1);
+                cross_validate(&a, &b);
+                seed += 2;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lcs_cross_validation_edge_cases() {
+    let a = random_symbols(50, 4, 7);
+    let repeated: Vec<u64> = vec![3; 20];
+    let cases: [(&[u64], &[u64]); 9] = [
+        (&[], &[]),                  // empty
+        (&[], &a),                   // one empty
+        (&a, &[]),                   // the other empty
+        (&a, &a),                    // identical
+        (&[0, 1, 2], &[3, 4, 5, 6]), // disjoint
+        (&repeated, &repeated[..7]), // single repeated character
+        (&repeated, &a),
+        (&a[10..], &a), // suffix
+        (&a, &a[..30]), // prefix
+    ];
+    for (a, b) in cases {
+        cross_validate(a, b);
+    }
+}
@@ -0,0 +1,65 @@
+/*
+Deadline lets the LCS algorithms abort once a point in time has passed, bounding the latency
+of e.g. interactive tools on large dissimilar inputs (where the algorithms degrade towards
+quadratic time).
+
+Reading the clock on every iteration would be a noticeable overhead in the inner loops, so
+the clock is only read every DEADLINE_CHECK_INTERVAL iterations.
+*/
+
+use std::time::Instant;
+
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+pub(crate) struct Deadline {
+    instant: Option<Instant>, // never exceeded if None
+    countdown: u32,           // iterations left until the clock is read
+    is_exceeded: bool,
+}
+
+impl Deadline {
+    pub(crate) fn new(instant: Option<Instant>) -> Deadline {
+        Deadline {
+            instant,
+            countdown: 0,
+            is_exceeded: false,
+        }
+    }
+
+    // counts an iteration and returns whether the deadline has passed
+    pub(crate) fn is_exceeded(&mut self) -> bool {
+        let Some(instant) = self.instant else {
+            return false;
+        };
+        if self.countdown == 0 {
+            self.countdown = DEADLINE_CHECK_INTERVAL;
+            self.is_exceeded = Instant::now() >= instant;
+        }
+        self.countdown -= 1;
+        self.is_exceeded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_deadline() {
+        let mut deadline = Deadline::new(None);
+        assert!(!deadline.is_exceeded());
+
+        let mut deadline = Deadline::new(Some(Instant::now()));
+        assert!(deadline.is_exceeded());
+
+        // the clock is only read every DEADLINE_CHECK_INTERVAL iterations
+        let mut deadline = Deadline::new(Some(Instant::now() + Duration::from_millis(10)));
+        assert!(!deadline.is_exceeded());
+        std::thread::sleep(Duration::from_millis(20));
+        for _ in 1..DEADLINE_CHECK_INTERVAL {
+            assert!(!deadline.is_exceeded());
+        }
+        assert!(deadline.is_exceeded());
+    }
+}
@@ -0,0 +1,171 @@
+/*
+Computes the Heaviest Common Subsequence: rather than maximizing the number of common
+characters (chunks), it maximizes the sum of their weights. Weighting each chunk by its
+size maximizes the number of bytes reused from the old data, which for a delta is the
+actual objective (two equally long LCS solutions may reuse very different amounts of data).
+
+The algorithm follows Hunt-Szymanski: the matching character pairs are visited row by row
+(and in descending column order within a row), finding the heaviest chain ending at each
+pair with a prefix-maximum Fenwick tree indexed by column.
+TIME:   O((r+m) log n)
+SPACE:  O(r+n)
+
+where:
+n,m - the legths of the inputs
+r   - the number of matching character pairs
+
+This implementation only returns one subsequence.
+*/
+
+use super::deadline::*;
+use super::hunt_szymanski::*;
+
+// Computes the heaviest common subsequence, b_weights are the weights of b's characters
+#[allow(dead_code)]
+pub(crate) fn hcs<T>(a_string: &[T], b_string: &[T], b_weights: &[u64]) -> Vec<T>
+where
+    T: Ord + Clone,
+{
+    hcs_with_deadline(a_string, b_string, b_weights, &mut Deadline::new(None))
+        .expect("HCS without a deadline cannot be aborted")
+}
+
+// same as hcs but returns None once the deadline has passed
+pub(crate) fn hcs_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    b_weights: &[u64],
+    deadline: &mut Deadline,
+) -> Option<Vec<T>>
+where
+    T: Ord + Clone,
+{
+    assert_eq!(b_string.len(), b_weights.len(), "b_weights must match b_string");
+
+    // 1. Find coordinates of all pairs with matching characters (1-based, rows ascending,
+    // columns descending within a row, so that pairs of the same row never chain)
+    let r = matching_characters_coordinates_with_deadline(a_string, b_string, deadline)?;
+
+    // 2. Find the heaviest chain ending at each pair
+    let mut tree = PrefixMaxTree::new(b_string.len());
+    let mut predecessors: Vec<Option<usize>> = Vec::with_capacity(r.len());
+    let mut heaviest: Option<(u64, usize)> = None; // (weight, pair index)
+    for (pair_index, &(_, j)) in r.iter().enumerate() {
+        if deadline.is_exceeded() {
+            return None;
+        }
+        let (predecessor_weight, predecessor) = match tree.query(j - 1) {
+            Some((weight, index)) => (weight, Some(index)),
+            None => (0, None),
+        };
+        let weight = predecessor_weight + b_weights[j - 1];
+        predecessors.push(predecessor);
+        tree.update(j, (weight, pair_index));
+        if heaviest.is_none_or(|(heaviest_weight, _)| weight > heaviest_weight) {
+            heaviest = Some((weight, pair_index));
+        }
+    }
+
+    // 3. Trace back the subsequence
+    let mut hcs: Vec<T> = Vec::new();
+    let mut pair_index = heaviest.map(|(_, index)| index);
+    while let Some(index) = pair_index {
+        hcs.push(b_string[r[index].1 - 1].clone());
+        pair_index = predecessors[index];
+    }
+    hcs.reverse();
+
+    Some(hcs)
+}
+
+// Fenwick tree over 1-based positions answering "the heaviest (weight, pair index) stored
+// at any position <= i"
+struct PrefixMaxTree {
+    nodes: Vec<Option<(u64, usize)>>,
+}
+
+impl PrefixMaxTree {
+    fn new(len: usize) -> PrefixMaxTree {
+        PrefixMaxTree {
+            nodes: vec![None; len + 1],
+        }
+    }
+
+    fn update(&mut self, mut position: usize, value: (u64, usize)) {
+        while position < self.nodes.len() {
+            if self.nodes[position].is_none_or(|node| value.0 > node.0) {
+                self.nodes[position] = Some(value);
+            }
+            position += position & position.wrapping_neg();
+        }
+    }
+
+    fn query(&self, mut position: usize) -> Option<(u64, usize)> {
+        let mut heaviest: Option<(u64, usize)> = None;
+        while position > 0 {
+            if let Some(node) = self.nodes[position] {
+                if heaviest.is_none_or(|heaviest| node.0 > heaviest.0) {
+                    heaviest = Some(node);
+                }
+            }
+            position -= position & position.wrapping_neg();
+        }
+        heaviest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::{delta, Segment};
+    use crate::lcs::hunt_szymanski::lcs_hunt_szymanski;
+    use crate::slicer::Chunk;
+
+    #[test]
+    fn test_hcs_unit_weights() {
+        // with unit weights the heaviest is the longest common subsequence
+        let a_string = "bcdabab".as_bytes();
+        let b_string = "cbacbaaba".as_bytes();
+        let hcs = hcs(a_string, b_string, &[1; 9]);
+        assert_eq!(hcs.len(), lcs_hunt_szymanski(a_string, b_string).len());
+    }
+
+    #[test]
+    fn test_hcs_reuses_more_bytes() {
+        // a large chunk D moved to the front of three small ones: the longest common
+        // subsequence keeps A, B, C while the heaviest one keeps D
+        let chunk_sizes = |hash: u8| -> u64 { if hash == b'D' { 4096 } else { 16 } };
+        let make_chunks = |hashes: &[u8]| -> Vec<Chunk> {
+            let mut end: u64 = 0;
+            hashes
+                .iter()
+                .map(|&hash| {
+                    end += chunk_sizes(hash);
+                    Chunk {
+                        hash: vec![hash],
+                        end,
+                    }
+                })
+                .collect()
+        };
+        let chunks_old = make_chunks(b"ABCD");
+        let chunks_new = make_chunks(b"DABC");
+        let hashes_old: Vec<Vec<u8>> = chunks_old.iter().map(|chunk| chunk.hash.clone()).collect();
+        let hashes_new: Vec<Vec<u8>> = chunks_new.iter().map(|chunk| chunk.hash.clone()).collect();
+        let weights_new: Vec<u64> = b"DABC".iter().map(|&hash| chunk_sizes(hash)).collect();
+
+        let reused_bytes = |segments: Vec<Segment>| -> u64 {
+            segments
+                .iter()
+                .filter(|segment| matches!(segment, Segment::Old(_)))
+                .map(|segment| segment.len())
+                .sum()
+        };
+        let lcs = lcs_hunt_szymanski(&hashes_old, &hashes_new);
+        let hcs = hcs(&hashes_old, &hashes_new, &weights_new);
+        assert_eq!(lcs, vec![vec![b'A'], vec![b'B'], vec![b'C']]);
+        assert_eq!(hcs, vec![vec![b'D']]);
+        assert_eq!(reused_bytes(delta(&chunks_old, &chunks_new, &lcs)), 48);
+        assert_eq!(reused_bytes(delta(&chunks_old, &chunks_new, &hcs)), 4096);
+    }
+}
@@ -0,0 +1,162 @@
+/*
+Computes the Longest Common Subsequence in linear space using Hirschberg's divide and conquer
+algorithm, as proposed in:
+https://dl.acm.org/doi/10.1145/360825.360861
+
+TIME:   O(nm)
+SPACE:  O(n+m)
+
+where:
+n,m - the legths of the inputs
+
+a is split in halves; the last row of the LCS lengths table of the first half against b and
+that of the (reversed) second half against the (reversed) b, each computed keeping only two
+rows, tell where b must be split so that the LCS of the halves add up to the LCS of the
+whole. Both pairs of halves are then solved recursively, the recursion being O(log n) deep.
+
+Unlike Nakatsu, whose L matrix is quadratic in the inputs length, the memory stays linear,
+at the price of quadratic time even for similar inputs. The common prefix and suffix are
+stripped first (at each level), which for similar inputs takes most of the work away.
+
+This implementation only returns one subsequence.
+*/
+
+use super::deadline::*;
+
+#[allow(dead_code)]
+pub(crate) fn lcs_hirschberg<T>(a_string: &[T], b_string: &[T]) -> Vec<T>
+where
+    T: Ord + Clone,
+{
+    lcs_hirschberg_with_deadline(a_string, b_string, &mut Deadline::new(None))
+        .expect("LCS without a deadline cannot be aborted")
+}
+
+// same as lcs_hirschberg but returns None once the deadline has passed
+pub(crate) fn lcs_hirschberg_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    deadline: &mut Deadline,
+) -> Option<Vec<T>>
+where
+    T: Ord + Clone,
+{
+    let mut lcs: Vec<T> = Vec::with_capacity(a_string.len().min(b_string.len()));
+    hirschberg(a_string, b_string, &mut lcs, deadline)?;
+    Some(lcs)
+}
+
+// appends the LCS of a and b to lcs
+fn hirschberg<T>(a_string: &[T], b_string: &[T], lcs: &mut Vec<T>, deadline: &mut Deadline) -> Option<()>
+where
+    T: Ord + Clone,
+{
+    // the common prefix and suffix belong to an LCS
+    let prefix_len = a_string
+        .iter()
+        .zip(b_string)
+        .take_while(|(a_character, b_character)| a_character == b_character)
+        .count();
+    lcs.extend_from_slice(&a_string[..prefix_len]);
+    let (a_string, b_string) = (&a_string[prefix_len..], &b_string[prefix_len..]);
+    let suffix_len = a_string
+        .iter()
+        .rev()
+        .zip(b_string.iter().rev())
+        .take_while(|(a_character, b_character)| a_character == b_character)
+        .count();
+    let suffix = &a_string[a_string.len() - suffix_len..];
+    let a_string = &a_string[..a_string.len() - suffix_len];
+    let b_string = &b_string[..b_string.len() - suffix_len];
+
+    if a_string.is_empty() || b_string.is_empty() {
+        // nothing more in common
+    } else if a_string.len() == 1 {
+        if b_string.contains(&a_string[0]) {
+            lcs.push(a_string[0].clone());
+        }
+    } else {
+        let middle = a_string.len() / 2;
+        let forward = last_row(a_string[..middle].iter(), b_string.iter(), b_string.len(), deadline)?;
+        let backward = last_row(
+            a_string[middle..].iter().rev(),
+            b_string.iter().rev(),
+            b_string.len(),
+            deadline,
+        )?;
+        // the split of b maximizing the sum of the halves' LCS lengths
+        let b_split = (0..=b_string.len())
+            .max_by_key(|&k| (forward[k] + backward[b_string.len() - k], std::cmp::Reverse(k)))
+            .unwrap();
+        hirschberg(&a_string[..middle], &b_string[..b_split], lcs, deadline)?;
+        hirschberg(&a_string[middle..], &b_string[b_split..], lcs, deadline)?;
+    }
+    lcs.extend_from_slice(suffix);
+    Some(())
+}
+
+// the last row of the LCS lengths table of a against b: the LCS length of a and each prefix
+// of b, computed keeping a single row
+fn last_row<'a, T, A, B>(a_iter: A, b_iter: B, b_len: usize, deadline: &mut Deadline) -> Option<Vec<usize>>
+where
+    T: Eq + 'a,
+    A: Iterator<Item = &'a T>,
+    B: Iterator<Item = &'a T> + Clone,
+{
+    let mut row: Vec<usize> = vec![0; b_len + 1];
+    for a_character in a_iter {
+        let mut diagonal = 0; // the previous row's value left of the current cell
+        for (j, b_character) in b_iter.clone().enumerate() {
+            if deadline.is_exceeded() {
+                return None;
+            }
+            let above = row[j + 1];
+            row[j + 1] = if a_character == b_character {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    Some(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcs::nakatsu::lcs_nakatsu;
+
+    fn is_subsequence(subsequence: &[u8], sequence: &[u8]) -> bool {
+        let mut sequence = sequence.iter();
+        subsequence
+            .iter()
+            .all(|item| sequence.any(|sequence_item| sequence_item == item))
+    }
+
+    #[test]
+    fn test_lcs_hirschberg() {
+        // the same cases as Nakatsu's; the subsequence found may differ from Nakatsu's (there
+        // can be many), but not its length
+        let cases: [(&str, &str, &str); 3] = [
+            ("bcdabab", "cbacbaaba", "cabab"), // Nakatsu finds "bcaba"
+            ("eiger", "equilibrium", "eir"),
+            (
+                "a blockchain is a growing list of records",
+                "the blockchain - an ever-growing decentralized ledger",
+                " blockchain  a growing li ed", // Nakatsu finds " blockchain  a growing li er"
+            ),
+        ];
+        for (a_string, b_string, expected) in cases {
+            let (a_string, b_string) = (a_string.as_bytes(), b_string.as_bytes()); // ascii-only
+            let lcs = lcs_hirschberg(a_string, b_string);
+            assert_eq!(String::from_utf8(lcs.clone()).unwrap(), expected);
+            assert_eq!(lcs.len(), lcs_nakatsu(a_string, b_string).len());
+            assert!(is_subsequence(&lcs, a_string) && is_subsequence(&lcs, b_string));
+        }
+
+        assert!(lcs_hirschberg::<u8>(&[], &[]).is_empty());
+        assert!(lcs_hirschberg("abc".as_bytes(), "xyz".as_bytes()).is_empty());
+        assert_eq!(lcs_hirschberg("abc".as_bytes(), "abc".as_bytes()), b"abc");
+    }
+}
@@ -14,28 +14,46 @@ Doesn't seem to be the best choice for network file system where the motivation
 that files usually only differ slightly.
 Also, it is quadratic space so the allocated memory grows big for larger inputs.
 This is the algorithm used by Linux diff.
+has_too_many_matching_pairs checks up front, without generating the pairs, whether r is
+large enough relative to the input lengths for Nakatsu to be used instead.
 
 This implementation only returns one subsequence.
 
-Possible optimizations:
-1. Improve the way new nodes are determined for each row. Now it's done by comparing rows of
-   head_indices. It should be done with an unordered set data structure (hash table?) where
-   moved head_indices are put into (and updated) while the inner while loop is running. Then
-   when the loop completes, these indices can be used to insert new nodes.
-2. Searching for next active node while backtracing should be made better, at the moment it
-   simply checks the nodes one-by-one.
+Nodes (the points where a head index changes) are determined from the set of head indices
+moved while processing a row, so a row costs O(changes) rather than O(row length). For the
+backtrace, the nodes are also grouped by block; within a block they come in ascending row
+(and descending column) order, so the predecessor of a node is found with a binary search.
 */
 
+use super::deadline::*;
 use crate::helper::*;
+use std::collections::{BTreeMap, HashSet};
+
+// the number of matching pairs per input character above which Hunt-Szymanski isn't worth
+// using anymore (it then approaches O(n^2 log n) time and quadratic memory)
+const MAX_MATCHING_PAIRS_PER_CHARACTER: usize = 64;
 
 // Computes the longest common subsequence
 #[allow(dead_code)]
 pub(crate) fn lcs_hunt_szymanski<T>(a_string: &[T], b_string: &[T]) -> Vec<T>
+where
+    T: Ord + Clone,
+{
+    lcs_hunt_szymanski_with_deadline(a_string, b_string, &mut Deadline::new(None))
+        .expect("LCS without a deadline cannot be aborted")
+}
+
+// same as lcs_hunt_szymanski but returns None once the deadline has passed
+pub(crate) fn lcs_hunt_szymanski_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    deadline: &mut Deadline,
+) -> Option<Vec<T>>
 where
     T: Ord + Clone,
 {
     // 1. Find coordinates of all pairs with matching characters
-    let r = matching_characters_coordinates(a_string, b_string);
+    let r = matching_characters_coordinates_with_deadline(a_string, b_string, deadline)?;
     let r_len = r.len();
 
     // 2. Determine head indices of dynamic programming matrix and store node coordinates
@@ -45,75 +63,92 @@ where
     let a_len = a_string.len();
     let mut r_index: usize = 0;
     let mut nodes: Vec<(usize, usize, usize)> = vec![(0, 0, 0)]; // row, col, block_index
-    let mut prev_row_head_indices: Vec<usize>;
+    let mut block_nodes: Vec<Vec<usize>> = vec![vec![0]]; // node indices for each block
+    let mut moved_blocks: HashSet<usize> = HashSet::new();
+    let mut new_node_blocks: Vec<usize> = Vec::new();
     for i in 1..=a_len {
-        // TODO: this is needed to store nodes while we run the algorithm; it should be
-        // replaced with more optimized approach using HashSet or similar data structure for
-        // storing unique values - we'd store all head_indices that changed over the course of
-        // the inner (while) loop and then add nodes when the loop completes
-        prev_row_head_indices = head_indices.clone();
-
         // here we drop the r's for already-processed rows and only perform binary
         // search (lower_bound) within the remaining r's
         // for efficient backtracing, we store nodes, as outlined in the original paper
         let trailing_r = &r[r_index..r_len];
 
         r_index += lower_bound_by(trailing_r, |lhs| lhs.0.cmp(&i)).unwrap_or(0);
-        // iterate over all active indices (matching char pairs) for this row
+        // iterate over all active indices (matching char pairs) for this row, keeping track
+        // of the blocks whose head index has moved (head indices never move back within a row)
         while r_index != r_len && r[r_index].0 == i {
+            if deadline.is_exceeded() {
+                return None;
+            }
             let j = r[r_index].1;
             if let Some(successor) = lower_bound(j, &head_indices) {
-                head_indices[successor] = j;
+                if head_indices[successor] != j {
+                    head_indices[successor] = j;
+                    moved_blocks.insert(successor);
+                }
             } else {
                 head_indices.push(j);
+                moved_blocks.insert(head_indices.len() - 1);
             }
             r_index += 1;
         }
 
         // determines nodes
-        let block_count = head_indices.len();
-        let prev_node_block_count = prev_row_head_indices.len();
-        for block_index in 0..block_count {
-            if block_index == prev_node_block_count
-                || head_indices[block_index] != prev_row_head_indices[block_index]
-            {
-                nodes.push((i, head_indices[block_index], block_index));
+        new_node_blocks.extend(moved_blocks.drain());
+        new_node_blocks.sort_unstable();
+        for block_index in new_node_blocks.drain(..) {
+            if block_index == block_nodes.len() {
+                block_nodes.push(Vec::new());
             }
+            block_nodes[block_index].push(nodes.len());
+            nodes.push((i, head_indices[block_index], block_index));
         }
     }
 
     // 3. Trace back the subsequence
-    let mut active_node_index = nodes.len() - 1;
-    let mut char_indices: Vec<usize> = Vec::new();
+    // start with the (last) node of the highest block, which is where the LCS ends; the
+    // predecessor of a node is the last node of the block below it from an earlier row
+    let lcs_len = head_indices.len() - 1;
+    let mut active_node_index = *block_nodes[lcs_len].last().unwrap();
+    let mut char_indices: Vec<usize> = Vec::with_capacity(lcs_len);
     while active_node_index > 0 {
         let active_node = nodes[active_node_index];
         char_indices.push(active_node.1);
-        let next_active_block_index = active_node.2 - 1;
-        let mut node_index = active_node_index - 1;
-        while node_index > 0 {
-            let node = nodes[node_index];
-            if node.0 < active_node.0 && node.1 < active_node.1 && node.2 == next_active_block_index
-            {
-                break;
-            }
-            node_index -= 1;
-        }
-        active_node_index = node_index;
+        let candidates = &block_nodes[active_node.2 - 1];
+        let candidate_count = lower_bound_by(candidates, |&node_index| {
+            nodes[node_index].0.cmp(&active_node.0)
+        })
+        .unwrap_or(candidates.len());
+        active_node_index = candidates[candidate_count - 1];
+        debug_assert!(nodes[active_node_index].1 < active_node.1);
     }
 
-    let mut lcs: Vec<T> = Vec::with_capacity(nodes.len());
+    let mut lcs: Vec<T> = Vec::with_capacity(char_indices.len());
     for char_index in char_indices.iter().rev() {
         lcs.push(b_string[char_index - 1].clone());
     }
 
-    lcs
+    Some(lcs)
 }
 
 // Returns the coordinates of the matching characters (cartesian product of their indices within the strings)
 // This method is faster than checking all cartesian product elements (brute force) and can be done in
 // O(r log n + m log(m)) instead of O(n*m)
 #[allow(dead_code)]
-fn matching_characters_coordinates<T>(a_string: &[T], b_string: &[T]) -> Vec<(usize, usize)>
+pub(crate) fn matching_characters_coordinates<T>(a_string: &[T], b_string: &[T]) -> Vec<(usize, usize)>
+where
+    T: Ord,
+{
+    matching_characters_coordinates_with_deadline(a_string, b_string, &mut Deadline::new(None))
+        .expect("Matching without a deadline cannot be aborted")
+}
+
+// same as matching_characters_coordinates but returns None once the deadline has passed
+// (the number of pairs can be quadratic in the worst case)
+pub(crate) fn matching_characters_coordinates_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    deadline: &mut Deadline,
+) -> Option<Vec<(usize, usize)>>
 where
     T: Ord,
 {
@@ -130,8 +165,8 @@ where
         .collect();
 
     // sort by character
-    a_string.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
-    b_string.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+    a_string.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+    b_string.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
 
     // iterate over matching characters and get cross product (indices of matching characters)
     let mut matching_character_coords: Vec<(usize, usize)> = Vec::new();
@@ -145,6 +180,9 @@ where
         let b_first_index_matching_a = b_index;
         while b_index < b_string_len && b_string[b_index].0 == a.0 {
             // store matching positions pairs
+            if deadline.is_exceeded() {
+                return None;
+            }
             let b = b_string[b_index];
             matching_character_coords.push((a.1 + 1, b.1 + 1));
             b_index += 1;
@@ -161,12 +199,43 @@ where
         }
     });
 
-    matching_character_coords
+    Some(matching_character_coords)
+}
+
+// Returns whether the inputs have too many matching pairs for Hunt-Szymanski, signaling that
+// Nakatsu should be used instead. The pairs are counted from the characters' occurrences
+// rather than generated, O((n+m) log m), stopping as soon as the limit is exceeded.
+pub(crate) fn has_too_many_matching_pairs<T>(a_string: &[T], b_string: &[T]) -> bool
+where
+    T: Ord,
+{
+    let limit = MAX_MATCHING_PAIRS_PER_CHARACTER.saturating_mul(a_string.len() + b_string.len());
+    let mut b_occurrences: BTreeMap<&T, usize> = BTreeMap::new();
+    for character in b_string {
+        *b_occurrences.entry(character).or_insert(0) += 1;
+    }
+    let mut pair_count: usize = 0;
+    for character in a_string {
+        pair_count += b_occurrences.get(character).copied().unwrap_or(0);
+        if pair_count > limit {
+            return true;
+        }
+    }
+    false
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lcs::cross_validation::{lcs_len, random_symbols};
+    use std::time::Instant;
+
+    fn is_subsequence(subsequence: &[u64], sequence: &[u64]) -> bool {
+        let mut sequence = sequence.iter();
+        subsequence
+            .iter()
+            .all(|item| sequence.any(|sequence_item| sequence_item == item))
+    }
 
     #[test]
     fn test_lcs_hunt_szymanski_matching_character_coordinates() {
@@ -196,4 +265,54 @@ mod tests {
         let lcs_string = String::from_utf8(lcs).unwrap();
         assert_eq!(lcs_string, " blockchain  a growing li ed");
     }
+
+    #[test]
+    fn test_lcs_hunt_szymanski_traceback() {
+        // the last node recorded (c, in the last row) is in a lower block than the one the
+        // LCS ends in; the traceback used to start from it, returning "c"
+        let lcs = lcs_hunt_szymanski("abc".as_bytes(), "cab".as_bytes());
+        assert_eq!(String::from_utf8(lcs).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_lcs_hunt_szymanski_low_similarity() {
+        let a = random_symbols(2000, 1000, 1);
+        let b = random_symbols(2000, 1000, 2);
+        let lcs = lcs_hunt_szymanski(&a, &b);
+        assert!(is_subsequence(&lcs, &a));
+        assert!(is_subsequence(&lcs, &b));
+        assert_eq!(lcs.len(), lcs_len(&a, &b));
+    }
+
+    #[test]
+    fn test_lcs_hunt_szymanski_too_many_matching_pairs() {
+        // near-identical inputs over a small alphabet, r approaches n^2 / 4
+        let a = random_symbols(2000, 4, 1);
+        let mut b = a.clone();
+        b[100] = 4;
+        b.remove(1000);
+        assert!(has_too_many_matching_pairs(&a, &b));
+
+        // near-identical inputs with (mostly) distinct characters, r is about n
+        let a: Vec<u64> = (0..2000).collect();
+        let mut b = a.clone();
+        b[100] = 4;
+        assert!(!has_too_many_matching_pairs(&a, &b));
+
+        let a = random_symbols(2000, 1000, 1);
+        let b = random_symbols(2000, 1000, 2);
+        assert!(!has_too_many_matching_pairs(&a, &b));
+        assert!(!has_too_many_matching_pairs(&a, &[]));
+    }
+
+    // run with: cargo test --release bench_lcs_hunt_szymanski -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_lcs_hunt_szymanski_low_similarity() {
+        let a = random_symbols(100000, 50000, 1);
+        let b = random_symbols(100000, 50000, 2);
+        let start = Instant::now();
+        let lcs = lcs_hunt_szymanski(&a, &b);
+        println!("LCS of length {} found in {:?}", lcs.len(), start.elapsed());
+    }
 }
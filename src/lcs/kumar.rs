@@ -0,0 +1,255 @@
+/*
+Computes the Longest Common Subsequence in linear space and in time depending on how much
+the inputs differ, the bounds of Kumar and Rangan's algorithm:
+https://www.academia.edu/4127816/A_Linear_Space_Algorithm_for_the_LCS_Problem
+
+TIME:   O(n(m-p))
+SPACE:  O(n)
+
+where:
+n,m - the legths of the inputs (n the longer one)
+p   - the length of the LCS
+
+Like Nakatsu, the work is proportional to how much the inputs differ, but it doesn't keep
+the quadratic L matrix to trace the subsequence back: as in Hirschberg's algorithm, the
+inputs are split where an LCS crosses their middle and both halves are solved recursively.
+The split is found with Myers' greedy search of the furthest reaching paths, run from both
+ends until they overlap (the "middle snake"): with D = n + m - 2p unmatched characters, it
+explores at most D diagonals each way, so the split costs O((n+m)D) = O(n(m-p)) time and
+O(D) space. As D halves at each level of the recursion, the whole takes the same time.
+
+This implementation only returns one subsequence.
+*/
+
+use super::deadline::*;
+
+#[allow(dead_code)]
+pub(crate) fn lcs_kumar<T>(a_string: &[T], b_string: &[T]) -> Vec<T>
+where
+    T: Ord + Clone,
+{
+    lcs_kumar_with_deadline(a_string, b_string, &mut Deadline::new(None))
+        .expect("LCS without a deadline cannot be aborted")
+}
+
+// same as lcs_kumar but returns None once the deadline has passed
+pub(crate) fn lcs_kumar_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    deadline: &mut Deadline,
+) -> Option<Vec<T>>
+where
+    T: Ord + Clone,
+{
+    let mut lcs: Vec<T> = Vec::with_capacity(a_string.len().min(b_string.len()));
+    kumar(a_string, b_string, &mut lcs, deadline)?;
+    Some(lcs)
+}
+
+// appends the LCS of a and b to lcs
+fn kumar<T>(a_string: &[T], b_string: &[T], lcs: &mut Vec<T>, deadline: &mut Deadline) -> Option<()>
+where
+    T: Ord + Clone,
+{
+    // the common prefix and suffix belong to an LCS
+    let prefix_len = a_string
+        .iter()
+        .zip(b_string)
+        .take_while(|(a_character, b_character)| a_character == b_character)
+        .count();
+    lcs.extend_from_slice(&a_string[..prefix_len]);
+    let (a_string, b_string) = (&a_string[prefix_len..], &b_string[prefix_len..]);
+    let suffix_len = a_string
+        .iter()
+        .rev()
+        .zip(b_string.iter().rev())
+        .take_while(|(a_character, b_character)| a_character == b_character)
+        .count();
+    let suffix = &a_string[a_string.len() - suffix_len..];
+    let a_string = &a_string[..a_string.len() - suffix_len];
+    let b_string = &b_string[..b_string.len() - suffix_len];
+
+    if !a_string.is_empty() && !b_string.is_empty() {
+        if let Some((a_split, b_split)) = middle_split(a_string, b_string, deadline)? {
+            kumar(&a_string[..a_split], &b_string[..b_split], lcs, deadline)?;
+            kumar(&a_string[a_split..], &b_string[b_split..], lcs, deadline)?;
+        } // else nothing in common
+    }
+    lcs.extend_from_slice(suffix);
+    Some(())
+}
+
+// Finds where an LCS crosses the middle of the edit graph: the furthest reaching paths are
+// extended from the start (forward) and from the end (backward), one more unmatched
+// character at a time, until a forward and a backward path overlap on the same diagonal.
+// The inputs are expected to differ in their first and last characters. Returns the split
+// of a and b, None if they have nothing in common (and None as a whole once the deadline
+// has passed)
+fn middle_split<T>(a_string: &[T], b_string: &[T], deadline: &mut Deadline) -> Option<Option<(usize, usize)>>
+where
+    T: Eq,
+{
+    let (a_len, b_len) = (a_string.len() as isize, b_string.len() as isize);
+    let max_d = (a_len + b_len + 1) / 2;
+    let v_offset = max_d;
+    let v_len = 2 * max_d + 2;
+    // the furthest x reached on each diagonal k = x - y, from the start and from the end
+    let mut v_forward: Vec<isize> = vec![-1; v_len as usize];
+    let mut v_backward: Vec<isize> = vec![-1; v_len as usize];
+    v_forward[(v_offset + 1) as usize] = 0;
+    v_backward[(v_offset + 1) as usize] = 0;
+    let delta = a_len - b_len;
+    // with an odd delta the forward paths meet the backward ones, otherwise the reverse
+    let front = delta % 2 != 0;
+    // the diagonals leaving the graph are skipped from then on
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            if deadline.is_exceeded() {
+                return None;
+            }
+            let k1_offset = (v_offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && v_forward[k1_offset - 1] < v_forward[k1_offset + 1]) {
+                v_forward[k1_offset + 1]
+            } else {
+                v_forward[k1_offset - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < a_len && y1 < b_len && a_string[x1 as usize] == b_string[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+            v_forward[k1_offset] = x1;
+            if x1 > a_len {
+                k1_end += 2; // ran off the right of the graph
+            } else if y1 > b_len {
+                k1_start += 2; // ran off the bottom of the graph
+            } else if front {
+                let k2_offset = v_offset + delta - k1;
+                if (0..v_len).contains(&k2_offset) && v_backward[k2_offset as usize] != -1 {
+                    // mirror x2 onto the top-left coordinate system
+                    let x2 = a_len - v_backward[k2_offset as usize];
+                    if x1 >= x2 {
+                        return Some(Some((x1 as usize, y1 as usize)));
+                    }
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            if deadline.is_exceeded() {
+                return None;
+            }
+            let k2_offset = (v_offset + k2) as usize;
+            let mut x2 = if k2 == -d || (k2 != d && v_backward[k2_offset - 1] < v_backward[k2_offset + 1]) {
+                v_backward[k2_offset + 1]
+            } else {
+                v_backward[k2_offset - 1] + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < a_len
+                && y2 < b_len
+                && a_string[(a_len - x2 - 1) as usize] == b_string[(b_len - y2 - 1) as usize]
+            {
+                x2 += 1;
+                y2 += 1;
+            }
+            v_backward[k2_offset] = x2;
+            if x2 > a_len {
+                k2_end += 2; // ran off the left of the graph
+            } else if y2 > b_len {
+                k2_start += 2; // ran off the top of the graph
+            } else if !front {
+                let k1_offset = v_offset + delta - k2;
+                if (0..v_len).contains(&k1_offset) && v_forward[k1_offset as usize] != -1 {
+                    let x1 = v_forward[k1_offset as usize];
+                    let y1 = v_offset + x1 - k1_offset;
+                    // mirror x2 onto the top-left coordinate system
+                    if x1 >= a_len - x2 {
+                        return Some(Some((x1 as usize, y1 as usize)));
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    Some(None) // no path crosses the middle, there's no common character
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcs::lcs::nakatsu_memory;
+    use crate::lcs::nakatsu::lcs_nakatsu;
+
+    fn is_subsequence<T: PartialEq>(subsequence: &[T], sequence: &[T]) -> bool {
+        let mut sequence = sequence.iter();
+        subsequence
+            .iter()
+            .all(|item| sequence.any(|sequence_item| sequence_item == item))
+    }
+
+    #[test]
+    fn test_lcs_kumar() {
+        // the same cases as Nakatsu's; the subsequence found may differ from Nakatsu's (there
+        // can be many), but not its length
+        let cases: [(&str, &str); 3] = [
+            ("bcdabab", "cbacbaaba"),
+            ("eiger", "equilibrium"),
+            (
+                "a blockchain is a growing list of records",
+                "the blockchain - an ever-growing decentralized ledger",
+            ),
+        ];
+        for (a_string, b_string) in cases {
+            let (a_string, b_string) = (a_string.as_bytes(), b_string.as_bytes()); // ascii-only
+            let lcs = lcs_kumar(a_string, b_string);
+            assert_eq!(lcs.len(), lcs_nakatsu(a_string, b_string).len());
+            assert!(is_subsequence(&lcs, a_string) && is_subsequence(&lcs, b_string));
+            // and either way round
+            assert_eq!(lcs_kumar(b_string, a_string).len(), lcs.len());
+        }
+
+        assert!(lcs_kumar::<u8>(&[], &[]).is_empty());
+        assert!(lcs_kumar("abc".as_bytes(), "xyz".as_bytes()).is_empty());
+        assert_eq!(lcs_kumar("abc".as_bytes(), "abc".as_bytes()), b"abc");
+        assert_eq!(lcs_kumar("abc".as_bytes(), "xabcx".as_bytes()), b"abc");
+    }
+
+    #[test]
+    fn test_lcs_kumar_large() {
+        // 100k-chunk sequences (as of a multi-gigabyte file): Nakatsu's matrix would take
+        // tens of gigabytes, Kumar only needs memory proportional to the lengths
+        let len: usize = 100_000;
+        assert!(nakatsu_memory(len, len) > 50_000_000_000);
+
+        // the chunk hashes are unique; every 100th one is replaced by a new one, every 250th
+        // deleted and a new one inserted every 300, so the LCS length is known exactly
+        // (multiplying by an odd constant is a bijection, so distinct indices give distinct
+        // hashes)
+        let hash = |index: u64| index.wrapping_mul(0x9e3779b97f4a7c15);
+        let a: Vec<u64> = (0..len as u64).map(hash).collect();
+        let mut b: Vec<u64> = Vec::with_capacity(len);
+        let mut new_index = len as u64; // never in a
+        let mut lcs_len = 0;
+        for (index, &a_hash) in a.iter().enumerate() {
+            if index % 300 == 0 {
+                b.push(hash(new_index));
+                new_index += 1;
+            }
+            if index % 100 == 0 {
+                b.push(hash(new_index));
+                new_index += 1;
+            } else if index % 250 != 0 {
+                b.push(a_hash);
+                lcs_len += 1;
+            }
+        }
+        let lcs = lcs_kumar(&a, &b);
+        assert_eq!(lcs.len(), lcs_len);
+        assert!(is_subsequence(&lcs, &a) && is_subsequence(&lcs, &b));
+    }
+}
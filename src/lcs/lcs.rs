@@ -24,6 +24,21 @@
     n,m - the legths of the inputs
     p   - the length of the LCS
 
+    Hirschberg, linear space divide and conquer (implemented):
+    https://dl.acm.org/doi/10.1145/360825.360861
+    TIME:   O(nm)
+    SPACE:  O(n+m)
+    where:
+    n,m - the legths of the inputs
+
+    Kumar (implemented, the middle split found with Myers' bidirectional search):
+    https://www.academia.edu/4127816/A_Linear_Space_Algorithm_for_the_LCS_Problem
+    TIME:   O(n(m-p))
+    SPACE:  O(n)
+    where:
+    n,m - the legths of the inputs
+    p   - the length of the LCS
+
     Hirschberg:
     https://www.ics.uci.edu/~dan/pubs/p664-hirschberg.pdf
     Paper outlines two algorithms:
@@ -34,14 +49,6 @@
     p   - the length of the LCS sequence
 
 
-    Another very promising algorithm that seems to offer the best properties is.
-    It's only been discovered recently and has not been implemented yet.
-    
-    Kumar:
-    https://www.academia.edu/4127816/A_Linear_Space_Algorithm_for_the_LCS_Problem
-    TIME:   O(n(m-p))
-    SPACE:  O(n)
-
     This doc, describing bit-vector approach to speeding up LCS calculation is probably worth reading as well.
     Not sure it can help our case as it seems to require allocating arrays for the entire alphabet (hash space).
     https://www.researchgate.net/publication/3940105_Speeding-up_Hirschberg_and_Hunt-Szymanski_LCS_Algorithms
@@ -49,4 +56,223 @@
     Returning all solution subsequences (there can be many) would potentially allow us to check the preferred one
     based on the chunk size (to minimize the amount of data sent over the network) but it's not sure whether the
     pros (bandwidth reduction) outweigh the cons (more computations).
+    Instead, the Heaviest Common Subsequence (implemented) directly maximizes the total size of the matched
+    chunks rather than their count:
+    TIME:   O((r+m) log n)
+    SPACE:  O(r+n)
+
+    Whatever the algorithm, inputs without any common character (e.g. a completely rewritten
+    file) are detected first in O(n+m) time, returning the empty LCS without running it.
 */
+
+use super::deadline::*;
+use super::heaviest::*;
+use super::hirschberg::*;
+use super::hunt_szymanski::*;
+use super::kumar::*;
+use super::nakatsu::*;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::Hash;
+use std::time::Instant;
+
+// the share of the inputs' elements that need to match for the inputs to be considered similar
+const SIMILARITY_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LcsAlgorithm {
+    Nakatsu,       // efficient for similar inputs
+    HuntSzymanski, // efficient for dissimilar inputs
+    Weighted,      // heaviest common subsequence, maximizes the matched bytes rather than chunks
+    Hirschberg,    // linear space, for inputs too large for Nakatsu's quadratic matrix
+    Kumar,         // linear space and, like Nakatsu, efficient for similar inputs
+}
+
+// computes the longest common subsequence using the chosen algorithm; b_weights (the weights
+// of b's characters, e.g. chunk sizes) are only used by the Weighted algorithm
+#[allow(dead_code)]
+pub(crate) fn lcs<T>(
+    algorithm: LcsAlgorithm,
+    a_string: &[T],
+    b_string: &[T],
+    b_weights: &[u64],
+) -> Vec<T>
+where
+    T: Ord + Clone + Hash,
+{
+    lcs_with_deadline(algorithm, a_string, b_string, b_weights, None)
+        .expect("LCS without a deadline cannot be aborted")
+}
+
+// the number of LCS computations run by the current thread, so that tests can check that
+// the LCS isn't computed more than needed
+#[cfg(test)]
+thread_local! {
+    pub(crate) static LCS_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// same as lcs but gives up (returning None) once the deadline, if any, has passed
+pub(crate) fn lcs_with_deadline<T>(
+    algorithm: LcsAlgorithm,
+    a_string: &[T],
+    b_string: &[T],
+    b_weights: &[u64],
+    deadline: Option<Instant>,
+) -> Option<Vec<T>>
+where
+    T: Ord + Clone + Hash,
+{
+    #[cfg(test)]
+    LCS_CALLS.with(|calls| calls.set(calls.get() + 1));
+    if are_disjoint(a_string, b_string) {
+        return Some(Vec::new()); // exact, so even once the deadline has passed
+    }
+    let deadline = &mut Deadline::new(deadline);
+    match algorithm {
+        LcsAlgorithm::Nakatsu => lcs_nakatsu_with_deadline(a_string, b_string, deadline),
+        LcsAlgorithm::HuntSzymanski => {
+            lcs_hunt_szymanski_with_deadline(a_string, b_string, deadline)
+        }
+        LcsAlgorithm::Weighted => hcs_with_deadline(a_string, b_string, b_weights, deadline),
+        LcsAlgorithm::Hirschberg => lcs_hirschberg_with_deadline(a_string, b_string, deadline),
+        LcsAlgorithm::Kumar => lcs_kumar_with_deadline(a_string, b_string, deadline),
+    }
+}
+
+// whether the inputs have no character in common, O(n+m)
+pub(crate) fn are_disjoint<T>(a_string: &[T], b_string: &[T]) -> bool
+where
+    T: Eq + Hash,
+{
+    let a_characters: HashSet<&T> = a_string.iter().collect();
+    !b_string.iter().any(|character| a_characters.contains(character))
+}
+
+// cheap similarity estimate, O((n+m) log n): the share of b's characters that appear
+// anywhere in a; it's an upper bound of the actual p/m ratio
+pub(crate) fn match_ratio<T>(a_string: &[T], b_string: &[T]) -> f32
+where
+    T: Ord,
+{
+    if b_string.is_empty() {
+        return 1.0;
+    }
+    let a_characters: BTreeSet<&T> = a_string.iter().collect();
+    let matching = b_string
+        .iter()
+        .filter(|character| a_characters.contains(character))
+        .count();
+    matching as f32 / b_string.len() as f32
+}
+
+// picks Nakatsu when the inputs appear similar (where it approaches linear time) and
+// Hunt-Szymanski otherwise (where Nakatsu degrades towards O(nm) time, quadratic space),
+// unless the inputs have so many matching pairs that Hunt-Szymanski would degrade even more
+pub(crate) fn choose_lcs_algorithm<T>(a_string: &[T], b_string: &[T]) -> LcsAlgorithm
+where
+    T: Ord,
+{
+    if match_ratio(a_string, b_string) >= SIMILARITY_THRESHOLD
+        || has_too_many_matching_pairs(a_string, b_string)
+    {
+        LcsAlgorithm::Nakatsu
+    } else {
+        LcsAlgorithm::HuntSzymanski
+    }
+}
+
+// the memory taken by Nakatsu's L matrix, (m+1)^2 entries where m is the shorter input length
+pub(crate) fn nakatsu_memory(a_len: usize, b_len: usize) -> u64 {
+    let m = a_len.min(b_len) as u64 + 1;
+    m.saturating_mul(m)
+        .saturating_mul(std::mem::size_of::<usize>() as u64)
+}
+
+// picks the algorithm as choose_lcs_algorithm does, unless Nakatsu's quadratic matrix doesn't
+// fit the memory budget, in which case Kumar is used: it's linear space, so it fits whatever
+// the inputs (Hunt-Szymanski's memory grows with the number of matching pairs, which can be
+// quadratic too), and it keeps Nakatsu's O(n(m-p)) time rather than Hirschberg's O(nm)
+pub(crate) fn choose_lcs_algorithm_for_budget<T>(
+    a_string: &[T],
+    b_string: &[T],
+    memory_budget: u64,
+) -> LcsAlgorithm
+where
+    T: Ord,
+{
+    if nakatsu_memory(a_string.len(), b_string.len()) > memory_budget {
+        LcsAlgorithm::Kumar
+    } else {
+        choose_lcs_algorithm(a_string, b_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_lcs_algorithm() {
+        let a_string = "a blockchain is a growing list of records".as_bytes();
+        let b_string = "a blockchain is a growing list of blocks".as_bytes();
+        assert_eq!(choose_lcs_algorithm(a_string, b_string), LcsAlgorithm::Nakatsu);
+
+        let a_string = "abcdefghijklm".as_bytes();
+        let b_string = "nopqrstuvwxyzan".as_bytes();
+        let algorithm = choose_lcs_algorithm(a_string, b_string);
+        assert_eq!(algorithm, LcsAlgorithm::HuntSzymanski);
+
+        // the result is still a valid LCS
+        let lcs = lcs(algorithm, a_string, b_string, &[]);
+        assert_eq!(lcs, "a".as_bytes());
+        assert_eq!(lcs.len(), lcs_nakatsu(a_string, b_string).len());
+
+        // few of b's characters appear in a, but the repeated ones make r quadratic
+        let mut a_string: Vec<u64> = vec![0; 500];
+        a_string.extend(500..1000);
+        let mut b_string: Vec<u64> = vec![0; 400];
+        b_string.extend(1000..1600);
+        assert!(match_ratio(&a_string, &b_string) < SIMILARITY_THRESHOLD);
+        assert_eq!(choose_lcs_algorithm(&a_string, &b_string), LcsAlgorithm::Nakatsu);
+    }
+
+    #[test]
+    fn test_lcs_disjoint() {
+        let a_string: Vec<u64> = (0..2000).collect();
+        let b_string: Vec<u64> = (2000..5000).collect();
+        assert!(are_disjoint(&a_string, &b_string));
+        assert!(!are_disjoint(&a_string, &[4000, 1999]));
+        assert!(are_disjoint::<u64>(&[], &[]));
+
+        // the empty LCS is returned right away: even a deadline which has already passed
+        // doesn't abort it
+        let deadline = Some(Instant::now() - std::time::Duration::from_secs(1));
+        let weights: Vec<u64> = vec![1; b_string.len()];
+        for algorithm in [LcsAlgorithm::Nakatsu, LcsAlgorithm::HuntSzymanski, LcsAlgorithm::Weighted] {
+            let lcs = lcs_with_deadline(algorithm, &a_string, &b_string, &weights, deadline);
+            assert_eq!(lcs, Some(vec![]));
+            let lcs = lcs_with_deadline(algorithm, &a_string, &a_string, &weights[..2000], deadline);
+            assert_eq!(lcs, None);
+        }
+    }
+
+    #[test]
+    fn test_choose_lcs_algorithm_for_budget() {
+        let a_string = "a blockchain is a growing list of records".as_bytes();
+        let b_string = "a blockchain is a growing list of blocks".as_bytes();
+        let matrix_size = nakatsu_memory(a_string.len(), b_string.len());
+        assert_eq!(matrix_size, 41 * 41 * std::mem::size_of::<usize>() as u64);
+
+        // similar inputs, Nakatsu is used as long as its matrix fits
+        let algorithm = choose_lcs_algorithm_for_budget(a_string, b_string, matrix_size);
+        assert_eq!(algorithm, LcsAlgorithm::Nakatsu);
+        let algorithm = choose_lcs_algorithm_for_budget(a_string, b_string, matrix_size - 1);
+        assert_eq!(algorithm, LcsAlgorithm::Kumar);
+
+        // dissimilar inputs never use Nakatsu
+        let a_string = "abcdefghijklm".as_bytes();
+        let b_string = "nopqrstuvwxyzan".as_bytes();
+        let algorithm = choose_lcs_algorithm_for_budget(a_string, b_string, u64::MAX);
+        assert_eq!(algorithm, LcsAlgorithm::HuntSzymanski);
+    }
+}
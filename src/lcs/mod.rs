@@ -1,3 +1,12 @@
+#[allow(clippy::module_inception)]
 pub mod lcs;
+pub mod anchored;
+pub mod banded;
+pub mod deadline;
+pub mod heaviest;
+pub mod hirschberg;
 pub mod hunt_szymanski;
-pub mod nakatsu;
\ No newline at end of file
+pub mod kumar;
+pub mod nakatsu;
+#[cfg(test)]
+pub(crate) mod cross_validation;
@@ -26,8 +26,23 @@ Possible optimizations:
 4. Use binary search when tracing back (horizontally). Not sure it'll help when inputs are similar.
 */
 
+use super::deadline::*;
+
 #[allow(dead_code)]
 pub(crate) fn lcs_nakatsu<T>(a_string: &[T], b_string: &[T]) -> Vec<T>
+where
+    T: Ord + Clone,
+{
+    lcs_nakatsu_with_deadline(a_string, b_string, &mut Deadline::new(None))
+        .expect("LCS without a deadline cannot be aborted")
+}
+
+// same as lcs_nakatsu but returns None once the deadline has passed
+pub(crate) fn lcs_nakatsu_with_deadline<T>(
+    a_string: &[T],
+    b_string: &[T],
+    deadline: &mut Deadline,
+) -> Option<Vec<T>>
 where
     T: Ord + Clone,
 {
@@ -38,11 +53,11 @@ where
     let m_string: &[T];
     let n_string: &[T];
     if a_len <= b_len {
-        m_string = &a_string;
-        n_string = &b_string;
+        m_string = a_string;
+        n_string = b_string;
     } else {
-        m_string = &b_string;
-        n_string = &a_string;
+        m_string = b_string;
+        n_string = a_string;
     }
     let m_len: usize = m_string.len();
     let n_len: usize = n_string.len();
@@ -62,13 +77,7 @@ where
 
     // initialize the L matrix
     let m_size = (m_len + 1) * (m_len + 1);
-    let mut l: Vec<usize> = Vec::with_capacity(m_size);
-    unsafe { l.set_len(m_size) }; // this is safe, we only need to initialize the diagonal
-    let mut i = m_len;
-    for _ in 0..m_len + 1 {
-        l[i] = 0;
-        i += m_len;
-    }
+    let mut l: Vec<usize> = vec![0; m_size]; // only the diagonal needs to be zero, but it's cheap
 
     let mut diagonal_len = m_len;
     while diagonal_len > 0 {
@@ -90,6 +99,9 @@ where
             l[index] = lower_bound;
             let searched_character = &m_string[i - 1];
             for h in (lower_bound + 1..upper_bound).rev() {
+                if deadline.is_exceeded() {
+                    return None;
+                }
                 if n_string[h - 1] == *searched_character {
                     l[index] = h;
                     break;
@@ -100,36 +112,34 @@ where
                 got_zero = true;
             }
         }
-        if got_zero == false {
+        if !got_zero {
             break; // solved!
         }
 
         diagonal_len -= 1;
     }
 
-    // for j in 0..m_len + 1 {
-    //     for i in 0..m_len + 1 {
-    //         print!("{},", l[j * (m_len + 1) + i]);
-    //     }
-    //     print!("\n");
-    // }
-    // print!("\n");
-
     // trace back the longest subsequence
     // TODO: because rows are in order we could use binary search to speed things up.
     // However, for moderate or small differences between compared strings this may
     // turn up being slower than plain one-by-one search (we usually don't need to inspect
     // too many cells until the condition is met)
     let mut lcs: Vec<T> = Vec::with_capacity(diagonal_len);
+    if diagonal_len == 0 {
+        return Some(lcs); // nothing in common
+    }
     let mut index = (diagonal_len - 1) * (m_len + 1);
-    while index > 0 {
+    loop {
         while l[index] == l[index + 1] {
             index += 1;
         }
         lcs.push(n_string[l[index] - 1].clone());
-        index = if index > m_len { index - m_len } else { break };
+        if index <= m_len {
+            break; // first row reached
+        }
+        index -= m_len;
     }
-    lcs
+    Some(lcs)
 }
 
 #[cfg(test)]
@@ -164,4 +174,44 @@ mod tests {
         let lcs_string = String::from_utf8(lcs).unwrap();
         assert_eq!(lcs_string, " blockchain  a growing li er");
     }
+
+    #[test]
+    fn test_lcs_nakatsu_traceback() {
+        // nothing in common (the traceback used to start from the row before the first one
+        // and panic)
+        assert!(lcs_nakatsu("abc".as_bytes(), "xyz".as_bytes()).is_empty());
+        // single-character subsequences (the traceback used to stop before the first row,
+        // returning nothing)
+        assert_eq!(lcs_nakatsu("a".as_bytes(), "xa".as_bytes()), b"a");
+        assert_eq!(lcs_nakatsu("abc".as_bytes(), "xbz".as_bytes()), b"b");
+    }
+
+    // the markers delimiting the output of lcs_nakatsu in print_lcs_nakatsu
+    const OUTPUT_START: &str = "<lcs_nakatsu>";
+    const OUTPUT_END: &str = "</lcs_nakatsu>";
+
+    // only run by test_lcs_nakatsu_no_output, in a separate process whose stdout is read
+    #[test]
+    #[ignore]
+    fn print_lcs_nakatsu() {
+        println!("{}", OUTPUT_START);
+        let lcs = lcs_nakatsu("bcdabab".as_bytes(), "cbacbaaba".as_bytes());
+        println!("{}", OUTPUT_END);
+        assert_eq!(lcs, b"bcaba");
+    }
+
+    #[test]
+    fn test_lcs_nakatsu_no_output() {
+        // the test harness captures the output of the tests run in-process, so the test
+        // binary is run again with print_lcs_nakatsu only, not capturing its output
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["lcs::nakatsu::tests::print_lcs_nakatsu", "--exact", "--ignored", "--nocapture"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let start = stdout.find(OUTPUT_START).unwrap() + OUTPUT_START.len();
+        let end = stdout.find(OUTPUT_END).unwrap();
+        assert_eq!(stdout[start..end].trim(), "");
+    }
 }
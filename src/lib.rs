@@ -0,0 +1,142 @@
+/*
+    Content-defined chunking and chunk-level differ.
+
+    The public interface consists of:
+    - Differ, which compares two versions of data and returns the delta (Segments) and
+      optionally the DiffArtifacts (the chunks and their LCS, which also explain why a new
+      chunk is or isn't reused), and ChunkIndex, a set of chunks new data can be diffed
+      against
+    - the delta serialization formats (the native one, split and run-length encodings and
+      git's packfile delta) and the passes refining a delta given both versions in memory
+      (refine, fuzzy_reuse); stream emits the delta segment by segment from the chunks
+      and the LCS of DiffArtifacts
+    - Chunker and chunk, which only slice data into content-defined chunks, and
+      fastcdc_chunk, which does so as the fastcdc crate does; StableChunking pins the
+      chunking of a Chunker so that it never changes across releases
+    - measure_boundary_probability, a diagnostic of how uniformly a rolling hasher places
+      the chunk boundaries
+    - patch, which applies the delta to files, reading the old data from a file, a stream
+      or (with the "http" feature) a URL, optionally (with the "gzip" feature) compressing
+      the patched data
+    - apply_delta_to, which applies a self-contained delta to a writer, verifying the
+      patched data against the hash the delta carries in the same pass
+    - diff_two_tier, which diffs buffers computing the strong chunk hashes only where needed
+    - pipe_diff, which diffs a file against a stream, writing the delta to another stream
+    - Journal, an append-only log of a file's versions stored as deltas
+    - the manifest module, building the chunk lists of many files in parallel, comparing
+      them structurally and certifying them with Merkle roots
+
+    The digest backends are features ("sha2", "sha1" and "md5", all enabled by default).
+    Everything but chunking relies on SHA256, so without "sha2" only the Chunker (hashing
+    the chunks with FNV-1a, see FnvHasher), the rolling hashers and the file reader remain.
+*/
+
+// without the digest backends, the internals only used for diffing are left unused
+#![cfg_attr(not(feature = "sha2"), allow(dead_code))]
+
+mod boundary_probability;
+#[cfg(feature = "sha2")]
+mod chunk_index;
+mod chunk_spill;
+mod chunker;
+#[cfg(all(test, feature = "sha2", feature = "sha1", feature = "md5"))]
+mod combinations;
+#[cfg(feature = "sha2")]
+mod dedup_stats;
+#[cfg(feature = "sha2")]
+mod fast_cdc;
+#[cfg(feature = "sha2")]
+mod delta;
+#[cfg(feature = "sha2")]
+mod differ;
+#[cfg(feature = "sha2")]
+mod diffable;
+#[cfg(feature = "sha2")]
+mod fuzzy;
+mod hasher;
+mod hashing_pool;
+mod helper;
+#[cfg(feature = "http")]
+mod http_source;
+#[cfg(feature = "sha2")]
+mod journal;
+#[cfg(feature = "sha2")]
+mod lcs;
+#[cfg(feature = "sha2")]
+pub mod manifest;
+mod normalizer;
+#[cfg(feature = "sha2")]
+mod patcher;
+#[cfg(feature = "sha2")]
+mod pipe;
+mod reader;
+#[cfg(feature = "sha2")]
+mod refiner;
+mod rolling_hasher;
+mod slicer;
+#[cfg(feature = "sha2")]
+mod two_tier;
+
+pub use boundary_probability::{measure_boundary_probability, BoundaryProbability};
+#[cfg(feature = "sha2")]
+pub use chunk_index::ChunkIndex;
+#[cfg(feature = "sha2")]
+pub use chunker::StableChunking;
+pub use chunker::{chunk, Chunker};
+#[cfg(feature = "sha2")]
+pub use dedup_stats::DedupStats;
+#[cfg(feature = "sha2")]
+pub use delta::{
+    apply_git, apply_rle, apply_split, deserialize, deserialize_checked, deserialize_git,
+    deserialize_split, from_rle, output_offsets, serialize, serialize_git, serialize_split,
+    serialize_split_deduplicated, stream, to_rle, DeltaError, Op, Segment,
+};
+#[cfg(feature = "sha2")]
+pub use diffable::Diffable;
+#[cfg(feature = "sha2")]
+pub use differ::{
+    CollisionPolicy, DiffArtifacts, DiffTimings, Differ, DifferConfig, Explanation,
+};
+#[cfg(feature = "sha2")]
+pub use fast_cdc::{fastcdc_chunk, FastCdcParameters};
+#[cfg(feature = "sha2")]
+pub use fuzzy::fuzzy_reuse;
+#[cfg(any(feature = "sha2", feature = "sha1"))]
+pub use hasher::digest::DigestHasher;
+#[cfg(feature = "md5")]
+pub use hasher::md5::Md5Hasher;
+#[cfg(feature = "sha1")]
+pub use hasher::sha1::Sha1Hasher;
+#[cfg(feature = "sha2")]
+pub use hasher::{hmac::HmacSha256Hasher, sha256::Sha256Hasher};
+pub use hasher::{fnv::FnvHasher, hasher::Hasher};
+#[cfg(feature = "http")]
+pub use http_source::HttpChunkSource;
+#[cfg(feature = "sha2")]
+pub use journal::Journal;
+#[cfg(feature = "sha2")]
+pub use lcs::lcs::LcsAlgorithm;
+pub use normalizer::{Normalizer, TextNormalizer};
+#[cfg(feature = "sha2")]
+pub use patcher::{
+    apply_delta_to, apply_reverse, patch, patch_from_reader, patch_with_source, read_range,
+    reconstruct_iter, ChunkKey, ChunkSource, FileChunkSource,
+};
+#[cfg(feature = "gzip")]
+pub use patcher::patch_gzip;
+#[cfg(feature = "sha2")]
+pub use pipe::pipe_diff;
+#[cfg(feature = "sha2")]
+pub use refiner::refine;
+pub use reader::{read_file_with_progress, ProgressInterval, FILE_READER_BUF_SIZE};
+pub use rolling_hasher::{
+    fibonacci::FibonacciRollingHasher, gear::GearRollingHasher,
+    moving_sum::MovingSumRollingHasher, polynomial::PolynomialRollingHasher,
+    rolling_hasher::RollingHasher,
+};
+pub use slicer::{
+    BoundaryCallback, BoundaryEvent, Chunk, ChunkMetadataCallback, ChunkingParameters, CutKind,
+    SlicerError, Symbol,
+};
+#[cfg(feature = "sha2")]
+pub use two_tier::diff_two_tier;
@@ -1,22 +1,10 @@
-use differ::*;
-use patcher::patch;
-use reader::*;
+use differ::{patch, read_file_with_progress, Differ, ProgressInterval, FILE_READER_BUF_SIZE};
 use std::{
     env,
     fs::OpenOptions,
     io::Write,
 };
 
-mod delta;
-mod differ;
-mod hasher;
-mod helper;
-mod lcs;
-mod patcher;
-mod reader;
-mod rolling_hasher;
-mod slicer;
-
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -40,19 +28,28 @@ fn main() {
         Some(min_chunk_size),
         Some(max_chunk_size),
         Some(boundary_mask),
+        None,
     );
 
     // slice the old file and compute hashes (they could be analyzed concurrently, too)
     println!("Processing old file");
-    read_file(old_file_path, |bytes, _| {
-        differ.process_old(bytes);
-    });
+    read_file_with_progress(
+        old_file_path,
+        FILE_READER_BUF_SIZE,
+        ProgressInterval::Percent(10),
+        |bytes| differ.process_old(bytes),
+        |progress| println!("{}%", progress),
+    );
 
     // slice the new file and compute hashes
     println!("Processing new file");
-    read_file(new_file_path, |bytes, _| {
-        differ.process_new(bytes);
-    });
+    read_file_with_progress(
+        new_file_path,
+        FILE_READER_BUF_SIZE,
+        ProgressInterval::Percent(10),
+        |bytes| differ.process_new(bytes),
+        |progress| println!("{}%", progress),
+    );
 
     // compute longest common subsequence and determine delta
     println!("Computing delta");
@@ -64,6 +61,7 @@ fn main() {
     _ = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(delta_file_path).expect("Could not open delta file for writing")
         .write(segments_text.as_bytes());
 
@@ -74,7 +72,7 @@ fn main() {
 
     println!("Done!");
 
-    let percent_reused: usize = 100 * bytes_old / (bytes_new + bytes_old);
+    let percent_reused: u64 = 100 * bytes_old / (bytes_new + bytes_old);
     println!(
         "{} bytes ({}%) have been reused, {} bytes ({}%) have been added.",
         bytes_old,
@@ -0,0 +1,393 @@
+/*
+    Manifest is the ordered list of the chunks a stream consists of, e.g. as produced by the
+    Chunker. It describes the stream structurally, without its bytes.
+
+    compare reports what changed structurally between two manifests (e.g. for UIs), which
+    is much cheaper than a byte-level diff as it only compares the chunk hashes:
+    - added chunks, only present in the new manifest
+    - removed chunks, only present in the old manifest
+    - common chunks, present in both; those not belonging to the longest common subsequence
+      of the manifests have moved (their order relative to the other common chunks changed)
+
+    Each distinct chunk hash is classified once, in the order of its first occurrence.
+
+    missing_chunks is the sync handshake of a deduplicating store: given the manifest a
+    client has and the one it wants, it lists the chunks the client has to fetch (those it
+    has can be copied from its own data), each once.
+
+    merkle_root certifies the whole stream with a single SHA256 hash, the root of a binary
+    Merkle tree over the ordered chunk hashes, and merkle_proof proves that a chunk belongs
+    to the stream (at a given position) with O(log n) hashes. The leaves and the inner nodes
+    are hashed with distinct prefixes (0 and 1) so that an inner node can't pass for a leaf.
+    A node without a sibling (the last one of a level of odd length) is carried up as it is.
+    The root of an empty manifest is the hash of no data.
+
+    batch_manifest builds the manifests of many files at once (e.g. for an initial backup),
+    slicing them concurrently: each thread takes the next file not sliced yet, so that a few
+    large files don't keep the other threads idle. The manifests are those Chunker makes.
+*/
+
+use crate::chunker::*;
+use crate::lcs::lcs::*;
+use crate::slicer::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// the buffer each file is read with by batch_manifest
+const BATCH_READ_BUF_SIZE: usize = 1 << 20;
+
+const MERKLE_LEAF_PREFIX: u8 = 0;
+const MERKLE_NODE_PREFIX: u8 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    chunks: Vec<Chunk>,
+}
+
+impl Manifest {
+    pub fn new(chunks: Vec<Chunk>) -> Manifest {
+        Manifest { chunks }
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    // the size of the stream described by the manifest
+    pub fn size(&self) -> u64 {
+        self.chunks.last().map_or(0, |chunk| chunk.end)
+    }
+
+    // the root of the Merkle tree over the chunk hashes, see the module description
+    pub fn merkle_root(&self) -> [u8; 32] {
+        match self.merkle_levels().last() {
+            Some(root_level) => root_level[0],
+            None => Sha256::digest([]).into(),
+        }
+    }
+
+    // the proof that the chunk at the given index belongs to the manifest, None if there's
+    // no such chunk
+    pub fn merkle_proof(&self, chunk_index: usize) -> Option<MerkleProof> {
+        if chunk_index >= self.chunks.len() {
+            return None;
+        }
+        let mut steps: Vec<MerkleStep> = Vec::new();
+        let mut index = chunk_index;
+        for level in self.merkle_levels().iter().filter(|level| level.len() > 1) {
+            if index % 2 == 1 {
+                steps.push(MerkleStep::Left(level[index - 1]));
+            } else if index + 1 < level.len() {
+                steps.push(MerkleStep::Right(level[index + 1]));
+            }
+            index /= 2;
+        }
+        Some(MerkleProof { steps })
+    }
+
+    fn hashes(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.chunks.iter().map(|chunk| &chunk.hash)
+    }
+
+    // the levels of the Merkle tree, from the leaves up to the root (none if empty)
+    fn merkle_levels(&self) -> Vec<Vec<[u8; 32]>> {
+        let leaves: Vec<[u8; 32]> = self.hashes().map(|hash| merkle_leaf(hash)).collect();
+        if leaves.is_empty() {
+            return Vec::new();
+        }
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => merkle_node(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+// the sibling hashes on the path from a leaf to the Merkle root
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    steps: Vec<MerkleStep>,
+}
+
+// a sibling node, either on the left or on the right of the path
+#[derive(Debug, Clone, PartialEq)]
+pub enum MerkleStep {
+    Left([u8; 32]),
+    Right([u8; 32]),
+}
+
+impl MerkleProof {
+    // checks that the chunk hash belongs to the manifest with the given Merkle root
+    pub fn verify(&self, chunk_hash: &[u8], root: &[u8; 32]) -> bool {
+        let computed_root = self.steps.iter().fold(merkle_leaf(chunk_hash), |node, step| match step {
+            MerkleStep::Left(sibling) => merkle_node(sibling, &node),
+            MerkleStep::Right(sibling) => merkle_node(&node, sibling),
+        });
+        computed_root == *root
+    }
+
+    pub fn steps(&self) -> &[MerkleStep] {
+        &self.steps
+    }
+}
+
+fn merkle_leaf(chunk_hash: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([MERKLE_LEAF_PREFIX]);
+    hasher.update(chunk_hash);
+    hasher.finalize().into()
+}
+
+fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([MERKLE_NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+impl From<Vec<Chunk>> for Manifest {
+    fn from(chunks: Vec<Chunk>) -> Manifest {
+        Manifest::new(chunks)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManifestDiff {
+    pub added: Vec<Vec<u8>>,   // only in the new manifest, in the new order
+    pub removed: Vec<Vec<u8>>, // only in the old manifest, in the old order
+    pub common: Vec<Vec<u8>>,  // in both manifests, in the new order
+    pub moved: Vec<Vec<u8>>,   // the common chunks which have moved, in the new order
+}
+
+// compares the manifests structurally, see the module description
+pub fn compare(old: &Manifest, new: &Manifest) -> ManifestDiff {
+    let old_hashes: HashSet<&Vec<u8>> = old.hashes().collect();
+    let new_hashes: HashSet<&Vec<u8>> = new.hashes().collect();
+    let mut diff = ManifestDiff::default();
+
+    let mut classified: HashSet<&Vec<u8>> = HashSet::new();
+    for hash in old.hashes() {
+        if !new_hashes.contains(hash) && classified.insert(hash) {
+            diff.removed.push(hash.clone());
+        }
+    }
+
+    // the common chunks in the LCS kept their relative order, the others have moved
+    let common_old: Vec<&Vec<u8>> = old.hashes().filter(|hash| new_hashes.contains(hash)).collect();
+    let common_new: Vec<&Vec<u8>> = new.hashes().filter(|hash| old_hashes.contains(hash)).collect();
+    let lcs_algorithm = choose_lcs_algorithm(&common_old, &common_new);
+    let in_order: HashSet<&Vec<u8>> = lcs(lcs_algorithm, &common_old, &common_new, &[])
+        .into_iter()
+        .collect();
+
+    for hash in new.hashes() {
+        if !classified.insert(hash) {
+            continue;
+        }
+        if !old_hashes.contains(hash) {
+            diff.added.push(hash.clone());
+            continue;
+        }
+        diff.common.push(hash.clone());
+        if !in_order.contains(hash) {
+            diff.moved.push(hash.clone());
+        }
+    }
+
+    diff
+}
+
+// the hashes of the chunks in 'want' but not in 'have', deduplicated, in the 'want' order
+pub fn missing_chunks(have: &Manifest, want: &Manifest) -> Vec<Vec<u8>> {
+    let mut known: HashSet<&Vec<u8>> = have.hashes().collect();
+    want.hashes()
+        .filter(|hash| known.insert(hash))
+        .cloned()
+        .collect()
+}
+
+// the manifests of the files (in the same order), sliced on up to 'threads' threads; fails
+// if any of the files can't be read or the parameters are invalid
+pub fn batch_manifest(
+    paths: &[&str],
+    parameters: ChunkingParameters,
+    threads: usize,
+) -> Result<Vec<Manifest>> {
+    assert!(threads > 0, "Batch manifest needs at least one thread");
+    // fails early, rather than once per file
+    Chunker::new(parameters).map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+
+    let next_path = AtomicUsize::new(0);
+    let slice_files = || {
+        let mut manifests: Vec<(usize, Result<Manifest>)> = Vec::new();
+        let mut buffer: Vec<u8> = vec![0; BATCH_READ_BUF_SIZE];
+        loop {
+            let index = next_path.fetch_add(1, Ordering::Relaxed);
+            let Some(path) = paths.get(index) else {
+                return manifests;
+            };
+            manifests.push((index, file_manifest(path, parameters, &mut buffer)));
+        }
+    };
+    let mut manifests: Vec<(usize, Result<Manifest>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(paths.len()))
+            .map(|_| scope.spawn(slice_files))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Batch manifest worker panicked"))
+            .collect()
+    });
+    manifests.sort_unstable_by_key(|&(index, _)| index);
+    manifests.into_iter().map(|(_, manifest)| manifest).collect()
+}
+
+// slices the file with a Chunker, reading it through the buffer
+fn file_manifest(path: &str, parameters: ChunkingParameters, buffer: &mut [u8]) -> Result<Manifest> {
+    let mut chunker = Chunker::new(parameters).map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    let mut file = File::open(path)?;
+    loop {
+        match file.read(buffer) {
+            Ok(0) => break,
+            Ok(bytes_read) => chunker.process(&buffer[..bytes_read]),
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    let chunks = chunker.finalize().map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    Ok(Manifest::new(chunks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_manifest(hashes: &[u8]) -> Manifest {
+        let chunks: Vec<Chunk> = hashes
+            .iter()
+            .enumerate()
+            .map(|(index, &hash)| Chunk {
+                hash: vec![hash],
+                end: (index as u64 + 1) * 1024,
+            })
+            .collect();
+        Manifest::from(chunks)
+    }
+
+    #[test]
+    fn test_manifest_compare() {
+        // B removed, X added, E moved to the front
+        let old = make_manifest(b"ABCDEF");
+        let new = make_manifest(b"EAXCDF");
+        assert_eq!(new.size(), 6 * 1024);
+
+        let diff = compare(&old, &new);
+        assert_eq!(diff.added, vec![b"X".to_vec()]);
+        assert_eq!(diff.removed, vec![b"B".to_vec()]);
+        let common: Vec<Vec<u8>> = b"EACDF".iter().map(|&hash| vec![hash]).collect();
+        assert_eq!(diff.common, common);
+        assert_eq!(diff.moved, vec![b"E".to_vec()]);
+
+        // nothing changes between identical manifests
+        let diff = compare(&old, &old);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.moved.is_empty());
+        assert_eq!(diff.common.len(), 6);
+
+        let diff = compare(&Manifest::default(), &new);
+        assert_eq!(diff.added.len(), 6);
+    }
+
+    #[test]
+    fn test_manifest_missing_chunks() {
+        // X and Y are missing, Y twice; A, C and D are there in another order
+        let have = make_manifest(b"ABCDE");
+        let want = make_manifest(b"DYAXCYA");
+        let missing: Vec<Vec<u8>> = b"YX".iter().map(|&hash| vec![hash]).collect();
+        assert_eq!(missing_chunks(&have, &want), missing);
+
+        assert!(missing_chunks(&want, &want).is_empty());
+        assert!(missing_chunks(&want, &Manifest::default()).is_empty());
+        assert_eq!(missing_chunks(&Manifest::default(), &have).len(), 5);
+    }
+
+    #[test]
+    fn test_manifest_merkle() {
+        let manifest = make_manifest(b"ABCDEFG");
+        let root = manifest.merkle_root();
+        assert_eq!(root, make_manifest(b"ABCDEFG").merkle_root());
+        assert_ne!(root, make_manifest(b"ABCDEFH").merkle_root());
+        assert_ne!(root, make_manifest(b"ABCDEGF").merkle_root());
+
+        // every chunk's proof verifies, but not for another chunk or another root
+        for (index, chunk) in manifest.chunks().iter().enumerate() {
+            let proof = manifest.merkle_proof(index).unwrap();
+            assert!(proof.steps().len() <= 3);
+            assert!(proof.verify(&chunk.hash, &root));
+            assert!(!proof.verify(b"X", &root));
+            assert!(!proof.verify(&chunk.hash, &make_manifest(b"ABCDEFH").merkle_root()));
+        }
+        assert_eq!(manifest.merkle_proof(7), None);
+
+        // a single chunk is its own tree
+        let manifest = make_manifest(b"A");
+        let proof = manifest.merkle_proof(0).unwrap();
+        assert!(proof.steps().is_empty());
+        assert!(proof.verify(b"A", &manifest.merkle_root()));
+        assert_ne!(Manifest::default().merkle_root(), manifest.merkle_root());
+    }
+
+    #[test]
+    fn test_batch_manifest() -> Result<()> {
+        let parameters = ChunkingParameters {
+            window_size: 64,
+            min_chunk_size: 2048,
+            max_chunk_size: 8192,
+            boundary_mask: (1 << 12) - 1,
+        };
+        // files of various sizes, the example ones and generated ones
+        let directory = std::env::temp_dir().join(format!("differ_batch_manifest_{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let mut paths: Vec<String> = vec![
+            "./example/monkey_before.tiff".to_string(),
+            "./example/monkey_after.tiff".to_string(),
+        ];
+        for (index, len) in [0u32, 100, 50000, 300000].into_iter().enumerate() {
+            let path = directory.join(format!("file_{}", index));
+            let bytes: Vec<u8> = (0..len).map(|i| ((i ^ index as u32).wrapping_mul(2654435761) >> 13) as u8).collect();
+            std::fs::write(&path, bytes)?;
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+
+        // each manifest matches the file sliced on its own, whatever the number of threads
+        let expected: Vec<Manifest> = paths
+            .iter()
+            .map(|path| Manifest::new(chunk(&std::fs::read(path).unwrap(), parameters).unwrap()))
+            .collect();
+        for threads in [1, 3, 8] {
+            assert_eq!(batch_manifest(&paths, parameters, threads)?, expected);
+        }
+        assert!(batch_manifest(&[], parameters, 4)?.is_empty());
+
+        // a missing file fails the batch
+        let error = batch_manifest(&[paths[0], "./example/missing"], parameters, 2).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+
+        std::fs::remove_dir_all(&directory)
+    }
+}
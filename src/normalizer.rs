@@ -0,0 +1,85 @@
+/*
+    Normalizers transform the bytes of a stream before they get to the Slicer's rolling
+    hasher and hasher, so that chunks which only differ in formatting (e.g. letter case or
+    whitespace) get the same boundaries and hashes, and thus are matched.
+
+    Chunk offsets still refer to the original (not normalized) bytes. A chunk which only
+    matches once normalized is not reused (it would bring the old formatting into the patched
+    file): the Slicer appends a hash of the original bytes to the chunk hashes. Formatting
+    changes thus cost the chunks they occur in, but they don't move any boundary.
+*/
+
+pub trait Normalizer {
+    fn normalize(&mut self, byte: u8) -> Option<u8>; // returns None if the byte is to be skipped
+    fn reset(&mut self) {} // forgets the state carried over from the previous bytes, if any
+}
+
+// Case and whitespace insensitive text normalizer
+#[derive(Clone, Default)]
+pub struct TextNormalizer {
+    lowercase: bool,
+    collapse_whitespace: bool,
+    in_whitespace: bool,
+}
+
+impl Normalizer for TextNormalizer {
+    #[inline(always)]
+    fn normalize(&mut self, byte: u8) -> Option<u8> {
+        if self.collapse_whitespace {
+            // any run of ASCII whitespace becomes a single space
+            if byte.is_ascii_whitespace() {
+                let is_run = self.in_whitespace;
+                self.in_whitespace = true;
+                return if is_run { None } else { Some(b' ') };
+            }
+            self.in_whitespace = false;
+        }
+        if self.lowercase {
+            Some(byte.to_ascii_lowercase())
+        } else {
+            Some(byte)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.in_whitespace = false;
+    }
+}
+
+impl TextNormalizer {
+    pub fn new(lowercase: bool, collapse_whitespace: bool) -> TextNormalizer {
+        TextNormalizer {
+            lowercase,
+            collapse_whitespace,
+            in_whitespace: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(normalizer: &mut TextNormalizer, input: &str) -> String {
+        let bytes: Vec<u8> = input.bytes().filter_map(|byte| normalizer.normalize(byte)).collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_text_normalizer() {
+        let input = "The  Quick\tBrown \n\n Fox";
+        assert_eq!(normalize(&mut TextNormalizer::new(false, false), input), input);
+        assert_eq!(
+            normalize(&mut TextNormalizer::new(true, false), input),
+            "the  quick\tbrown \n\n fox"
+        );
+        assert_eq!(
+            normalize(&mut TextNormalizer::new(false, true), input),
+            "The Quick Brown Fox"
+        );
+        assert_eq!(
+            normalize(&mut TextNormalizer::new(true, true), input),
+            "the quick brown fox"
+        );
+    }
+}
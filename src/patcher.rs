@@ -1,49 +1,757 @@
 /*
     This is a simple patcher mainly used for local testing purposes. It takes an old and new file
     paths as well as the patched file path and builds the patched file from old/new using the delta
-    array provided (array of segments)
+    array provided (array of segments). Store segments (produced by diffing against a ChunkIndex)
+    refer to a chunk store rather than to the old file, so they can't be patched here.
+
+    patch_with_source generalizes this to any ChunkSource backend resolving the Old segments
+    (by offset range) and the Store segments (by chunk hash), e.g. a content-addressed store.
+
+    patch_from_reader reads the old data from a forward-only (non-seekable) stream instead,
+    e.g. a pipe, skipping the bytes between the Old segments. This only works if the Old
+    segments come in ascending offset order without overlapping, which is always the case
+    for the deltas computed by the Differ (the common subsequence preserves the chunk order),
+    but not for deltas with Store segments or edited by hand.
+
+    apply_reverse reconstructs the old data from the new file and a self-contained reverse
+    delta (see pipe_diff): its Old segments refer to the new file, while its New segments refer
+    to its payload, holding the old bytes the new file lacks.
+
+    apply_delta_to applies a self-contained delta to any writer, computing the SHA256 of the
+    patched data as it's written, so that it's verified against the hash the delta carries
+    in the same streaming pass, without reading the patched data back.
+
+    patch_gzip (with the "gzip" feature) compresses the patched data as it's reconstructed,
+    in a single streaming pass, rather than compressing the patched file afterwards.
 */
 
 use crate::delta::*;
+use crate::helper::*;
+use crate::pipe::*;
+use sha2::{Digest, Sha256};
 use std::{
+    collections::HashMap,
     fs::{File, OpenOptions},
-    io::{Read, Result, Seek, SeekFrom, Write},
+    io::{copy, sink, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    ops::Range,
 };
 
-pub(crate) fn patch(
+// Identifies the bytes to be read from a ChunkSource
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkKey<'a> {
+    Range(Range<u64>), // the byte range of the old data (Old segments)
+    Hash(&'a [u8]),    // the hash of a stored chunk (Store segments)
+}
+
+// The backend the old bytes are read from when patching
+pub trait ChunkSource {
+    fn read(&self, key: ChunkKey) -> Result<Vec<u8>>;
+
+    // writes the bytes identified by the key, returning their count; can be overridden
+    // to stream the bytes rather than reading them into memory at once
+    fn copy_to(&self, key: ChunkKey, writer: &mut dyn Write) -> Result<u64> {
+        let bytes = self.read(key)?;
+        writer.write_all(&bytes)?;
+        Ok(bytes.len() as u64)
+    }
+}
+
+// The old file, resolving offset ranges only
+pub struct FileChunkSource {
+    file: File,
+}
+
+impl FileChunkSource {
+    pub fn open(file_path: &str) -> Result<FileChunkSource> {
+        Ok(FileChunkSource {
+            file: File::open(file_path)?,
+        })
+    }
+}
+
+impl ChunkSource for FileChunkSource {
+    fn read(&self, key: ChunkKey) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+        self.copy_to(key, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    // copied in a streaming manner, segments can be larger than the addressable memory
+    fn copy_to(&self, key: ChunkKey, writer: &mut dyn Write) -> Result<u64> {
+        let range = match key {
+            ChunkKey::Range(range) => range,
+            ChunkKey::Hash(_) => return Err(unresolvable_store_segment()),
+        };
+        let len = range.end.saturating_sub(range.start);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(range.start))?;
+        let bytes_copied = copy(&mut file.take(len), writer)?;
+        if bytes_copied != len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Segment range exceeds the source file",
+            ));
+        }
+        Ok(bytes_copied)
+    }
+}
+
+// In-memory chunk store keyed by chunk hashes, resolving hashes only
+impl ChunkSource for HashMap<Vec<u8>, Vec<u8>> {
+    fn read(&self, key: ChunkKey) -> Result<Vec<u8>> {
+        match key {
+            ChunkKey::Hash(hash) => self
+                .get(hash)
+                .cloned()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "Chunk not found in the store")),
+            ChunkKey::Range(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                "Old segments cannot be resolved from a chunk store",
+            )),
+        }
+    }
+}
+
+pub fn patch(
     old_file_path: &str,
     new_file_path: &str,
     patched_file_path: &str,
     segments: Vec<Segment>,
-) -> Result<(usize,usize)> {        // returns (old_bytes, new_bytes) - how many bytes were used from old and new 
-    let old_file = File::open(old_file_path)?;
-    let new_file = File::open(new_file_path)?;
+) -> Result<(u64, u64)> {        // returns (old_bytes, new_bytes) - how many bytes were used from old and new 
+    let old_source = FileChunkSource::open(old_file_path)?;
+    patch_with_source(&old_source, new_file_path, patched_file_path, segments)
+}
+
+// Same as patch but resolves the Old and Store segments through the given source; New
+// segments are still read from the new file
+pub fn patch_with_source<S: ChunkSource>(
+    old_source: &S,
+    new_file_path: &str,
+    patched_file_path: &str,
+    segments: Vec<Segment>,
+) -> Result<(u64, u64)> {        // returns (old_bytes, new_bytes) - how many bytes were used from old and new 
     let mut patched_file = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(patched_file_path)?;
-    let mut old_bytes_used: usize = 0;
-    let mut new_bytes_used: usize = 0;
+    let bytes_used = patch_to_writer(old_source, new_file_path, &mut patched_file, segments)?;
+    patched_file.flush()?;
+
+    Ok(bytes_used)
+}
+
+// Same as patch_with_source but writes the patched data gzip-compressed, compressing it
+// as it's reconstructed
+#[cfg(feature = "gzip")]
+pub fn patch_gzip<S: ChunkSource>(
+    old_source: &S,
+    new_file_path: &str,
+    patched_file_path: &str,
+    segments: Vec<Segment>,
+) -> Result<(u64, u64)> {        // returns (old_bytes, new_bytes) - how many (uncompressed) bytes were used from old and new
+    let patched_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(patched_file_path)?;
+    let mut encoder = flate2::write::GzEncoder::new(patched_file, flate2::Compression::default());
+    let bytes_used = patch_to_writer(old_source, new_file_path, &mut encoder, segments)?;
+    encoder.finish()?.flush()?;
+
+    Ok(bytes_used)
+}
+
+// Writes the patched data to the writer, resolving the Old and Store segments through the
+// source and the New segments from the new file
+fn patch_to_writer<S: ChunkSource, W: Write>(
+    old_source: &S,
+    new_file_path: &str,
+    patched_writer: &mut W,
+    segments: Vec<Segment>,
+) -> Result<(u64, u64)> {
+    let new_source = FileChunkSource::open(new_file_path)?;
+    let mut old_bytes_used: u64 = 0;
+    let mut new_bytes_used: u64 = 0;
     for segment in segments {
-        let (mut source_file, range) = match segment {
-            Segment::Old(range) => { 
-                old_bytes_used += range.len();
-                (&old_file, range)
-            },
+        let segment_len = segment.len();
+        match segment {
+            Segment::Old(range) => {
+                old_source.copy_to(ChunkKey::Range(range), patched_writer)?;
+                old_bytes_used += segment_len;
+            }
             Segment::New(range) => {
-                new_bytes_used += range.len();
-                (&new_file, range)
-            },
-        };
-        // pretty bad way of reading a file, where each chunk requires new heap allocation
-        // anyway, good enough for a test
-        let mut buffer: Vec<u8> = vec![0; range.len()];
-        source_file.seek(SeekFrom::Start(u64::try_from(range.start).unwrap()))?;
-        source_file.read_exact(&mut buffer[..])?;
-        let bytes_written = patched_file.write(&buffer)?;
-        assert_eq!(bytes_written, range.len());
+                new_source.copy_to(ChunkKey::Range(range), patched_writer)?;
+                new_bytes_used += segment_len;
+            }
+            Segment::Store(hash, size) => {
+                let bytes_copied = old_source.copy_to(ChunkKey::Hash(&hash), patched_writer)?;
+                if bytes_copied != size {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Stored chunk size doesn't match the Store segment",
+                    ));
+                }
+                old_bytes_used += segment_len;
+            }
+        }
+    }
+
+    Ok((old_bytes_used, new_bytes_used))
+}
+
+// Reconstructs the old data from the new file and the self-contained reverse delta (as
+// written by pipe_diff diffing the new file against the old data)
+pub fn apply_reverse(
+    new_file_path: &str,
+    reverse_delta: &[u8],
+    patched_file_path: &str,
+) -> Result<(u64, u64)> {        // returns (new_bytes, delta_bytes) - how many bytes were used from the new file and the delta payload
+    let new_source = FileChunkSource::open(new_file_path)?;
+    let mut patched_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(patched_file_path)?;
+    let (new_bytes_used, payload_bytes_used, _) =
+        apply_self_contained(&new_source, reverse_delta, &mut patched_file)?;
+    patched_file.flush()?;
+
+    Ok((new_bytes_used, payload_bytes_used))
+}
+
+// Applies the self-contained delta (as written by pipe_diff), resolving the Old and Store
+// segments through the source and writing the patched data to the writer. Returns the SHA256
+// of the patched data; fails if it doesn't match the hash carried by the delta, once all
+// of it has been written
+pub fn apply_delta_to<S: ChunkSource, W: Write>(
+    old_source: &S,
+    delta: &[u8],
+    patched_writer: &mut W,
+) -> Result<Vec<u8>> {
+    let (_, _, hash) = apply_self_contained(old_source, delta, patched_writer)?;
+    Ok(hash)
+}
+
+// Writes the data reconstructed by the self-contained delta, hashing it on the way
+fn apply_self_contained<S: ChunkSource, W: Write>(
+    source: &S,
+    delta: &[u8],
+    patched_writer: &mut W,
+) -> Result<(u64, u64, Vec<u8>)> {
+    let (structure, payload, expected_hash) = split_self_contained(delta)
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    let (_, segments) = deserialize_split(structure)
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+    let mut hashing_writer = HashingWriter {
+        writer: patched_writer,
+        hasher: Sha256::new(),
+    };
+    let mut source_bytes_used: u64 = 0;
+    let mut payload_bytes_used: u64 = 0;
+    for segment in segments {
+        let segment_len = segment.len();
+        match segment {
+            Segment::Old(range) => {
+                source.copy_to(ChunkKey::Range(range), &mut hashing_writer)?;
+                source_bytes_used += segment_len;
+            }
+            Segment::New(range) => {
+                let bytes = payload.get(buffer_range(&range)).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "Segment range exceeds the delta payload")
+                })?;
+                hashing_writer.write_all(bytes)?;
+                payload_bytes_used += segment_len;
+            }
+            Segment::Store(hash, size) => {
+                if source.copy_to(ChunkKey::Hash(&hash), &mut hashing_writer)? != size {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Stored chunk size doesn't match the Store segment",
+                    ));
+                }
+                source_bytes_used += segment_len;
+            }
+        }
+    }
+
+    let hash = hashing_writer.hasher.finalize().to_vec();
+    if expected_hash.is_some_and(|expected_hash| expected_hash != hash) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Patched data doesn't match the hash carried by the delta",
+        ));
+    }
+    Ok((source_bytes_used, payload_bytes_used, hash))
+}
+
+// Forwards the writes, computing the running hash of the bytes written
+struct HashingWriter<'a, W: Write> {
+    writer: &'a mut W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        let bytes_written = self.writer.write(buffer)?;
+        self.hasher.update(&buffer[..bytes_written]);
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Same as patch but reads the old data from a forward-only reader; fails (before writing
+// anything) unless the Old segments are in ascending offset order and don't overlap
+pub fn patch_from_reader<R: Read>(
+    mut old_reader: R,
+    new_file_path: &str,
+    patched_file_path: &str,
+    segments: Vec<Segment>,
+) -> Result<(u64, u64)> {        // returns (old_bytes, new_bytes) - how many bytes were used from old and new 
+    let mut old_position: u64 = 0;
+    for segment in &segments {
+        match segment {
+            Segment::Old(range) if range.start < old_position => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Old segments are not in ascending order, the old data must be seekable",
+                ))
+            }
+            Segment::Old(range) => old_position = range.end,
+            Segment::New(_) => {}
+            Segment::Store(..) => return Err(unresolvable_store_segment()),
+        }
+    }
+
+    let new_source = FileChunkSource::open(new_file_path)?;
+    let mut patched_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(patched_file_path)?;
+    let mut old_bytes_used: u64 = 0;
+    let mut new_bytes_used: u64 = 0;
+    let mut old_position: u64 = 0;
+    for segment in segments {
+        let segment_len = segment.len();
+        match segment {
+            Segment::Old(range) => {
+                // skip the old bytes not used by the delta
+                let gap = range.start - old_position;
+                copy_exactly(&mut old_reader, gap, &mut sink())?;
+                copy_exactly(&mut old_reader, segment_len, &mut patched_file)?;
+                old_position = range.end;
+                old_bytes_used += segment_len;
+            }
+            Segment::New(range) => {
+                new_source.copy_to(ChunkKey::Range(range), &mut patched_file)?;
+                new_bytes_used += segment_len;
+            }
+            Segment::Store(..) => unreachable!("Store segments have been rejected"),
+        }
     }
     patched_file.flush()?;
 
     Ok((old_bytes_used, new_bytes_used))
 }
+
+// copies the next len bytes of the reader, failing if it ends before
+fn copy_exactly<R: Read, W: Write>(reader: &mut R, len: u64, writer: &mut W) -> Result<()> {
+    if copy(&mut reader.take(len), writer)? != len {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "Segment range exceeds the old data",
+        ));
+    }
+    Ok(())
+}
+
+// Reconstructs only the requested byte range of the patched file. Only the segments
+// overlapping the range are visited and only the bytes falling into it are read from
+// the old and new files
+pub fn read_range(
+    old_file_path: &str,
+    new_file_path: &str,
+    segments: &[Segment],
+    range: Range<u64>,
+) -> Result<Vec<u8>> {
+    let old_file = File::open(old_file_path)?;
+    let new_file = File::open(new_file_path)?;
+    let offsets = output_offsets(segments);
+    let mut buffer: Vec<u8> = Vec::new();
+
+    // the last segment starting at or before range.start is the first one to overlap it
+    let first_segment_index = match upper_bound(range.start, &offsets) {
+        Some(0) => return Ok(buffer),
+        Some(index) => index - 1,
+        None => offsets.len().saturating_sub(1),
+    };
+    for (segment, segment_offset) in segments.iter().zip(offsets.iter()).skip(first_segment_index) {
+        if *segment_offset >= range.end {
+            break;
+        }
+        let (mut source_file, source_range) = match segment {
+            Segment::Old(source_range) => (&old_file, source_range),
+            Segment::New(source_range) => (&new_file, source_range),
+            Segment::Store(..) => return Err(unresolvable_store_segment()),
+        };
+        // clip the segment to the requested range and translate to the source file offsets
+        let start = range.start.max(*segment_offset);
+        let end = range.end.min(segment_offset + segment.len());
+        if start >= end {
+            continue;
+        }
+        let source_start = source_range.start + start - segment_offset;
+        let buffer_len = buffer.len();
+        let len = usize::try_from(end - start)
+            .map_err(|_| Error::new(ErrorKind::OutOfMemory, "Range exceeds the addressable memory"))?;
+        buffer.resize(buffer_len + len, 0);
+        source_file.seek(SeekFrom::Start(source_start))?;
+        source_file.read_exact(&mut buffer[buffer_len..])?;
+    }
+
+    Ok(buffer)
+}
+
+// Zero-copy in-memory counterpart of patch: yields the slices of the old and new buffers
+// which, concatenated, make up the patched data. Panics on Store segments
+pub fn reconstruct_iter<'a>(
+    old: &'a [u8],
+    new: &'a [u8],
+    segments: &'a [Segment],
+) -> impl Iterator<Item = &'a [u8]> {
+    segments.iter().map(move |segment| match segment {
+        Segment::Old(range) => &old[buffer_range(range)],
+        Segment::New(range) => &new[buffer_range(range)],
+        Segment::Store(..) => panic!("Store segments cannot be reconstructed from old/new data"),
+    })
+}
+
+fn unresolvable_store_segment() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "Store segments cannot be patched from the old file",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differ::{make_slicer, Differ, DifferConfig};
+    use crate::slicer::ChunkingParameters;
+    use crate::reader::read_file;
+    use std::fs;
+
+    #[test]
+    fn test_read_range() -> Result<()> {
+        let window_size: u32 = 64;
+        let min_chunk_size: usize = 2048;
+        let max_chunk_size: usize = 8192;
+        let boundary_mask: u32 = (1 << 12) - 1; // avg chunk size is 2^12 = 4096
+        let mut differ = Differ::new(
+            Some(window_size),
+            Some(min_chunk_size),
+            Some(max_chunk_size),
+            Some(boundary_mask),
+            None,
+        );
+
+        let old_file_path = "./example/monkey_before.tiff";
+        let new_file_path = "./example/monkey_after.tiff";
+        read_file(old_file_path, |bytes, _| {
+            differ.process_old(bytes);
+        });
+        read_file(new_file_path, |bytes, _| {
+            differ.process_new(bytes);
+        });
+        let segments = differ.finalize();
+        let new_bytes = fs::read(new_file_path)?;
+
+        // ranges within a single segment, spanning several, at the edges and past the end
+        let new_len = new_bytes.len() as u64;
+        let ranges = [
+            1000..2000,
+            0..1,
+            5000..150000,
+            0..new_len,
+            new_len - 10..new_len,
+            new_len - 10..new_len + 10,
+            100..100,
+        ];
+        for range in ranges {
+            let bytes = read_range(old_file_path, new_file_path, &segments, range.clone())?;
+            let expected_end = range.end.min(new_len);
+            assert_eq!(bytes, &new_bytes[buffer_range(&(range.start..expected_end))]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)] // relies on sparse files, the old file is never actually written in full
+    fn test_patch_beyond_4gib() -> Result<()> {
+        let directory = std::env::temp_dir().join(format!("differ_patch_{}", std::process::id()));
+        fs::create_dir_all(&directory)?;
+        let old_file_path = directory.join("old");
+        let new_file_path = directory.join("new");
+        let patched_file_path = directory.join("patched");
+
+        // a 5GiB old file, holding data only right past the u32 range
+        let offset: u64 = u32::MAX as u64 + 1000;
+        let old_data = "data beyond the 32-bit offsets".as_bytes();
+        let old_len = offset + old_data.len() as u64 + 1_000_000_000;
+        let mut old_file = File::create(&old_file_path)?;
+        old_file.set_len(old_len)?;
+        old_file.seek(SeekFrom::Start(offset))?;
+        old_file.write_all(old_data)?;
+        fs::write(&new_file_path, "new bytes".as_bytes())?;
+
+        let segments = vec![
+            Segment::New(0..4),
+            Segment::Old(offset..offset + old_data.len() as u64),
+            Segment::Old(old_len - 3..old_len),
+        ];
+        let expected = [&b"new "[..], old_data, &[0; 3]].concat();
+        let (old_file_path, new_file_path, patched_file_path) = (
+            old_file_path.to_str().unwrap(),
+            new_file_path.to_str().unwrap(),
+            patched_file_path.to_str().unwrap(),
+        );
+
+        let bytes_used = patch(old_file_path, new_file_path, patched_file_path, segments.clone())?;
+        assert_eq!(bytes_used, (old_data.len() as u64 + 3, 4));
+        assert_eq!(fs::read(patched_file_path)?, expected);
+        let bytes = read_range(old_file_path, new_file_path, &segments, 2..10)?;
+        assert_eq!(bytes, &expected[2..10]);
+
+        // reading past the end of the old file fails rather than truncating the output
+        let segments = vec![Segment::Old(old_len - 3..old_len + 3)];
+        let error = patch(old_file_path, new_file_path, patched_file_path, segments).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        fs::remove_dir_all(&directory)
+    }
+
+    #[test]
+    fn test_patch_with_source() -> Result<()> {
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let new_file_path = "./example/monkey_after.tiff";
+        let old_bytes = fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = fs::read(new_file_path)?;
+
+        // an in-memory chunk store holding the old chunks keyed by their hashes
+        let mut slicer = make_slicer(&config).unwrap();
+        slicer.process(&old_bytes);
+        let mut store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+        let mut chunk_start: u64 = 0;
+        for chunk in slicer.finalize().unwrap() {
+            let chunk_range = buffer_range(&(chunk_start..chunk.end));
+            store.insert(chunk.hash.clone(), old_bytes[chunk_range].to_vec());
+            chunk_start = chunk.end;
+        }
+
+        let mut differ = Differ::from_config(config);
+        differ.enable_hash_references();
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let segments = differ.finalize();
+
+        let patched_file_path = std::env::temp_dir()
+            .join(format!("differ_patch_with_source_{}", std::process::id()));
+        let patched_file_path = patched_file_path.to_str().unwrap();
+        let (old_bytes_used, new_bytes_used) =
+            patch_with_source(&store, new_file_path, patched_file_path, segments.clone())?;
+        assert!(old_bytes_used > 0);
+        assert_eq!(old_bytes_used + new_bytes_used, new_bytes.len() as u64);
+        assert_eq!(fs::read(patched_file_path)?, new_bytes);
+
+        // an in-memory store can't resolve offset ranges
+        let segments = vec![Segment::Old(0..16)];
+        let error = patch_with_source(&store, new_file_path, patched_file_path, segments);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::Unsupported);
+
+        fs::remove_file(patched_file_path)
+    }
+
+    #[test]
+    fn test_patch_from_reader() -> Result<()> {
+        let new_file_path = "./example/monkey_after.tiff";
+        let old_bytes = fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = fs::read(new_file_path)?;
+        let segments = Differ::diff(
+            &old_bytes[..],
+            &new_bytes[..],
+            Some(64),
+            Some(2048),
+            Some(8192),
+            Some((1 << 12) - 1),
+        )
+        .unwrap();
+        // the first chunks have changed, so some old data is skipped
+        assert!(!matches!(segments.first(), Some(Segment::Old(range)) if range.start == 0));
+
+        let patched_file_path = std::env::temp_dir()
+            .join(format!("differ_patch_from_reader_{}", std::process::id()));
+        let patched_file_path = patched_file_path.to_str().unwrap();
+        let (old_bytes_used, new_bytes_used) =
+            patch_from_reader(&old_bytes[..], new_file_path, patched_file_path, segments)?;
+        assert!(old_bytes_used > 0);
+        assert_eq!(old_bytes_used + new_bytes_used, new_bytes.len() as u64);
+        assert_eq!(fs::read(patched_file_path)?, new_bytes);
+
+        // the old data can't be read backwards
+        let segments = vec![Segment::Old(100..200), Segment::New(0..10), Segment::Old(150..250)];
+        let error = patch_from_reader(&old_bytes[..], new_file_path, patched_file_path, segments);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        // nor past its end
+        let segments = vec![Segment::Old(100..200)];
+        let error = patch_from_reader(&old_bytes[..150], new_file_path, patched_file_path, segments);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        fs::remove_file(patched_file_path)
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_patch_gzip() -> Result<()> {
+        let old_file_path = "./example/monkey_before.tiff";
+        let new_file_path = "./example/monkey_after.tiff";
+        let new_bytes = fs::read(new_file_path)?;
+        let segments = Differ::diff(
+            &fs::read(old_file_path)?[..],
+            &new_bytes[..],
+            Some(64),
+            Some(2048),
+            Some(8192),
+            Some((1 << 12) - 1),
+        )
+        .unwrap();
+
+        let patched_file_path = std::env::temp_dir()
+            .join(format!("differ_patch_gzip_{}", std::process::id()));
+        let patched_file_path = patched_file_path.to_str().unwrap();
+        let old_source = FileChunkSource::open(old_file_path)?;
+        let (old_bytes_used, new_bytes_used) =
+            patch_gzip(&old_source, new_file_path, patched_file_path, segments)?;
+        assert!(old_bytes_used > 0);
+        assert_eq!(old_bytes_used + new_bytes_used, new_bytes.len() as u64);
+
+        let mut patched_bytes: Vec<u8> = Vec::new();
+        flate2::read::GzDecoder::new(File::open(patched_file_path)?).read_to_end(&mut patched_bytes)?;
+        assert_eq!(patched_bytes, new_bytes);
+
+        fs::remove_file(patched_file_path)
+    }
+
+    #[test]
+    fn test_apply_reverse() -> Result<()> {
+        let new_file_path = "./example/monkey_after.tiff";
+        let old_bytes = fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = fs::read(new_file_path)?;
+        let config = DifferConfig {
+            chunking: ChunkingParameters {
+                window_size: 64,
+                min_chunk_size: 2048,
+                max_chunk_size: 8192,
+                boundary_mask: (1 << 12) - 1,
+            },
+            ..DifferConfig::default()
+        };
+        let mut differ = Differ::from_config(config);
+        differ.process_old(&old_bytes);
+        differ.process_new(&new_bytes);
+        let (_, reverse_segments) = differ.finalize_bidirectional();
+
+        // the reverse delta carries the old bytes missing from the new file
+        let mut reverse_delta: Vec<u8> = Vec::new();
+        write_self_contained(&reverse_segments, &old_bytes, &config, &mut reverse_delta)?;
+        let patched_file_path = std::env::temp_dir()
+            .join(format!("differ_apply_reverse_{}", std::process::id()));
+        let patched_file_path = patched_file_path.to_str().unwrap();
+        let (new_bytes_used, delta_bytes_used) =
+            apply_reverse(new_file_path, &reverse_delta, patched_file_path)?;
+        assert!(new_bytes_used > 0 && delta_bytes_used > 0);
+        assert_eq!(new_bytes_used + delta_bytes_used, old_bytes.len() as u64);
+        assert_eq!(fs::read(patched_file_path)?, old_bytes);
+
+        // a truncated delta is rejected
+        let error = apply_reverse(new_file_path, &reverse_delta[..100], patched_file_path);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        fs::remove_file(patched_file_path)
+    }
+
+    #[test]
+    fn test_apply_delta_to() -> Result<()> {
+        let old_file_path = "./example/monkey_before.tiff";
+        let new_bytes = fs::read("./example/monkey_after.tiff")?;
+        let parameters = ChunkingParameters {
+            window_size: 64,
+            min_chunk_size: 2048,
+            max_chunk_size: 8192,
+            boundary_mask: (1 << 12) - 1,
+        };
+        let mut delta: Vec<u8> = Vec::new();
+        pipe_diff(old_file_path, &new_bytes[..], &mut delta, parameters)?;
+        let old_source = FileChunkSource::open(old_file_path)?;
+
+        let mut patched_bytes: Vec<u8> = Vec::new();
+        let hash = apply_delta_to(&old_source, &delta, &mut patched_bytes)?;
+        assert_eq!(patched_bytes, new_bytes);
+        assert_eq!(hash, Sha256::digest(&new_bytes).to_vec());
+
+        // a corrupted payload byte is caught by the running hash, the patched data never
+        // being read back
+        let last = delta.len() - 1;
+        delta[last] ^= 0xff;
+        let mut patched_bytes: Vec<u8> = Vec::new();
+        let error = apply_delta_to(&old_source, &delta, &mut patched_bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(patched_bytes.len(), new_bytes.len());
+
+        // without a hash in the header, the corruption goes unnoticed
+        let (structure, payload, _) = split_self_contained(&delta).unwrap();
+        let unverified = [&(structure.len() as u64).to_le_bytes()[..], &[0], structure, payload].concat();
+        let mut patched_bytes: Vec<u8> = Vec::new();
+        apply_delta_to(&old_source, &unverified, &mut patched_bytes)?;
+        assert_ne!(patched_bytes, new_bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconstruct_iter() -> Result<()> {
+        let old_bytes = fs::read("./example/monkey_before.tiff")?;
+        let new_bytes = fs::read("./example/monkey_after.tiff")?;
+        let segments = Differ::diff(
+            &old_bytes[..],
+            &new_bytes[..],
+            Some(64),
+            Some(2048),
+            Some(8192),
+            Some((1 << 12) - 1),
+        )
+        .unwrap();
+
+        let patched_bytes: Vec<u8> = reconstruct_iter(&old_bytes, &new_bytes, &segments)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(patched_bytes, new_bytes);
+
+        Ok(())
+    }
+}
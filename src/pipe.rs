@@ -0,0 +1,131 @@
+/*
+    Pipe-to-pipe diffing: the old data comes from a file, the new data from a reader (e.g.
+    stdin) and the delta goes to a writer (e.g. stdout), without any intermediate files.
+
+    The delta is self-contained, so that it can be applied without access to the new data:
+    [structure length: u64][hash length: u8][hash][structure][payload]
+    where the structure and the payload are those produced by delta::serialize_split (the
+    New segments refer to the payload, which holds their bytes) and the hash is the SHA256
+    of the data the delta reconstructs, verified as it's applied (see apply_delta_to). A hash
+    length of 0 means that the delta carries no hash.
+
+    The delta is only known once the whole new input has been processed, so the new input
+    is kept in memory until then.
+*/
+
+use crate::delta::*;
+use crate::differ::*;
+use crate::reader::*;
+use crate::slicer::ChunkingParameters;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Read, Result, Write},
+};
+
+// Diffs the old file against the new input, writing the self-contained delta
+pub fn pipe_diff<R: Read, W: Write>(
+    old_file_path: &str,
+    mut new_reader: R,
+    mut delta_writer: W,
+    parameters: ChunkingParameters,
+) -> Result<()> {
+    let config = DifferConfig {
+        chunking: parameters,
+        ..DifferConfig::default()
+    };
+    let mut differ = Differ::try_from_config(config)
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+
+    let mut buffer: Vec<u8> = vec![0; STREAM_READ_BUF_SIZE];
+    let mut old_file = File::open(old_file_path)?;
+    while let Some(bytes_read) = read_buffer(&mut old_file, &mut buffer)? {
+        differ.process_old(&buffer[..bytes_read]);
+    }
+    let mut new: Vec<u8> = Vec::new();
+    while let Some(bytes_read) = read_buffer(&mut new_reader, &mut buffer)? {
+        differ.process_new(&buffer[..bytes_read]);
+        new.extend_from_slice(&buffer[..bytes_read]);
+    }
+    let segments = differ.finalize();
+
+    write_self_contained(&segments, &new, &config, &mut delta_writer)
+}
+
+// writes the self-contained delta (see the module description); 'new' is the data the New
+// segments refer to, which is also the data reconstructed by the delta
+pub(crate) fn write_self_contained<W: Write>(
+    segments: &[Segment],
+    new: &[u8],
+    config: &DifferConfig,
+    delta_writer: &mut W,
+) -> Result<()> {
+    let (structure, payload) = serialize_split(segments, new, config)
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    let hash = Sha256::digest(new);
+    delta_writer.write_all(&(structure.len() as u64).to_le_bytes())?;
+    delta_writer.write_all(&[hash.len() as u8])?;
+    delta_writer.write_all(&hash)?;
+    delta_writer.write_all(&structure)?;
+    delta_writer.write_all(&payload)?;
+    delta_writer.flush()
+}
+
+// the structure, the payload and the hash (if any) of a self-contained delta
+pub(crate) type SelfContainedParts<'a> = (&'a [u8], &'a [u8], Option<&'a [u8]>);
+
+// splits the self-contained delta into its structure, its payload and the hash of the
+// reconstructed data (None if the delta carries no hash)
+pub(crate) fn split_self_contained(
+    delta: &[u8],
+) -> std::result::Result<SelfContainedParts<'_>, DeltaError> {
+    let structure_len = delta.get(..8).ok_or(DeltaError::Truncated)?;
+    let structure_len = u64::from_le_bytes(structure_len.try_into().unwrap());
+    let hash_len = *delta.get(8).ok_or(DeltaError::Truncated)? as usize;
+    let hash = delta.get(9..9 + hash_len).ok_or(DeltaError::Truncated)?;
+    let delta = &delta[9 + hash_len..];
+    match usize::try_from(structure_len) {
+        Ok(structure_len) if structure_len <= delta.len() => {
+            let (structure, payload) = delta.split_at(structure_len);
+            Ok((structure, payload, (hash_len > 0).then_some(hash)))
+        }
+        _ => Err(DeltaError::Truncated),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe_diff() -> Result<()> {
+        let old_file_path = "./example/monkey_before.tiff";
+        let old_bytes = std::fs::read(old_file_path)?;
+        let new_bytes = std::fs::read("./example/monkey_after.tiff")?;
+        let parameters = ChunkingParameters {
+            window_size: 64,
+            min_chunk_size: 2048,
+            max_chunk_size: 8192,
+            boundary_mask: (1 << 12) - 1,
+        };
+
+        let mut delta: Vec<u8> = Vec::new();
+        pipe_diff(old_file_path, &new_bytes[..], &mut delta, parameters)?;
+        assert!(delta.len() < new_bytes.len());
+
+        let (structure, payload, hash) = split_self_contained(&delta).unwrap();
+        assert_eq!(apply_split(structure, payload, &old_bytes).unwrap(), new_bytes);
+        assert_eq!(hash, Some(&Sha256::digest(&new_bytes)[..]));
+
+        assert_eq!(split_self_contained(&delta[..7]), Err(DeltaError::Truncated));
+        assert_eq!(split_self_contained(&delta[..structure.len() + 7]), Err(DeltaError::Truncated));
+
+        // invalid parameters are reported rather than panicking
+        let mut invalid_parameters = parameters;
+        invalid_parameters.max_chunk_size = 1024;
+        let error = pipe_diff(old_file_path, &new_bytes[..], Vec::new(), invalid_parameters);
+        assert_eq!(error.unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+}
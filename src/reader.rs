@@ -1,8 +1,21 @@
 use std::fs::File;
-use std::io::{BufReader, BufRead};
+use std::io::{BufReader, BufRead, ErrorKind, Read, Result};
 
 pub const FILE_READER_BUF_SIZE: usize = 16;
 
+// the buffer streamed inputs (readers rather than files) are read with
+pub(crate) const STREAM_READ_BUF_SIZE: usize = 1 << 20;
+
+// the percentage of the file processed so far, capped at 100 (the file may grow while being
+// read); None if the size is unknown (pipes or /proc files report a zero size)
+fn progress_percent(processed_so_far: usize, file_size: usize) -> Option<u64> {
+    if file_size == 0 {
+        return None;
+    }
+    Some((100 * processed_so_far / file_size).min(100) as u64)
+}
+
+#[cfg(test)]
 pub(crate) fn read_file<F>(path: &str, mut on_read: F) where F: FnMut(&[u8], u64) {
 
     let file = File::open(path).expect("Could not open file");
@@ -13,16 +26,152 @@ pub(crate) fn read_file<F>(path: &str, mut on_read: F) where F: FnMut(&[u8], u64
     let mut processed_so_far: usize = 0;
     loop {
         let buffer = reader.fill_buf().expect("File read failed");
-        let bytes_read: usize = buffer.len().try_into().unwrap();
+        let bytes_read: usize = buffer.len();
         if bytes_read == 0 {
             break;
         }
-        let progress: u64 = (100 * processed_so_far / file_size).try_into().unwrap();
+        let progress: u64 = progress_percent(processed_so_far, file_size).unwrap_or(0);
 
         on_read(buffer, progress);
 
-        processed_so_far = processed_so_far + bytes_read;
+        processed_so_far += bytes_read;
         let length = buffer.len();
         reader.consume(length);
     }
 }
+
+// reads the next buffer, None once the reader is exhausted
+pub(crate) fn read_buffer<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<Option<usize>> {
+    loop {
+        match reader.read(buffer) {
+            Ok(0) => return Ok(None),
+            Ok(bytes_read) => return Ok(Some(bytes_read)),
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// How often the progress callback of read_file_with_progress fires
+pub enum ProgressInterval {
+    Bytes(usize),  // at most every given number of bytes
+    Percent(u64),  // at most every given percentage of the file
+}
+
+// Same as read_file but with a configurable buffer size and the progress reported to
+// a separate callback, at most once per progress_interval (and once the file has been read);
+// nothing is reported if the file size is unknown
+pub fn read_file_with_progress<F, P>(
+    path: &str,
+    buffer_size: usize,
+    progress_interval: ProgressInterval,
+    mut on_read: F,
+    mut on_progress: P,
+) where
+    F: FnMut(&[u8]),
+    P: FnMut(u64),
+{
+    let file = File::open(path).expect("Could not open file");
+    let file_size: usize = file.metadata().expect("Could not read file metadata").len().try_into().unwrap();
+
+    let interval_bytes: usize = match progress_interval {
+        ProgressInterval::Bytes(bytes) => bytes,
+        ProgressInterval::Percent(percent) => file_size * usize::try_from(percent).unwrap() / 100,
+    }
+    .max(1);
+
+    let mut reader = BufReader::with_capacity(buffer_size, file);
+
+    let mut processed_so_far: usize = 0;
+    let mut reported_so_far: usize = 0;
+    loop {
+        let buffer = reader.fill_buf().expect("File read failed");
+        let bytes_read: usize = buffer.len();
+        if bytes_read == 0 {
+            break;
+        }
+
+        on_read(buffer);
+
+        processed_so_far += bytes_read;
+        reader.consume(bytes_read);
+
+        let Some(progress) = progress_percent(processed_so_far, file_size) else {
+            continue;
+        };
+        if processed_so_far - reported_so_far >= interval_bytes || processed_so_far == file_size {
+            reported_so_far = processed_so_far;
+            on_progress(progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_PATH: &str = "./example/monkey_before.tiff";
+
+    #[test]
+    fn test_read_file_progress_interval() {
+        let file_size = std::fs::metadata(FILE_PATH).unwrap().len() as usize;
+
+        // every 64KiB, with the buffer way smaller than that
+        let mut bytes_read: usize = 0;
+        let mut progress_reports: Vec<u64> = Vec::new();
+        read_file_with_progress(
+            FILE_PATH,
+            16,
+            ProgressInterval::Bytes(64 << 10),
+            |bytes| bytes_read += bytes.len(),
+            |progress| progress_reports.push(progress),
+        );
+        assert_eq!(bytes_read, file_size);
+        assert_eq!(progress_reports.len(), file_size / (64 << 10) + 1);
+        assert_eq!(*progress_reports.last().unwrap(), 100);
+
+        // every 10%
+        let mut progress_reports: Vec<u64> = Vec::new();
+        read_file_with_progress(
+            FILE_PATH,
+            4096,
+            ProgressInterval::Percent(10),
+            |_| {},
+            |progress| progress_reports.push(progress),
+        );
+        assert!(progress_reports.len() <= 11);
+        // (but the final report which is always sent)
+        let (_, intermediate_reports) = progress_reports.split_last().unwrap();
+        assert!(intermediate_reports.windows(2).all(|pair| pair[1] >= pair[0] + 9));
+        assert_eq!(*progress_reports.last().unwrap(), 100);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_read_file_unknown_size() {
+        // /proc files report a zero size, yet have contents
+        let path = "/proc/self/status";
+        assert_eq!(std::fs::metadata(path).unwrap().len(), 0);
+
+        let mut bytes_read: usize = 0;
+        let mut progress_reports: Vec<u64> = Vec::new();
+        read_file_with_progress(
+            path,
+            16,
+            ProgressInterval::Percent(10),
+            |bytes| bytes_read += bytes.len(),
+            |progress| progress_reports.push(progress),
+        );
+        assert!(bytes_read > 0);
+        assert!(progress_reports.is_empty());
+
+        let mut progress_reports: Vec<u64> = Vec::new();
+        read_file(path, |_, progress| progress_reports.push(progress));
+        assert!(!progress_reports.is_empty());
+        assert!(progress_reports.iter().all(|&progress| progress == 0));
+
+        // a file larger than its size at opening time doesn't report more than 100%
+        assert_eq!(progress_percent(150, 100), Some(100));
+        assert_eq!(progress_percent(50, 100), Some(50));
+    }
+}
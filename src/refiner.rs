@@ -0,0 +1,253 @@
+/*
+    Byte-level refinement of a chunk-level delta.
+
+    Chunk-level diffing can't reuse a region smaller than a chunk, so a single changed byte
+    makes the whole chunk New. The refinement pass revisits every New segment adjacent to
+    an Old one and diffs it, byte by byte, against the old region it replaces (the bytes
+    between the preceding and the following Old segments), turning the common runs into Old
+    segments.
+
+    The byte-level diff is Myers' O((N+M)D) algorithm:
+    http://www.xmailserver.org/diff2.pdf
+    It's efficient for small, scattered edits, which is exactly the case the refinement helps
+    with. To bound the cost, the edit distance is capped (segments differing more are left
+    as they are) and so are the sizes of the compared regions.
+
+    Unlike the chunk-level diffing, the refinement needs random access to both old and new
+    data, so it's a separate pass over in-memory buffers.
+*/
+
+use crate::delta::*;
+use std::ops::Range;
+
+// common runs shorter than this are not worth a separate segment
+const MIN_REFINED_MATCH_SIZE: usize = 64;
+// New segments (and old regions) larger than this are not refined
+const MAX_REFINED_REGION_SIZE: usize = 1 << 20;
+// New segments differing from the old region by more edits than this are not refined
+const MAX_REFINED_EDIT_DISTANCE: usize = 1024;
+
+// Refines the segments computed for the old and new data, replacing parts of the New
+// segments with Old ones wherever a run of at least MIN_REFINED_MATCH_SIZE bytes is reused
+pub fn refine(segments: Vec<Segment>, old: &[u8], new: &[u8]) -> Vec<Segment> {
+    let mut refined: Vec<Segment> = Vec::with_capacity(segments.len());
+    for (index, segment) in segments.iter().enumerate() {
+        let new_range = match segment {
+            Segment::New(range) => buffer_range(range),
+            _ => {
+                push_coalesced(&mut refined, segment.clone());
+                continue;
+            }
+        };
+        let old_range = buffer_range(&replaced_old_range(&segments, index, old.len() as u64));
+
+        for refined_segment in refine_segment(old, new, old_range, new_range) {
+            push_coalesced(&mut refined, refined_segment);
+        }
+    }
+    refined
+}
+
+// Returns the old region replaced by the New segment at the given index: the bytes between
+// the preceding and the following Old segments
+pub(crate) fn replaced_old_range(segments: &[Segment], index: usize, old_len: u64) -> Range<u64> {
+    let previous_old_end = match index.checked_sub(1).map(|index| &segments[index]) {
+        Some(Segment::Old(range)) => Some(range.end),
+        _ => None,
+    };
+    let next_old_start = match segments.get(index + 1) {
+        Some(Segment::Old(range)) => Some(range.start),
+        _ => None,
+    };
+    match (previous_old_end, next_old_start) {
+        (None, None) => 0..0,
+        (Some(start), None) => start..old_len,
+        (None, Some(end)) => 0..end,
+        (Some(start), Some(end)) => start..end.max(start),
+    }
+}
+
+// splits the New segment into New and Old ones, based on the byte-level diff against
+// the old region
+fn refine_segment(
+    old: &[u8],
+    new: &[u8],
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+) -> Vec<Segment> {
+    let segment_range = |range: Range<usize>| range.start as u64..range.end as u64;
+    if old_range.len() < MIN_REFINED_MATCH_SIZE
+        || new_range.len() < MIN_REFINED_MATCH_SIZE
+        || old_range.len() > MAX_REFINED_REGION_SIZE
+        || new_range.len() > MAX_REFINED_REGION_SIZE
+    {
+        return vec![Segment::New(segment_range(new_range))];
+    }
+    let Some(runs) = common_runs(
+        &old[old_range.clone()],
+        &new[new_range.clone()],
+        MAX_REFINED_EDIT_DISTANCE,
+    ) else {
+        return vec![Segment::New(segment_range(new_range))];
+    };
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut new_position = new_range.start;
+    for (old_start, new_start, len) in runs {
+        if len < MIN_REFINED_MATCH_SIZE {
+            continue;
+        }
+        let new_start = new_range.start + new_start;
+        let old_start = old_range.start + old_start;
+        if new_start > new_position {
+            segments.push(Segment::New(segment_range(new_position..new_start)));
+        }
+        segments.push(Segment::Old(segment_range(old_start..old_start + len)));
+        new_position = new_start + len;
+    }
+    if new_position < new_range.end {
+        segments.push(Segment::New(segment_range(new_position..new_range.end)));
+    }
+    segments
+}
+
+// appends the segment, merging it with the last one if they are contiguous
+pub(crate) fn push_coalesced(segments: &mut Vec<Segment>, segment: Segment) {
+    match (segments.last_mut(), &segment) {
+        (Some(Segment::New(last)), Segment::New(range))
+        | (Some(Segment::Old(last)), Segment::Old(range))
+            if last.end == range.start =>
+        {
+            last.end = range.end;
+        }
+        _ => segments.push(segment),
+    }
+}
+
+// Finds the shortest edit script with Myers' algorithm and returns the runs of bytes common
+// to a and b along it, as (a_start, b_start, len), or None if a and b differ by more than
+// max_edit_distance edits (insertions plus deletions)
+fn common_runs(
+    a: &[u8],
+    b: &[u8],
+    max_edit_distance: usize,
+) -> Option<Vec<(usize, usize, usize)>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max_d = max_edit_distance as isize;
+    let offset = max_d + 1; // v is indexed with k + offset, k being in -d-1..=d+1
+    let mut v: Vec<isize> = vec![0; 2 * max_edit_distance + 3];
+    // for each d, the furthest reaching x on diagonals -d-1..=d+1 before d edits were made
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut edit_distance: Option<isize> = None;
+    'search: for d in 0..=max_d {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1] // moving down (insertion)
+            } else {
+                v[index - 1] + 1 // moving right (deletion)
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                edit_distance = Some(d);
+                break 'search;
+            }
+        }
+    }
+    let edit_distance = edit_distance?;
+
+    // trace back the edit script, collecting the diagonals (common runs)
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..=edit_distance).rev() {
+        let v = &trace[d as usize];
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        let run_end = x;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+        }
+        if run_end > x {
+            runs.push((x as usize, y as usize, (run_end - x) as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    runs.reverse();
+    Some(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differ::Differ;
+    use crate::lcs::hunt_szymanski::lcs_hunt_szymanski;
+    use crate::patcher::reconstruct_iter;
+
+    fn new_bytes_count(segments: &[Segment]) -> u64 {
+        segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::New(_)))
+            .map(Segment::len)
+            .sum()
+    }
+
+    #[test]
+    fn test_refiner_common_runs() {
+        let a = "a blockchain is a growing list of records".as_bytes();
+        let b = "the blockchain - a growing list of blocks".as_bytes();
+        let runs = common_runs(a, b, 64).unwrap();
+        for (a_start, b_start, len) in runs.iter() {
+            assert_eq!(a[*a_start..a_start + len], b[*b_start..b_start + len]);
+        }
+        // the shortest edit script keeps a longest common subsequence
+        let common: usize = runs.iter().map(|run| run.2).sum();
+        assert_eq!(common, lcs_hunt_szymanski(a, b).len());
+
+        assert_eq!(common_runs(a, b, 2), None);
+        assert_eq!(common_runs(&[], &[], 0), Some(vec![]));
+    }
+
+    #[test]
+    fn test_refiner_single_byte_change() {
+        // fixed-size 4KiB chunks, one byte changed in the middle of one of them
+        let old: Vec<u8> = (0..65536u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut new = old.clone();
+        new[20000] ^= 0xff;
+
+        let segments = Differ::diff(&old[..], &new[..], Some(64), Some(4096), Some(4096), Some(0)).unwrap();
+        assert_eq!(new_bytes_count(&segments), 4096);
+
+        let refined = refine(segments, &old, &new);
+        assert_eq!(new_bytes_count(&refined), 1);
+        assert_eq!(
+            refined,
+            vec![
+                Segment::Old(0..20000),
+                Segment::New(20000..20001),
+                Segment::Old(20001..65536)
+            ]
+        );
+        let patched: Vec<u8> = reconstruct_iter(&old, &new, &refined)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(patched, new);
+    }
+}
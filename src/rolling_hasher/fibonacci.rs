@@ -0,0 +1,81 @@
+/*
+    FibonacciRollingHasher
+
+    Wraps another rolling hasher, transforming its output with Fibonacci (multiplicative)
+    hashing: the hash is multiplied by 2^64/phi (a large odd constant) and the high 32 bits
+    of the product are taken. Each of the resulting bits depends on all of the bits of the
+    wrapped hash.
+
+    The Slicer detects boundaries by testing the hash's low bits, which can be weakly
+    distributed for some rolling hashes (e.g. the polynomial hash with a base and modulus
+    sharing factors, whose low bits only depend on the last bytes of the window). The
+    transform improves their entropy without changing how the window is updated.
+*/
+
+use super::rolling_hasher::*;
+
+const FIBONACCI_MULTIPLIER: u64 = 0x9e3779b97f4a7c15; // 2^64 / golden ratio
+
+pub struct FibonacciRollingHasher<RH: RollingHasher> {
+    rolling_hasher: RH,
+}
+
+impl<RH: RollingHasher> RollingHasher for FibonacciRollingHasher<RH> {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) -> u32 {
+        let hash = u64::from(self.rolling_hasher.push(byte));
+        (hash.wrapping_mul(FIBONACCI_MULTIPLIER) >> 32) as u32
+    }
+
+    fn get_window_size(&self) -> usize {
+        self.rolling_hasher.get_window_size()
+    }
+
+    fn reset(&mut self) {
+        self.rolling_hasher.reset();
+    }
+}
+
+impl<RH: RollingHasher> FibonacciRollingHasher<RH> {
+    pub fn new(rolling_hasher: RH) -> Self {
+        FibonacciRollingHasher { rolling_hasher }
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::*;
+    use crate::hasher::sha256::*;
+    use crate::rolling_hasher::polynomial::*;
+    use crate::slicer::*;
+
+    // the average chunk size of the file sliced with the rolling hasher
+    fn average_chunk_size<RH: RollingHasher>(rolling_hasher: RH, boundary_mask: u32) -> f64 {
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let mut slicer = Slicer::new(
+            rolling_hasher,
+            Sha256Hasher::new(1 << 16),
+            boundary_mask,
+            64,
+            1 << 16,
+        )
+        .unwrap();
+        slicer.process(&data);
+        let chunks = slicer.finalize().unwrap();
+        data.len() as f64 / chunks.len() as f64
+    }
+
+    #[test]
+    fn test_fibonacci_rolling_hasher() {
+        // with base 256 and a power of 2 modulus, the low 8 bits of the polynomial hash are
+        // just the last byte of the window
+        let make_polynomial = || PolynomialRollingHasher::new(64, Some(1 << 31), Some(256));
+        let boundary_mask: u32 = (1 << 10) - 1;
+        let target = 64.0 + (boundary_mask + 1) as f64; // min_chunk_size + 2^10 on average
+
+        let plain = average_chunk_size(make_polynomial(), boundary_mask);
+        let transformed =
+            average_chunk_size(FibonacciRollingHasher::new(make_polynomial()), boundary_mask);
+        assert!((transformed - target).abs() < (plain - target).abs());
+    }
+}
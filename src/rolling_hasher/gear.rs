@@ -0,0 +1,114 @@
+/*
+    GearRollingHasher
+
+    Implements the Gear hash used by FastCDC:
+    hash[i] = (hash[i-1] << 1) + table[x[i]]     (modulo 2^64)
+
+    where:
+    x     - input data
+    table - 256 random 64-bit values, one per byte value
+
+    Each shift pushes the oldest contribution one bit further, so after 64 bytes it's gone
+    entirely: the hash only depends on the last 64 bytes, an implicit window which needs no
+    circular buffer, and there's no modulo. Bit k only depends on the last k+1 bytes, so the
+    high 32 bits are returned; those the Slicer's mask selects then depend on at least 33
+    bytes. The window size is the full 64 bits (the bytes the returned hash may depend on),
+    which is what the Slicer primes after each boundary.
+
+    The table is generated with SplitMix64 from the seed, so that a given seed always gives
+    the same table (and thus the same boundaries), while distinct seeds give unrelated ones.
+*/
+
+use super::rolling_hasher::*;
+
+pub(crate) const GEAR_WINDOW_SIZE: usize = u64::BITS as usize;
+pub(crate) const DEFAULT_GEAR_SEED: u64 = 0;
+
+pub struct GearRollingHasher {
+    table: [u64; 256],
+    rolling_hash: u64,
+}
+
+impl RollingHasher for GearRollingHasher {
+    #[inline(always)]
+    fn push(&mut self, byte: u8) -> u32 {
+        self.rolling_hash = (self.rolling_hash << 1).wrapping_add(self.table[byte as usize]);
+        (self.rolling_hash >> 32) as u32
+    }
+
+    fn get_window_size(&self) -> usize {
+        GEAR_WINDOW_SIZE
+    }
+
+    fn reset(&mut self) {
+        self.rolling_hash = 0;
+    }
+}
+
+impl GearRollingHasher {
+    pub fn new(seed: Option<u64>) -> Self {
+        let mut state = seed.unwrap_or(DEFAULT_GEAR_SEED);
+        let mut table = [0u64; 256];
+        for value in table.iter_mut() {
+            // SplitMix64
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *value = z ^ (z >> 31);
+        }
+        GearRollingHasher {
+            table,
+            rolling_hash: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gear_rolling_hash() {
+        // the first values of the default table (SplitMix64 seeded with 0)
+        let hasher = GearRollingHasher::new(None);
+        assert_eq!(hasher.table[..2], [0xe220a8397b1dcdaf, 0x6e789e6aa1b965f4]);
+        assert_eq!(hasher.get_window_size(), 64);
+
+        // trying some basic sequence first
+        let mut hasher = GearRollingHasher::new(None);
+        assert_eq!(hasher.push(0), 0xe220a839);
+        assert_eq!(hasher.push(1), 0x32b9eedd);
+        assert_eq!(hasher.push(0), 0x479485f4);
+
+        // and now some less naive examples: the hash only depends on the last 64 bytes
+        let mut hasher = GearRollingHasher::new(None);
+        let suffix = " a state of no motion, a state of no motion, a state of no motion";
+        let mut hash = 0u32;
+        for byte in format!("equilibrium is{}", suffix).bytes() {
+            hash = hasher.push(byte);
+        }
+        assert_eq!(hash, 0xdda56156);
+
+        for byte in format!("standing still is{}", suffix).bytes() {
+            hash = hasher.push(byte);
+        }
+        assert_eq!(hash, 0xdda56156);
+
+        for byte in "eiger is an alpine peak".bytes() {
+            hash = hasher.push(byte);
+        }
+        assert_eq!(hash, 0x9300ecf1);
+
+        // once reset, the hash only depends on the bytes pushed since
+        hasher.reset();
+        assert_eq!(hasher.push(0), 0xe220a839);
+
+        // the seed regenerates the table deterministically
+        let mut seeded = GearRollingHasher::new(Some(42));
+        let mut reseeded = GearRollingHasher::new(Some(42));
+        assert_eq!(seeded.table, reseeded.table);
+        assert_ne!(seeded.table, GearRollingHasher::new(None).table);
+        assert_eq!(seeded.push(b'x'), reseeded.push(b'x'));
+    }
+}
@@ -1,3 +1,6 @@
+#[allow(clippy::module_inception)]
 pub mod rolling_hasher;
+pub mod fibonacci;
+pub mod gear;
 pub mod polynomial;
-pub mod moving_sum;
\ No newline at end of file
+pub mod moving_sum;
@@ -12,7 +12,7 @@
 use super::rolling_hasher::*;
 use crate::helper::*;
 
-pub(crate) struct MovingSumRollingHasher {
+pub struct MovingSumRollingHasher {
     rolling_hash: u32,
     buffer: Vec<u8>, // circular buffer
     buffer_tap: usize,
@@ -35,12 +35,17 @@ impl RollingHasher for MovingSumRollingHasher {
     fn get_window_size(&self) -> usize {
         self.buffer.len()
     }
+
+    fn reset(&mut self) {
+        self.rolling_hash = 0;
+        self.buffer.fill(0);
+        self.buffer_tap = 0;
+    }
 }
 
 impl MovingSumRollingHasher {
     // window_size must be a power of 2
-    #[allow(dead_code)]
-    pub(crate) fn new(window_size: u32) -> Self {
+    pub fn new(window_size: u32) -> Self {
         assert!(
             is_power_of_two(window_size),
             "Sliding window size must be power of 2"
@@ -85,5 +90,9 @@ mod tests {
             hash = hasher.push(byte);
         }
         assert_eq!(hash, 1506);
+
+        // once reset, the hash only depends on the bytes pushed since
+        hasher.reset();
+        assert_eq!(hasher.push(7), 7);
     }
 }
@@ -14,8 +14,8 @@
 use super::rolling_hasher::*;
 use crate::helper::*;
 
-const DEFAULT_MODULUS: u32 = 1000000007;
-const DEFAULT_BASE: u32 = 29791; // lower than modulus
+pub(crate) const DEFAULT_MODULUS: u32 = 1000000007;
+pub(crate) const DEFAULT_BASE: u32 = 29791; // lower than modulus
 
 // the parameters (modulus, base) are expected to be 32-bit
 // we run hashing internally in 64-bit precision to as even
@@ -27,7 +27,7 @@ const DEFAULT_BASE: u32 = 29791; // lower than modulus
 // TODO: we could probably let it overflow (use wrapping arithmetics)
 // but it might adversely affect collision rate (just a hypothesis, to be checked)
 
-pub(crate) struct PolynomialRollingHasher {
+pub struct PolynomialRollingHasher {
     modulus: u64,
     base: u64,
     rolling_hash: u64,
@@ -66,12 +66,17 @@ impl RollingHasher for PolynomialRollingHasher {
     fn get_window_size(&self) -> usize {
         self.buffer.len()
     }
+
+    fn reset(&mut self) {
+        self.rolling_hash = 0;
+        self.buffer.fill(0);
+        self.buffer_tap = 0;
+    }
 }
 
 impl PolynomialRollingHasher {
     // window_size must be a power of 2
-    #[allow(dead_code)]
-    pub(crate) fn new(window_size: u32, modulus: Option<u32>, base: Option<u32>) -> Self {
+    pub fn new(window_size: u32, modulus: Option<u32>, base: Option<u32>) -> Self {
         assert!(
             is_power_of_two(window_size),
             "Sliding window size must be power of 2"
@@ -140,5 +145,9 @@ mod tests {
             hash = hasher.push(byte);
         }
         assert_eq!(hash, 958536060);
+
+        // once reset, the hash only depends on the bytes pushed since
+        hasher.reset();
+        assert_eq!(hasher.push(b'a'), 97);
     }
 }
@@ -2,7 +2,8 @@
     Rolling hasher interface, to be used with Slicer
 */
 
-pub(crate) trait RollingHasher {
+pub trait RollingHasher {
     fn push(&mut self, byte: u8) -> u32;        // pushes new input value and returns current hash
     fn get_window_size(&self) -> usize;
+    fn reset(&mut self);                         // empties the window, as if newly created
 }
@@ -1,5 +1,19 @@
+use super::chunk_spill::*;
 use super::hasher::hasher::*;
+use super::hashing_pool::*;
+use super::helper::*;
+use super::normalizer::*;
 use super::rolling_hasher::rolling_hasher::*;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+use std::path::Path;
+
+// bounds of the average chunk size picked in the auto mode
+const AUTO_MIN_AVG_CHUNK_SIZE: usize = 1 << 5;
+const AUTO_MAX_AVG_CHUNK_SIZE: usize = 1 << 20;
+const AUTO_MAX_WINDOW_SIZE: usize = 64;
 
 /*
 
@@ -9,6 +23,24 @@ Chunk size depends on:
 - boundary_mask, which determines average chunk size (for random input)
 - min_chunk_size, max_chunk_size, which set the allowed chunk length range
 
+Setting min_chunk_size equal to max_chunk_size is allowed and results in fixed-size
+chunking (every chunk but the last one is exactly min_chunk_size long) since content-based
+boundaries can never be detected before the max_chunk_size cut. min_chunk_size of 0 is
+rejected as it would allow zero-length chunks. Invalid parameter combinations are reported
+by 'new' as SlicerError rather than panicking.
+
+Similarly, a boundary_mask of 0 makes every position a boundary, so (once min_chunk_size is
+reached) all chunks but the last one are exactly min_chunk_size long. It's a valid config,
+but a degenerate one: fixed-size chunking defeats the content-defined boundaries, so an
+insertion shifts (and changes) all the following chunks.
+
+The rolling hasher is reset at each boundary and primed over the bytes of the next chunk:
+no content boundary is detected until the window has been filled since the reset, so the
+boundaries never depend on the bytes preceding the chunk. min_chunk_size is then independent
+of the window size: below it, the chunks cut at content boundaries are at least window size
+- 1 bytes long (the window includes the byte starting the next chunk). With min_chunk_size
+greater than or equal the window size, priming never delays a boundary.
+
 The content-based boundary detection requires RollingHasher trait-implementing
 instance, injected as the 'rolling_hasher' argument to 'new'
 
@@ -17,32 +49,295 @@ be assigned to it. The computation is performed by a Hasher trait-implementing
 instance passed as a 'hasher' argument to 'new'.
 
 The Slicer instance is being fed with bytes of the analyzed stream to its 'process'
-associated function.
+associated function. Processing an empty buffer is a no-op, so the stream can be split at
+any positions (including empty buffers) without affecting the chunks.
 When the stream ends the 'finalize' must be called to correctly terminate the last chunk.
+Finalizing an empty stream results in no chunks at all. Once finalized, the Slicer accepts
+no more data: 'try_process' returns SlicerError::Finalized ('process' panics) while calling
+'finalize' again just returns the same chunks.
 
 The result of the Slicer processing are:
 - boundaries, which holds start indices of each chunk (and the length of the stream as last)
 - hashes, containing collision-resistant hashes of each chunk
 
+Alternatively, the Slicer can be created with 'new_pipelined', in which case the calling
+thread only performs the (cheap) boundary detection while the chunk bytes are sent to a pool
+of hashing worker threads. The resulting chunks are identical (and in the same order) as
+those produced by the sequential Slicer.
+
+As a safeguard against boundary math errors (which would otherwise only show up as a
+corrupted patch), 'finalize' checks that the chunks cover exactly the number of bytes
+processed. A violation panics in debug builds and is reported as an error in release ones.
+
+An optional Normalizer (set with 'set_normalizer') transforms the bytes before they get to
+the rolling hasher and the hasher, making both boundary detection and chunk hashes
+insensitive to e.g. formatting changes. The min/max chunk sizes apply to the normalized
+bytes (so that the boundaries don't depend on the bytes skipped by the normalizer) while
+chunk offsets still refer to the original bytes. Chunks matching once normalized may still
+differ in their original bytes (reusing such a chunk would bring the old formatting into
+the patched data), so a hash (FNV-1a) of the original bytes is also computed for each
+chunk and appended to its hash by 'finalize': chunks only match if their original bytes
+are the same too, while the boundaries don't depend on the formatting.
+
+For very small chunks (not larger than a digest) hashing is a pointless overhead. With
+'set_raw_hash_threshold', a Slicer whose max_chunk_size doesn't exceed the threshold uses the
+raw chunk bytes as the chunk hash instead, never calling the hasher. Raw hashes compare the
+same way as the digests do, only without any collision risk.
+
+Storage systems may need to annotate the chunks (e.g. with the compression codec used). The
+callback set with 'set_chunk_metadata_callback' is called for each chunk with its byte range
+as soon as the chunk is terminated; the metadata it returns is kept in a vector parallel to
+the chunks ('chunk_metadata'). Nothing is stored unless the callback is set.
+
+Hash collisions are assumed never to happen. To verify that on real data, the optional
+collision detection (enabled with 'enable_collision_detection') also computes a cheap secondary
+hash (FNV-1a) of each chunk. Chunks sharing the primary hash but not the secondary one are
+distinct chunks whose hashes collide; they're reported by 'collisions'. Only chunks of the
+same stream are compared, the Differ compares the secondary hashes ('secondary_hashes') of
+both streams to enforce its CollisionPolicy.
+
+For telemetry (e.g. watching the chunking behavior live), the callback set with
+'set_boundary_callback' receives a BoundaryEvent for each chunk as soon as it's terminated:
+its offset, size, hash prefix and whether the cut was content-defined, forced by
+max_chunk_size or made by the end of the stream. The events are only built if the callback is
+set. In pipelined mode the hashes are computed later, so the events carry no hash prefix.
+
+Content which rarely matches the boundary_mask (e.g. low-entropy data) produces runs of
+max_chunk_size chunks cut by force rather than by content, so an insertion shifts all their
+boundaries. With 'set_secondary_boundary_mask', a chunk which has grown to the given size
+without a boundary is also cut where the rolling hash matches a second, weaker mask (fewer
+bits, so more likely to match), catching a content-defined boundary before max_chunk_size
+is reached. The boundaries it finds are still content-defined (CutKind::Content).
+
+With 'set_normalized_chunking', the chunk sizes are normalized as in FastCDC: below the
+given average chunk size a stricter mask (more bits than boundary_mask, so less likely to
+match) is checked, from it on a looser one (fewer bits). Boundaries are then found closer to
+the average size, both fewer small chunks and fewer chunks cut by force at max_chunk_size,
+which makes the chunk size distribution tighter than with a single mask. Usually the masks
+have one or two bits more and less, respectively, than the single mask would.
+
+With 'set_local_minimum_boundaries', the boundary_mask (and the secondary one) is ignored:
+a position is a boundary when its rolling hash is the minimum of the last W positions'
+hashes (ties going to the latest one), the min and max chunk sizes still applying. The
+minimum is tracked with a monotonic deque of the recent hashes, so it costs O(1) amortized
+per byte. Unlike a mask match, the condition depends on the hashes around the position
+rather than on the hash alone, which gives a chunk size distribution less skewed towards
+the small sizes: a boundary can't be followed by another one within W positions. The
+deque is cleared with the rolling hasher at each boundary, so the positions of a chunk are
+only compared with one another, and the W positions must have been seen before a cut.
+
+The chunk list of an enormous stream (millions of chunks) can take a lot of memory itself.
+With 'enable_chunk_spilling', whenever the number of chunks held in memory reaches the
+threshold they're moved to a temporary file (see ChunkSpill), and 'finalize' reads them back.
+If writing or reading the file fails, 'finalize' returns a ChunkSpillFailed error.
+It bounds the memory used while slicing, e.g. while both streams of a Differ are being
+sliced, but not the memory of the LCS computation which needs all the chunk hashes at once.
+It's not available in pipelined mode, whose chunk hashes are only known on 'finalize'.
+
+Streams of wider symbols (e.g. UTF-16 text or u32 tokens) are processed with
+'process_symbols', which feeds the bytes of each Symbol (little-endian) to the rolling hasher
+and the hasher, but only cuts chunks between symbols, so a chunk never splits one. The chunk
+offsets are still byte offsets. The boundary is decided on the rolling hash after the whole
+symbol, and a forced cut happens before the symbol which would exceed max_chunk_size, so for
+single bytes this is exactly what 'process' does.
+
+A Slicer created with 'new_at_offset' continues a stream from the given offset, e.g. when
+slicing is resumed from a checkpoint or split across processes: the chunk offsets are absolute
+(continuing from those of the previous Slicer) rather than starting at 0. The offset always
+terminates a chunk, so a chunk straddling it in a single-pass slicing is cut in two.
+
 Slicer cannot be reset. It is mean for analyzing a single stream. Create new instance if
 another stream needs to be analyzed.
 
 */
 
-pub(crate) struct Chunk {
+#[derive(Debug, PartialEq)]
+pub enum SlicerError {
+    InvalidWindowSize(u32), // not a power of 2, as the polynomial rolling hash requires
+    ZeroMinChunkSize,
+    MaxSmallerThanMin { min_chunk_size: usize, max_chunk_size: usize },
+    ByteCountMismatch { processed: u64, chunked: u64 },
+    Finalized,
+    UnfinalizedData, // a reset would discard the data processed since the last finalize
+    NormalizedChunkingReset, // its masks only fit the chunk sizes it was set for
+    ChunkSpillFailed(String), // the chunks cannot be spilled or read back
+    FastCdcParameterOutOfRange { parameter: &'static str, value: u32 },
+}
+
+impl Display for SlicerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SlicerError::InvalidWindowSize(window_size) => write!(
+                f,
+                "window_size ({}) must be a power of 2",
+                window_size
+            ),
+            SlicerError::ZeroMinChunkSize => write!(f, "min_chunk_size must be greater than 0"),
+            SlicerError::MaxSmallerThanMin {
+                min_chunk_size,
+                max_chunk_size,
+            } => write!(
+                f,
+                "max_chunk_size ({}) cannot be lower than min_chunk_size ({})",
+                max_chunk_size, min_chunk_size
+            ),
+            SlicerError::ByteCountMismatch { processed, chunked } => write!(
+                f,
+                "chunks cover {} bytes but {} bytes have been processed",
+                chunked, processed
+            ),
+            SlicerError::Finalized => write!(f, "the slicer has already been finalized"),
+            SlicerError::UnfinalizedData => {
+                write!(f, "the slicer holds processed data which hasn't been finalized")
+            }
+            SlicerError::NormalizedChunkingReset => write!(
+                f,
+                "normalized chunking cannot be kept with different chunking parameters"
+            ),
+            SlicerError::FastCdcParameterOutOfRange { parameter, value } => write!(
+                f,
+                "{} ({}) is out of the range FastCDC allows",
+                parameter, value
+            ),
+            SlicerError::ChunkSpillFailed(error) => {
+                write!(f, "chunk spilling failed: {}", error)
+            }
+        }
+    }
+}
+
+impl Error for SlicerError {}
+
+/// Slicing parameters, as accepted by Differ::new and Chunker::new
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkingParameters {
+    pub window_size: u32,
+    pub min_chunk_size: usize,
+    pub max_chunk_size: usize,
+    pub boundary_mask: u32,
+}
+
+impl ChunkingParameters {
+    /// Picks chunk sizes scaling with the input size: the average chunk size is the square
+    /// root of the input size (rounded up to a power of 2 and clamped to 32B..1MiB), so that
+    /// both the chunk size and the chunk count grow moderately. The minimum and maximum are
+    /// a quarter of and four times the average, respectively
+    /// 
+    /// Arguments:
+    /// input_size      - the (expected or estimated) size of the input in bytes
+    pub fn auto(input_size: u64) -> ChunkingParameters {
+        let avg_chunk_size = ((input_size as f64).sqrt() as usize)
+            .next_power_of_two()
+            .clamp(AUTO_MIN_AVG_CHUNK_SIZE, AUTO_MAX_AVG_CHUNK_SIZE);
+        let min_chunk_size = avg_chunk_size / 4;
+        ChunkingParameters {
+            window_size: u32::try_from(min_chunk_size.min(AUTO_MAX_WINDOW_SIZE)).unwrap(),
+            min_chunk_size,
+            max_chunk_size: avg_chunk_size * 4,
+            boundary_mask: u32::try_from(avg_chunk_size - 1).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk {
     pub hash: Vec<u8>,
-    pub end: usize,
+    pub end: u64, // the offset of the chunk end (exclusive) within the stream
+}
+
+// A stream element, which chunks never split
+pub trait Symbol: Copy {
+    type Bytes: AsRef<[u8]>;
+    fn to_bytes(self) -> Self::Bytes; // the bytes fed to the rolling hasher and the hasher
+}
+
+impl Symbol for u8 {
+    type Bytes = [u8; 1];
+    fn to_bytes(self) -> [u8; 1] {
+        [self]
+    }
+}
+
+impl Symbol for u16 {
+    type Bytes = [u8; 2];
+    fn to_bytes(self) -> [u8; 2] {
+        self.to_le_bytes()
+    }
+}
+
+impl Symbol for u32 {
+    type Bytes = [u8; 4];
+    fn to_bytes(self) -> [u8; 4] {
+        self.to_le_bytes()
+    }
+}
+
+// computes a chunk's metadata given the chunk's byte range
+pub type ChunkMetadataCallback = Box<dyn FnMut(Range<u64>) -> Vec<u8> + Send>;
+
+// the number of leading hash bytes reported in a BoundaryEvent
+const BOUNDARY_EVENT_HASH_PREFIX_LEN: usize = 8;
+
+// why a chunk has been terminated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CutKind {
+    Content, // a content-defined boundary
+    Forced,  // the max_chunk_size has been reached
+    End,     // the end of the stream (the last chunk)
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryEvent {
+    pub offset: u64, // the chunk start within the stream
+    pub size: u64,
+    pub hash_prefix: Vec<u8>, // empty in pipelined mode
+    pub cut: CutKind,
+}
+
+// receives the event of each chunk boundary
+pub type BoundaryCallback = Box<dyn FnMut(&BoundaryEvent) + Send>;
+
 pub(crate) struct Slicer<RH: RollingHasher, H: Hasher> {
     rolling_hasher: RH,
+    window_size: usize,
+    window_fill: usize, // the bytes pushed to the rolling hasher since its reset, up to window_size
     hasher: H,
     boundary_mask: u32, // if masked hash bits are all zeros, it's a boundary
+    large_chunk_boundary_mask: u32, // checked instead past the normalization_chunk_size
+    normalization_chunk_size: usize, // usize::MAX unless the normalized chunking is set
+    secondary_boundary_mask: u32, // checked past the secondary_mask_chunk_size only
+    secondary_mask_chunk_size: usize, // usize::MAX unless the secondary mask is set
+    local_minimum_window: usize, // 0 unless the local minimum boundaries are set
+    local_minimum_candidates: VecDeque<(u64, u32)>, // (position, hash), increasing hashes
+    local_minimum_position: u64, // the positions hashed since the rolling hasher's reset
     min_chunk_size: usize,
     max_chunk_size: usize,
     current_chunk_size: usize,
-    current_chunk_start: usize,
+    current_chunk_normalized_size: usize, // equals current_chunk_size unless normalized
+    current_chunk_start: u64,
+    start_offset: u64, // the stream offset the Slicer starts at
+    total_bytes_processed: u64,
     chunks: Vec<Chunk>,
+    hashing_pool: Option<HashingPool>, // set in pipelined mode only
+    chunk_bytes: Vec<u8>,              // current chunk bytes, pipelined and raw hash modes only
+    raw_hashes: bool,                  // chunk bytes are used as chunk hashes
+    raw_hash_threshold: usize,         // the largest max_chunk_size using raw hashes
+    normalizer: Option<Box<dyn Normalizer + Send>>,
+    original_hash: u64, // of the current chunk's bytes before normalization
+    original_hashes: Vec<u64>, // of each chunk's bytes before normalization, normalizer only
+    collision_detection: bool,
+    secondary_hash: u64, // of the current chunk, sequential mode only
+    secondary_hashes: Vec<u64>, // of each chunk, collision detection only
+    collisions: Vec<Vec<u8>>, // primary hashes shared by distinct chunks
+    chunk_metadata_callback: Option<ChunkMetadataCallback>,
+    chunk_metadata: Vec<Vec<u8>>, // of each chunk, metadata callback only
+    boundary_callback: Option<BoundaryCallback>,
+    chunk_spill: Option<ChunkSpill>,
+    spill_threshold: usize, // the number of chunks held in memory before spilling
+    spill_error: Option<String>, // the failure to spill, reported by finalize
+    is_finalized: bool,
 }
 
 impl<RH: RollingHasher, H: Hasher> Slicer<RH, H> {
@@ -52,95 +347,751 @@ impl<RH: RollingHasher, H: Hasher> Slicer<RH, H> {
         boundary_mask: u32,
         min_chunk_size: usize,
         max_chunk_size: usize,
-    ) -> Slicer<RH, H> {
-        assert!(
-            min_chunk_size >= rolling_hasher.get_window_size(),
-            "min_chunk_size must be greater than or equal the hasher sliding window size"
-        );
-        assert!(
-            max_chunk_size >= min_chunk_size,
-            "max_chunk_size cannot be lower min_chunk_size"
-        );
-        Slicer {
+    ) -> Result<Slicer<RH, H>, SlicerError> {
+        Slicer::new_at_offset(
+            rolling_hasher,
+            hasher,
+            boundary_mask,
+            min_chunk_size,
+            max_chunk_size,
+            0,
+        )
+    }
+
+    // creates a Slicer whose chunk offsets start at 'offset' rather than at 0
+    pub(crate) fn new_at_offset(
+        rolling_hasher: RH,
+        hasher: H,
+        boundary_mask: u32,
+        min_chunk_size: usize,
+        max_chunk_size: usize,
+        offset: u64,
+    ) -> Result<Slicer<RH, H>, SlicerError> {
+        if min_chunk_size == 0 {
+            return Err(SlicerError::ZeroMinChunkSize);
+        }
+        if max_chunk_size < min_chunk_size {
+            return Err(SlicerError::MaxSmallerThanMin {
+                min_chunk_size,
+                max_chunk_size,
+            });
+        }
+        Ok(Slicer {
+            window_size: rolling_hasher.get_window_size(),
+            window_fill: 0,
             rolling_hasher,
             hasher,
             boundary_mask,
+            large_chunk_boundary_mask: boundary_mask,
+            normalization_chunk_size: usize::MAX,
+            secondary_boundary_mask: 0,
+            secondary_mask_chunk_size: usize::MAX,
+            local_minimum_window: 0,
+            local_minimum_candidates: VecDeque::new(),
+            local_minimum_position: 0,
             min_chunk_size,
             max_chunk_size,
             current_chunk_size: 0,
-            current_chunk_start: 0,
+            current_chunk_normalized_size: 0,
+            current_chunk_start: offset,
+            start_offset: offset,
+            total_bytes_processed: 0,
             chunks: vec![],
+            hashing_pool: None,
+            chunk_bytes: vec![],
+            raw_hashes: false,
+            raw_hash_threshold: 0,
+            normalizer: None,
+            original_hash: FNV_OFFSET_BASIS,
+            original_hashes: vec![],
+            collision_detection: false,
+            secondary_hash: FNV_OFFSET_BASIS,
+            secondary_hashes: vec![],
+            collisions: vec![],
+            chunk_metadata_callback: None,
+            chunk_metadata: vec![],
+            boundary_callback: None,
+            chunk_spill: None,
+            spill_threshold: usize::MAX,
+            spill_error: None,
+            is_finalized: false,
+        })
+    }
+
+    // creates a Slicer which offloads chunk hashing to 'threads' worker threads, each of them
+    // using its own Hasher instance created with 'make_hasher'
+    pub(crate) fn new_pipelined<F>(
+        rolling_hasher: RH,
+        make_hasher: F,
+        threads: usize,
+        boundary_mask: u32,
+        min_chunk_size: usize,
+        max_chunk_size: usize,
+    ) -> Result<Slicer<RH, H>, SlicerError>
+    where
+        H: Send + 'static,
+        F: Fn() -> H,
+    {
+        let hasher = make_hasher();
+        let mut slicer = Slicer::new(
+            rolling_hasher,
+            hasher,
+            boundary_mask,
+            min_chunk_size,
+            max_chunk_size,
+        )?;
+        slicer.hashing_pool = Some(HashingPool::new(threads, make_hasher));
+        slicer.chunk_bytes = Vec::with_capacity(max_chunk_size);
+        Ok(slicer)
+    }
+
+    // sets the normalizer applied to bytes prior to boundary detection and hashing
+    pub(crate) fn set_normalizer(&mut self, normalizer: Box<dyn Normalizer + Send>) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Normalizer must be set before processing"
+        );
+        self.normalizer = Some(normalizer);
+    }
+
+    // makes the raw chunk bytes the chunk hashes (skipping the hasher) if max_chunk_size
+    // doesn't exceed the threshold; returns whether the raw hashes are used
+    pub(crate) fn set_raw_hash_threshold(&mut self, threshold: usize) -> bool {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Raw hash threshold must be set before processing"
+        );
+        self.raw_hash_threshold = threshold;
+        self.raw_hashes = self.max_chunk_size <= threshold;
+        if self.raw_hashes {
+            self.chunk_bytes = Vec::with_capacity(self.max_chunk_size);
         }
+        self.raw_hashes
+    }
+
+    // sets the callback computing each chunk's metadata from the chunk's byte range
+    pub(crate) fn set_chunk_metadata_callback(
+        &mut self,
+        callback: ChunkMetadataCallback,
+    ) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Chunk metadata callback must be set before processing"
+        );
+        self.chunk_metadata_callback = Some(callback);
+    }
+
+    // returns the metadata of each chunk (empty if no callback has been set), to be called
+    // after finalize
+    pub(crate) fn chunk_metadata(&self) -> &[Vec<u8>] {
+        &self.chunk_metadata
+    }
+
+    // sets the weaker mask checked once a chunk has grown to 'chunk_size' (but never before
+    // min_chunk_size), see the module description
+    pub(crate) fn set_secondary_boundary_mask(&mut self, mask: u32, chunk_size: usize) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Secondary boundary mask must be set before processing"
+        );
+        self.secondary_boundary_mask = mask;
+        self.secondary_mask_chunk_size = chunk_size.max(self.min_chunk_size);
+    }
+
+    // normalizes the chunk sizes with a stricter mask below the average chunk size and a
+    // looser one from it on, see the module description
+    pub(crate) fn set_normalized_chunking(
+        &mut self,
+        mask_small: u32,
+        mask_large: u32,
+        avg_chunk_size: usize,
+    ) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Normalized chunking must be set before processing"
+        );
+        self.boundary_mask = mask_small;
+        self.large_chunk_boundary_mask = mask_large;
+        self.normalization_chunk_size = avg_chunk_size;
+    }
+
+    // makes the boundaries the positions whose rolling hash is the minimum of the last
+    // 'window' positions' hashes rather than those matching the mask, see the module
+    // description
+    pub(crate) fn set_local_minimum_boundaries(&mut self, window: usize) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Local minimum boundaries must be set before processing"
+        );
+        assert!(window > 0, "Local minimum window must not be empty");
+        self.local_minimum_window = window;
+    }
+
+    // sets the callback receiving the boundary events, see the module description
+    pub(crate) fn set_boundary_callback(&mut self, callback: BoundaryCallback) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Boundary callback must be set before processing"
+        );
+        self.boundary_callback = Some(callback);
+    }
+
+    // enables moving the chunks to a temporary file in the directory whenever 'threshold'
+    // of them are held in memory, see the module description
+    pub(crate) fn enable_chunk_spilling(
+        &mut self,
+        threshold: usize,
+        directory: &Path,
+    ) -> std::io::Result<()> {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Chunk spilling must be enabled before processing"
+        );
+        assert!(
+            self.hashing_pool.is_none(),
+            "Chunk spilling is not available in pipelined mode"
+        );
+        assert!(threshold > 0, "Chunk spilling threshold must be positive");
+        self.chunk_spill = Some(ChunkSpill::create(directory)?);
+        self.spill_threshold = threshold;
+        Ok(())
+    }
+
+    // enables detecting distinct chunks sharing the same hash
+    pub(crate) fn enable_collision_detection(&mut self) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Collision detection must be enabled before processing"
+        );
+        self.collision_detection = true;
+    }
+
+    pub(crate) fn disable_collision_detection(&mut self) {
+        assert!(
+            self.total_bytes_processed == 0,
+            "Collision detection must be disabled before processing"
+        );
+        self.collision_detection = false;
+    }
+
+    // returns the hashes shared by distinct chunks, to be called after finalize
+    pub(crate) fn collisions(&self) -> &[Vec<u8>] {
+        &self.collisions
+    }
+
+    // returns the secondary hash of each chunk (see 'collisions'), to be called after finalize
+    pub(crate) fn secondary_hashes(&self) -> &[u64] {
+        &self.secondary_hashes
+    }
+
+    // returns the chunks, to be called after finalize
+    pub(crate) fn chunks(&self) -> &[Chunk] {
+        &self.chunks
     }
 
     pub(crate) fn process(&mut self, buffer: &[u8]) {
-        for byte in buffer {
-            let rolling_hash = self.rolling_hasher.push(*byte); // compute rolling hash
-            if (self.current_chunk_size >= self.min_chunk_size
-                && (rolling_hash & self.boundary_mask) == 0)
-                || self.current_chunk_size == self.max_chunk_size
-            {
-                self.add_chunk();
+        self.try_process(buffer)
+            .unwrap_or_else(|error| panic!("Cannot process: {}", error));
+    }
+
+    // same as process but fails (rather than panics) if the slicer has been finalized
+    pub(crate) fn try_process(&mut self, buffer: &[u8]) -> Result<(), SlicerError> {
+        if self.is_finalized {
+            return Err(SlicerError::Finalized);
+        }
+        if buffer.is_empty() {
+            return Ok(()); // a no-op, it must neither start nor terminate a chunk
+        }
+        self.total_bytes_processed += buffer.len() as u64;
+        for &original_byte in buffer {
+            let byte = match self.normalizer.as_mut() {
+                Some(normalizer) => normalizer.normalize(original_byte),
+                None => Some(original_byte),
+            };
+            let Some(byte) = byte else {
+                // skipped by the normalizer, it only extends the current chunk
+                self.push_original_bytes(&[original_byte]);
+                self.current_chunk_size += 1;
+                continue;
+            };
+            let rolling_hash = self.push_rolling_hash(byte); // compute rolling hash
+            let cut = if self.is_content_boundary(rolling_hash) {
+                Some(CutKind::Content)
+            } else if self.current_chunk_normalized_size == self.max_chunk_size {
+                Some(CutKind::Forced)
+            } else {
+                None
+            };
+            if let Some(cut) = cut {
+                self.add_chunk(cut);
+                self.push_rolling_hash(byte); // starts priming the window of the new chunk
             }
-            self.hasher.push(*byte);
+            self.push_original_bytes(&[original_byte]);
+            self.push_chunk_byte(byte);
             self.current_chunk_size += 1;
+            self.current_chunk_normalized_size += 1;
         }
+        Ok(())
     }
 
-    pub(crate) fn finalize(&mut self) -> &Vec<Chunk> {
-        self.add_chunk();
-        &self.chunks
+    // same as process but for a stream of symbols, cutting the chunks between symbols only
+    pub(crate) fn process_symbols<S: Symbol>(&mut self, symbols: &[S]) {
+        self.try_process_symbols(symbols)
+            .unwrap_or_else(|error| panic!("Cannot process: {}", error));
+    }
+
+    // same as process_symbols but fails (rather than panics) if the slicer has been finalized
+    pub(crate) fn try_process_symbols<S: Symbol>(&mut self, symbols: &[S]) -> Result<(), SlicerError> {
+        if self.is_finalized {
+            return Err(SlicerError::Finalized);
+        }
+        let mut normalized_bytes: Vec<u8> = Vec::new(); // of the current symbol
+        for symbol in symbols {
+            let bytes = symbol.to_bytes();
+            let bytes = bytes.as_ref();
+            self.total_bytes_processed += bytes.len() as u64;
+            normalized_bytes.clear();
+            for &byte in bytes {
+                match self.normalizer.as_mut() {
+                    Some(normalizer) => normalized_bytes.extend(normalizer.normalize(byte)),
+                    None => normalized_bytes.push(byte),
+                }
+            }
+            if normalized_bytes.is_empty() {
+                // skipped by the normalizer, it only extends the current chunk
+                self.push_original_bytes(bytes);
+                self.current_chunk_size += bytes.len();
+                continue;
+            }
+            let mut rolling_hash: u32 = 0;
+            for &byte in &normalized_bytes {
+                rolling_hash = self.push_rolling_hash(byte);
+            }
+            let cut = if self.is_content_boundary(rolling_hash) {
+                Some(CutKind::Content)
+            } else if self.current_chunk_normalized_size > 0
+                && self.current_chunk_normalized_size + normalized_bytes.len() > self.max_chunk_size
+            {
+                Some(CutKind::Forced)
+            } else {
+                None
+            };
+            if let Some(cut) = cut {
+                self.add_chunk(cut);
+                for &byte in &normalized_bytes {
+                    self.push_rolling_hash(byte);
+                }
+            }
+            self.push_original_bytes(bytes);
+            for &byte in &normalized_bytes {
+                self.push_chunk_byte(byte);
+            }
+            self.current_chunk_size += bytes.len();
+            self.current_chunk_normalized_size += normalized_bytes.len();
+        }
+        Ok(())
+    }
+
+    // pushes the byte to the rolling hasher, counting the bytes priming its window
+    #[inline(always)]
+    fn push_rolling_hash(&mut self, byte: u8) -> u32 {
+        if self.window_fill < self.window_size {
+            self.window_fill += 1;
+        }
+        let rolling_hash = self.rolling_hasher.push(byte);
+        if self.local_minimum_window > 0 && self.window_fill == self.window_size {
+            self.push_local_minimum_candidate(rolling_hash);
+        }
+        rolling_hash
+    }
+
+    // adds the hash of a primed position to the monotonic deque: the hashes it supersedes
+    // (not smaller) can never be the minimum again, nor can those older than the window
+    fn push_local_minimum_candidate(&mut self, rolling_hash: u32) {
+        let position = self.local_minimum_position;
+        self.local_minimum_position += 1;
+        while self
+            .local_minimum_candidates
+            .back()
+            .is_some_and(|&(_, hash)| hash >= rolling_hash)
+        {
+            self.local_minimum_candidates.pop_back();
+        }
+        self.local_minimum_candidates.push_back((position, rolling_hash));
+        while self
+            .local_minimum_candidates
+            .front()
+            .is_some_and(|&(front_position, _)| front_position + self.local_minimum_window as u64 <= position)
+        {
+            self.local_minimum_candidates.pop_front();
+        }
+    }
+
+    // whether the last pushed position's hash is the minimum of a full window of positions
+    #[inline(always)]
+    fn is_local_minimum(&self) -> bool {
+        self.local_minimum_position >= self.local_minimum_window as u64
+            && self.local_minimum_candidates.len() == 1
+    }
+
+    // whether the current chunk ends before the byte completing the rolling hash, which
+    // requires the window to have been primed since the previous boundary
+    #[inline(always)]
+    fn is_content_boundary(&self, rolling_hash: u32) -> bool {
+        if self.local_minimum_window > 0 {
+            return self.window_fill == self.window_size
+                && self.current_chunk_normalized_size >= self.min_chunk_size
+                && self.is_local_minimum();
+        }
+        let boundary_mask = if self.current_chunk_normalized_size < self.normalization_chunk_size {
+            self.boundary_mask
+        } else {
+            self.large_chunk_boundary_mask
+        };
+        self.window_fill == self.window_size
+            && ((self.current_chunk_normalized_size >= self.min_chunk_size
+                && (rolling_hash & boundary_mask) == 0)
+                || (self.current_chunk_normalized_size >= self.secondary_mask_chunk_size
+                    && (rolling_hash & self.secondary_boundary_mask) == 0))
+    }
+
+    // adds the original (not normalized) bytes to the current chunk's original hash, only
+    // computed if a normalizer is set
+    fn push_original_bytes(&mut self, bytes: &[u8]) {
+        if self.normalizer.is_some() {
+            self.original_hash = bytes.iter().fold(self.original_hash, |hash, &byte| fnv1a(hash, byte));
+        }
+    }
+
+    // adds the (normalized) byte to the current chunk
+    fn push_chunk_byte(&mut self, byte: u8) {
+        if self.raw_hashes || self.hashing_pool.is_some() {
+            self.chunk_bytes.push(byte);
+        } else {
+            self.hasher.push(byte);
+            if self.collision_detection {
+                self.secondary_hash = fnv1a(self.secondary_hash, byte);
+            }
+        }
+    }
+
+    pub(crate) fn finalize(&mut self) -> Result<&Vec<Chunk>, SlicerError> {
+        if self.is_finalized {
+            return Ok(&self.chunks);
+        }
+        if let Some(error) = &self.spill_error {
+            return Err(SlicerError::ChunkSpillFailed(error.clone()));
+        }
+        if let Some(chunk_spill) = self.chunk_spill.as_mut() {
+            // the spill is only dropped once reloaded, so that the spilled chunks aren't lost
+            // (and 'finalize' can be called again) if reloading fails
+            let mut chunks = chunk_spill
+                .reload()
+                .map_err(|error| SlicerError::ChunkSpillFailed(error.to_string()))?;
+            self.chunk_spill = None;
+            chunks.append(&mut self.chunks);
+            self.chunks = chunks;
+        }
+        self.is_finalized = true;
+        if self.current_chunk_size > 0 {
+            self.add_chunk(CutKind::End); // the last chunk; an empty stream has no chunks at all
+        }
+        let chunked = self.chunks.last().map_or(0, |chunk| chunk.end - self.start_offset);
+        debug_assert_eq!(
+            chunked, self.total_bytes_processed,
+            "Chunks do not cover the processed bytes"
+        );
+        if chunked != self.total_bytes_processed {
+            return Err(SlicerError::ByteCountMismatch {
+                processed: self.total_bytes_processed,
+                chunked,
+            });
+        }
+        if let Some(hashing_pool) = self.hashing_pool.as_mut() {
+            let hashes = hashing_pool.finish();
+            for (chunk, hash) in self.chunks.iter_mut().zip(hashes) {
+                chunk.hash = hash;
+            }
+        }
+        if self.normalizer.is_some() {
+            for (chunk, original_hash) in self.chunks.iter_mut().zip(&self.original_hashes) {
+                chunk.hash.extend_from_slice(&original_hash.to_le_bytes());
+            }
+        }
+        if self.collision_detection {
+            self.detect_collisions();
+        }
+        Ok(&self.chunks)
+    }
+
+    // returns the chunks, to be called after finalize
+    pub(crate) fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
+    // makes the slicer ready for another stream, sliced with the given rolling hasher and
+    // chunking parameters; the hasher, the buffers and the other settings are kept (the raw
+    // hash threshold being applied to the new max_chunk_size). Fails if data has been
+    // processed since the last finalize, or if normalized chunking is set (its masks depend
+    // on the chunk sizes). Neither available in pipelined mode (the hashing pool is
+    // finished) nor with chunk spilling
+    pub(crate) fn reset(
+        &mut self,
+        rolling_hasher: RH,
+        boundary_mask: u32,
+        min_chunk_size: usize,
+        max_chunk_size: usize,
+    ) -> Result<(), SlicerError> {
+        assert!(self.hashing_pool.is_none(), "Slicer cannot be reset in pipelined mode");
+        assert!(
+            self.spill_threshold == usize::MAX,
+            "Slicer cannot be reset with chunk spilling enabled"
+        );
+        if min_chunk_size == 0 {
+            return Err(SlicerError::ZeroMinChunkSize);
+        }
+        if max_chunk_size < min_chunk_size {
+            return Err(SlicerError::MaxSmallerThanMin {
+                min_chunk_size,
+                max_chunk_size,
+            });
+        }
+        if self.total_bytes_processed > 0 && !self.is_finalized {
+            return Err(SlicerError::UnfinalizedData);
+        }
+        if self.normalization_chunk_size != usize::MAX {
+            return Err(SlicerError::NormalizedChunkingReset);
+        }
+        self.window_size = rolling_hasher.get_window_size();
+        self.window_fill = 0;
+        self.rolling_hasher = rolling_hasher;
+        self.boundary_mask = boundary_mask;
+        self.large_chunk_boundary_mask = boundary_mask;
+        if self.secondary_mask_chunk_size != usize::MAX {
+            self.secondary_mask_chunk_size = self.secondary_mask_chunk_size.max(min_chunk_size);
+        }
+        self.local_minimum_candidates.clear();
+        self.local_minimum_position = 0;
+        self.min_chunk_size = min_chunk_size;
+        self.max_chunk_size = max_chunk_size;
+        self.raw_hashes = max_chunk_size <= self.raw_hash_threshold;
+        self.current_chunk_size = 0;
+        self.current_chunk_normalized_size = 0;
+        self.current_chunk_start = self.start_offset;
+        self.total_bytes_processed = 0;
+        self.chunks.clear();
+        self.chunk_bytes.clear();
+        if let Some(normalizer) = self.normalizer.as_mut() {
+            normalizer.reset();
+        }
+        self.original_hash = FNV_OFFSET_BASIS;
+        self.original_hashes.clear();
+        self.secondary_hash = FNV_OFFSET_BASIS;
+        self.secondary_hashes.clear();
+        self.collisions.clear();
+        self.chunk_metadata.clear();
+        self.is_finalized = false;
+        Ok(())
     }
 
-    fn add_chunk(&mut self) {
-        let hash = self.hasher.finalize();
-        let chunk_end = self.current_chunk_start + self.current_chunk_size;
+    fn add_chunk(&mut self, cut: CutKind) {
+        if self.normalizer.is_some() {
+            self.original_hashes.push(std::mem::replace(&mut self.original_hash, FNV_OFFSET_BASIS));
+        }
+        if self.collision_detection {
+            let secondary_hash = if self.raw_hashes || self.hashing_pool.is_some() {
+                self.chunk_bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| fnv1a(hash, byte))
+            } else {
+                std::mem::replace(&mut self.secondary_hash, FNV_OFFSET_BASIS)
+            };
+            self.secondary_hashes.push(secondary_hash);
+        }
+        let hash = if self.raw_hashes {
+            let capacity = self.chunk_bytes.capacity();
+            std::mem::replace(&mut self.chunk_bytes, Vec::with_capacity(capacity))
+        } else if let Some(hashing_pool) = self.hashing_pool.as_mut() {
+            // the hash will be filled in by finalize
+            let capacity = self.chunk_bytes.capacity();
+            hashing_pool.submit(std::mem::replace(
+                &mut self.chunk_bytes,
+                Vec::with_capacity(capacity),
+            ));
+            Vec::new()
+        } else {
+            self.hasher.finalize()
+        };
+        let chunk_end = self.current_chunk_start + self.current_chunk_size as u64;
+        if let Some(callback) = self.chunk_metadata_callback.as_mut() {
+            self.chunk_metadata.push(callback(self.current_chunk_start..chunk_end));
+        }
+        if let Some(callback) = self.boundary_callback.as_mut() {
+            callback(&BoundaryEvent {
+                offset: self.current_chunk_start,
+                size: self.current_chunk_size as u64,
+                hash_prefix: hash[..hash.len().min(BOUNDARY_EVENT_HASH_PREFIX_LEN)].to_vec(),
+                cut,
+            });
+        }
         let chunk = Chunk {
             hash,
             end: chunk_end,
         };
         self.chunks.push(chunk);
+        if self.chunks.len() >= self.spill_threshold && self.spill_error.is_none() {
+            if let Some(chunk_spill) = self.chunk_spill.as_mut() {
+                // on failure, spilling stops and the chunks are kept until finalize fails
+                match chunk_spill.spill(&self.chunks) {
+                    Ok(()) => self.chunks.clear(),
+                    Err(error) => self.spill_error = Some(error.to_string()),
+                }
+            }
+        }
         self.current_chunk_start = chunk_end;
         self.current_chunk_size = 0;
+        self.current_chunk_normalized_size = 0;
+        self.rolling_hasher.reset();
+        self.window_fill = 0;
+        self.local_minimum_candidates.clear();
+        self.local_minimum_position = 0;
+    }
+
+    fn detect_collisions(&mut self) {
+        let mut secondary_hashes: HashMap<&[u8], u64> = HashMap::new();
+        for (chunk, &secondary_hash) in self.chunks.iter().zip(self.secondary_hashes.iter()) {
+            let first_secondary_hash = *secondary_hashes
+                .entry(&chunk.hash)
+                .or_insert(secondary_hash);
+            if first_secondary_hash != secondary_hash && !self.collisions.contains(&chunk.hash) {
+                self.collisions.push(chunk.hash.clone());
+            }
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sha2"))]
 mod tests {
     use super::*;
     use crate::hasher::sha256::*;
+    use crate::rolling_hasher::moving_sum::*;
     use crate::rolling_hasher::polynomial::*;
-    use crate::read_file;
-
-    #[test]
-    #[should_panic(
-        expected = r#"min_chunk_size must be greater than or equal the hasher sliding window size"#
-    )]
-    fn test_slicer_min_chunk_size_wrong() {
-        // To avoid the need to reset rolling hash on each boundary detection we ensure it keeps
-        // running for at least window_size before the next chunk can be detected (so that all irrelevant
-        // values in the buffer get overwritten). For this to work, slicer's min_chunk_size must be
-        // greater than or equal the rolling hash window_size
-        let min_chunk_size: usize = 32;
-        let max_chunk_size: usize = 8192;
-        let rolling_hash_window_size: u32 = 64;
-        let rolling_hash_modulus: u32 = 1000000007;
-        let rolling_hash_base: u32 = 29791;
-        let boundary_mask: u32 = (1 << 6) - 1; // 6 least significant bits set, chunk size is 2^6 bytes on average
+    use crate::reader::read_file;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
 
-        let rolling_hasher = PolynomialRollingHasher::new(
-            rolling_hash_window_size,
-            Some(rolling_hash_modulus),
-            Some(rolling_hash_base),
+    #[test]
+    fn test_slicer_min_chunk_smaller_than_window() {
+        // the rolling hasher is reset at each boundary and primed over the next chunk, so
+        // min_chunk_size doesn't need to cover the window
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let window_size: u32 = 64;
+        let boundary_mask: u32 = (1 << 6) - 1;
+        let slice = |min_chunk_size: usize, buffer_size: usize| -> Vec<Chunk> {
+            let mut slicer = Slicer::new(
+                PolynomialRollingHasher::new(window_size, None, None),
+                Sha256Hasher::new(8192),
+                boundary_mask,
+                min_chunk_size,
+                8192,
+            )
+            .unwrap();
+            for buffer in data.chunks(buffer_size) {
+                slicer.process(buffer);
+            }
+            slicer.finalize().unwrap().clone()
+        };
+
+        let chunks = slice(16, data.len());
+        assert_eq!(chunks, slice(16, 1000)); // reproducible however the stream is split
+        assert_eq!(chunks.last().unwrap().end, data.len() as u64);
+        let mut chunk_start: u64 = 0;
+        for chunk in &chunks[..chunks.len() - 1] {
+            // no boundary before the window is primed
+            assert!(chunk.end - chunk_start >= window_size as u64 - 1);
+            // each chunk only depends on its own bytes (and the byte following it)
+            let chunk_bytes = &data[chunk_start as usize..chunk.end as usize + 1];
+            let mut chunk_slicer = Slicer::new(
+                PolynomialRollingHasher::new(window_size, None, None),
+                Sha256Hasher::new(8192),
+                boundary_mask,
+                16,
+                8192,
+            )
+            .unwrap();
+            chunk_slicer.process(chunk_bytes);
+            let rechunked = chunk_slicer.finalize().unwrap();
+            assert_eq!(rechunked[0].end, chunk.end - chunk_start);
+            assert_eq!(rechunked[0].hash, chunk.hash);
+            chunk_start = chunk.end;
+        }
+        // with min_chunk_size covering the window, priming never delays a boundary
+        let chunks = slice(window_size as usize, data.len());
+        assert!(chunks.windows(2).any(|pair| pair[1].end - pair[0].end == window_size as u64));
+    }
+
+    #[test]
+    fn test_slicer_min_chunk_size_zero() {
+        let rolling_hasher = MovingSumRollingHasher::new(1);
+        let hasher = Sha256Hasher::new(16);
+        let result = Slicer::new(rolling_hasher, hasher, 0, 0, 16);
+        assert_eq!(result.err(), Some(SlicerError::ZeroMinChunkSize));
+    }
+
+    #[test]
+    fn test_slicer_max_chunk_size_wrong() {
+        let rolling_hasher = MovingSumRollingHasher::new(16);
+        let hasher = Sha256Hasher::new(64);
+        let result = Slicer::new(rolling_hasher, hasher, (1 << 5) - 1, 64, 32);
+        assert_eq!(
+            result.err(),
+            Some(SlicerError::MaxSmallerThanMin {
+                min_chunk_size: 64,
+                max_chunk_size: 32
+            })
         );
-        let hasher = Sha256Hasher::new(max_chunk_size);
-        _ = Slicer::new(
+    }
+
+    #[test]
+    fn test_slicer_empty() {
+        let mut slicer = Slicer::new(
+            PolynomialRollingHasher::new(16, None, None),
+            Sha256Hasher::new(64),
+            (1 << 4) - 1,
+            16,
+            64,
+        )
+        .unwrap();
+        assert!(slicer.finalize().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_slicer_fixed_size() {
+        // with min_chunk_size == max_chunk_size all chunks but the last must be of that size
+        let chunk_size: usize = 1024;
+        let rolling_hash_window_size: u32 = 32;
+        let boundary_mask: u32 = (1 << 4) - 1; // would yield chunks way below chunk_size if honored
+
+        let rolling_hasher = PolynomialRollingHasher::new(rolling_hash_window_size, None, None);
+        let hasher = Sha256Hasher::new(chunk_size);
+        let mut slicer = Slicer::new(
             rolling_hasher,
             hasher,
             boundary_mask,
-            min_chunk_size,
-            max_chunk_size,
-        );
+            chunk_size,
+            chunk_size,
+        )
+        .unwrap();
+        read_file("./example/monkey_before.tiff", |bytes, _| {
+            slicer.process(bytes);
+        });
+        let chunks = slicer.finalize().unwrap();
+
+        let mut chunk_start: u64 = 0;
+        let (last_chunk, chunks) = chunks.split_last().unwrap();
+        for chunk in chunks {
+            assert_eq!(chunk.end - chunk_start, chunk_size as u64);
+            chunk_start = chunk.end;
+        }
+        assert!(last_chunk.end - chunk_start <= chunk_size as u64);
     }
 
     #[test]
@@ -164,13 +1115,737 @@ mod tests {
             boundary_mask,
             min_chunk_size,
             max_chunk_size,
-        );
+        )
+        .unwrap();
         read_file("./example/monkey_before.tiff", |bytes, _| {
             old_file_slicer.process(bytes);
         });
-        old_file_slicer.finalize();
+        old_file_slicer.finalize().unwrap();
 
         // got 69 chunks for a file size of ~353KB, avg chunk size is 5115 bytes
         assert_eq!(old_file_slicer.chunks.len(), 69);
     }
+
+    #[test]
+    fn test_slicer_pipelined() {
+        let min_chunk_size: usize = 512;
+        let max_chunk_size: usize = 4096;
+        let rolling_hash_window_size: u32 = 32;
+        let boundary_mask: u32 = (1 << 10) - 1; // avg chunk size is 2^10 = 1024 bytes on average
+
+        let mut sequential_slicer = Slicer::new(
+            PolynomialRollingHasher::new(rolling_hash_window_size, None, None),
+            Sha256Hasher::new(max_chunk_size),
+            boundary_mask,
+            min_chunk_size,
+            max_chunk_size,
+        )
+        .unwrap();
+        let mut pipelined_slicer = Slicer::new_pipelined(
+            PolynomialRollingHasher::new(rolling_hash_window_size, None, None),
+            || Sha256Hasher::new(max_chunk_size),
+            4,
+            boundary_mask,
+            min_chunk_size,
+            max_chunk_size,
+        )
+        .unwrap();
+        read_file("./example/monkey_before.tiff", |bytes, _| {
+            sequential_slicer.process(bytes);
+            pipelined_slicer.process(bytes);
+        });
+        let sequential_chunks = sequential_slicer.finalize().unwrap();
+        let pipelined_chunks = pipelined_slicer.finalize().unwrap();
+
+        assert_eq!(sequential_chunks.len(), pipelined_chunks.len());
+        for (sequential, pipelined) in sequential_chunks.iter().zip(pipelined_chunks.iter()) {
+            assert_eq!(sequential.end, pipelined.end);
+            assert_eq!(sequential.hash, pipelined.hash);
+        }
+    }
+
+    #[test]
+    fn test_slicer_byte_count_invariant() {
+        let min_chunk_size: usize = 64;
+        let max_chunk_size: usize = 256;
+        let rolling_hash_window_size: u32 = 16;
+        let boundary_mask: u32 = (1 << 7) - 1; // avg chunk size is 2^7 = 128 bytes on average
+        let make_slicer = || {
+            Slicer::new(
+                PolynomialRollingHasher::new(rolling_hash_window_size, None, None),
+                Sha256Hasher::new(max_chunk_size),
+                boundary_mask,
+                min_chunk_size,
+                max_chunk_size,
+            )
+            .unwrap()
+        };
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+        let mut single_pass_slicer = make_slicer();
+        single_pass_slicer.process(&data);
+        let single_pass_chunks = single_pass_slicer.finalize().unwrap();
+        assert_eq!(single_pass_chunks.last().unwrap().end, data.len() as u64);
+
+        // awkward splits: empty, single byte, prime-sized and straddling chunk boundaries
+        let split_sizes: &[usize] = &[0, 1, 1, 13, 0, 255, 256, 257, 1021, 63, 64, 65];
+        let mut split_slicer = make_slicer();
+        let mut position: usize = 0;
+        let mut split_index: usize = 0;
+        while position < data.len() {
+            let end = (position + split_sizes[split_index % split_sizes.len()]).min(data.len());
+            split_slicer.process(&data[position..end]);
+            position = end;
+            split_index += 1;
+        }
+        let split_chunks = split_slicer.finalize().unwrap();
+        assert_eq!(split_chunks.last().unwrap().end, data.len() as u64);
+        assert_eq!(split_chunks.len(), single_pass_chunks.len());
+        for (split, single_pass) in split_chunks.iter().zip(single_pass_chunks.iter()) {
+            assert_eq!(split.end, single_pass.end);
+            assert_eq!(split.hash, single_pass.hash);
+        }
+    }
+
+    #[test]
+    fn test_slicer_empty_process() {
+        let max_chunk_size: usize = 256;
+        let make_slicer = || {
+            Slicer::new(
+                PolynomialRollingHasher::new(16, None, None),
+                Sha256Hasher::new(max_chunk_size),
+                (1 << 7) - 1,
+                64,
+                max_chunk_size,
+            )
+            .unwrap()
+        };
+        let make_pipelined_slicer = || {
+            Slicer::new_pipelined(
+                PolynomialRollingHasher::new(16, None, None),
+                || Sha256Hasher::new(max_chunk_size),
+                2,
+                (1 << 7) - 1,
+                64,
+                max_chunk_size,
+            )
+            .unwrap()
+        };
+        let data: Vec<u8> = (0..4099u32).map(|i| (i * 7919 % 251) as u8).collect();
+
+        let mut slicer = make_slicer();
+        slicer.process(&data);
+        let expected_chunks = slicer.finalize().unwrap().clone();
+
+        // only empty buffers
+        let mut slicer = make_slicer();
+        slicer.process(&[]);
+        slicer.process(&[]);
+        assert!(slicer.finalize().unwrap().is_empty());
+
+        // empty buffers before, between and after the real ones, also right at the
+        // chunk boundaries
+        for mut slicer in [make_slicer(), make_pipelined_slicer()] {
+            slicer.process(&[]);
+            let mut chunk_start: usize = 0;
+            for chunk in expected_chunks.iter() {
+                let chunk_end = chunk.end as usize;
+                slicer.process(&data[chunk_start..chunk_end - 1]);
+                slicer.process(&[]);
+                slicer.process(&data[chunk_end - 1..chunk_end]);
+                slicer.process(&[]);
+                chunk_start = chunk_end;
+            }
+            assert_eq!(slicer.finalize().unwrap(), &expected_chunks);
+        }
+    }
+
+    // keeps only the first byte of the SHA256 digest, so that distinct chunks collide
+    struct TruncatedHasher(Sha256Hasher);
+
+    impl Hasher for TruncatedHasher {
+        fn push(&mut self, byte: u8) {
+            self.0.push(byte);
+        }
+
+        fn finalize(&mut self) -> Vec<u8> {
+            self.0.finalize()[..1].to_vec()
+        }
+    }
+
+    #[test]
+    fn test_slicer_collision_detection() {
+        // 1024 fixed-size chunks, each of them repeated, must share some of the 256 hashes
+        let distinct: Vec<u8> = (0..65536u32).map(|i| (i * 7919 % 251 + i / 251) as u8).collect();
+        let data = [&distinct[..], &distinct[..]].concat();
+        let chunk_size: usize = 64;
+        let sequential = Slicer::new(
+            MovingSumRollingHasher::new(16),
+            TruncatedHasher(Sha256Hasher::new(chunk_size)),
+            0,
+            chunk_size,
+            chunk_size,
+        )
+        .unwrap();
+        let pipelined = Slicer::new_pipelined(
+            MovingSumRollingHasher::new(16),
+            || TruncatedHasher(Sha256Hasher::new(chunk_size)),
+            2,
+            0,
+            chunk_size,
+            chunk_size,
+        )
+        .unwrap();
+        for mut slicer in [sequential, pipelined] {
+            slicer.enable_collision_detection();
+            slicer.process(&data);
+            slicer.finalize().unwrap();
+            assert!(!slicer.collisions().is_empty());
+            assert!(slicer.collisions().iter().all(|hash| hash.len() == 1));
+        }
+
+        // identical chunks sharing the full hash are not collisions
+        let mut slicer = Slicer::new(
+            MovingSumRollingHasher::new(16),
+            Sha256Hasher::new(chunk_size),
+            0,
+            chunk_size,
+            chunk_size,
+        )
+        .unwrap();
+        slicer.enable_collision_detection();
+        slicer.process(&data);
+        slicer.finalize().unwrap();
+        assert!(slicer.collisions().is_empty());
+    }
+
+    // counts the digests computed
+    struct CountingHasher(Sha256Hasher, Arc<AtomicUsize>);
+
+    impl Hasher for CountingHasher {
+        fn push(&mut self, byte: u8) {
+            self.0.push(byte);
+        }
+
+        fn finalize(&mut self) -> Vec<u8> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.finalize()
+        }
+    }
+
+    #[test]
+    fn test_slicer_raw_hashes() {
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let max_chunk_size: usize = 16;
+        let slice = |raw_hash_threshold: usize| {
+            let digests = Arc::new(AtomicUsize::new(0));
+            let mut slicer = Slicer::new(
+                PolynomialRollingHasher::new(4, None, None),
+                CountingHasher(Sha256Hasher::new(max_chunk_size), digests.clone()),
+                (1 << 3) - 1,
+                4,
+                max_chunk_size,
+            )
+            .unwrap();
+            let raw_hashes = slicer.set_raw_hash_threshold(raw_hash_threshold);
+            slicer.process(&data);
+            let chunks = slicer.finalize().unwrap().clone();
+            (raw_hashes, chunks, digests.load(Ordering::Relaxed))
+        };
+
+        let (raw_hashes, hashed_chunks, digests) = slice(max_chunk_size - 1);
+        assert!(!raw_hashes);
+        assert_eq!(digests, hashed_chunks.len());
+
+        let (raw_hashes, raw_chunks, digests) = slice(max_chunk_size);
+        assert!(raw_hashes);
+        assert_eq!(digests, 0);
+        // same boundaries, the hashes being the chunk bytes
+        assert_eq!(raw_chunks.len(), hashed_chunks.len());
+        let mut chunk_start: usize = 0;
+        for (raw_chunk, hashed_chunk) in raw_chunks.iter().zip(hashed_chunks.iter()) {
+            assert_eq!(raw_chunk.end, hashed_chunk.end);
+            assert_eq!(raw_chunk.hash, &data[chunk_start..raw_chunk.end as usize]);
+            chunk_start = raw_chunk.end as usize;
+        }
+    }
+
+    #[test]
+    fn test_slicer_at_offset() {
+        let data: Vec<u8> = (0..20000u32).map(|i| (i * 7919 % 251 + i / 251) as u8).collect();
+        let n: usize = 10000;
+        let make_slicer = |offset: u64| {
+            Slicer::new_at_offset(
+                PolynomialRollingHasher::new(16, None, None),
+                Sha256Hasher::new(1024),
+                (1 << 8) - 1,
+                64,
+                1024,
+                offset,
+            )
+            .unwrap()
+        };
+        let mut single_pass_slicer = make_slicer(0);
+        single_pass_slicer.process(&data);
+        let single_pass_chunks = single_pass_slicer.finalize().unwrap().clone();
+
+        let mut first_slicer = make_slicer(0);
+        first_slicer.process(&data[..n]);
+        let first_chunks = first_slicer.finalize().unwrap();
+        let mut second_slicer = make_slicer(n as u64);
+        second_slicer.process(&data[n..]);
+        let second_chunks = second_slicer.finalize().unwrap();
+        assert_eq!(first_chunks.last().unwrap().end, n as u64);
+        assert!(second_chunks.iter().all(|chunk| chunk.end > n as u64));
+        assert_eq!(second_chunks.last().unwrap().end, data.len() as u64);
+
+        // apart from the chunks cut at n (until the boundaries resynchronize), the combined
+        // chunks are those of the single-pass slicing
+        let (before_n, after_n): (Vec<&Chunk>, Vec<&Chunk>) = single_pass_chunks
+            .iter()
+            .partition(|chunk| chunk.end < n as u64);
+        let (_, first_chunks) = first_chunks.split_last().unwrap();
+        assert_eq!(first_chunks.iter().collect::<Vec<&Chunk>>(), before_n);
+        let resync_index = second_chunks
+            .iter()
+            .position(|chunk| after_n.iter().any(|other| other.end == chunk.end))
+            .unwrap();
+        let resynced_chunks: Vec<&Chunk> = second_chunks[resync_index + 1..].iter().collect();
+        assert!(!resynced_chunks.is_empty());
+        assert!(after_n.ends_with(&resynced_chunks));
+    }
+
+    #[test]
+    fn test_slicer_zero_boundary_mask() {
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let min_chunk_size: usize = 100;
+        for mut slicer in [
+            Slicer::new(
+                PolynomialRollingHasher::new(64, None, None),
+                Sha256Hasher::new(1000),
+                0,
+                min_chunk_size,
+                1000,
+            )
+            .unwrap(),
+            Slicer::new_pipelined(
+                PolynomialRollingHasher::new(64, None, None),
+                || Sha256Hasher::new(1000),
+                2,
+                0,
+                min_chunk_size,
+                1000,
+            )
+            .unwrap(),
+        ] {
+            slicer.process(&data);
+            let chunks = slicer.finalize().unwrap();
+            // every position is a boundary, so the chunks are uniformly min_chunk_size long
+            assert_eq!(chunks.len(), data.len().div_ceil(min_chunk_size));
+            let mut chunk_start: u64 = 0;
+            for chunk in chunks.iter() {
+                let expected_size = (min_chunk_size as u64).min(data.len() as u64 - chunk_start);
+                assert_eq!(chunk.end - chunk_start, expected_size);
+                chunk_start = chunk.end;
+            }
+        }
+
+        // below the rolling hash window, the chunks are as long as it takes to prime it
+        let mut slicer = Slicer::new(
+            PolynomialRollingHasher::new(64, None, None),
+            Sha256Hasher::new(1000),
+            0,
+            32,
+            1000,
+        )
+        .unwrap();
+        slicer.process(&data);
+        let chunks = slicer.finalize().unwrap();
+        assert_eq!(chunks.len(), data.len().div_ceil(63));
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.end, (63 * (index as u64 + 1)).min(data.len() as u64));
+        }
+    }
+
+    #[test]
+    fn test_slicer_chunk_metadata() {
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let make_slicer = || {
+            Slicer::new(
+                PolynomialRollingHasher::new(16, None, None),
+                Sha256Hasher::new(1024),
+                (1 << 8) - 1,
+                64,
+                1024,
+            )
+            .unwrap()
+        };
+
+        let mut slicer = make_slicer();
+        slicer.process(&data);
+        slicer.finalize().unwrap();
+        assert!(slicer.chunk_metadata().is_empty());
+
+        // tag each chunk with its length parity
+        let mut slicer = make_slicer();
+        slicer.set_chunk_metadata_callback(Box::new(|range: Range<u64>| {
+            vec![((range.end - range.start) % 2) as u8]
+        }));
+        slicer.process(&data);
+        let chunks = slicer.finalize().unwrap().clone();
+        assert_eq!(slicer.chunk_metadata().len(), chunks.len());
+        let mut chunk_start: u64 = 0;
+        for (chunk, metadata) in chunks.iter().zip(slicer.chunk_metadata()) {
+            assert_eq!(metadata, &vec![((chunk.end - chunk_start) % 2) as u8]);
+            chunk_start = chunk.end;
+        }
+    }
+
+    #[test]
+    fn test_slicer_boundary_events() {
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let max_chunk_size: usize = 256;
+        let mut slicer = Slicer::new(
+            PolynomialRollingHasher::new(16, None, None),
+            Sha256Hasher::new(max_chunk_size),
+            (1 << 8) - 1,
+            64,
+            max_chunk_size,
+        )
+        .unwrap();
+        let events: Arc<Mutex<Vec<BoundaryEvent>>> = Arc::new(Mutex::new(Vec::new()));
+        let collected_events = events.clone();
+        slicer.set_boundary_callback(Box::new(move |event: &BoundaryEvent| {
+            collected_events.lock().unwrap().push(event.clone());
+        }));
+        slicer.process(&data);
+        let chunks = slicer.finalize().unwrap().clone();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), chunks.len());
+        let mut chunk_start: u64 = 0;
+        for (event, chunk) in events.iter().zip(chunks.iter()) {
+            assert_eq!(event.offset, chunk_start);
+            assert_eq!(event.offset + event.size, chunk.end);
+            assert_eq!(event.hash_prefix, chunk.hash[..8]);
+            chunk_start = chunk.end;
+        }
+
+        // only the last chunk is cut by the end of the stream, the forced cuts are the
+        // max_chunk_size long chunks which are not content-defined
+        let count = |cut: CutKind| events.iter().filter(|event| event.cut == cut).count();
+        assert_eq!(events.last().unwrap().cut, CutKind::End);
+        assert_eq!(count(CutKind::End), 1);
+        assert!(count(CutKind::Forced) > 0 && count(CutKind::Content) > 0);
+        assert_eq!(count(CutKind::Forced) + count(CutKind::Content) + 1, chunks.len());
+        assert!(events
+            .iter()
+            .filter(|event| event.cut == CutKind::Forced)
+            .all(|event| event.size == max_chunk_size as u64));
+    }
+
+    #[test]
+    fn test_slicer_secondary_boundary_mask() {
+        // repetitive data (regions repeating short patterns), whose few distinct rolling
+        // hashes rarely match a 12-bit mask, so most chunks are cut at the max chunk size
+        let data: Vec<u8> = (0..400_000u32)
+            .map(|i| ((i % 150 + i / 50_000 * 1000).wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let slice = |secondary_mask: Option<u32>| {
+            let mut slicer = Slicer::new(
+                PolynomialRollingHasher::new(64, None, None),
+                Sha256Hasher::new(16384),
+                (1 << 12) - 1,
+                2048,
+                16384,
+            )
+            .unwrap();
+            if let Some(mask) = secondary_mask {
+                slicer.set_secondary_boundary_mask(mask, 2 * 4096);
+            }
+            let cuts: Arc<Mutex<Vec<CutKind>>> = Arc::new(Mutex::new(Vec::new()));
+            let collected_cuts = cuts.clone();
+            slicer.set_boundary_callback(Box::new(move |event: &BoundaryEvent| {
+                collected_cuts.lock().unwrap().push(event.cut);
+            }));
+            slicer.process(&data);
+            let chunks = slicer.finalize().unwrap().clone();
+            let forced_cuts = cuts.lock().unwrap().iter().filter(|&&cut| cut == CutKind::Forced).count();
+            (chunks, forced_cuts)
+        };
+
+        let (chunks, forced_cuts) = slice(None);
+        let (secondary_chunks, secondary_forced_cuts) = slice(Some((1 << 6) - 1));
+        assert!(forced_cuts > chunks.len() / 2);
+        assert!(secondary_forced_cuts * 4 < forced_cuts); // most forced cuts are avoided
+
+        // the boundaries found by the secondary mask only (the chunks the single-mask slicing
+        // doesn't have) cut chunks which have grown past its size
+        let mut chunk_start: u64 = 0;
+        for chunk in &secondary_chunks {
+            let size = chunk.end - chunk_start;
+            assert!(size <= 16384);
+            if !chunks.iter().any(|primary_chunk| primary_chunk.end == chunk.end) {
+                assert!(size >= 2 * 4096);
+            }
+            chunk_start = chunk.end;
+        }
+        assert_eq!(chunk_start, data.len() as u64);
+    }
+
+    #[test]
+    fn test_slicer_normalized_chunking() {
+        let data = std::fs::read("./example/monkey_before.tiff").unwrap();
+        let avg_chunk_size: usize = 1024;
+        // the chunk sizes (but the last one's) and the number of those cut by force
+        let slice = |normalized: bool| -> (Vec<f64>, usize) {
+            let mut slicer = Slicer::new(
+                PolynomialRollingHasher::new(64, None, None),
+                Sha256Hasher::new(8192),
+                (1 << 10) - 1,
+                256,
+                8192,
+            )
+            .unwrap();
+            if normalized {
+                slicer.set_normalized_chunking((1 << 12) - 1, (1 << 8) - 1, avg_chunk_size);
+            }
+            let forced_cuts = Arc::new(AtomicUsize::new(0));
+            let counted_forced_cuts = forced_cuts.clone();
+            slicer.set_boundary_callback(Box::new(move |event: &BoundaryEvent| {
+                if event.cut == CutKind::Forced {
+                    counted_forced_cuts.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+            slicer.process(&data);
+            let chunks = slicer.finalize().unwrap();
+            assert_eq!(chunks.last().unwrap().end, data.len() as u64);
+            let mut chunk_start: u64 = 0;
+            let mut sizes: Vec<f64> = Vec::new();
+            for chunk in &chunks[..chunks.len() - 1] {
+                sizes.push((chunk.end - chunk_start) as f64);
+                chunk_start = chunk.end;
+            }
+            (sizes, forced_cuts.load(Ordering::Relaxed))
+        };
+        let mean = |sizes: &[f64]| sizes.iter().sum::<f64>() / sizes.len() as f64;
+        let std_dev = |sizes: &[f64]| {
+            let mean = mean(sizes);
+            (sizes.iter().map(|size| (size - mean).powi(2)).sum::<f64>() / sizes.len() as f64).sqrt()
+        };
+
+        let (sizes, forced_cuts) = slice(false);
+        let (normalized_sizes, normalized_forced_cuts) = slice(true);
+        // about the same average size, but much closer to it
+        let avg_chunk_size = avg_chunk_size as f64;
+        assert!((mean(&sizes) - avg_chunk_size).abs() < avg_chunk_size * 0.5);
+        assert!((mean(&normalized_sizes) - avg_chunk_size).abs() < avg_chunk_size * 0.5);
+        assert!(std_dev(&normalized_sizes) * 2.0 < std_dev(&sizes));
+        let small_chunk_count =
+            |sizes: &[f64]| sizes.iter().filter(|&&size| size < avg_chunk_size / 2.0).count();
+        assert!(small_chunk_count(&normalized_sizes) * 2 < small_chunk_count(&sizes));
+        assert!(normalized_forced_cuts <= forced_cuts);
+    }
+
+    #[test]
+    fn test_slicer_local_minimum_boundaries() {
+        // xorshift pseudo-random bytes
+        let mut state: u32 = 2463534242;
+        let data: Vec<u8> = (0..1 << 18)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let slice = |data: &[u8]| {
+            let mut slicer = Slicer::new(
+                PolynomialRollingHasher::new(64, None, None),
+                Sha256Hasher::new(16384),
+                (1 << 12) - 1,
+                1024,
+                8192,
+            )
+            .unwrap();
+            slicer.set_local_minimum_boundaries(2048);
+            slicer.process(data);
+            slicer.finalize().unwrap().clone()
+        };
+        let ends = |chunks: &[Chunk]| chunks.iter().map(|chunk| chunk.end).collect::<Vec<u64>>();
+
+        // every chunk but the last is within the min and max sizes, most cut by content
+        let chunks = slice(&data);
+        let mut chunk_start: u64 = 0;
+        let mut max_size_chunks: usize = 0;
+        for chunk in &chunks[..chunks.len() - 1] {
+            let size = chunk.end - chunk_start;
+            assert!((1024..=8192).contains(&size));
+            if size == 8192 {
+                max_size_chunks += 1;
+            }
+            chunk_start = chunk.end;
+        }
+        assert!(chunks.len() > 40);
+        assert!(max_size_chunks * 4 < chunks.len());
+        assert_eq!(chunks.last().unwrap().end, data.len() as u64);
+
+        // a single-byte edit only moves the boundaries of the chunk it's in and the next
+        let mut edited = data.clone();
+        edited[100_000] ^= 0xff;
+        let edited_chunks = slice(&edited);
+        let (old_ends, new_ends) = (ends(&chunks), ends(&edited_chunks));
+        let away_from_edit = |end: u64| !(100_000..=120_000).contains(&end);
+        assert!(old_ends.iter().filter(|end| !new_ends.contains(end)).count() <= 2);
+        for end in old_ends.iter().filter(|&&end| away_from_edit(end)) {
+            assert!(new_ends.contains(end));
+        }
+
+        // nor does a single-byte insertion, the boundaries after it being shifted by one
+        let mut inserted = data.clone();
+        inserted.insert(100_000, 0x5a);
+        let inserted_ends = ends(&slice(&inserted));
+        for &end in old_ends.iter().filter(|&&end| away_from_edit(end)) {
+            let shifted = if end < 100_000 { end } else { end + 1 };
+            assert!(inserted_ends.contains(&shifted));
+        }
+    }
+
+    #[test]
+    fn test_slicer_symbols() {
+        let make_slicer = || {
+            Slicer::new(
+                PolynomialRollingHasher::new(16, None, None),
+                Sha256Hasher::new(255),
+                (1 << 6) - 1,
+                64,
+                255, // odd, a forced cut can't happen right at it
+            )
+            .unwrap()
+        };
+        let symbols: Vec<u16> = (0..20000u32).map(|i| (i * 7919 % 4099 + i / 7) as u16).collect();
+        let mut slicer = make_slicer();
+        for buffer in symbols.chunks(1000) {
+            slicer.process_symbols(buffer);
+        }
+        let chunks = slicer.finalize().unwrap().clone();
+        let mut chunk_start: u64 = 0;
+        for chunk in &chunks {
+            // the boundaries never split a symbol, nor exceed max_chunk_size
+            assert_eq!(chunk.end % 2, 0);
+            assert!(chunk.end - chunk_start <= 255);
+            chunk_start = chunk.end;
+        }
+        assert_eq!(chunk_start, 40000);
+        assert!(chunks.len() > 200);
+
+        // the chunk hashes are those of the symbols' bytes
+        let bytes: Vec<u8> = symbols.iter().flat_map(|symbol| symbol.to_le_bytes()).collect();
+        let mut chunk_start: usize = 0;
+        for chunk in &chunks {
+            let mut hasher = Sha256Hasher::new(255);
+            for &byte in &bytes[chunk_start..chunk.end as usize] {
+                hasher.push(byte);
+            }
+            assert_eq!(hasher.finalize(), chunk.hash);
+            chunk_start = chunk.end as usize;
+        }
+
+        // single-byte symbols are chunked exactly as the bytes are
+        let mut byte_slicer = make_slicer();
+        byte_slicer.process(&bytes);
+        let mut symbol_slicer = make_slicer();
+        symbol_slicer.process_symbols(&bytes);
+        assert_eq!(byte_slicer.finalize(), symbol_slicer.finalize());
+    }
+
+    #[test]
+    fn test_slicer_finalized() {
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let sequential = Slicer::new(
+            PolynomialRollingHasher::new(16, None, None),
+            Sha256Hasher::new(1024),
+            (1 << 8) - 1,
+            64,
+            1024,
+        )
+        .unwrap();
+        let pipelined = Slicer::new_pipelined(
+            PolynomialRollingHasher::new(16, None, None),
+            || Sha256Hasher::new(1024),
+            2,
+            (1 << 8) - 1,
+            64,
+            1024,
+        )
+        .unwrap();
+        for mut slicer in [sequential, pipelined] {
+            assert_eq!(slicer.try_process(&data), Ok(()));
+            let chunks = slicer.finalize().unwrap().clone();
+
+            // no more data is accepted, the chunks stay the same
+            assert_eq!(slicer.try_process(&data), Err(SlicerError::Finalized));
+            assert_eq!(slicer.try_process(&[]), Err(SlicerError::Finalized));
+            assert_eq!(slicer.finalize().unwrap(), &chunks);
+        }
+    }
+
+    #[test]
+    fn test_slicer_chunk_spill_reload_failure() -> std::io::Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("differ_spill_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let make_slicer = || {
+            Slicer::new(
+                PolynomialRollingHasher::new(16, None, None),
+                Sha256Hasher::new(256),
+                (1 << 6) - 1,
+                16,
+                256,
+            )
+            .unwrap()
+        };
+        let mut slicer = make_slicer();
+        slicer.process(&data);
+        let expected_chunks = slicer.finalize().unwrap().clone();
+
+        let mut slicer = make_slicer();
+        slicer.enable_chunk_spilling(4, &directory)?;
+        slicer.process(&data);
+        // truncating the spill file within a chunk makes the reload fail
+        let spill_path = std::fs::read_dir(&directory)?.next().unwrap()?.path();
+        let spilled = std::fs::read(&spill_path)?;
+        std::fs::write(&spill_path, &spilled[..5])?;
+        assert!(matches!(slicer.finalize(), Err(SlicerError::ChunkSpillFailed(_))));
+        // the spilled chunks are still there, so finalizing succeeds once the file is restored
+        std::fs::write(&spill_path, &spilled)?;
+        assert_eq!(slicer.finalize().unwrap(), &expected_chunks);
+        drop(slicer);
+
+        std::fs::remove_dir_all(&directory)
+    }
+
+    #[test]
+    fn test_slicer_chunk_spill_write_failure() -> std::io::Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("differ_spill_write_{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let data: Vec<u8> = (0..10007u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let mut slicer = Slicer::new(
+            PolynomialRollingHasher::new(16, None, None),
+            Sha256Hasher::new(256),
+            (1 << 6) - 1,
+            16,
+            256,
+        )
+        .unwrap();
+        slicer.enable_chunk_spilling(4, &directory)?;
+        slicer.chunk_spill = Some(ChunkSpill::create_read_only(&directory)?);
+        slicer.process(&data);
+        assert!(matches!(slicer.finalize(), Err(SlicerError::ChunkSpillFailed(_))));
+        drop(slicer);
+
+        std::fs::remove_dir_all(&directory)
+    }
 }
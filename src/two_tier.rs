@@ -0,0 +1,153 @@
+/*
+    Two-tier chunk hashing: the chunks of both inputs are first fingerprinted with a cheap
+    hash (64-bit FNV-1a) and the strong one (SHA256) is only computed for the chunks whose
+    fingerprint appears in the other input, i.e. those which may be reused. For dissimilar
+    inputs most chunks are then never hashed with SHA256.
+
+    A chunk whose fingerprint doesn't appear in the other input can't match any of its chunks,
+    so the fingerprint itself serves as its hash: it's shorter than a SHA256 hash, so it never
+    equals a strong one, nor (by definition) any fingerprint of the other input. Chunks whose
+    fingerprints collide are told apart by their strong hashes, so the delta is the same as
+    the one of the default Differ (the boundaries don't depend on the hash).
+
+    The matched chunks are re-read to be hashed, so this works on in-memory buffers only.
+*/
+
+use crate::delta::*;
+use crate::differ::*;
+use crate::hasher::fnv::*;
+use crate::hasher::hasher::*;
+use crate::hasher::sha256::*;
+use crate::lcs::lcs::*;
+use crate::slicer::*;
+use std::collections::HashSet;
+
+/// Compares two versions of data as Differ does, computing the SHA256 chunk hashes only for
+/// the chunks which may be reused (see the two_tier module)
+/// 
+/// Arguments:
+/// old             - the old data
+/// new             - the new (updated) data
+/// parameters      - the chunking parameters
+/// 
+/// Returned:
+/// the vector of Segments (see Differ::finalize) and the number of chunks hashed with SHA256,
+/// or the error describing the invalid parameter combination
+pub fn diff_two_tier(
+    old: &[u8],
+    new: &[u8],
+    parameters: ChunkingParameters,
+) -> Result<(Vec<Segment>, usize), SlicerError> {
+    diff_two_tier_with(old, new, parameters, FnvHasher::new)
+}
+
+// same as diff_two_tier with the cheap hasher created by make_cheap_hasher
+fn diff_two_tier_with<H: Hasher, F: Fn() -> H>(
+    old: &[u8],
+    new: &[u8],
+    parameters: ChunkingParameters,
+    make_cheap_hasher: F,
+) -> Result<(Vec<Segment>, usize), SlicerError> {
+    let config = DifferConfig {
+        chunking: parameters,
+        ..DifferConfig::default()
+    };
+    let mut slicer_old = make_slicer_with_hasher(&config, make_cheap_hasher())?;
+    let mut slicer_new = make_slicer_with_hasher(&config, make_cheap_hasher())?;
+    slicer_old.process(old);
+    slicer_new.process(new);
+    slicer_old.finalize()?;
+    slicer_new.finalize()?;
+    let mut chunks_old = slicer_old.into_chunks();
+    let mut chunks_new = slicer_new.into_chunks();
+
+    let fingerprints_old: HashSet<Vec<u8>> = chunks_old.iter().map(|chunk| chunk.hash.clone()).collect();
+    let fingerprints_new: HashSet<Vec<u8>> = chunks_new.iter().map(|chunk| chunk.hash.clone()).collect();
+    let mut hasher = Sha256Hasher::new(parameters.max_chunk_size);
+    let strong_hash_count = strengthen(&mut chunks_old, old, &fingerprints_new, &mut hasher)
+        + strengthen(&mut chunks_new, new, &fingerprints_old, &mut hasher);
+
+    let hashes_old: Vec<Vec<u8>> = chunks_old.iter().map(|chunk| chunk.hash.clone()).collect();
+    let hashes_new: Vec<Vec<u8>> = chunks_new.iter().map(|chunk| chunk.hash.clone()).collect();
+    let lcs_algorithm = choose_lcs_algorithm(&hashes_old[..], &hashes_new[..]);
+    let lcs = lcs(lcs_algorithm, &hashes_old, &hashes_new, &[]);
+    Ok((delta(&chunks_old, &chunks_new, &lcs), strong_hash_count))
+}
+
+// replaces the fingerprints found among the other input's ones with the strong hashes of the
+// chunks; returns the number of chunks hashed
+fn strengthen(
+    chunks: &mut [Chunk],
+    data: &[u8],
+    other_fingerprints: &HashSet<Vec<u8>>,
+    hasher: &mut Sha256Hasher,
+) -> usize {
+    let mut strong_hash_count: usize = 0;
+    let mut chunk_start: u64 = 0;
+    for chunk in chunks {
+        if other_fingerprints.contains(&chunk.hash) {
+            for &byte in &data[buffer_range(&(chunk_start..chunk.end))] {
+                hasher.push(byte);
+            }
+            chunk.hash = hasher.finalize();
+            strong_hash_count += 1;
+        }
+        chunk_start = chunk.end;
+    }
+    strong_hash_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const PARAMETERS: ChunkingParameters = ChunkingParameters {
+        window_size: 64,
+        min_chunk_size: 2048,
+        max_chunk_size: 8192,
+        boundary_mask: (1 << 12) - 1,
+    };
+
+    // a fingerprint which never tells the chunks apart
+    struct ConstantHasher;
+
+    impl Hasher for ConstantHasher {
+        fn push(&mut self, _byte: u8) {}
+
+        fn finalize(&mut self) -> Vec<u8> {
+            vec![0; 8]
+        }
+    }
+
+    #[test]
+    fn test_diff_two_tier() {
+        let old = fs::read("./example/monkey_before.tiff").unwrap();
+        let new = fs::read("./example/monkey_after.tiff").unwrap();
+        let mut differ = Differ::from_config(DifferConfig {
+            chunking: PARAMETERS,
+            ..DifferConfig::default()
+        });
+        differ.process_old(&old);
+        differ.process_new(&new);
+        let (expected, artifacts) = differ.finalize_with_artifacts();
+        let chunk_count = artifacts.chunks_old.len() + artifacts.chunks_new.len();
+
+        // the same delta, hashing only the chunks which may be reused
+        let (segments, strong_hash_count) = diff_two_tier(&old, &new, PARAMETERS).unwrap();
+        assert_eq!(segments, expected);
+        assert!(strong_hash_count < chunk_count);
+
+        // colliding fingerprints are told apart by the strong hashes
+        let (segments, strong_hash_count) =
+            diff_two_tier_with(&old, &new, PARAMETERS, || ConstantHasher).unwrap();
+        assert_eq!(segments, expected);
+        assert_eq!(strong_hash_count, chunk_count);
+
+        // dissimilar inputs need no strong hash at all
+        let noise: Vec<u8> = (0..100000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let (segments, strong_hash_count) = diff_two_tier(&old, &noise, PARAMETERS).unwrap();
+        assert_eq!(segments, vec![Segment::New(0..noise.len() as u64)]);
+        assert_eq!(strong_hash_count, 0);
+    }
+}
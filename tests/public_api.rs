@@ -0,0 +1,242 @@
+/*
+    Drives the public workflow of the library as an external consumer would, so that the
+    public interface stays usable from outside the crate: diffing in-memory buffers and
+    streamed files, storing the deltas and applying them, and the Differ options.
+*/
+
+use differ::{
+    chunk, deserialize, fuzzy_reuse, patch, patch_from_reader, patch_with_source, pipe_diff,
+    read_file_with_progress, reconstruct_iter, refine, serialize, stream, ChunkIndex, Chunker,
+    ChunkingParameters, CollisionPolicy, Differ, DifferConfig, FileChunkSource, Journal,
+    ProgressInterval, Segment, TextNormalizer,
+};
+#[cfg(feature = "sha1")]
+use differ::{MovingSumRollingHasher, Sha1Hasher};
+use std::fs;
+use std::io::{Read, Result};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const OLD_FILE_PATH: &str = "./example/monkey_before.tiff";
+const NEW_FILE_PATH: &str = "./example/monkey_after.tiff";
+
+const PARAMETERS: ChunkingParameters = ChunkingParameters {
+    window_size: 64,
+    min_chunk_size: 2048,
+    max_chunk_size: 8192,
+    boundary_mask: (1 << 12) - 1,
+};
+
+fn make_differ() -> Differ {
+    Differ::try_new(
+        Some(PARAMETERS.window_size),
+        Some(PARAMETERS.min_chunk_size),
+        Some(PARAMETERS.max_chunk_size),
+        Some(PARAMETERS.boundary_mask),
+        None,
+    )
+    .unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("differ_public_api_{}_{}", name, std::process::id()))
+}
+
+fn reused(segments: &[Segment]) -> u64 {
+    segments
+        .iter()
+        .filter(|segment| matches!(segment, Segment::Old(_)))
+        .map(|segment| segment.len())
+        .sum()
+}
+
+#[test]
+fn test_public_api_buffers() {
+    let old = fs::read(OLD_FILE_PATH).unwrap();
+    let new = fs::read(NEW_FILE_PATH).unwrap();
+
+    let mut differ = make_differ();
+    differ.process_old(&old);
+    differ.process_new(&new);
+    let segments = differ.finalize();
+    assert!(reused(&segments) > 0);
+    assert_eq!(segments.iter().map(|segment| segment.len()).sum::<u64>(), new.len() as u64);
+
+    // the deltas are stored (serialized) and applied by the journal
+    let config = DifferConfig {
+        chunking: PARAMETERS,
+        ..DifferConfig::default()
+    };
+    let mut journal = Journal::new(config).unwrap();
+    journal.append(&old);
+    journal.append(&new);
+    let journal = Journal::from_bytes(journal.as_bytes().to_vec()).unwrap();
+    assert_eq!(journal.version_count(), 2);
+    assert_eq!(journal.reconstruct(0).unwrap(), old);
+    assert_eq!(journal.reconstruct(1).unwrap(), new);
+
+    // the standalone chunking agrees with itself, whether streamed or not
+    let mut chunker = Chunker::new(PARAMETERS).unwrap();
+    for buffer in old.chunks(1000) {
+        chunker.process(buffer);
+    }
+    assert_eq!(chunker.finalize().unwrap(), chunk(&old, PARAMETERS).unwrap());
+}
+
+#[test]
+fn test_public_api_streaming() -> Result<()> {
+    let mut differ = make_differ();
+    let mut progress_reported = false;
+    read_file_with_progress(
+        OLD_FILE_PATH,
+        4096,
+        ProgressInterval::Percent(10),
+        |bytes| differ.process_old(bytes),
+        |_| progress_reported = true,
+    );
+    read_file_with_progress(
+        NEW_FILE_PATH,
+        4096,
+        ProgressInterval::Percent(10),
+        |bytes| differ.process_new(bytes),
+        |_| {},
+    );
+    assert!(progress_reported);
+    let segments = differ.finalize();
+    let new = fs::read(NEW_FILE_PATH)?;
+
+    // the delta is applied reading the old data from a file, a source or a stream
+    let patched_file_path = temp_path("patched");
+    let patched_file_path = patched_file_path.to_str().unwrap();
+    let (old_bytes_used, new_bytes_used) =
+        patch(OLD_FILE_PATH, NEW_FILE_PATH, patched_file_path, segments.clone())?;
+    assert_eq!(old_bytes_used, reused(&segments));
+    assert_eq!(old_bytes_used + new_bytes_used, new.len() as u64);
+    assert_eq!(fs::read(patched_file_path)?, new);
+
+    let source = FileChunkSource::open(OLD_FILE_PATH)?;
+    patch_with_source(&source, NEW_FILE_PATH, patched_file_path, segments.clone())?;
+    assert_eq!(fs::read(patched_file_path)?, new);
+
+    let old_reader = fs::File::open(OLD_FILE_PATH)?;
+    patch_from_reader(old_reader, NEW_FILE_PATH, patched_file_path, segments)?;
+    assert_eq!(fs::read(patched_file_path)?, new);
+    fs::remove_file(patched_file_path)?;
+
+    // the self-contained delta streamed from the new data is smaller than the new data
+    let mut delta: Vec<u8> = Vec::new();
+    pipe_diff(OLD_FILE_PATH, &new[..], &mut delta, PARAMETERS)?;
+    assert!(delta.len() < new.len());
+
+    // the inputs can also be file paths or readers, whose read errors are returned
+    let diff = |old: &str, new: &str| -> Result<Vec<Segment>> {
+        let old_reader: Box<dyn Read> = Box::new(fs::File::open(old)?);
+        let segments = Differ::diff(
+            old_reader,
+            new,
+            Some(PARAMETERS.window_size),
+            Some(PARAMETERS.min_chunk_size),
+            Some(PARAMETERS.max_chunk_size),
+            Some(PARAMETERS.boundary_mask),
+        )?;
+        Ok(segments)
+    };
+    let old = fs::read(OLD_FILE_PATH)?;
+    let segments = diff(OLD_FILE_PATH, NEW_FILE_PATH)?;
+    let patched: Vec<u8> = reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+    assert_eq!(patched, new);
+    assert!(diff(OLD_FILE_PATH, "./example/missing_file").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_public_api_options() {
+    let old = fs::read(OLD_FILE_PATH).unwrap();
+    let new = fs::read(NEW_FILE_PATH).unwrap();
+    let patched = |segments: &[Segment]| -> Vec<u8> {
+        reconstruct_iter(&old, &new, segments).flatten().copied().collect()
+    };
+
+    let mut differ = make_differ();
+    differ.set_lcs_deadline(Instant::now() + Duration::from_secs(3600));
+    differ.set_collision_policy(CollisionPolicy::TreatAsNew);
+    differ.enable_anchored_lcs();
+    differ.set_parallelism(2);
+    differ.set_max_new_segment_size(1 << 14);
+    differ.set_common_header_len(8);
+    differ.process_old(&old);
+    differ.process_new(&new);
+    let (segments, lcs_skipped) = differ.finalize_with_lcs_status();
+    assert!(!lcs_skipped);
+    assert!(reused(&segments) > 0);
+    assert_eq!(patched(&segments), new);
+
+    let mut differ = make_differ();
+    differ.set_normalizer(TextNormalizer::new(true, true));
+    differ.process_old(&old);
+    differ.process_new(&new);
+    let (forward, reverse) = differ.finalize_bidirectional();
+    assert_eq!(patched(&forward), new);
+    assert_eq!(reconstruct_iter(&new, &old, &reverse).flatten().copied().collect::<Vec<u8>>(), old);
+    let (forward, _) = Differ::diff_bidirectional(
+        &old[..],
+        &new[..],
+        Some(PARAMETERS.window_size),
+        Some(PARAMETERS.min_chunk_size),
+        Some(PARAMETERS.max_chunk_size),
+        Some(PARAMETERS.boundary_mask),
+    )
+    .unwrap();
+    assert_eq!(patched(&forward), new);
+
+    let segments = make_differ().diff_pair_with_params(&old[..], &new[..], PARAMETERS).unwrap();
+    assert_eq!(patched(&refine(segments.clone(), &old, &new)), new);
+    let config = DifferConfig {
+        chunking: PARAMETERS,
+        ..DifferConfig::default()
+    };
+    assert_eq!(deserialize(&serialize(&segments, &config).unwrap()), Ok((config, segments)));
+
+    let mut differ = make_differ();
+    differ.process_old(&old);
+    differ.process_new(&new);
+    let (segments, artifacts) = differ.finalize_with_artifacts();
+    // the same delta is emitted segment by segment
+    let mut streamed: Vec<Segment> = Vec::new();
+    stream(&artifacts.chunks_old, &artifacts.chunks_new, &artifacts.lcs, |segment| {
+        streamed.push(segment)
+    });
+    assert_eq!(streamed, segments);
+    let fuzzy = fuzzy_reuse(segments, &artifacts.chunks_old, &artifacts.chunks_new, &old, &new, 0);
+    assert_eq!(patched(&fuzzy), new);
+
+    // the data diffed against an index of itself is made of indexed chunks only
+    let mut index = ChunkIndex::new(config);
+    index.add(&old[..]).unwrap();
+    assert!(!index.is_empty());
+    let segments = Differ::diff_against_index(&old[..], &index).unwrap();
+    assert!(segments.iter().all(|segment| matches!(segment, Segment::Store(..))));
+}
+
+#[cfg(feature = "sha1")]
+#[test]
+fn test_public_api_custom_hashers() {
+    let old = fs::read(OLD_FILE_PATH).unwrap();
+    let new = fs::read(NEW_FILE_PATH).unwrap();
+
+    let mut differ = Differ::with_hashers(
+        MovingSumRollingHasher::new(PARAMETERS.window_size),
+        MovingSumRollingHasher::new(PARAMETERS.window_size),
+        Sha1Hasher::new(PARAMETERS.max_chunk_size),
+        Sha1Hasher::new(PARAMETERS.max_chunk_size),
+        PARAMETERS,
+    )
+    .unwrap();
+    differ.process_old(&old);
+    differ.process_new(&new);
+    let segments = differ.finalize();
+    assert!(reused(&segments) > 0);
+    let patched: Vec<u8> = reconstruct_iter(&old, &new, &segments).flatten().copied().collect();
+    assert_eq!(patched, new);
+}